@@ -0,0 +1,7 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // The generated gRPC code is only needed, and protoc only required, with the `grpc` feature
+    if std::env::var_os("CARGO_FEATURE_GRPC").is_some() {
+        tonic_build::compile_protos("proto/tracer.proto")?;
+    }
+    Ok(())
+}
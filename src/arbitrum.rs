@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+
+use alloy_primitives::{Address, Bytes, TxHash, B256, U256, U64};
+use futures::future::BoxFuture;
+use serde_json::{json, Value};
+
+use crate::l2::{undo_l1_to_l2_alias, L2Adapter};
+use crate::rpc::RpcClient;
+
+// EIP-2718 types of the pseudo-transactions ArbOS inserts for L1 to L2 messages. The retry of a
+// retryable ticket runs the L1 sender's call and is where its creates and selfdestructs happen
+const DEPOSIT_TX_TYPE: u64 = 0x64;
+const UNSIGNED_TX_TYPE: u64 = 0x65;
+const CONTRACT_TX_TYPE: u64 = 0x66;
+const RETRY_TX_TYPE: u64 = 0x68;
+const SUBMIT_RETRYABLE_TX_TYPE: u64 = 0x69;
+// ArbOS bookkeeping at the start of every block, it never runs user code
+const INTERNAL_TX_TYPE: u64 = 0x6a;
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BlockTransaction {
+    hash: TxHash,
+    transaction_index: U64,
+    #[serde(rename = "type")]
+    tx_type: U64,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Block {
+    hash: B256,
+    number: U64,
+    transactions: Vec<BlockTransaction>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TxTrace {
+    tx_hash: Option<TxHash>,
+    result: Option<CallFrame>,
+}
+
+// Frame of the callTracer as Nitro returns it. Its `beforeEVMTransfers` and `afterEVMTransfers`
+// (ArbOS moving ETH outside the EVM) carry no creates or selfdestructs and are left out
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CallFrame {
+    #[serde(rename = "type")]
+    frame_type: String,
+    from: Address,
+    #[serde(default)]
+    to: Option<Address>,
+    #[serde(default)]
+    value: Option<U256>,
+    #[serde(default)]
+    gas: Option<U64>,
+    #[serde(default)]
+    gas_used: Option<U64>,
+    #[serde(default)]
+    input: Bytes,
+    #[serde(default)]
+    output: Option<Bytes>,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    calls: Vec<CallFrame>,
+}
+
+fn is_l1_message(tx_type: u64) -> bool {
+    matches!(
+        tx_type,
+        DEPOSIT_TX_TYPE
+            | UNSIGNED_TX_TYPE
+            | CONTRACT_TX_TYPE
+            | RETRY_TX_TYPE
+            | SUBMIT_RETRYABLE_TX_TYPE
+    )
+}
+
+/// Arbitrum Nitro, which serves no trace API
+#[derive(Debug)]
+pub struct Arbitrum;
+
+impl L2Adapter for Arbitrum {
+    fn name(&self) -> &'static str {
+        "arbitrum"
+    }
+
+    fn trace_module(&self) -> &'static str {
+        "debug"
+    }
+
+    fn trace_block<'a>(
+        &'a self,
+        client: &'a RpcClient,
+        block_tag: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<Option<Vec<Value>>>> {
+        Box::pin(trace_block(client, block_tag))
+    }
+
+    fn system_transactions<'a>(
+        &'a self,
+        client: &'a RpcClient,
+        block_tag: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<HashMap<TxHash, u64>>> {
+        Box::pin(l1_messages(client, block_tag))
+    }
+
+    fn unalias(&self, address: Address) -> Option<Address> {
+        Some(undo_l1_to_l2_alias(address))
+    }
+
+    fn fee_fields(&self) -> &'static [&'static str] {
+        &["gasUsedForL1", "l1BlockNumber"]
+    }
+}
+
+// L1 to L2 message transactions of the block mapped to their position
+async fn l1_messages(client: &RpcClient, block_tag: &str) -> anyhow::Result<HashMap<TxHash, u64>> {
+    let block = client
+        .request::<Block>("eth_getBlockByNumber", json!([block_tag, true]))
+        .await?;
+    Ok(block
+        .map(|block| block.transactions)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|transaction| is_l1_message(transaction.tx_type.to::<u64>()))
+        .map(|transaction| (transaction.hash, transaction.transaction_index.to::<u64>()))
+        .collect())
+}
+
+// Nitro serves no `trace_block`, so the block is traced with `debug_traceBlockByNumber` and the
+// call frames are rewritten into Parity-style traces. Gas is copied as the node reports it, on
+// Nitro `gasUsed` of the top-level frame includes the L1 data fee, but no record field carries
+// gas
+async fn trace_block(client: &RpcClient, block_tag: &str) -> anyhow::Result<Option<Vec<Value>>> {
+    let Some(block) = client
+        .request::<Block>("eth_getBlockByNumber", json!([block_tag, true]))
+        .await?
+    else {
+        return Ok(None);
+    };
+    let tx_traces = client
+        .request::<Vec<TxTrace>>(
+            "debug_traceBlockByNumber",
+            json!([block_tag, { "tracer": "callTracer" }]),
+        )
+        .await?
+        .unwrap_or_default();
+
+    let mut traces = Vec::new();
+    for (transaction, tx_trace) in block.transactions.iter().zip(tx_traces) {
+        if transaction.tx_type.to::<u64>() == INTERNAL_TX_TYPE {
+            continue;
+        }
+        let Some(frame) = tx_trace.result else {
+            continue;
+        };
+        let transaction_hash = tx_trace.tx_hash.unwrap_or(transaction.hash);
+        let location = json!({
+            "blockHash": block.hash,
+            "blockNumber": block.number.to::<u64>(),
+            "transactionHash": transaction_hash,
+            "transactionPosition": transaction.transaction_index.to::<u64>(),
+        });
+        flatten(&frame, Vec::new(), &location, &mut traces);
+    }
+    Ok(Some(traces))
+}
+
+// Parents precede their subtraces, as in `trace_block` output
+fn flatten(
+    frame: &CallFrame,
+    trace_address: Vec<usize>,
+    location: &Value,
+    traces: &mut Vec<Value>,
+) {
+    let value = frame.value.unwrap_or_default();
+    let gas = frame.gas.unwrap_or_default();
+    let gas_used = frame.gas_used.unwrap_or_default();
+    let output = frame.output.clone().unwrap_or_default();
+    let (trace_type, action, result) = match frame.frame_type.as_str() {
+        "CREATE" | "CREATE2" => (
+            "create",
+            json!({
+                "from": frame.from,
+                "gas": gas,
+                "init": frame.input,
+                "value": value,
+                "creationMethod": frame.frame_type.to_lowercase(),
+            }),
+            match (&frame.error, frame.to) {
+                (None, Some(address)) => {
+                    json!({ "address": address, "code": output, "gasUsed": gas_used })
+                }
+                _ => Value::Null,
+            },
+        ),
+        "SELFDESTRUCT" => (
+            "suicide",
+            json!({
+                "address": frame.from,
+                "refundAddress": frame.to.unwrap_or_default(),
+                "balance": value,
+            }),
+            Value::Null,
+        ),
+        call_type => (
+            "call",
+            json!({
+                "callType": call_type.to_lowercase(),
+                "from": frame.from,
+                "to": frame.to.unwrap_or_default(),
+                "gas": gas,
+                "input": frame.input,
+                "value": value,
+            }),
+            match &frame.error {
+                None => json!({ "gasUsed": gas_used, "output": output }),
+                Some(_) => Value::Null,
+            },
+        ),
+    };
+
+    let mut trace = json!({
+        "type": trace_type,
+        "action": action,
+        "result": result,
+        "subtraces": frame.calls.len(),
+        "traceAddress": trace_address,
+    });
+    if let Some(error) = &frame.error {
+        trace["error"] = json!(error);
+    }
+    if let (Some(trace), Some(location)) = (trace.as_object_mut(), location.as_object()) {
+        trace.extend(location.clone());
+    }
+    traces.push(trace);
+
+    for (index, call) in frame.calls.iter().enumerate() {
+        let mut call_address = trace_address.clone();
+        call_address.push(index);
+        flatten(call, call_address, location, traces);
+    }
+}
@@ -0,0 +1,44 @@
+use alloy_primitives::{Address, Bytes};
+use futures::{stream, StreamExt};
+use serde_json::json;
+
+use crate::progress::{log_line, progress};
+use crate::rpc::RpcClient;
+
+// eth_getCode calls in flight at once, a range can destroy many thousands of addresses
+const CONCURRENT_LOOKUPS: usize = 16;
+
+// Verification without a local database: a destroyed address that has code again at the end of
+// the range was recreated. Needs an archive node for blocks older than its state history
+pub async fn recreated_at(
+    client: &RpcClient,
+    addresses: &[Address],
+    block_num: u64,
+) -> anyhow::Result<Vec<Address>> {
+    let mut addresses = addresses.to_vec();
+    addresses.sort();
+    addresses.dedup();
+
+    let block_num_hex = format!("0x{:x}", block_num);
+    let block_num_hex = &block_num_hex;
+    let mut codes = stream::iter(addresses)
+        .map(|address| async move {
+            let code = client
+                .request::<Bytes>("eth_getCode", json!([address, block_num_hex]))
+                .await;
+            (address, code)
+        })
+        .buffer_unordered(CONCURRENT_LOOKUPS);
+
+    let mut recreated = Vec::new();
+    while let Some((address, code)) = codes.next().await {
+        if code?.is_some_and(|code| !code.is_empty()) {
+            log_line(format!("Address {} has been recreated", address));
+            progress().finding(format!("{} has code again at block {}", address, block_num));
+            recreated.push(address);
+        }
+    }
+    // Lookups finish in any order
+    recreated.sort();
+    Ok(recreated)
+}
@@ -0,0 +1,82 @@
+use std::sync::Arc;
+
+use arrow::array::{ArrayRef, Float64Array, StringArray, UInt64Array};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+
+use crate::TraceResponse;
+
+// Addresses and hashes are 0x prefixed hex and value is in wei as a decimal string, as in the
+// gRPC API
+pub fn schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("chain_id", DataType::UInt64, false),
+        Field::new("trace_type", DataType::Utf8, false),
+        Field::new("contract_address", DataType::Utf8, false),
+        Field::new("block_number", DataType::UInt64, false),
+        Field::new("block_timestamp", DataType::UInt64, true),
+        Field::new("transaction_position", DataType::UInt64, false),
+        Field::new("transaction_hash", DataType::Utf8, true),
+        Field::new("sender", DataType::Utf8, true),
+        Field::new("depth", DataType::UInt64, false),
+        Field::new("revert_reason", DataType::Utf8, true),
+        Field::new("deployer", DataType::Utf8, true),
+        Field::new("beneficiary", DataType::Utf8, true),
+        Field::new("value", DataType::Utf8, false),
+        Field::new("value_usd", DataType::Float64, true),
+        Field::new("classification", DataType::Utf8, true),
+    ]))
+}
+
+pub fn record_batch(
+    schema: SchemaRef,
+    records: &[&TraceResponse],
+) -> Result<RecordBatch, ArrowError> {
+    fn strings<T: ToString>(
+        records: &[&TraceResponse],
+        field: impl Fn(&TraceResponse) -> Option<T>,
+    ) -> ArrayRef {
+        Arc::new(
+            records
+                .iter()
+                .map(|record| field(record).map(|value| value.to_string()))
+                .collect::<StringArray>(),
+        )
+    }
+    fn numbers(
+        records: &[&TraceResponse],
+        field: impl Fn(&TraceResponse) -> Option<u64>,
+    ) -> ArrayRef {
+        Arc::new(
+            records
+                .iter()
+                .map(|record| field(record))
+                .collect::<UInt64Array>(),
+        )
+    }
+
+    let columns = vec![
+        numbers(records, |record| Some(record.chain_id)),
+        strings(records, |record| Some(record.trace_type.to_string())),
+        strings(records, |record| Some(record.contract_address)),
+        numbers(records, |record| Some(record.block_number)),
+        numbers(records, |record| record.block_timestamp),
+        numbers(records, |record| Some(record.transaction_position)),
+        strings(records, |record| record.transaction_hash),
+        strings(records, |record| record.sender),
+        numbers(records, |record| Some(record.depth as u64)),
+        strings(records, |record| record.revert_reason.clone()),
+        strings(records, |record| record.deployer),
+        strings(records, |record| record.beneficiary),
+        strings(records, |record| Some(record.value)),
+        Arc::new(
+            records
+                .iter()
+                .map(|record| record.value_usd)
+                .collect::<Float64Array>(),
+        ),
+        strings(records, |record| record.classification.clone()),
+    ];
+    RecordBatch::try_new(schema, columns)
+}
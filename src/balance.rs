@@ -0,0 +1,103 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use alloy_primitives::{Address, I256, U256};
+use reth_rpc_types::trace::parity::{ChangedType, Delta, TraceResultsWithTransactionHash};
+use serde_json::json;
+
+use block_tracer::chain_db::ChainDb;
+
+use crate::labels::Label;
+use crate::rpc::RpcClient;
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BalanceChange {
+    pub block_number: u64,
+    pub address: Address,
+    pub delta: I256,
+    // Beacon chain withdrawals credited in the block, already included in `delta`
+    pub withdrawals: U256,
+    pub label: Option<Label>,
+}
+
+// Net balance delta of every watched account touched in the block, summed over all transactions
+pub async fn balance_changes(
+    client: RpcClient,
+    block_num: u64,
+    watchlist: Arc<Vec<Address>>,
+) -> anyhow::Result<Vec<BalanceChange>> {
+    let block_num_hex = format!("0x{:x}", block_num);
+    let replayed_txs = client
+        .request::<Vec<TraceResultsWithTransactionHash>>(
+            "trace_replayBlockTransactions",
+            json!([block_num_hex, ["stateDiff"]]),
+        )
+        .await?
+        .unwrap_or_default();
+
+    let mut deltas: BTreeMap<Address, I256> = BTreeMap::new();
+    for replayed_tx in replayed_txs {
+        let Some(state_diff) = replayed_tx.full_trace.state_diff else {
+            continue;
+        };
+        for (address, account_diff) in state_diff.iter() {
+            if !watchlist.contains(address) {
+                continue;
+            }
+            let delta = match &account_diff.balance {
+                Delta::Unchanged => continue,
+                Delta::Added(balance) => I256::from_raw(*balance),
+                Delta::Removed(balance) => -I256::from_raw(*balance),
+                Delta::Changed(ChangedType { from, to }) => {
+                    I256::from_raw(*to) - I256::from_raw(*from)
+                }
+            };
+            *deltas.entry(*address).or_insert(I256::ZERO) += delta;
+        }
+    }
+
+    Ok(deltas
+        .into_iter()
+        .filter(|(_, delta)| !delta.is_zero())
+        .map(|(address, delta)| BalanceChange {
+            block_number: block_num,
+            address,
+            delta,
+            withdrawals: U256::ZERO,
+            label: None,
+        })
+        .collect())
+}
+
+pub fn apply_withdrawals(
+    db: &dyn ChainDb,
+    start_block: u64,
+    end_block: u64,
+    watchlist: &[Address],
+    balance_changes: &mut Vec<BalanceChange>,
+) -> anyhow::Result<()> {
+    for withdrawal in db.withdrawals(start_block, end_block)? {
+        if !watchlist.contains(&withdrawal.address) {
+            continue;
+        }
+        let amount = withdrawal.amount_wei;
+        match balance_changes.iter_mut().find(|change| {
+            change.block_number == withdrawal.block_number && change.address == withdrawal.address
+        }) {
+            Some(change) => {
+                change.delta += I256::from_raw(amount);
+                change.withdrawals += amount;
+            }
+            None => balance_changes.push(BalanceChange {
+                block_number: withdrawal.block_number,
+                address: withdrawal.address,
+                delta: I256::from_raw(amount),
+                withdrawals: amount,
+                label: None,
+            }),
+        }
+    }
+
+    balance_changes.sort_by_key(|change| (change.block_number, change.address));
+    Ok(())
+}
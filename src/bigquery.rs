@@ -0,0 +1,81 @@
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use crate::output;
+use crate::TraceResponse;
+
+// Mirrors the serialized fields of `TraceResponse`, keep both in sync
+const TRACE_RECORD_SCHEMA: &[(&str, &str, &str)] = &[
+    ("chain_id", "INTEGER", "REQUIRED"),
+    ("trace_type", "STRING", "REQUIRED"),
+    ("contract_address", "STRING", "REQUIRED"),
+    ("block_number", "INTEGER", "REQUIRED"),
+    ("block_hash", "STRING", "NULLABLE"),
+    ("block_timestamp", "INTEGER", "NULLABLE"),
+    ("transaction_position", "INTEGER", "REQUIRED"),
+    ("transaction_hash", "STRING", "NULLABLE"),
+    ("sender", "STRING", "NULLABLE"),
+    ("transaction_gas_used", "INTEGER", "NULLABLE"),
+    ("transaction_success", "BOOLEAN", "NULLABLE"),
+    ("blob_transaction", "BOOLEAN", "REQUIRED"),
+    ("deposit_transaction", "BOOLEAN", "REQUIRED"),
+    ("trace_address", "INTEGER", "REPEATED"),
+    ("depth", "INTEGER", "REQUIRED"),
+    ("revert_reason", "STRING", "NULLABLE"),
+    ("selector", "STRING", "NULLABLE"),
+    ("function", "STRING", "NULLABLE"),
+    ("contract_name", "STRING", "NULLABLE"),
+    ("verified", "BOOLEAN", "NULLABLE"),
+    ("code_hash", "STRING", "NULLABLE"),
+    ("source_url", "STRING", "NULLABLE"),
+    ("deployer", "STRING", "NULLABLE"),
+    ("creator_nonce", "INTEGER", "NULLABLE"),
+    ("creator_kind", "STRING", "NULLABLE"),
+    ("deployer_ens", "STRING", "NULLABLE"),
+    ("beneficiary", "STRING", "NULLABLE"),
+    ("beneficiary_ens", "STRING", "NULLABLE"),
+    ("caller", "STRING", "NULLABLE"),
+    ("contract_label", "RECORD", "NULLABLE"),
+    ("deployer_label", "RECORD", "NULLABLE"),
+    ("beneficiary_label", "RECORD", "NULLABLE"),
+    ("sanctioned", "BOOLEAN", "REQUIRED"),
+    // Hex encoded wei, wider than BIGNUMERIC allows
+    ("value", "STRING", "REQUIRED"),
+    ("value_usd", "FLOAT", "NULLABLE"),
+    ("classification", "STRING", "NULLABLE"),
+    ("script_fields", "JSON", "NULLABLE"),
+];
+
+fn schema() -> Value {
+    Value::Array(
+        TRACE_RECORD_SCHEMA
+            .iter()
+            .map(|(name, field_type, mode)| {
+                if *field_type == "RECORD" {
+                    json!({
+                        "name": name,
+                        "type": field_type,
+                        "mode": mode,
+                        "fields": [
+                            { "name": "label", "type": "STRING", "mode": "REQUIRED" },
+                            { "name": "category", "type": "STRING", "mode": "NULLABLE" }
+                        ]
+                    })
+                } else {
+                    json!({ "name": name, "type": field_type, "mode": mode })
+                }
+            })
+            .collect(),
+    )
+}
+
+// Newline delimited JSON plus its schema, ready for `bq load --source_format=NEWLINE_DELIMITED_JSON`
+pub fn write_trace_records(
+    output_dir: &Path,
+    trace_records: &[&TraceResponse],
+) -> anyhow::Result<()> {
+    output::write_ndjson(output_dir, "trace_records.ndjson", trace_records)?;
+    output::write_json(output_dir, "trace_records.schema.json", &schema())?;
+    Ok(())
+}
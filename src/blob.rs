@@ -0,0 +1,58 @@
+use alloy_primitives::TxHash;
+use block_tracer::chain_db::ChainDb;
+
+// EIP-2718 type of blob carrying transactions
+const EIP4844_TX_TYPE: u8 = 3;
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BlobStats {
+    pub block_number: u64,
+    pub blob_gas_used: u64,
+    pub excess_blob_gas: u64,
+    pub blob_transactions: usize,
+    pub blob_count: usize,
+}
+
+pub fn is_blob_transaction(db: &dyn ChainDb, transaction_hash: TxHash) -> anyhow::Result<bool> {
+    Ok(db
+        .transaction_by_hash(transaction_hash)?
+        .map(|transaction| transaction.tx_type == EIP4844_TX_TYPE)
+        .unwrap_or_default())
+}
+
+// Blocks before Cancun have no blob gas fields in the header and are skipped
+pub fn blob_stats(
+    db: &dyn ChainDb,
+    start_block: u64,
+    end_block: u64,
+) -> anyhow::Result<Vec<BlobStats>> {
+    let mut stats = Vec::new();
+
+    for block_number in start_block..=end_block {
+        let Some(header) = db.header(block_number)? else {
+            continue;
+        };
+        let Some(blob_gas_used) = header.blob_gas_used else {
+            continue;
+        };
+
+        let blob_transactions = db
+            .block_transactions(block_number)?
+            .into_iter()
+            .filter(|transaction| transaction.tx_type == EIP4844_TX_TYPE)
+            .collect::<Vec<_>>();
+
+        stats.push(BlobStats {
+            block_number,
+            blob_gas_used,
+            excess_blob_gas: header.excess_blob_gas.unwrap_or_default(),
+            blob_transactions: blob_transactions.len(),
+            blob_count: blob_transactions
+                .iter()
+                .map(|transaction| transaction.blob_count)
+                .sum(),
+        });
+    }
+
+    Ok(stats)
+}
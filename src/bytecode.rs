@@ -0,0 +1,38 @@
+use std::path::Path;
+
+use alloy_primitives::{Address, B256};
+use block_tracer::chain_db::ChainDb;
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ExportedBytecode {
+    pub address: Address,
+    pub code_hash: B256,
+}
+
+// Writes the current runtime code of every address to `<code_dir>/<codehash>.bin`, addresses
+// without code (destroyed, or never deployed) are skipped
+pub fn export_bytecodes(
+    db: &dyn ChainDb,
+    addresses: &[Address],
+    code_dir: &Path,
+) -> anyhow::Result<Vec<ExportedBytecode>> {
+    std::fs::create_dir_all(code_dir)?;
+    let mut exported = Vec::new();
+    for address in addresses {
+        let Some((code_hash, bytecode)) = db.bytecode(*address)? else {
+            continue;
+        };
+
+        // Identical code is stored once, however many addresses share it
+        let path = code_dir.join(format!("{:x}.bin", code_hash));
+        if !path.exists() {
+            std::fs::write(&path, bytecode)?;
+        }
+        crate::output::record_written(path);
+        exported.push(ExportedBytecode {
+            address: *address,
+            code_hash,
+        });
+    }
+    Ok(exported)
+}
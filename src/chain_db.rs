@@ -0,0 +1,126 @@
+use alloy_primitives::{Address, Bytes, TxHash, B256, U256};
+
+/// Header fields read from the local datadir.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockHeader {
+    pub hash: B256,
+    /// Unix seconds
+    pub timestamp: u64,
+    /// Only set from Cancun on
+    pub blob_gas_used: Option<u64>,
+    pub excess_blob_gas: Option<u64>,
+}
+
+/// A transaction as stored in the local datadir.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalTransaction {
+    pub hash: TxHash,
+    /// Global, zero based number of the transaction in the chain
+    pub tx_number: u64,
+    pub block_number: u64,
+    /// Position inside its block
+    pub index: u64,
+    /// EIP-2718 type
+    pub tx_type: u8,
+    /// Blobs carried by an EIP-4844 transaction
+    pub blob_count: usize,
+    /// Nonce of the sender, zero for transactions without one (e.g. OP-stack deposits)
+    pub nonce: u64,
+}
+
+/// Outcome of a transaction as stored in the local datadir.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalReceipt {
+    /// Position of the transaction inside its block
+    pub index: u64,
+    pub success: bool,
+    /// Gas used by the transaction alone, not the block's cumulative gas
+    pub gas_used: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ommer {
+    pub block_number: u64,
+    pub number: u64,
+    pub hash: B256,
+    pub beneficiary: Address,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Withdrawal {
+    pub block_number: u64,
+    pub address: Address,
+    pub amount_wei: U256,
+}
+
+/// History the node can prune, and that some lookups depend on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistorySegment {
+    Senders,
+    TransactionLookup,
+    StorageHistory,
+    AccountHistory,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct StorageSlot {
+    pub slot: B256,
+    pub value: U256,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct StorageChange {
+    pub block_number: u64,
+    pub slot: B256,
+    /// Value of the slot before the block changed it
+    pub previous_value: U256,
+}
+
+/// Everything block-tracer reads from a local node datadir. The scan pipeline only talks to this
+/// trait, so supporting another reth release line (or another client) means implementing it
+/// behind a feature, without touching the pipeline.
+pub trait ChainDb: Send + Sync {
+    /// Hash of the canonical block at the height.
+    fn canonical_hash(&self, block_number: u64) -> anyhow::Result<Option<B256>>;
+
+    fn header(&self, block_number: u64) -> anyhow::Result<Option<BlockHeader>>;
+
+    fn transaction_by_hash(&self, hash: TxHash) -> anyhow::Result<Option<LocalTransaction>>;
+
+    fn transaction_at(
+        &self,
+        block_number: u64,
+        index: u64,
+    ) -> anyhow::Result<Option<LocalTransaction>>;
+
+    fn block_transactions(&self, block_number: u64) -> anyhow::Result<Vec<LocalTransaction>>;
+
+    /// Senders of the transactions, recovered from their signatures when not stored.
+    fn senders(&self, tx_numbers: &[u64]) -> anyhow::Result<Vec<Option<Address>>>;
+
+    /// Receipts of the block's transactions in order, empty when they are pruned.
+    fn receipts(&self, block_number: u64) -> anyhow::Result<Vec<LocalReceipt>>;
+
+    fn ommers(&self, start_block: u64, end_block: u64) -> anyhow::Result<Vec<Ommer>>;
+
+    fn withdrawals(&self, start_block: u64, end_block: u64) -> anyhow::Result<Vec<Withdrawal>>;
+
+    /// The addresses that exist in the latest state.
+    fn existing_accounts(&self, addresses: &[Address]) -> anyhow::Result<Vec<Address>>;
+
+    /// Code hash and runtime code of the address in the latest state.
+    fn bytecode(&self, address: Address) -> anyhow::Result<Option<(B256, Bytes)>>;
+
+    /// Segments pruned by the node, with the last pruned block.
+    fn pruned_segments(&self) -> anyhow::Result<Vec<(HistorySegment, u64)>>;
+
+    /// Non-zero slots of the contract in the latest state, zeroed slots are not stored.
+    fn plain_storage(&self, address: Address) -> anyhow::Result<Vec<StorageSlot>>;
+
+    /// Slot changes of the contract from `from_block` on, oldest first.
+    fn storage_changes(
+        &self,
+        address: Address,
+        from_block: u64,
+    ) -> anyhow::Result<Vec<StorageChange>>;
+}
@@ -0,0 +1,77 @@
+use std::path::PathBuf;
+
+use futures::future::join_all;
+
+use crate::progress::log_line;
+use crate::{Cmd, Command};
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct ChainConfig {
+    pub chain_id: u64,
+    pub rpc_url: String,
+    // Without a datadir the chain is verified over RPC
+    #[serde(default)]
+    pub db_path: Option<PathBuf>,
+    #[serde(default)]
+    pub static_files_path: Option<PathBuf>,
+    #[serde(default)]
+    pub erigon_chaindata: Option<PathBuf>,
+    // Deposit transactions of OP-stack chains
+    #[serde(default)]
+    pub op_stack: bool,
+    // Arbitrum Nitro, traced over the debug API
+    #[serde(default)]
+    pub arbitrum: bool,
+}
+
+pub fn load(path: &std::path::Path) -> anyhow::Result<Vec<ChainConfig>> {
+    Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+}
+
+// Scans the range or follows the head on every configured chain at the same time, each into
+// `<output_dir>/<chain_id>`. Followers keep their checkpoint there and share the `--output` sink
+pub async fn run(cmd: Cmd) -> anyhow::Result<()> {
+    let Some(chains_path) = cmd.chains.as_deref() else {
+        unreachable!("only called with --chains")
+    };
+    let chains = load(chains_path)?;
+    let verb = match cmd.command {
+        Some(Command::Follow(_)) => "Following",
+        _ => "Scanning",
+    };
+    log_line(format!("{} {} chains", verb, chains.len()));
+
+    let scans = chains.iter().map(|chain| {
+        let mut chain_cmd = cmd.clone();
+        chain_cmd.chains = None;
+        chain_cmd.chain_id = chain.chain_id;
+        chain_cmd.rpc_url = Some(chain.rpc_url.clone());
+        chain_cmd.db_path = chain.db_path.clone();
+        chain_cmd.static_files_path = chain.static_files_path.clone();
+        chain_cmd.erigon_chaindata = chain.erigon_chaindata.clone();
+        chain_cmd.op_stack = chain.op_stack;
+        chain_cmd.arbitrum = chain.arbitrum;
+        chain_cmd.output_dir = cmd.output_dir.join(chain.chain_id.to_string());
+        // Record keys carry no chain id, and an environment is opened once per process
+        chain_cmd.results_db = cmd
+            .results_db
+            .as_ref()
+            .map(|results_db| results_db.join(chain.chain_id.to_string()));
+        // The exporter is already listening from this process
+        chain_cmd.metrics_addr = None;
+        Box::pin(crate::amain(chain_cmd))
+    });
+
+    // One failing chain does not stop the others
+    let mut failed = 0;
+    for (chain, result) in chains.iter().zip(join_all(scans).await) {
+        if let Err(err) = result {
+            log_line(format!("{} chain {} failed: {}", verb, chain.chain_id, err));
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        anyhow::bail!("{} of {} chains failed", failed, chains.len());
+    }
+    Ok(())
+}
@@ -0,0 +1,61 @@
+use std::path::{Path, PathBuf};
+
+use clap::CommandFactory;
+use clap_complete::Shell;
+
+use crate::progress::log_line;
+use crate::Cmd;
+
+const BIN_NAME: &str = "block-tracer";
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct CompletionsArgs {
+    /// Shell to generate the completion script for
+    pub shell: Shell,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct ManArgs {
+    /// Directory the man pages are written to, one page per subcommand
+    #[arg(long, default_value = "man")]
+    pub out_dir: PathBuf,
+}
+
+// Written to stdout, e.g. `block-tracer completions zsh > ~/.zfunc/_block-tracer`
+pub fn completions(args: CompletionsArgs) {
+    let mut command = Cmd::command();
+    clap_complete::generate(args.shell, &mut command, BIN_NAME, &mut std::io::stdout());
+}
+
+// `block-tracer.1` for the global options and `block-tracer-<subcommand>.1` for every subcommand,
+// nested ones included
+pub fn man(args: ManArgs) -> anyhow::Result<()> {
+    std::fs::create_dir_all(&args.out_dir)?;
+    let command = Cmd::command().name(BIN_NAME);
+    let written = write_pages(&args.out_dir, command, BIN_NAME)?;
+    log_line(format!(
+        "Wrote {} man pages to {}, view one with `man {}/{}.1`",
+        written,
+        args.out_dir.display(),
+        args.out_dir.display(),
+        BIN_NAME
+    ));
+    Ok(())
+}
+
+fn write_pages(out_dir: &Path, command: clap::Command, name: &str) -> anyhow::Result<usize> {
+    let command = command.name(name.to_string());
+    let mut page = Vec::new();
+    clap_mangen::Man::new(command.clone()).render(&mut page)?;
+    std::fs::write(out_dir.join(format!("{}.1", name)), page)?;
+
+    let mut written = 1;
+    for subcommand in command
+        .get_subcommands()
+        .filter(|subcommand| !subcommand.is_hide_set())
+    {
+        let subcommand_name = format!("{}-{}", name, subcommand.get_name());
+        written += write_pages(out_dir, subcommand.clone(), &subcommand_name)?;
+    }
+    Ok(written)
+}
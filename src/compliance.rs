@@ -0,0 +1,77 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use alloy_primitives::{Address, TxHash};
+
+use crate::{TraceResponse, TraceType};
+
+#[derive(Debug, Default)]
+pub struct SanctionsList(HashSet<Address>);
+
+impl SanctionsList {
+    // Any 0x-prefixed 20 byte hex string is taken as an address, so plain lists as well as
+    // OFAC SDN exports (`Digital Currency Address - ETH 0x...`) can be loaded as is
+    pub fn load(paths: &[PathBuf]) -> anyhow::Result<Self> {
+        let mut addresses = HashSet::new();
+        for path in paths {
+            let content = std::fs::read_to_string(path)?;
+            for (position, _) in content.match_indices("0x") {
+                let Some(candidate) = content.get(position..position + 42) else {
+                    continue;
+                };
+                let terminated = content[position + 42..]
+                    .chars()
+                    .next()
+                    .map_or(true, |next| !next.is_ascii_hexdigit());
+                if terminated {
+                    if let Ok(address) = Address::from_str(candidate) {
+                        addresses.insert(address);
+                    }
+                }
+            }
+        }
+        Ok(Self(addresses))
+    }
+
+    pub fn contains(&self, address: &Address) -> bool {
+        self.0.contains(address)
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ComplianceFinding {
+    pub block_number: u64,
+    pub transaction_hash: Option<TxHash>,
+    pub trace_type: TraceType,
+    // Which party of the record is listed: contract, deployer or beneficiary
+    pub role: &'static str,
+    pub address: Address,
+}
+
+pub fn findings<'a>(
+    sanctions_list: &SanctionsList,
+    trace_responses: impl Iterator<Item = &'a TraceResponse>,
+) -> Vec<ComplianceFinding> {
+    let mut findings = Vec::new();
+    for trace_response in trace_responses {
+        let parties = [
+            ("contract", Some(trace_response.contract_address)),
+            ("deployer", trace_response.deployer),
+            ("beneficiary", trace_response.beneficiary),
+        ];
+        for (role, address) in parties {
+            let Some(address) = address.filter(|address| sanctions_list.contains(address)) else {
+                continue;
+            };
+            findings.push(ComplianceFinding {
+                block_number: trace_response.block_number,
+                transaction_hash: trace_response.transaction_hash,
+                trace_type: trace_response.trace_type.clone(),
+                role,
+                address,
+            });
+        }
+    }
+    findings
+}
@@ -0,0 +1,92 @@
+use std::collections::{BTreeSet, HashMap};
+
+use alloy_primitives::{hex, Address, Bytes};
+use serde_json::json;
+
+use crate::progress::log_line;
+use crate::rpc::RpcClient;
+use crate::{TraceResponse, TraceType};
+
+// Code of an EOA that delegated to a contract with an EIP-7702 authorization
+pub const DELEGATION_PREFIX: [u8; 3] = hex!("ef0100");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CreatorKind {
+    Eoa,
+    Contract,
+}
+
+// Sets `creator_kind` of every create. A create below the top of its transaction was made by a
+// frame running code, so its deployer is a contract, even one still in its constructor that has
+// no code yet. A top-level create is made by the sender of its transaction, an EOA (EIP-7702
+// delegated ones included). Only deposit transactions, sent on behalf of L1 accounts that may be
+// contracts, are looked up with `eth_getCode` at the block. A lookup that fails leaves the kind
+// unset, nodes without historical state never fail the scan here
+pub async fn classify_creators<'a>(
+    client: &RpcClient,
+    trace_responses: impl Iterator<Item = &'a mut TraceResponse>,
+) {
+    let mut creates = trace_responses
+        .filter(|trace_response| trace_response.trace_type == TraceType::Create)
+        .collect::<Vec<_>>();
+
+    let lookups = creates
+        .iter()
+        .filter(|trace_response| {
+            trace_response.trace_address.is_empty() && trace_response.deposit_transaction
+        })
+        .filter_map(|trace_response| {
+            trace_response
+                .deployer
+                .map(|deployer| (deployer, trace_response.block_number))
+        })
+        .collect::<BTreeSet<_>>();
+    let mut kinds = HashMap::new();
+    if !lookups.is_empty() {
+        match client
+            .request_batch::<Bytes>(
+                "eth_getCode",
+                lookups
+                    .iter()
+                    .map(|(deployer, block_number)| {
+                        json!([deployer, format!("0x{:x}", block_number)])
+                    })
+                    .collect(),
+            )
+            .await
+        {
+            Ok(codes) => {
+                for (lookup, code) in lookups.into_iter().zip(codes) {
+                    let Ok(Some(code)) = code else {
+                        continue;
+                    };
+                    let kind = if code.is_empty() || code.starts_with(&DELEGATION_PREFIX) {
+                        CreatorKind::Eoa
+                    } else {
+                        CreatorKind::Contract
+                    };
+                    kinds.insert(lookup, kind);
+                }
+            }
+            Err(err) => log_line(format!(
+                "Looking up the code of {} deposit senders failed, their creates get no creator kind: {}",
+                lookups.len(),
+                err
+            )),
+        }
+    }
+
+    for trace_response in creates.iter_mut() {
+        trace_response.creator_kind = if !trace_response.trace_address.is_empty() {
+            Some(CreatorKind::Contract)
+        } else if trace_response.deposit_transaction {
+            trace_response
+                .deployer
+                .and_then(|deployer| kinds.get(&(deployer, trace_response.block_number)))
+                .copied()
+        } else {
+            Some(CreatorKind::Eoa)
+        };
+    }
+}
@@ -0,0 +1,176 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use alloy_primitives::U64;
+use serde_json::json;
+
+use crate::distributed::chunk_cmd;
+use crate::failures;
+use crate::follow::head_block;
+use crate::progress::log_line;
+use crate::rpc::RpcClient;
+use crate::sink::{NdjsonSink, Sink};
+use crate::{Cmd, TraceResponse};
+
+// Used when the node does not know the `safe` block tag, about two epochs
+const FALLBACK_CONFIRMATIONS: u64 = 64;
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct DaemonArgs {
+    /// Time between scans, e.g. 30s, 5m, 1h
+    #[arg(long, default_value = "5m", value_parser = parse_interval)]
+    pub interval: Duration,
+    /// File the last scanned block is kept in
+    #[arg(long, default_value = "checkpoint.json")]
+    pub checkpoint: PathBuf,
+    /// First block to scan when there is no checkpoint yet, defaults to the current safe head
+    #[arg(long)]
+    pub from_block: Option<u64>,
+}
+
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+struct Checkpoint {
+    // None until the first scan is written
+    last_block: Option<u64>,
+    // Size of the NDJSON sink with the records up to `last_block`. Lines past it were appended by
+    // a scan that stopped before moving the checkpoint
+    #[serde(default)]
+    sink_bytes: Option<u64>,
+}
+
+pub fn parse_interval(s: &str) -> Result<Duration, String> {
+    let (value, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+    let value = value
+        .parse::<u64>()
+        .map_err(|_| format!("Interval {} does not start with a number", s))?;
+    let seconds = match unit {
+        "" | "s" => value,
+        "m" => value * 60,
+        "h" => value * 60 * 60,
+        "d" => value * 24 * 60 * 60,
+        _ => return Err(format!("Unknown interval unit {}, use s, m, h or d", unit)),
+    };
+    Ok(Duration::from_secs(seconds))
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct SafeBlock {
+    number: U64,
+}
+
+// Latest block the consensus layer considers safe from reorgs
+pub async fn safe_head(client: &RpcClient) -> anyhow::Result<u64> {
+    match client
+        .request::<SafeBlock>("eth_getBlockByNumber", json!(["safe", false]))
+        .await
+    {
+        Ok(Some(block)) => Ok(block.number.to()),
+        _ => Ok(head_block(client)
+            .await?
+            .saturating_sub(FALLBACK_CONFIRMATIONS)),
+    }
+}
+
+fn read_checkpoint(path: &Path) -> anyhow::Result<Checkpoint> {
+    if !path.exists() {
+        return Ok(Checkpoint::default());
+    }
+    Ok(serde_json::from_slice::<Checkpoint>(&std::fs::read(path)?)?)
+}
+
+// Written next to the checkpoint and renamed over it, so an interrupted write never loses it
+fn write_checkpoint(path: &Path, checkpoint: &Checkpoint) -> anyhow::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    std::fs::write(&tmp_path, serde_json::to_vec(checkpoint)?)?;
+    std::fs::rename(tmp_path, path)?;
+    Ok(())
+}
+
+// Scans from the checkpoint to the safe head on every wake up, runs until interrupted
+pub async fn run(args: DaemonArgs, cmd: Cmd) -> anyhow::Result<()> {
+    let client = crate::rpc_client(
+        cmd.rpc_url.as_deref(),
+        &cmd.http,
+        cmd.record.clone(),
+        cmd.replay.clone(),
+        cmd.trace_rpc,
+        cmd.trace_rpc_dir.clone(),
+    )?;
+    std::fs::create_dir_all(&cmd.output_dir)?;
+    let mut sink = NdjsonSink::open(&cmd.output_dir.join("trace_records.ndjson"))?;
+    let mut interval = tokio::time::interval(args.interval);
+
+    loop {
+        interval.tick().await;
+        let safe_head = safe_head(&client).await?;
+        let checkpoint = read_checkpoint(&args.checkpoint)?;
+        let start_block = match checkpoint.last_block {
+            Some(last_block) => last_block + 1,
+            None => args.from_block.unwrap_or(safe_head),
+        };
+        if start_block > safe_head {
+            log_line(format!("Nothing to scan, safe head is {}", safe_head));
+            continue;
+        }
+
+        log_line(format!("Scanning blocks {}-{}", start_block, safe_head));
+        let scan_cmd = chunk_cmd(&cmd, start_block, safe_head, &cmd.output_dir);
+        let chunk_dir = scan_cmd.output_dir.clone();
+        // A failed scan is retried from the same checkpoint on the next wake up
+        if let Err(err) = Box::pin(crate::amain(scan_cmd)).await {
+            log_line(format!(
+                "Scanning blocks {}-{} failed: {}",
+                start_block, safe_head, err
+            ));
+            continue;
+        }
+
+        // A best-effort scan succeeds with failed blocks, the checkpoint stops before the first
+        // of them so it is scanned again on the next wake up
+        let last_block = match failures::read_failed_blocks(&chunk_dir)?.first() {
+            Some(failed_block) if failed_block.block_number == start_block => {
+                log_line(format!(
+                    "Block {} failed, the checkpoint stays",
+                    start_block
+                ));
+                continue;
+            }
+            Some(failed_block) => {
+                log_line(format!(
+                    "Block {} failed, the checkpoint stops before it",
+                    failed_block.block_number
+                ));
+                failed_block.block_number - 1
+            }
+            None => safe_head,
+        };
+        let trace_records = serde_json::from_slice::<Vec<TraceResponse>>(&std::fs::read(
+            chunk_dir.join("trace_records.json"),
+        )?)?
+        .into_iter()
+        .filter(|trace_response| trace_response.block_number <= last_block)
+        .collect::<Vec<_>>();
+
+        match checkpoint.sink_bytes {
+            // Records of an earlier scan of these blocks that never reached the checkpoint
+            Some(sink_bytes) if sink.written_bytes()? > sink_bytes => sink.truncate(sink_bytes)?,
+            Some(_) => {}
+            // Recorded before the first append, so an interrupted one is undone as well
+            None => write_checkpoint(
+                &args.checkpoint,
+                &Checkpoint {
+                    last_block: checkpoint.last_block,
+                    sink_bytes: Some(sink.written_bytes()?),
+                },
+            )?,
+        }
+        sink.write_records(&trace_records)?;
+        write_checkpoint(
+            &args.checkpoint,
+            &Checkpoint {
+                last_block: Some(last_block),
+                sink_bytes: Some(sink.written_bytes()?),
+            },
+        )?;
+    }
+}
@@ -0,0 +1,146 @@
+use crate::TraceResponse;
+
+// Delta Lake table trace records are committed to, partitioned by chain and UTC block date. Each
+// call is one Delta commit, so readers see a scan or a followed block entirely or not at all
+pub struct DeltaSink {
+    #[cfg(feature = "delta")]
+    table: deltalake::DeltaTable,
+}
+
+impl DeltaSink {
+    // Opens the table at a local path or an s3:// URI, it is created by the first commit
+    pub async fn open(table_uri: &str) -> anyhow::Result<Self> {
+        #[cfg(feature = "delta")]
+        return Ok(DeltaSink {
+            table: table::open(table_uri).await?,
+        });
+        #[cfg(not(feature = "delta"))]
+        {
+            let _ = table_uri;
+            anyhow::bail!("--delta-table requires building with `--features delta`");
+        }
+    }
+
+    pub async fn append(&mut self, trace_responses: &[&TraceResponse]) -> anyhow::Result<()> {
+        #[cfg(feature = "delta")]
+        if !trace_responses.is_empty() {
+            self.table = table::write(self.table.clone(), trace_responses, None).await?;
+        }
+        #[cfg(not(feature = "delta"))]
+        let _ = trace_responses;
+        Ok(())
+    }
+
+    // Replaces the records of the block in one commit, so writing a block again after a restart
+    // leaves no duplicates
+    pub async fn write_block(
+        &mut self,
+        chain_id: u64,
+        block_number: u64,
+        trace_responses: &[&TraceResponse],
+    ) -> anyhow::Result<()> {
+        #[cfg(feature = "delta")]
+        if !trace_responses.is_empty() {
+            self.table = table::write(
+                self.table.clone(),
+                trace_responses,
+                Some(block_predicate(chain_id, block_number)),
+            )
+            .await?;
+        }
+        #[cfg(not(feature = "delta"))]
+        let _ = (chain_id, block_number, trace_responses);
+        Ok(())
+    }
+
+    // Deletes the records of a block orphaned by a reorg
+    pub async fn retract_block(&mut self, chain_id: u64, block_number: u64) -> anyhow::Result<()> {
+        #[cfg(feature = "delta")]
+        if self.table.version() >= 0 {
+            let (table, _) = deltalake::DeltaOps(self.table.clone())
+                .delete()
+                .with_predicate(block_predicate(chain_id, block_number))
+                .await?;
+            self.table = table;
+        }
+        #[cfg(not(feature = "delta"))]
+        let _ = (chain_id, block_number);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "delta")]
+fn block_predicate(chain_id: u64, block_number: u64) -> String {
+    format!(
+        "chain_id = {} AND block_number = {}",
+        chain_id, block_number
+    )
+}
+
+#[cfg(feature = "delta")]
+mod table {
+    use std::sync::Arc;
+
+    use arrow::array::{ArrayRef, StringArray};
+    use arrow::datatypes::{DataType, Field, Schema};
+    use arrow::record_batch::RecordBatch;
+    use deltalake::protocol::SaveMode;
+    use deltalake::{DeltaOps, DeltaTable};
+
+    use crate::arrow_records::{record_batch, schema};
+    use crate::report::format_date;
+    use crate::TraceResponse;
+
+    const PARTITION_COLUMNS: [&str; 2] = ["chain_id", "block_date"];
+
+    pub async fn open(table_uri: &str) -> anyhow::Result<DeltaTable> {
+        if table_uri.starts_with("s3://") || table_uri.starts_with("s3a://") {
+            deltalake::aws::register_handlers(None);
+        }
+        Ok(DeltaOps::try_from_uri(table_uri).await?.0)
+    }
+
+    // Appends the records, or with a predicate replaces the rows matching it
+    pub async fn write(
+        table: DeltaTable,
+        trace_responses: &[&TraceResponse],
+        replace_where: Option<String>,
+    ) -> anyhow::Result<DeltaTable> {
+        let batch = partitioned_batch(trace_responses)?;
+        let write = DeltaOps(table)
+            .write(vec![batch])
+            .with_partition_columns(PARTITION_COLUMNS);
+        let table = match replace_where {
+            Some(predicate) => {
+                write
+                    .with_save_mode(SaveMode::Overwrite)
+                    .with_replace_where(predicate)
+                    .await?
+            }
+            None => write.with_save_mode(SaveMode::Append).await?,
+        };
+        Ok(table)
+    }
+
+    // Record batch of the Flight schema plus the `block_date` partition column, null for records
+    // without a block timestamp
+    fn partitioned_batch(trace_responses: &[&TraceResponse]) -> anyhow::Result<RecordBatch> {
+        let records = record_batch(schema(), trace_responses)?;
+        let mut fields = records.schema().fields().to_vec();
+        fields.push(Arc::new(Field::new("block_date", DataType::Utf8, true)));
+        let mut columns = records.columns().to_vec();
+        let block_dates = trace_responses
+            .iter()
+            .map(|trace_response| {
+                trace_response
+                    .block_timestamp
+                    .map(|timestamp| format_date((timestamp / 86_400) as i64))
+            })
+            .collect::<StringArray>();
+        columns.push(Arc::new(block_dates) as ArrayRef);
+        Ok(RecordBatch::try_new(
+            Arc::new(Schema::new(fields)),
+            columns,
+        )?)
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use alloy_primitives::Address;
+
+use crate::output;
+use crate::progress::log_line;
+use crate::report::RunOutput;
+use crate::{TraceResponse, TraceType};
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct DiffArgs {
+    /// Output directory of the first run
+    pub run_a: PathBuf,
+    /// Output directory of the second run
+    pub run_b: PathBuf,
+}
+
+// Records of one run are matched to the other's by block, transaction, address and type
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct RecordKey {
+    block_number: u64,
+    transaction_position: u64,
+    contract_address: Address,
+    trace_type: TraceType,
+}
+
+impl RecordKey {
+    fn of(trace_record: &TraceResponse) -> Self {
+        Self {
+            block_number: trace_record.block_number,
+            transaction_position: trace_record.transaction_position,
+            contract_address: trace_record.contract_address,
+            trace_type: trace_record.trace_type.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RunDiff {
+    pub run_a: PathBuf,
+    pub run_b: PathBuf,
+    pub common: usize,
+    pub only_in_a: Vec<DiffRecord>,
+    pub only_in_b: Vec<DiffRecord>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DiffRecord {
+    pub block_number: u64,
+    pub transaction_position: u64,
+    pub contract_address: Address,
+    pub trace_type: TraceType,
+}
+
+// Writes diff.json and fails when the runs differ, so it can gate a backend change or a node
+// upgrade in CI
+pub fn run(args: DiffArgs, output_dir: &Path) -> anyhow::Result<()> {
+    let run_a = RunOutput::load(&args.run_a)?;
+    let run_b = RunOutput::load(&args.run_b)?;
+    let diff = diff(&args, &run_a, &run_b);
+
+    std::fs::create_dir_all(output_dir)?;
+    output::write_json(output_dir, "diff.json", &diff)?;
+    log_line(format!(
+        "{} records in both runs, {} only in {}, {} only in {}",
+        diff.common,
+        diff.only_in_a.len(),
+        args.run_a.display(),
+        diff.only_in_b.len(),
+        args.run_b.display()
+    ));
+    if !diff.only_in_a.is_empty() || !diff.only_in_b.is_empty() {
+        anyhow::bail!("The runs differ, see diff.json");
+    }
+    Ok(())
+}
+
+// Reverted records are left out of both, they depend on --include-reverted rather than on the
+// backend. A key that appears more often in one run counts as that many extra records
+fn diff(args: &DiffArgs, run_a: &RunOutput, run_b: &RunOutput) -> RunDiff {
+    let mut keys: BTreeMap<RecordKey, (Vec<&TraceResponse>, usize)> = BTreeMap::new();
+    for trace_record in run_a.effective_records() {
+        keys.entry(RecordKey::of(trace_record))
+            .or_default()
+            .0
+            .push(trace_record);
+    }
+    let mut only_in_b = Vec::new();
+    for trace_record in run_b.effective_records() {
+        let entry = keys.entry(RecordKey::of(trace_record)).or_default();
+        if entry.1 < entry.0.len() {
+            entry.1 += 1;
+        } else {
+            only_in_b.push(DiffRecord::of(trace_record));
+        }
+    }
+
+    let mut common = 0;
+    let mut only_in_a = Vec::new();
+    for (records_a, matched) in keys.values() {
+        common += matched;
+        only_in_a.extend(
+            records_a[*matched..]
+                .iter()
+                .map(|record| DiffRecord::of(record)),
+        );
+    }
+    RunDiff {
+        run_a: args.run_a.clone(),
+        run_b: args.run_b.clone(),
+        common,
+        only_in_a,
+        only_in_b,
+    }
+}
+
+impl DiffRecord {
+    fn of(trace_record: &TraceResponse) -> Self {
+        Self {
+            block_number: trace_record.block_number,
+            transaction_position: trace_record.transaction_position,
+            contract_address: trace_record.contract_address,
+            trace_type: trace_record.trace_type.clone(),
+        }
+    }
+}
@@ -0,0 +1,489 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use tokio::sync::Notify;
+
+use crate::failures::{self, FailedBlock};
+use crate::progress::log_line;
+use crate::{output, Cmd};
+
+// How long a drained coordinator keeps answering, so idle workers learn there is no work left
+// before it goes away. Longer than the default --idle-interval of workers
+const DRAIN_GRACE: Duration = Duration::from_secs(120);
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct CoordinatorArgs {
+    /// Blocks per leased chunk
+    #[arg(long, default_value_t = 1000)]
+    pub chunk_size: u64,
+    /// Seconds after which an unfinished lease is handed to another worker
+    #[arg(long, default_value_t = 3600)]
+    pub lease_timeout: u64,
+    /// Leases of a chunk, failed or expired, before its blocks are given up on and written to
+    /// failed_blocks.json
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_attempts: u32,
+    /// Address workers reach the coordinator on
+    #[arg(long, default_value = "0.0.0.0:8090")]
+    pub listen: SocketAddr,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct WorkerArgs {
+    /// Base URL of the coordinator, e.g. http://10.0.0.1:8090
+    #[arg(long)]
+    pub coordinator: String,
+    /// Every chunk is written to its own `<start>-<end>` directory below this one
+    #[arg(long, default_value = "chunks")]
+    pub chunks_dir: PathBuf,
+    /// Seconds to wait when all remaining chunks are leased by other workers
+    #[arg(long, default_value_t = 30)]
+    pub idle_interval: u64,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Lease {
+    pub id: u64,
+    pub start_block: u64,
+    pub end_block: u64,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct LeaseResponse {
+    pub lease: Option<Lease>,
+    // Every chunk is completed, workers can exit
+    pub done: bool,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct QueueStatus {
+    pub pending: usize,
+    pub leased: usize,
+    pub completed: usize,
+    pub reassigned: u64,
+    // Chunks given up on after --max-attempts leases
+    pub failed: usize,
+}
+
+#[derive(Debug)]
+struct ActiveLease {
+    range: (u64, u64),
+    expires: Instant,
+}
+
+#[derive(Debug)]
+struct WorkQueue {
+    pending: VecDeque<(u64, u64)>,
+    leased: HashMap<u64, ActiveLease>,
+    completed: usize,
+    reassigned: u64,
+    next_id: u64,
+    lease_timeout: Duration,
+    // Leases handed out per chunk
+    attempts: HashMap<(u64, u64), u32>,
+    max_attempts: u32,
+    failed: Vec<(u64, u64)>,
+    // Notified once every chunk is completed or failed
+    drained: Arc<Notify>,
+}
+
+impl WorkQueue {
+    fn new(
+        start_block: u64,
+        end_block: u64,
+        chunk_size: u64,
+        lease_timeout: Duration,
+        max_attempts: u32,
+    ) -> Self {
+        let pending = (start_block..=end_block)
+            .step_by(chunk_size as usize)
+            .map(|chunk_start| {
+                (
+                    chunk_start,
+                    chunk_start.saturating_add(chunk_size - 1).min(end_block),
+                )
+            })
+            .collect();
+        Self {
+            pending,
+            leased: HashMap::new(),
+            completed: 0,
+            reassigned: 0,
+            next_id: 0,
+            lease_timeout,
+            attempts: HashMap::new(),
+            max_attempts,
+            failed: Vec::new(),
+            drained: Arc::new(Notify::new()),
+        }
+    }
+
+    fn is_drained(&self) -> bool {
+        self.pending.is_empty() && self.leased.is_empty()
+    }
+
+    // A chunk that failed or expired is handed out again until it used up its attempts
+    fn retry_or_give_up(&mut self, range: (u64, u64), requeue: impl FnOnce(&mut Self, (u64, u64))) {
+        if self.attempts.get(&range).copied().unwrap_or_default() >= self.max_attempts {
+            log_line(format!(
+                "Blocks {}-{} failed {} leases, giving up on them",
+                range.0, range.1, self.max_attempts
+            ));
+            self.failed.push(range);
+        } else {
+            requeue(self, range);
+            self.reassigned += 1;
+        }
+    }
+
+    fn notify_if_drained(&self) {
+        if self.is_drained() {
+            log_line(format!(
+                "All chunks are done, {} completed and {} failed",
+                self.completed,
+                self.failed.len()
+            ));
+            self.drained.notify_one();
+        }
+    }
+
+    // Expired leases go back to the front of the queue, their worker is presumed dead
+    fn reclaim_expired(&mut self) {
+        let now = Instant::now();
+        let expired = self
+            .leased
+            .iter()
+            .filter(|(_, lease)| lease.expires <= now)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        for id in expired.iter() {
+            let lease = self.leased.remove(id).unwrap();
+            log_line(format!(
+                "Lease {} of blocks {}-{} expired",
+                id, lease.range.0, lease.range.1
+            ));
+            self.retry_or_give_up(lease.range, |queue, range| queue.pending.push_front(range));
+        }
+        if !expired.is_empty() {
+            self.notify_if_drained();
+        }
+    }
+
+    fn lease(&mut self) -> LeaseResponse {
+        self.reclaim_expired();
+        let Some((start_block, end_block)) = self.pending.pop_front() else {
+            return LeaseResponse {
+                lease: None,
+                done: self.leased.is_empty(),
+            };
+        };
+        *self.attempts.entry((start_block, end_block)).or_default() += 1;
+        let id = self.next_id;
+        self.next_id += 1;
+        self.leased.insert(
+            id,
+            ActiveLease {
+                range: (start_block, end_block),
+                expires: Instant::now() + self.lease_timeout,
+            },
+        );
+        LeaseResponse {
+            lease: Some(Lease {
+                id,
+                start_block,
+                end_block,
+            }),
+            done: false,
+        }
+    }
+
+    fn complete(&mut self, id: u64) -> bool {
+        if self.leased.remove(&id).is_none() {
+            return false;
+        }
+        self.completed += 1;
+        self.notify_if_drained();
+        true
+    }
+
+    fn fail(&mut self, id: u64) -> bool {
+        let Some(lease) = self.leased.remove(&id) else {
+            return false;
+        };
+        self.retry_or_give_up(lease.range, |queue, range| queue.pending.push_back(range));
+        self.notify_if_drained();
+        true
+    }
+
+    fn status(&self) -> QueueStatus {
+        QueueStatus {
+            pending: self.pending.len(),
+            leased: self.leased.len(),
+            completed: self.completed,
+            reassigned: self.reassigned,
+            failed: self.failed.len(),
+        }
+    }
+
+    // Every block of the chunks given up on, for a follow-up run with --blocks-file
+    fn failed_blocks(&self) -> Vec<FailedBlock> {
+        let mut failed = self.failed.clone();
+        failed.sort();
+        failed
+            .into_iter()
+            .flat_map(|(start_block, end_block)| {
+                let err = anyhow::anyhow!(
+                    "chunk {}-{} failed {} leases",
+                    start_block,
+                    end_block,
+                    self.max_attempts
+                );
+                (start_block..=end_block).map(move |block| FailedBlock::new(block, &err))
+            })
+            .collect()
+    }
+}
+
+type SharedQueue = Arc<Mutex<WorkQueue>>;
+
+// Hands out chunks of the range to workers until every chunk is completed or given up on, whose
+// blocks are then written to failed_blocks.json in the output directory
+pub async fn coordinate(
+    args: CoordinatorArgs,
+    start_block: u64,
+    end_block: u64,
+    output_dir: &std::path::Path,
+) -> anyhow::Result<()> {
+    if args.chunk_size == 0 {
+        anyhow::bail!("--chunk-size must be at least 1");
+    }
+    let queue = WorkQueue::new(
+        start_block,
+        end_block,
+        args.chunk_size,
+        Duration::from_secs(args.lease_timeout),
+        args.max_attempts,
+    );
+    log_line(format!(
+        "Coordinating {} chunks of blocks {}-{} on {}",
+        queue.pending.len(),
+        start_block,
+        end_block,
+        args.listen
+    ));
+
+    let drained = queue.drained.clone();
+    let queue = Arc::new(Mutex::new(queue));
+    let app = Router::new()
+        .route("/leases", post(lease))
+        .route("/leases/:id/complete", post(complete))
+        .route("/leases/:id/fail", post(fail))
+        .route("/status", get(status))
+        .with_state(queue.clone());
+    let listener = tokio::net::TcpListener::bind(args.listen).await?;
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async move {
+            drained.notified().await;
+            tokio::time::sleep(DRAIN_GRACE).await;
+        })
+        .await?;
+
+    let failed_blocks = queue.lock().unwrap().failed_blocks();
+    if failed_blocks.is_empty() {
+        return Ok(());
+    }
+    std::fs::create_dir_all(output_dir)?;
+    output::write_json(output_dir, "failed_blocks.json", &failed_blocks)?;
+    anyhow::bail!(
+        "{} blocks were given up on, see failed_blocks.json",
+        failed_blocks.len()
+    )
+}
+
+async fn lease(State(queue): State<SharedQueue>) -> Json<LeaseResponse> {
+    Json(queue.lock().unwrap().lease())
+}
+
+// A lease that expired and was reassigned can no longer be completed by its old worker
+async fn complete(State(queue): State<SharedQueue>, Path(id): Path<u64>) -> StatusCode {
+    if queue.lock().unwrap().complete(id) {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::CONFLICT
+    }
+}
+
+async fn fail(State(queue): State<SharedQueue>, Path(id): Path<u64>) -> StatusCode {
+    if queue.lock().unwrap().fail(id) {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::CONFLICT
+    }
+}
+
+async fn status(State(queue): State<SharedQueue>) -> Json<QueueStatus> {
+    Json(queue.lock().unwrap().status())
+}
+
+// Scans leased chunks with the global options of `cmd` until the coordinator runs out of work
+pub async fn work(args: WorkerArgs, cmd: Cmd) -> anyhow::Result<()> {
+    let client = reqwest::Client::new();
+    let coordinator = args.coordinator.trim_end_matches('/');
+
+    loop {
+        let response = client
+            .post(format!("{}/leases", coordinator))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<LeaseResponse>()
+            .await?;
+        let Some(lease) = response.lease else {
+            if response.done {
+                log_line("Coordinator has no work left");
+                return Ok(());
+            }
+            tokio::time::sleep(Duration::from_secs(args.idle_interval)).await;
+            continue;
+        };
+
+        log_line(format!(
+            "Scanning blocks {}-{} (lease {})",
+            lease.start_block, lease.end_block, lease.id
+        ));
+        let scan_cmd = chunk_cmd(&cmd, lease.start_block, lease.end_block, &args.chunks_dir);
+        let chunk_dir = scan_cmd.output_dir.clone();
+        let scanned = match Box::pin(crate::amain(scan_cmd)).await {
+            // A best-effort scan succeeds with failed blocks, the chunk is leased again for them
+            Ok(()) => failures::read_failed_blocks(&chunk_dir).and_then(|failed_blocks| {
+                match failed_blocks.len() {
+                    0 => Ok(()),
+                    failed => Err(anyhow::anyhow!("{} blocks failed", failed)),
+                }
+            }),
+            Err(err) => Err(err),
+        };
+        let outcome = match scanned {
+            Ok(()) => "complete",
+            Err(err) => {
+                log_line(format!(
+                    "Scanning blocks {}-{} failed: {}",
+                    lease.start_block, lease.end_block, err
+                ));
+                "fail"
+            }
+        };
+        let response = client
+            .post(format!("{}/leases/{}/{}", coordinator, lease.id, outcome))
+            .send()
+            .await?;
+        if response.status() == reqwest::StatusCode::CONFLICT {
+            log_line(format!(
+                "Lease {} expired before it was reported, the chunk will be scanned again",
+                lease.id
+            ));
+        }
+    }
+}
+
+// Options of `cmd` narrowed to a plain scan of one chunk, written to `<chunks_dir>/<start>-<end>`
+pub fn chunk_cmd(cmd: &Cmd, start_block: u64, end_block: u64, chunks_dir: &std::path::Path) -> Cmd {
+    let mut chunk_cmd = cmd.clone();
+    chunk_cmd.command = None;
+    chunk_cmd.shard = None;
+    // The leased range replaces the blocks or chains the options list
+    chunk_cmd.chains = None;
+    chunk_cmd.blocks_file = None;
+    chunk_cmd.start_block = Some(start_block);
+    chunk_cmd.end_block = Some(end_block);
+    chunk_cmd.output_dir = chunks_dir.join(format!("{}-{}", start_block, end_block));
+    // The exporter is already listening from the parent scan
+    chunk_cmd.metrics_addr = None;
+    chunk_cmd
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leased_range(queue: &mut WorkQueue) -> (u64, (u64, u64)) {
+        let lease = queue.lease().lease.unwrap();
+        (lease.id, (lease.start_block, lease.end_block))
+    }
+
+    #[test]
+    fn chunks_cover_the_range() {
+        let queue = WorkQueue::new(10, 34, 10, Duration::from_secs(60), 3);
+        assert_eq!(
+            queue.pending.iter().copied().collect::<Vec<_>>(),
+            vec![(10, 19), (20, 29), (30, 34)]
+        );
+    }
+
+    #[test]
+    fn chunks_near_the_end_of_the_block_space_do_not_overflow() {
+        let queue = WorkQueue::new(u64::MAX - 5, u64::MAX, u64::MAX, Duration::from_secs(60), 3);
+        assert_eq!(
+            queue.pending.iter().copied().collect::<Vec<_>>(),
+            vec![(u64::MAX - 5, u64::MAX)]
+        );
+    }
+
+    #[test]
+    fn completed_chunks_drain_the_queue() {
+        let mut queue = WorkQueue::new(0, 19, 10, Duration::from_secs(60), 3);
+        let (first, _) = leased_range(&mut queue);
+        let (second, _) = leased_range(&mut queue);
+        let response = queue.lease();
+        assert!(response.lease.is_none());
+        assert!(!response.done);
+
+        assert!(queue.complete(first));
+        assert!(!queue.complete(first));
+        assert!(queue.complete(second));
+        assert!(queue.is_drained());
+        assert!(queue.lease().done);
+        assert!(queue.failed_blocks().is_empty());
+    }
+
+    #[test]
+    fn failed_chunks_are_retried_until_they_run_out_of_attempts() {
+        let mut queue = WorkQueue::new(0, 4, 10, Duration::from_secs(60), 2);
+        let (id, range) = leased_range(&mut queue);
+        assert_eq!(range, (0, 4));
+        assert!(queue.fail(id));
+        assert_eq!(queue.status().reassigned, 1);
+
+        let (id, retried_range) = leased_range(&mut queue);
+        assert_eq!(retried_range, range);
+        assert!(queue.fail(id));
+        assert!(queue.is_drained());
+        assert_eq!(queue.status().failed, 1);
+        assert_eq!(
+            queue
+                .failed_blocks()
+                .iter()
+                .map(|failed_block| failed_block.block_number)
+                .collect::<Vec<_>>(),
+            vec![0, 1, 2, 3, 4]
+        );
+    }
+
+    #[test]
+    fn expired_leases_are_handed_out_again() {
+        let mut queue = WorkQueue::new(0, 19, 10, Duration::ZERO, 3);
+        let (expired, range) = leased_range(&mut queue);
+        // Leasing reclaims the expired lease first, its chunk goes to the front of the queue
+        let (_, reassigned_range) = leased_range(&mut queue);
+        assert_eq!(reassigned_range, range);
+        assert!(!queue.complete(expired));
+        assert_eq!(queue.status().reassigned, 1);
+    }
+}
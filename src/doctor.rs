@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+use alloy_primitives::{B256, U64};
+use serde_json::json;
+
+use crate::follow::head_block;
+use crate::handshake;
+use crate::l2::L2Adapter;
+use crate::progress::log_line;
+use crate::rpc::{self, HttpArgs, HttpTransport, JsonRpcError, RpcClient};
+
+const GIB: u64 = 1024 * 1024 * 1024;
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct DoctorArgs {
+    /// Free space in GiB the output directory needs for its check to pass
+    #[arg(long, default_value_t = 10)]
+    pub min_free_gb: u64,
+}
+
+// The global options a run would use, checked as they are
+pub struct DoctorContext {
+    pub rpc_url: Option<String>,
+    pub http: HttpArgs,
+    pub chain_id: u64,
+    pub adapter: Arc<dyn L2Adapter>,
+    pub db_path: Option<PathBuf>,
+    pub static_files_path: Option<PathBuf>,
+    pub erigon_chaindata: Option<PathBuf>,
+    pub tx_refresh_interval: usize,
+    pub output_dir: PathBuf,
+}
+
+enum Outcome {
+    Pass(String),
+    Fail(String),
+    // Not configured, or depends on a check that failed
+    Skip(String),
+}
+
+#[derive(Default)]
+struct Checklist {
+    passed: usize,
+    failed: usize,
+}
+
+impl Checklist {
+    fn report(&mut self, name: &str, outcome: Outcome) {
+        let (status, detail) = match outcome {
+            Outcome::Pass(detail) => {
+                self.passed += 1;
+                ("PASS", detail)
+            }
+            Outcome::Fail(detail) => {
+                self.failed += 1;
+                ("FAIL", detail)
+            }
+            Outcome::Skip(detail) => ("SKIP", detail),
+        };
+        log_line(format!("[{}] {}: {}", status, name, detail));
+    }
+}
+
+// Runs every check instead of stopping at the first failure, so one run lists everything to fix
+// before a long scan
+pub async fn run(args: DoctorArgs, context: DoctorContext) -> anyhow::Result<()> {
+    let mut checklist = Checklist::default();
+
+    let client = match &context.rpc_url {
+        None => {
+            checklist.report(
+                "RPC endpoint",
+                Outcome::Fail("Set RPC_URL or pass --rpc-url".to_string()),
+            );
+            None
+        }
+        Some(rpc_url) => match HttpTransport::new(rpc_url.clone(), &context.http) {
+            Ok(transport) => Some(RpcClient::new(Arc::new(transport))),
+            Err(err) => {
+                checklist.report("RPC endpoint", Outcome::Fail(err.to_string()));
+                None
+            }
+        },
+    };
+
+    let mut rpc_genesis_hash = None;
+    match &client {
+        Some(client) => {
+            let rpc_url = context.rpc_url.as_deref().unwrap_or_default();
+            match connectivity(client, rpc_url).await {
+                Ok((outcome, head)) => {
+                    checklist.report("RPC endpoint", outcome);
+                    checklist.report("Chain id", chain_id(client, context.chain_id).await);
+                    checklist.report(
+                        "Trace API",
+                        trace_api(client, context.adapter.as_ref(), head).await,
+                    );
+                    rpc_genesis_hash = handshake::genesis_hash(client).await.ok();
+                }
+                Err(err) => {
+                    checklist.report(
+                        "RPC endpoint",
+                        Outcome::Fail(format!(
+                            "{} is unreachable: {}",
+                            rpc::redact_url(rpc_url),
+                            err
+                        )),
+                    );
+                    let skipped = || Outcome::Skip("the node is unreachable".to_string());
+                    checklist.report("Chain id", skipped());
+                    checklist.report("Trace API", skipped());
+                }
+            }
+        }
+        None => {
+            let skipped = || Outcome::Skip("no RPC endpoint".to_string());
+            checklist.report("Chain id", skipped());
+            checklist.report("Trace API", skipped());
+        }
+    }
+
+    checklist.report("Local datadir", local_datadir(&context, rpc_genesis_hash));
+    checklist.report(
+        "Output directory",
+        output_dir(&context.output_dir, args.min_free_gb),
+    );
+
+    if checklist.failed > 0 {
+        anyhow::bail!(
+            "{} of {} checks failed",
+            checklist.failed,
+            checklist.failed + checklist.passed
+        );
+    }
+    log_line(format!("All {} checks passed", checklist.passed));
+    Ok(())
+}
+
+async fn connectivity(client: &RpcClient, rpc_url: &str) -> anyhow::Result<(Outcome, u64)> {
+    let started = Instant::now();
+    let head = head_block(client).await?;
+    let latency = started.elapsed();
+    let client_version = client
+        .request::<String>("web3_clientVersion", json!([]))
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| "unknown client".to_string());
+    Ok((
+        Outcome::Pass(format!(
+            "{} at {}, head {}, answered in {} ms",
+            client_version,
+            rpc::redact_url(rpc_url),
+            head,
+            latency.as_millis()
+        )),
+        head,
+    ))
+}
+
+async fn chain_id(client: &RpcClient, expected_chain_id: u64) -> Outcome {
+    match client.request::<U64>("eth_chainId", json!([])).await {
+        Ok(Some(chain_id)) if chain_id.to::<u64>() == expected_chain_id => {
+            Outcome::Pass(format!("chain {}", expected_chain_id))
+        }
+        Ok(Some(chain_id)) => Outcome::Fail(format!(
+            "the node serves chain {} but chain {} is configured, pass --chain-id {} if that is intended",
+            chain_id, expected_chain_id, chain_id
+        )),
+        Ok(None) => Outcome::Fail("the node did not answer eth_chainId".to_string()),
+        Err(err) => Outcome::Fail(err.to_string()),
+    }
+}
+
+// The module list alone can be wrong or missing, so the head block is traced for real
+async fn trace_api(client: &RpcClient, adapter: &dyn L2Adapter, head: u64) -> Outcome {
+    let module = adapter.trace_module();
+    if let Ok(Some(modules)) = client
+        .request::<HashMap<String, String>>("rpc_modules", json!([]))
+        .await
+    {
+        if !modules.contains_key(module) {
+            return Outcome::Fail(format!(
+                "the node does not expose the {} API, enable it with --http.api {}",
+                module, module
+            ));
+        }
+    }
+
+    let started = Instant::now();
+    match adapter.trace_block(client, &format!("{:#x}", head)).await {
+        Ok(Some(traces)) => Outcome::Pass(format!(
+            "block {} traced over the {} API of {} ({} traces) in {} ms",
+            head,
+            module,
+            adapter.name(),
+            traces.len(),
+            started.elapsed().as_millis()
+        )),
+        Ok(None) => Outcome::Fail(format!("the node could not trace its head block {}", head)),
+        Err(err) => match err.downcast_ref::<JsonRpcError>() {
+            Some(err) if err.is_method_not_found() => Outcome::Fail(format!(
+                "the node does not serve the {} tracing methods, enable them with --http.api {}",
+                module, module
+            )),
+            _ => Outcome::Fail(format!("tracing block {} failed: {}", head, err)),
+        },
+    }
+}
+
+fn local_datadir(context: &DoctorContext, rpc_genesis_hash: Option<B256>) -> Outcome {
+    let chain_db = match (
+        &context.erigon_chaindata,
+        &context.db_path,
+        &context.static_files_path,
+    ) {
+        (Some(erigon_chaindata), _, _) => {
+            if !erigon_chaindata.is_dir() {
+                return Outcome::Fail(format!("{} is not a directory", erigon_chaindata.display()));
+            }
+            crate::open_erigon_db(erigon_chaindata)
+        }
+        (None, Some(db_path), Some(static_files_path)) => {
+            for path in [db_path, static_files_path] {
+                if !path.is_dir() {
+                    return Outcome::Fail(format!("{} is not a directory", path.display()));
+                }
+            }
+            crate::open_chain_db(
+                &context.db_path,
+                &context.static_files_path,
+                context.chain_id,
+                context.tx_refresh_interval,
+            )
+        }
+        (None, None, None) => {
+            return Outcome::Skip(
+                "DB_PATH and STATIC_FILES_PATH are not set, verification runs over RPC".to_string(),
+            )
+        }
+        _ => {
+            return Outcome::Fail(
+                "DB_PATH and STATIC_FILES_PATH have to be set together".to_string(),
+            )
+        }
+    };
+    let chain_db = match chain_db {
+        Ok(chain_db) => chain_db,
+        Err(err) => return Outcome::Fail(format!("opening the datadir failed: {}", err)),
+    };
+    match rpc_genesis_hash {
+        Some(rpc_genesis_hash) => {
+            match handshake::verify_db_genesis(chain_db.as_ref(), rpc_genesis_hash) {
+                Ok(()) => Outcome::Pass(format!("genesis {} matches the node", rpc_genesis_hash)),
+                Err(err) => Outcome::Fail(err.to_string()),
+            }
+        }
+        None => Outcome::Pass("opened, genesis not compared without the node".to_string()),
+    }
+}
+
+// The directory is created by the run, so a missing one is checked through its nearest existing
+// parent
+fn output_dir(output_dir: &Path, min_free_gb: u64) -> Outcome {
+    let Some(existing) = output_dir.ancestors().find(|path| path.is_dir()) else {
+        return Outcome::Fail(format!("no parent of {} exists", output_dir.display()));
+    };
+    let existing = match existing.canonicalize() {
+        Ok(existing) => existing,
+        Err(err) => return Outcome::Fail(format!("{}: {}", existing.display(), err)),
+    };
+    let probe = existing.join(".block-tracer-doctor");
+    if let Err(err) = std::fs::write(&probe, b"") {
+        return Outcome::Fail(format!("{} is not writable: {}", existing.display(), err));
+    }
+    let _ = std::fs::remove_file(&probe);
+
+    // The disk is the one mounted at the longest prefix of the path
+    let disks = sysinfo::Disks::new_with_refreshed_list();
+    let Some(disk) = disks
+        .list()
+        .iter()
+        .filter(|disk| existing.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+    else {
+        return Outcome::Pass(format!(
+            "{} is writable, free space unknown",
+            output_dir.display()
+        ));
+    };
+    let free_gb = disk.available_space() as f64 / GIB as f64;
+    if disk.available_space() < min_free_gb * GIB {
+        return Outcome::Fail(format!(
+            "{:.1} GiB free on {}, less than the {} GiB of --min-free-gb",
+            free_gb,
+            disk.mount_point().display(),
+            min_free_gb
+        ));
+    }
+    Outcome::Pass(format!(
+        "{} is writable, {:.1} GiB free",
+        output_dir.display(),
+        free_gb
+    ))
+}
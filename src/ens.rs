@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+
+use alloy_primitives::{address, keccak256, Address, Bytes, B256, U256};
+use futures::{stream, StreamExt};
+use serde_json::json;
+
+use crate::progress::log_line;
+use crate::rpc::RpcClient;
+
+const ENS_REGISTRY: Address = address!("00000000000C2E074eC69A0dFb2997BA6C7d2e1e");
+// resolver(bytes32)
+const RESOLVER_SELECTOR: [u8; 4] = [0x01, 0x78, 0xb8, 0xbf];
+// name(bytes32)
+const NAME_SELECTOR: [u8; 4] = [0x69, 0x1f, 0x34, 0x31];
+// addr(bytes32)
+const ADDR_SELECTOR: [u8; 4] = [0x3b, 0x3b, 0x57, 0xde];
+// Each lookup is up to four eth_calls
+const CONCURRENT_LOOKUPS: usize = 16;
+
+// Resolves each distinct address once, addresses without a verified primary name are left out.
+// A failed lookup only costs that name, it is printed and the address is left out as well
+pub async fn reverse_names(client: &RpcClient, addresses: &[Address]) -> HashMap<Address, String> {
+    let mut addresses = addresses.to_vec();
+    addresses.sort();
+    addresses.dedup();
+
+    let mut lookups = stream::iter(addresses)
+        .map(|address| async move { (address, reverse_name(client, address).await) })
+        .buffer_unordered(CONCURRENT_LOOKUPS);
+
+    let mut names = HashMap::new();
+    while let Some((address, name)) = lookups.next().await {
+        match name {
+            Ok(Some(name)) => {
+                names.insert(address, name);
+            }
+            Ok(None) => {}
+            Err(err) => log_line(format!(
+                "Resolving the ENS name of {} failed: {:#}",
+                address, err
+            )),
+        }
+    }
+
+    names
+}
+
+async fn reverse_name(client: &RpcClient, address: Address) -> anyhow::Result<Option<String>> {
+    let reverse_node = namehash(&format!("{:x}.addr.reverse", address));
+    let Some(resolver) = resolver(client, reverse_node).await? else {
+        return Ok(None);
+    };
+    let output = call(client, resolver, NAME_SELECTOR, reverse_node).await?;
+    let Some(name) = decode_string(&output) else {
+        return Ok(None);
+    };
+
+    // A reverse record is only trusted when the name resolves back to the address
+    let node = namehash(&name);
+    let Some(forward_resolver) = resolver(client, node).await? else {
+        return Ok(None);
+    };
+    let output = call(client, forward_resolver, ADDR_SELECTOR, node).await?;
+    if output.len() < 32 || Address::from_slice(&output[12..32]) != address {
+        return Ok(None);
+    }
+
+    Ok(Some(name))
+}
+
+async fn resolver(client: &RpcClient, node: B256) -> anyhow::Result<Option<Address>> {
+    let output = call(client, ENS_REGISTRY, RESOLVER_SELECTOR, node).await?;
+    if output.len() < 32 {
+        return Ok(None);
+    }
+    let resolver = Address::from_slice(&output[12..32]);
+    Ok((!resolver.is_zero()).then_some(resolver))
+}
+
+async fn call(
+    client: &RpcClient,
+    to: Address,
+    selector: [u8; 4],
+    node: B256,
+) -> anyhow::Result<Bytes> {
+    let data = Bytes::from([selector.as_slice(), node.as_slice()].concat());
+    Ok(client
+        .request::<Bytes>("eth_call", json!([{ "to": to, "data": data }, "latest"]))
+        .await?
+        .unwrap_or_default())
+}
+
+fn namehash(name: &str) -> B256 {
+    name.rsplit('.')
+        .filter(|label| !label.is_empty())
+        .fold(B256::ZERO, |node, label| {
+            keccak256([node.as_slice(), keccak256(label.as_bytes()).as_slice()].concat())
+        })
+}
+
+// ABI decoding of a single dynamic `string` return value
+fn decode_string(output: &[u8]) -> Option<String> {
+    let length = usize::try_from(U256::from_be_slice(output.get(32..64)?)).ok()?;
+    let name = String::from_utf8(output.get(64..64 + length)?.to_vec()).ok()?;
+    (!name.is_empty()).then_some(name)
+}
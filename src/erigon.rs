@@ -0,0 +1,123 @@
+use std::path::Path;
+
+use alloy_primitives::{Address, Bytes, TxHash, B256};
+use reth_libmdbx::{Environment, EnvironmentFlags, Mode};
+
+use crate::chain_db::{
+    BlockHeader, ChainDb, HistorySegment, LocalReceipt, LocalTransaction, Ommer, StorageChange,
+    StorageSlot, Withdrawal,
+};
+
+// Erigon 2 table names
+const PLAIN_STATE: &str = "PlainState";
+const CANONICAL_HEADER: &str = "CanonicalHeader";
+
+// Erigon keeps more named tables than reth, and all of them count against the limit
+const MAX_DBS: usize = 256;
+
+/// [`ChainDb`] over the `chaindata` MDBX environment of an Erigon 2 node, opened read only.
+///
+/// Only what recreation verification needs is read: canonical hashes (for the genesis check) and
+/// account existence from `PlainState`. Erigon's block and transaction encodings are not
+/// decoded, so header, transaction, receipt, ommer and withdrawal lookups find nothing, the same as for
+/// blocks the datadir does not have.
+pub struct ErigonDb {
+    env: Environment,
+}
+
+impl ErigonDb {
+    pub fn open(chaindata_path: &Path) -> anyhow::Result<Self> {
+        let env = Environment::builder()
+            .set_max_dbs(MAX_DBS)
+            .set_flags(EnvironmentFlags {
+                mode: Mode::ReadOnly,
+                ..Default::default()
+            })
+            .open(chaindata_path)?;
+        Ok(Self { env })
+    }
+}
+
+impl ChainDb for ErigonDb {
+    fn canonical_hash(&self, block_number: u64) -> anyhow::Result<Option<B256>> {
+        let txn = self.env.begin_ro_txn()?;
+        let canonical_headers = txn.open_db(Some(CANONICAL_HEADER))?;
+        Ok(txn
+            .get::<[u8; 32]>(canonical_headers.dbi(), &block_number.to_be_bytes())?
+            .map(B256::from))
+    }
+
+    fn header(&self, _block_number: u64) -> anyhow::Result<Option<BlockHeader>> {
+        Ok(None)
+    }
+
+    fn transaction_by_hash(&self, _hash: TxHash) -> anyhow::Result<Option<LocalTransaction>> {
+        Ok(None)
+    }
+
+    fn transaction_at(
+        &self,
+        _block_number: u64,
+        _index: u64,
+    ) -> anyhow::Result<Option<LocalTransaction>> {
+        Ok(None)
+    }
+
+    fn block_transactions(&self, _block_number: u64) -> anyhow::Result<Vec<LocalTransaction>> {
+        Ok(Vec::new())
+    }
+
+    fn senders(&self, tx_numbers: &[u64]) -> anyhow::Result<Vec<Option<Address>>> {
+        Ok(vec![None; tx_numbers.len()])
+    }
+
+    fn receipts(&self, _block_number: u64) -> anyhow::Result<Vec<LocalReceipt>> {
+        Ok(Vec::new())
+    }
+
+    fn ommers(&self, _start_block: u64, _end_block: u64) -> anyhow::Result<Vec<Ommer>> {
+        Ok(Vec::new())
+    }
+
+    fn withdrawals(&self, _start_block: u64, _end_block: u64) -> anyhow::Result<Vec<Withdrawal>> {
+        Ok(Vec::new())
+    }
+
+    // Account entries in PlainState are keyed by the bare address, storage entries by address,
+    // incarnation and slot
+    fn existing_accounts(&self, addresses: &[Address]) -> anyhow::Result<Vec<Address>> {
+        let txn = self.env.begin_ro_txn()?;
+        let plain_state = txn.open_db(Some(PLAIN_STATE))?;
+        let mut existing = Vec::new();
+        for address in addresses {
+            if txn
+                .get::<()>(plain_state.dbi(), address.as_slice())?
+                .is_some()
+            {
+                existing.push(*address);
+            }
+        }
+        Ok(existing)
+    }
+
+    fn bytecode(&self, _address: Address) -> anyhow::Result<Option<(B256, Bytes)>> {
+        Ok(None)
+    }
+
+    // Erigon prunes by distance from the head and keeps no per-segment checkpoints
+    fn pruned_segments(&self) -> anyhow::Result<Vec<(HistorySegment, u64)>> {
+        Ok(Vec::new())
+    }
+
+    fn plain_storage(&self, _address: Address) -> anyhow::Result<Vec<StorageSlot>> {
+        anyhow::bail!("Storage is not read from Erigon datadirs")
+    }
+
+    fn storage_changes(
+        &self,
+        _address: Address,
+        _from_block: u64,
+    ) -> anyhow::Result<Vec<StorageChange>> {
+        anyhow::bail!("Storage is not read from Erigon datadirs")
+    }
+}
@@ -0,0 +1,202 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use alloy_primitives::keccak256;
+use futures::future::join_all;
+
+use crate::filters::TraceFilter;
+use crate::l2::L2Adapter;
+use crate::progress::{log_line, progress};
+use crate::rpc::RpcClient;
+use crate::{output, trace_block, TraceType};
+
+pub struct EstimateContext {
+    pub client: RpcClient,
+    pub chain_id: u64,
+    pub adapter: Arc<dyn L2Adapter>,
+    pub filter: Arc<TraceFilter>,
+    pub include_reverted: bool,
+    pub block_deadline: u64,
+}
+
+struct SampledBlock {
+    latency: Duration,
+    records: usize,
+    creates: usize,
+    selfdestructs: usize,
+    // Bytes the records take in trace_records.json
+    output_bytes: usize,
+}
+
+// Random blocks of the range, a fresh sample every run. Blocks are ordered by the hash of a seed
+// and the block number, so no RNG is needed
+fn sample(blocks: &[u64], sample_size: usize) -> Vec<u64> {
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_be_bytes();
+    let mut sampled = blocks.to_vec();
+    sampled.sort_by_cached_key(|block| keccak256([&seed[..], &block.to_be_bytes()].concat()));
+    sampled.truncate(sample_size);
+    sampled.sort_unstable();
+    sampled
+}
+
+// Traces a sample of the blocks the way the scan does, concurrently and under the block deadline,
+// and scales what it took up to the whole range. Only tracing is measured, enrichment and
+// verification come on top
+pub async fn run(
+    blocks: &[u64],
+    sample_size: usize,
+    context: EstimateContext,
+) -> anyhow::Result<()> {
+    let sampled_blocks = sample(blocks, sample_size.max(1));
+    log_line(format!(
+        "Tracing {} random blocks of the {} in the range",
+        sampled_blocks.len(),
+        blocks.len()
+    ));
+
+    let EstimateContext {
+        client,
+        chain_id,
+        adapter,
+        filter,
+        include_reverted,
+        block_deadline,
+    } = context;
+    let requests_before = rpc_requests();
+    let started = Instant::now();
+    let results = join_all(sampled_blocks.iter().copied().map(|block| {
+        let client = client.clone();
+        let adapter = adapter.clone();
+        let filter = filter.clone();
+        async move {
+            let started = Instant::now();
+            let traced = tokio::time::timeout(
+                Duration::from_secs(block_deadline),
+                trace_block(client, block, chain_id, adapter, filter, include_reverted),
+            )
+            .await
+            .unwrap_or_else(|elapsed| Err(elapsed.into()));
+            let trace_responses = match traced {
+                Ok(trace_responses) => trace_responses.unwrap_or_default(),
+                Err(err) => {
+                    log_line(format!("Tracing block {} failed: {:#}", block, err));
+                    return Ok(None);
+                }
+            };
+            let mut output_bytes = 0;
+            for trace_response in &trace_responses {
+                output_bytes +=
+                    serde_json::to_vec(&output::to_output_value(trace_response)?)?.len() + 1;
+            }
+            let count = |trace_type: TraceType| {
+                trace_responses
+                    .iter()
+                    .filter(|trace_response| trace_response.trace_type == trace_type)
+                    .count()
+            };
+            anyhow::Ok(Some(SampledBlock {
+                latency: started.elapsed(),
+                records: trace_responses.len(),
+                creates: count(TraceType::Create),
+                selfdestructs: count(TraceType::SelfDestruct),
+                output_bytes,
+            }))
+        }
+    }))
+    .await
+    .into_iter()
+    .collect::<anyhow::Result<Vec<_>>>()?;
+    let elapsed = started.elapsed();
+    let rpc_calls = rpc_requests() - requests_before;
+
+    let failed = results.iter().filter(|result| result.is_none()).count();
+    let sampled = results.len();
+    let mut traced = results.into_iter().flatten().collect::<Vec<_>>();
+    if traced.is_empty() {
+        anyhow::bail!("None of the {} sampled blocks could be traced", failed);
+    }
+    traced.sort_by_key(|sampled_block| sampled_block.latency);
+    let per_block = |value: fn(&SampledBlock) -> usize| {
+        traced.iter().map(value).sum::<usize>() as f64 / traced.len() as f64
+    };
+    let latency_at =
+        |quantile: f64| traced[((traced.len() - 1) as f64 * quantile).round() as usize].latency;
+    let mean_latency = traced
+        .iter()
+        .map(|sampled_block| sampled_block.latency)
+        .sum::<Duration>()
+        / traced.len() as u32;
+    let scale = blocks.len() as f64 / sampled as f64;
+
+    log_line(format!(
+        "Traced {} blocks in {:.1?}, {} failed: {:.2?} per block on average (p50 {:.2?}, p95 {:.2?})",
+        traced.len(),
+        elapsed,
+        failed,
+        mean_latency,
+        latency_at(0.5),
+        latency_at(0.95)
+    ));
+    log_line(format!(
+        "Per block: {:.2} records ({:.2} creates, {:.2} selfdestructs), {:.1} RPC calls",
+        per_block(|sampled_block| sampled_block.records),
+        per_block(|sampled_block| sampled_block.creates),
+        per_block(|sampled_block| sampled_block.selfdestructs),
+        rpc_calls as f64 / sampled as f64
+    ));
+    log_line(format!("Estimated for all {} blocks:", blocks.len()));
+    log_line(format!(
+        "  tracing time  {}",
+        format_duration(elapsed.mul_f64(scale))
+    ));
+    log_line(format!(
+        "  records       {:.0} ({:.0} creates, {:.0} selfdestructs)",
+        per_block(|sampled_block| sampled_block.records) * blocks.len() as f64,
+        per_block(|sampled_block| sampled_block.creates) * blocks.len() as f64,
+        per_block(|sampled_block| sampled_block.selfdestructs) * blocks.len() as f64
+    ));
+    log_line(format!(
+        "  output size   {} of trace records",
+        format_bytes(per_block(|sampled_block| sampled_block.output_bytes) * blocks.len() as f64)
+    ));
+    log_line(format!("  RPC calls     {:.0}", rpc_calls as f64 * scale));
+    if failed > 0 {
+        log_line(format!(
+            "{:.0}% of the sampled blocks failed, expect about as many in failed_blocks.json",
+            failed as f64 * 100.0 / sampled as f64
+        ));
+    }
+    Ok(())
+}
+
+fn rpc_requests() -> u64 {
+    progress()
+        .endpoints()
+        .values()
+        .map(|stats| stats.requests)
+        .sum()
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3_599 => format!("{}m {}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {}m", seconds / 3_600, seconds % 3_600 / 60),
+    }
+}
+
+fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1000.0 && unit < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use alloy_primitives::Address;
+
+use crate::progress::log_line;
+
+const ETHERSCAN_API_URL: &str = "https://api.etherscan.io/api";
+// Free tier allows 5 calls per second
+const REQUEST_INTERVAL: Duration = Duration::from_millis(200);
+
+#[derive(Debug, serde::Deserialize)]
+struct EtherscanResponse {
+    status: String,
+    result: serde_json::Value,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SourceCode {
+    source_code: String,
+    contract_name: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ContractInfo {
+    pub contract_name: Option<String>,
+    pub verified: bool,
+}
+
+pub async fn contract_infos(
+    api_key: &str,
+    addresses: &[Address],
+) -> anyhow::Result<HashMap<Address, ContractInfo>> {
+    let client = reqwest::Client::new();
+    let mut interval = tokio::time::interval(REQUEST_INTERVAL);
+    let mut infos = HashMap::new();
+
+    for address in addresses {
+        interval.tick().await;
+        let address_hex = address.to_string();
+        let response = client
+            .get(ETHERSCAN_API_URL)
+            .query(&[
+                ("module", "contract"),
+                ("action", "getsourcecode"),
+                ("address", address_hex.as_str()),
+                ("apikey", api_key),
+            ])
+            .send()
+            .await?
+            .json::<EtherscanResponse>()
+            .await?;
+
+        if response.status != "1" {
+            log_line(format!(
+                "Etherscan lookup failed for {}: {}",
+                address, response.result
+            ));
+            continue;
+        }
+
+        let source_code = serde_json::from_value::<Vec<SourceCode>>(response.result)?
+            .into_iter()
+            .next();
+        let info = match source_code {
+            Some(source_code) if !source_code.source_code.is_empty() => ContractInfo {
+                contract_name: Some(source_code.contract_name),
+                verified: true,
+            },
+            _ => ContractInfo {
+                contract_name: None,
+                verified: false,
+            },
+        };
+        infos.insert(*address, info);
+    }
+
+    Ok(infos)
+}
@@ -0,0 +1,133 @@
+use std::path::Path;
+
+use crate::rpc::{JsonRpcError, RateLimited};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureCategory {
+    // The block deadline or the request timeout ran out
+    Timeout,
+    RateLimited,
+    // The node answered with a JSON-RPC error
+    NodeError,
+    Connection,
+    MalformedResponse,
+    Other,
+}
+
+// A block that could not be traced, written to failed_blocks.json
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct FailedBlock {
+    pub block_number: u64,
+    pub category: FailureCategory,
+    pub error: String,
+}
+
+impl FailedBlock {
+    pub fn new(block_number: u64, err: &anyhow::Error) -> Self {
+        Self {
+            block_number,
+            category: categorize(err),
+            error: format!("{:#}", err),
+        }
+    }
+}
+
+// Share of the scanned blocks that made it into the results, written to completeness.json
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Completeness {
+    pub blocks: u64,
+    pub failed_blocks: u64,
+    pub percent: f64,
+}
+
+impl Completeness {
+    pub fn new(blocks: u64, failed_blocks: u64) -> Self {
+        let percent = if blocks == 0 {
+            100.0
+        } else {
+            (blocks - failed_blocks) as f64 * 100.0 / blocks as f64
+        };
+        Self {
+            blocks,
+            failed_blocks,
+            percent,
+        }
+    }
+}
+
+// The first error in the chain that is recognized decides the category
+pub fn categorize(err: &anyhow::Error) -> FailureCategory {
+    for cause in err.chain() {
+        if cause.is::<tokio::time::error::Elapsed>() {
+            return FailureCategory::Timeout;
+        }
+        if cause.is::<RateLimited>() {
+            return FailureCategory::RateLimited;
+        }
+        if cause.is::<JsonRpcError>() {
+            return FailureCategory::NodeError;
+        }
+        if cause.is::<serde_json::Error>() {
+            return FailureCategory::MalformedResponse;
+        }
+        if let Some(err) = cause.downcast_ref::<reqwest::Error>() {
+            if err.is_timeout() {
+                return FailureCategory::Timeout;
+            }
+            if err.is_connect() || err.is_request() {
+                return FailureCategory::Connection;
+            }
+            if err.is_decode() || err.is_body() {
+                return FailureCategory::MalformedResponse;
+            }
+        }
+    }
+    FailureCategory::Other
+}
+
+// Blocks a finished scan could not trace, none when it left no failed_blocks.json in its output
+// directory. Best-effort scans succeed with failed blocks, so callers moving on from a scan check
+// these first
+pub fn read_failed_blocks(output_dir: &Path) -> anyhow::Result<Vec<FailedBlock>> {
+    let path = output_dir.join("failed_blocks.json");
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let mut failed_blocks = serde_json::from_slice::<Vec<FailedBlock>>(&std::fs::read(&path)?)
+        .map_err(|err| anyhow::anyhow!("Reading {} failed: {}", path.display(), err))?;
+    failed_blocks.sort_by_key(|failed_block| failed_block.block_number);
+    Ok(failed_blocks)
+}
+
+// Accepts a failed_blocks.json of an earlier run, a JSON array of block numbers or one block
+// number per line, and returns the blocks sorted and without duplicates
+pub fn load_blocks_file(path: &Path) -> anyhow::Result<Vec<u64>> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|err| anyhow::anyhow!("Reading {} failed: {}", path.display(), err))?;
+    let mut blocks = if contents.trim_start().starts_with('[') {
+        match serde_json::from_str::<Vec<FailedBlock>>(&contents) {
+            Ok(failed_blocks) => failed_blocks
+                .into_iter()
+                .map(|failed_block| failed_block.block_number)
+                .collect(),
+            Err(_) => serde_json::from_str::<Vec<u64>>(&contents)?,
+        }
+    } else {
+        contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                line.parse::<u64>()
+                    .map_err(|_| anyhow::anyhow!("{} is not a block number", line))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?
+    };
+    blocks.sort_unstable();
+    blocks.dedup();
+    if blocks.is_empty() {
+        anyhow::bail!("{} lists no blocks", path.display());
+    }
+    Ok(blocks)
+}
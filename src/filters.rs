@@ -0,0 +1,109 @@
+use std::collections::HashSet;
+use std::path::Path;
+use std::str::FromStr;
+
+use alloy_primitives::{Address, U256};
+use reth_rpc_types::trace::parity::Action;
+
+use crate::{TraceResponse, TraceType};
+
+// Applied while a block's traces are parsed, so records that are filtered out are never collected
+#[derive(Debug, Clone)]
+pub struct TraceFilter {
+    // Action types records are extracted for, creates and selfdestructs by default
+    pub trace_types: HashSet<TraceType>,
+    // Keep only records attributable to one of these, empty keeps everything
+    pub deployers: HashSet<Address>,
+    // Drop records involving any of these
+    pub excluded: HashSet<Address>,
+    // Drop selfdestructs and calls moving less wei than this, creates are always kept
+    pub min_value: U256,
+    // Drop records nested deeper in their transaction's call tree, 0 keeps top-level ones only
+    pub max_depth: Option<usize>,
+}
+
+impl TraceFilter {
+    pub fn extracts(&self, action: &Action) -> bool {
+        let trace_type = match action {
+            Action::Call(_) => TraceType::Call,
+            Action::Create(_) => TraceType::Create,
+            Action::Selfdestruct(_) => TraceType::SelfDestruct,
+            Action::Reward(_) => TraceType::Reward,
+        };
+        self.trace_types.contains(&trace_type)
+    }
+
+    // Whether the accounts that started each frame have to be remembered while parsing
+    pub fn tracks_senders(&self) -> bool {
+        !self.deployers.is_empty() || !self.excluded.is_empty()
+    }
+
+    // `senders` are the accounts that started the record's frame and every frame above it, so a
+    // create or selfdestruct is attributed to the EOA that sent the transaction and to every
+    // factory on the way down
+    pub fn keeps(&self, trace_response: &TraceResponse, senders: &[Address]) -> bool {
+        let attributed = self.deployers.is_empty()
+            || senders.iter().any(|sender| self.deployers.contains(sender));
+        let involved = [
+            Some(trace_response.contract_address),
+            trace_response.deployer,
+            trace_response.beneficiary,
+        ];
+        let excluded = involved
+            .iter()
+            .flatten()
+            .chain(senders)
+            .any(|address| self.excluded.contains(address));
+        let dust = matches!(
+            trace_response.trace_type,
+            TraceType::SelfDestruct | TraceType::Call
+        ) && trace_response.value < self.min_value;
+        let too_deep = self
+            .max_depth
+            .is_some_and(|max_depth| trace_response.depth > max_depth);
+        attributed && !excluded && !dust && !too_deep
+    }
+}
+
+// Amount like `0.1eth`, `20gwei` or `1000` (wei) in wei
+pub fn parse_value(s: &str) -> Result<U256, String> {
+    let (amount, unit) = s.split_at(
+        s.find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(s.len()),
+    );
+    let decimals = match unit.to_lowercase().as_str() {
+        "" | "wei" => 0,
+        "gwei" => 9,
+        "eth" | "ether" => 18,
+        _ => return Err(format!("Unknown unit {}, use eth, gwei or wei", unit)),
+    };
+    let (integer, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+    if integer.is_empty() && fraction.is_empty() {
+        return Err(format!("Value {} does not start with a number", s));
+    }
+    if fraction.len() > decimals || fraction.contains('.') {
+        return Err(format!("Value {} is more precise than a wei", s));
+    }
+    let digits = format!(
+        "{}{}{}",
+        integer,
+        fraction,
+        "0".repeat(decimals - fraction.len())
+    );
+    U256::from_str_radix(&digits, 10).map_err(|err| format!("Value {} is invalid: {}", s, err))
+}
+
+// One address per line, blank lines and `#` comments are skipped
+pub fn load_addresses(path: &Path) -> anyhow::Result<HashSet<Address>> {
+    let content = std::fs::read_to_string(path)?;
+    content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            Address::from_str(line).map_err(|err| {
+                anyhow::anyhow!("{} is no address in {}: {}", line, path.display(), err)
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,183 @@
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use arrow::ipc::writer::IpcWriteOptions;
+use arrow_flight::encode::FlightDataEncoderBuilder;
+use arrow_flight::error::FlightError;
+use arrow_flight::flight_service_server::{FlightService, FlightServiceServer};
+use arrow_flight::{
+    Action, ActionType, Criteria, Empty, FlightData, FlightDescriptor, FlightEndpoint, FlightInfo,
+    HandshakeRequest, HandshakeResponse, PollInfo, PutResult, SchemaAsIpc, SchemaResult, Ticket,
+};
+use futures::stream;
+use futures::{Stream, StreamExt, TryStreamExt};
+use tonic::{Request, Response, Status, Streaming};
+
+use crate::arrow_records::{record_batch, schema};
+use crate::index::ResultIndex;
+use crate::progress::log_line;
+
+// Rows per record batch
+const BATCH_ROWS: usize = 8192;
+
+// Tickets and descriptor commands are this as JSON, both ends inclusive
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct BlockRange {
+    start_block: u64,
+    end_block: u64,
+}
+
+impl BlockRange {
+    fn parse(bytes: &[u8]) -> Result<Self, Status> {
+        let range = serde_json::from_slice::<BlockRange>(bytes).map_err(|err| {
+            Status::invalid_argument(format!(
+                "Expected {{\"start_block\": n, \"end_block\": m}}: {}",
+                err
+            ))
+        })?;
+        if range.start_block > range.end_block {
+            return Err(Status::invalid_argument(format!(
+                "start_block {} is after end_block {}",
+                range.start_block, range.end_block
+            )));
+        }
+        Ok(range)
+    }
+}
+
+struct FlightServer {
+    index: Arc<ResultIndex>,
+}
+
+// Serves the records of a block range as Arrow record batches: `DoGet` with a block range
+// ticket, `GetFlightInfo` and `GetSchema` with a block range command
+pub async fn serve(listen: SocketAddr, index: Arc<ResultIndex>) -> anyhow::Result<()> {
+    log_line(format!("Serving Arrow Flight on {}", listen));
+    tonic::transport::Server::builder()
+        .add_service(FlightServiceServer::new(FlightServer { index }))
+        .serve(listen)
+        .await?;
+    Ok(())
+}
+
+type FlightStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send + 'static>>;
+
+fn unsupported<T>(method: &str) -> Result<T, Status> {
+    Err(Status::unimplemented(format!(
+        "{} is not supported, use DoGet with a block range ticket",
+        method
+    )))
+}
+
+#[tonic::async_trait]
+impl FlightService for FlightServer {
+    type HandshakeStream = FlightStream<HandshakeResponse>;
+    type ListFlightsStream = FlightStream<FlightInfo>;
+    type DoGetStream = FlightStream<FlightData>;
+    type DoPutStream = FlightStream<PutResult>;
+    type DoActionStream = FlightStream<arrow_flight::Result>;
+    type ListActionsStream = FlightStream<ActionType>;
+    type DoExchangeStream = FlightStream<FlightData>;
+
+    async fn do_get(
+        &self,
+        request: Request<Ticket>,
+    ) -> Result<Response<Self::DoGetStream>, Status> {
+        let range = BlockRange::parse(&request.into_inner().ticket)?;
+        let schema = schema();
+        let positions = self
+            .index
+            .range_positions(range.start_block, range.end_block);
+        // Batches are built as the client reads them, a wide range is never held in Arrow at once
+        let index = self.index.clone();
+        let batch_schema = schema.clone();
+        let batches = (0..positions.len()).step_by(BATCH_ROWS).map(move |offset| {
+            let chunk = &positions[offset..(offset + BATCH_ROWS).min(positions.len())];
+            record_batch(batch_schema.clone(), &index.records(chunk)).map_err(FlightError::Arrow)
+        });
+        let stream = FlightDataEncoderBuilder::new()
+            .with_schema(schema)
+            .build(stream::iter(batches))
+            .map_err(Status::from)
+            .boxed();
+        Ok(Response::new(stream))
+    }
+
+    async fn get_flight_info(
+        &self,
+        request: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        let descriptor = request.into_inner();
+        let range = BlockRange::parse(&descriptor.cmd)?;
+        let records = self.index.range(range.start_block, range.end_block).len();
+        let info = FlightInfo::new()
+            .try_with_schema(&schema())
+            .map_err(|err| Status::internal(err.to_string()))?
+            .with_endpoint(FlightEndpoint::new().with_ticket(Ticket::new(descriptor.cmd.clone())))
+            .with_descriptor(descriptor)
+            .with_total_records(records as i64);
+        Ok(Response::new(info))
+    }
+
+    async fn get_schema(
+        &self,
+        request: Request<FlightDescriptor>,
+    ) -> Result<Response<SchemaResult>, Status> {
+        BlockRange::parse(&request.into_inner().cmd)?;
+        let schema = schema();
+        let schema_result = SchemaAsIpc::new(&schema, &IpcWriteOptions::default())
+            .try_into()
+            .map_err(|err: arrow::error::ArrowError| Status::internal(err.to_string()))?;
+        Ok(Response::new(schema_result))
+    }
+
+    async fn handshake(
+        &self,
+        _request: Request<Streaming<HandshakeRequest>>,
+    ) -> Result<Response<Self::HandshakeStream>, Status> {
+        unsupported("Handshake")
+    }
+
+    async fn list_flights(
+        &self,
+        _request: Request<Criteria>,
+    ) -> Result<Response<Self::ListFlightsStream>, Status> {
+        unsupported("ListFlights")
+    }
+
+    async fn poll_flight_info(
+        &self,
+        _request: Request<FlightDescriptor>,
+    ) -> Result<Response<PollInfo>, Status> {
+        unsupported("PollFlightInfo")
+    }
+
+    async fn do_put(
+        &self,
+        _request: Request<Streaming<FlightData>>,
+    ) -> Result<Response<Self::DoPutStream>, Status> {
+        unsupported("DoPut")
+    }
+
+    async fn do_action(
+        &self,
+        _request: Request<Action>,
+    ) -> Result<Response<Self::DoActionStream>, Status> {
+        unsupported("DoAction")
+    }
+
+    async fn list_actions(
+        &self,
+        _request: Request<Empty>,
+    ) -> Result<Response<Self::ListActionsStream>, Status> {
+        unsupported("ListActions")
+    }
+
+    async fn do_exchange(
+        &self,
+        _request: Request<Streaming<FlightData>>,
+    ) -> Result<Response<Self::DoExchangeStream>, Status> {
+        unsupported("DoExchange")
+    }
+}
@@ -0,0 +1,625 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use alloy_primitives::{Address, TxHash, B256, U64};
+use serde_json::json;
+
+use crate::compliance::SanctionsList;
+use crate::delta::DeltaSink;
+use crate::filters::TraceFilter;
+use crate::l2::L2Adapter;
+use crate::labels::Labels;
+use crate::plugins::Plugins;
+use crate::progress::{log_line, progress};
+use crate::receipts;
+use crate::rpc::RpcClient;
+use crate::rules::{self, Rule};
+use crate::scripting::Hooks;
+use crate::selectors::SelectorDb;
+use crate::sink::{SharedSink, Sink};
+use crate::webhook::{Finding, FindingKind};
+use crate::{annotate, trace_block, trace_block_at, webhook, TraceType};
+
+// Processed blocks remembered for reorg detection, a deeper reorg stops following with an error
+const MAX_REORG_DEPTH: usize = 128;
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct FollowArgs {
+    /// Blocks a block has to be buried under before it is traced
+    #[arg(long, default_value_t = 12)]
+    pub confirmations: u64,
+    /// Seconds between polls for a new head, defaults to the block time of the `--chain` profile
+    /// or else 12
+    #[arg(long)]
+    pub poll_interval: Option<u64>,
+    /// First block to trace, defaults to the block after the checkpoint or else the current
+    /// confirmed head
+    #[arg(long)]
+    pub from_block: Option<u64>,
+    /// File trace records are appended to as newline delimited JSON, shared by all chains when
+    /// following several
+    #[arg(long, default_value = "trace_records.ndjson")]
+    pub output: PathBuf,
+    /// Also trace the node's pending block on every poll and send watched selfdestructs and
+    /// recreations in it as pending findings, before the block is mined
+    #[arg(long)]
+    pub pending: bool,
+}
+
+pub struct FollowContext {
+    pub client: RpcClient,
+    pub chain_id: u64,
+    pub labels: Labels,
+    pub sanctions_list: SanctionsList,
+    pub rules: Vec<Rule>,
+    pub plugins: Arc<Plugins>,
+    pub hooks: Option<Arc<Hooks>>,
+    pub webhooks: Vec<String>,
+    pub watchlist: Arc<Vec<Address>>,
+    pub selector_db: SelectorDb,
+    pub adapter: Arc<dyn L2Adapter>,
+    pub filter: Arc<TraceFilter>,
+    // Each followed block is one commit to it
+    pub delta_sink: Option<DeltaSink>,
+    pub forced_ether: bool,
+    // Next block to follow, rewritten after every block
+    pub checkpoint: PathBuf,
+}
+
+// Besides the next block, what a restarted follower needs to detect recreations of addresses
+// destroyed and reorgs of blocks followed before the restart
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct FollowCheckpoint {
+    chain_id: u64,
+    next_block: u64,
+    #[serde(default)]
+    destroyed: HashSet<Address>,
+    #[serde(default)]
+    processed: VecDeque<ProcessedBlock>,
+    // Size of the output after the records of the blocks before `next_block`
+    #[serde(default)]
+    sink_bytes: Option<u64>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BlockHeader {
+    hash: B256,
+    parent_hash: B256,
+    timestamp: U64,
+}
+
+// Change a block made to the destroyed addresses, in the order of its records
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum DestroyedChange {
+    Destroyed(Address),
+    Recreated(Address),
+}
+
+// What following a block changed, so it can be undone when the block is orphaned
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct ProcessedBlock {
+    number: u64,
+    hash: B256,
+    changes: Vec<DestroyedChange>,
+}
+
+impl ProcessedBlock {
+    fn recreated(&self) -> Vec<Address> {
+        self.changes
+            .iter()
+            .filter_map(|change| match change {
+                DestroyedChange::Recreated(address) => Some(*address),
+                DestroyedChange::Destroyed(_) => None,
+            })
+            .collect()
+    }
+
+    // Undone newest first, a block can destroy and recreate the same address
+    fn undo(self, destroyed: &mut HashSet<Address>) {
+        for change in self.changes.into_iter().rev() {
+            match change {
+                DestroyedChange::Destroyed(address) => destroyed.remove(&address),
+                DestroyedChange::Recreated(address) => destroyed.insert(address),
+            };
+        }
+    }
+}
+
+async fn block_header(client: &RpcClient, block_num: u64) -> anyhow::Result<BlockHeader> {
+    let block_num_hex = format!("0x{:x}", block_num);
+    client
+        .request::<BlockHeader>("eth_getBlockByNumber", json!([block_num_hex, false]))
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Node does not know block {}", block_num))
+}
+
+pub async fn head_block(client: &RpcClient) -> anyhow::Result<u64> {
+    Ok(client
+        .request::<U64>("eth_blockNumber", json!([]))
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Node did not answer eth_blockNumber"))?
+        .to::<u64>())
+}
+
+// Polls for new heads and never returns unless tracing fails
+pub async fn follow(args: FollowArgs, mut context: FollowContext) -> anyhow::Result<()> {
+    let mut sink = SharedSink::open(&args.output)?;
+    let checkpoint = match std::fs::read(&context.checkpoint) {
+        Ok(checkpoint) => Some(serde_json::from_slice::<FollowCheckpoint>(&checkpoint)?),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+        Err(err) => return Err(err.into()),
+    };
+    if let Some(checkpoint) = &checkpoint {
+        if checkpoint.chain_id != context.chain_id {
+            anyhow::bail!(
+                "{} is a checkpoint of chain {}, not of chain {}",
+                context.checkpoint.display(),
+                checkpoint.chain_id,
+                context.chain_id
+            );
+        }
+    }
+    // Contracts destroyed since following started, a later create at one of them is a recreation
+    let mut destroyed: HashSet<Address> = HashSet::new();
+    let mut processed: VecDeque<ProcessedBlock> = VecDeque::new();
+    // Blocks up to this one were written to the output before a crash kept them out of the
+    // checkpoint, they are traced again but not written twice
+    let mut written_through = None;
+    let mut next_block = match (args.from_block, checkpoint) {
+        // The remembered blocks don't lead up to another block, the destroyed addresses still hold
+        (Some(from_block), Some(checkpoint)) => {
+            destroyed = checkpoint.destroyed;
+            from_block
+        }
+        (Some(from_block), None) => from_block,
+        (None, Some(checkpoint)) => {
+            destroyed = checkpoint.destroyed;
+            processed = checkpoint.processed;
+            written_through = crate::sink::last_written_block(
+                &args.output,
+                context.chain_id,
+                checkpoint.sink_bytes.unwrap_or_default(),
+            )?;
+            checkpoint.next_block
+        }
+        (None, None) => head_block(&context.client)
+            .await?
+            .saturating_sub(args.confirmations),
+    };
+    // Pending findings already sent, with the pending block they were seen in
+    let mut announced: HashMap<(Option<TxHash>, Address, FindingKind), u64> = HashMap::new();
+    let poll_interval = Duration::from_secs(args.poll_interval.unwrap_or(12));
+    let mut interval = tokio::time::interval(poll_interval);
+    log_line(format!(
+        "Following chain {} from block {} with {} confirmations",
+        context.chain_id, next_block, args.confirmations
+    ));
+
+    loop {
+        interval.tick().await;
+        let head = head_block(&context.client).await?;
+        let confirmed_head = head.saturating_sub(args.confirmations);
+
+        while next_block <= confirmed_head {
+            let header = block_header(&context.client, next_block).await?;
+            if let Some(parent) = processed.back() {
+                if parent.number + 1 == next_block && parent.hash != header.parent_hash {
+                    next_block = rollback(
+                        &context.client,
+                        context.chain_id,
+                        &mut processed,
+                        &mut destroyed,
+                        &mut sink,
+                        context.delta_sink.as_mut(),
+                    )
+                    .await?;
+                    // The retracted blocks have to be written again
+                    written_through =
+                        written_through.filter(|written_through| *written_through < next_block);
+                    continue;
+                }
+            }
+
+            let mut trace_responses = trace_block(
+                context.client.clone(),
+                next_block,
+                context.chain_id,
+                context.adapter.clone(),
+                context.filter.clone(),
+                false,
+            )
+            .await?
+            .unwrap_or_default();
+            // The block may have been replaced while it was traced, trace it again in that case
+            if block_header(&context.client, next_block).await?.hash != header.hash {
+                continue;
+            }
+            trace_responses.sort_by(|a, b| {
+                (a.transaction_position, &a.trace_address)
+                    .cmp(&(b.transaction_position, &b.trace_address))
+            });
+            for trace_response in trace_responses.iter_mut() {
+                trace_response.block_timestamp = Some(header.timestamp.to::<u64>());
+            }
+            receipts::apply_rpc_receipts(&context.client, trace_responses.iter_mut()).await?;
+
+            let mut processed_block = ProcessedBlock {
+                number: next_block,
+                hash: header.hash,
+                changes: Vec::new(),
+            };
+            for trace_response in trace_responses.iter_mut() {
+                if let Some(selector) = trace_response.selector {
+                    trace_response.function = context.selector_db.resolve(selector).await;
+                }
+                annotate(
+                    trace_response,
+                    &context.labels,
+                    &context.sanctions_list,
+                    context.adapter.as_ref(),
+                );
+
+                match trace_response.trace_type {
+                    TraceType::SelfDestruct => {
+                        if destroyed.insert(trace_response.contract_address) {
+                            processed_block
+                                .changes
+                                .push(DestroyedChange::Destroyed(trace_response.contract_address));
+                        }
+                    }
+                    TraceType::Create => {
+                        if destroyed.remove(&trace_response.contract_address) {
+                            log_line(format!(
+                                "Address {} has been recreated",
+                                trace_response.contract_address
+                            ));
+                            progress().finding(format!(
+                                "{} recreated in block {}",
+                                trace_response.contract_address, next_block
+                            ));
+                            processed_block
+                                .changes
+                                .push(DestroyedChange::Recreated(trace_response.contract_address));
+                        }
+                    }
+                    TraceType::Call | TraceType::Reward => {}
+                }
+            }
+            context.selector_db.flush()?;
+
+            if let Some(hooks) = &context.hooks {
+                hooks.apply(trace_responses.iter_mut())?;
+            }
+            let plugin_findings = context.plugins.apply(&mut trace_responses)?;
+            if written_through.is_some_and(|written_through| next_block <= written_through) {
+                log_line(format!(
+                    "Block {} is already in {}, not writing it again",
+                    next_block,
+                    args.output.display()
+                ));
+            } else {
+                sink.write_records(&trace_responses)?;
+            }
+            if let Some(delta_sink) = &mut context.delta_sink {
+                let records: Vec<_> = trace_responses.iter().collect();
+                delta_sink
+                    .write_block(context.chain_id, next_block, &records)
+                    .await?;
+            }
+
+            let self_destructed: Vec<_> = trace_responses
+                .iter()
+                .filter(|trace_response| trace_response.trace_type == TraceType::SelfDestruct)
+                .cloned()
+                .collect();
+            let created: Vec<_> = trace_responses
+                .iter()
+                .filter(|trace_response| trace_response.trace_type == TraceType::Create)
+                .cloned()
+                .collect();
+            let mut findings = webhook::findings(
+                &processed_block.recreated(),
+                &created,
+                &self_destructed,
+                &context.watchlist,
+            );
+            if context.forced_ether {
+                findings.extend(
+                    crate::forced_ether::findings(&context.client, self_destructed.iter()).await,
+                );
+            }
+            findings.extend(rules::evaluate(&context.rules, trace_responses.iter()));
+            findings.extend(plugin_findings);
+            if !findings.is_empty() {
+                webhook::notify(&context.webhooks, &findings).await;
+            }
+
+            processed.push_back(processed_block);
+            if processed.len() > MAX_REORG_DEPTH {
+                processed.pop_front();
+            }
+            next_block += 1;
+            crate::stages::write_atomically(
+                &context.checkpoint,
+                &serde_json::to_vec_pretty(&FollowCheckpoint {
+                    chain_id: context.chain_id,
+                    next_block,
+                    destroyed: destroyed.clone(),
+                    processed: processed.clone(),
+                    sink_bytes: Some(sink.written_bytes()?),
+                })?,
+            )?;
+        }
+
+        if args.pending {
+            // Once its block is confirmed and traced, a finding is sent as a mined one anyway
+            announced.retain(|_, pending_block| *pending_block >= next_block);
+            if let Err(err) = follow_pending(&context, head + 1, &destroyed, &mut announced).await {
+                log_line(format!("Tracing the pending block failed: {}", err));
+            }
+        }
+    }
+}
+
+// The pending block is the node's guess at the next block, its records are not written to the
+// output and nothing is remembered but the findings sent, so a transaction that is dropped or
+// mined elsewhere leaves no trace
+async fn follow_pending(
+    context: &FollowContext,
+    pending_block: u64,
+    destroyed: &HashSet<Address>,
+    announced: &mut HashMap<(Option<TxHash>, Address, FindingKind), u64>,
+) -> anyhow::Result<()> {
+    let trace_responses = trace_block_at(
+        context.client.clone(),
+        "pending".to_string(),
+        pending_block,
+        context.chain_id,
+        context.adapter.clone(),
+        context.filter.clone(),
+        false,
+    )
+    .await?
+    .unwrap_or_default();
+
+    let mut findings = Vec::new();
+    for trace_response in trace_responses.iter() {
+        let kind = match trace_response.trace_type {
+            TraceType::SelfDestruct
+                if context.watchlist.contains(&trace_response.contract_address) =>
+            {
+                FindingKind::PendingSelfdestruct
+            }
+            TraceType::Create if destroyed.contains(&trace_response.contract_address) => {
+                FindingKind::PendingRecreation
+            }
+            _ => continue,
+        };
+        let key = (
+            trace_response.transaction_hash,
+            trace_response.contract_address,
+            kind.clone(),
+        );
+        if announced.insert(key, pending_block).is_some() {
+            continue;
+        }
+        log_line(format!(
+            "Pending block {} would {} {}",
+            pending_block,
+            if kind == FindingKind::PendingRecreation {
+                "recreate"
+            } else {
+                "selfdestruct"
+            },
+            trace_response.contract_address
+        ));
+        findings.push(Finding {
+            kind,
+            rule: None,
+            address: trace_response.contract_address,
+            block_number: Some(pending_block),
+            transaction_hash: trace_response.transaction_hash,
+        });
+    }
+    if !findings.is_empty() {
+        webhook::notify(&context.webhooks, &findings).await;
+    }
+    Ok(())
+}
+
+// Retracts processed blocks that are no longer canonical, newest first, and returns the first
+// block number that has to be traced again. A reorg past every remembered block is an error, the
+// records before them can't be checked anymore
+async fn rollback(
+    client: &RpcClient,
+    chain_id: u64,
+    processed: &mut VecDeque<ProcessedBlock>,
+    destroyed: &mut HashSet<Address>,
+    sink: &mut impl Sink,
+    mut delta_sink: Option<&mut DeltaSink>,
+) -> anyhow::Result<u64> {
+    // Found before anything is retracted, so a too deep reorg leaves the output as it was
+    let mut orphaned = 0;
+    for processed_block in processed.iter().rev() {
+        if block_header(client, processed_block.number).await?.hash == processed_block.hash {
+            break;
+        }
+        orphaned += 1;
+    }
+    if orphaned == processed.len() {
+        anyhow::bail!(
+            "Reorg of chain {} reaches past the last {} followed blocks, the records from block {} on may be stale, follow again with --from-block",
+            chain_id,
+            processed.len(),
+            processed.front().map_or(0, |processed_block| processed_block.number)
+        );
+    }
+
+    for processed_block in processed.drain(processed.len() - orphaned..).rev() {
+        log_line(format!(
+            "Block {} ({}) was orphaned by a reorg, retracting its records",
+            processed_block.number, processed_block.hash
+        ));
+        metrics::counter!("block_tracer_reorged_blocks_total").increment(1);
+        sink.retract_block(chain_id, processed_block.number, processed_block.hash)?;
+        if let Some(delta_sink) = delta_sink.as_mut() {
+            delta_sink
+                .retract_block(chain_id, processed_block.number)
+                .await?;
+        }
+        processed_block.undo(destroyed);
+    }
+    Ok(processed
+        .back()
+        .map_or(0, |processed_block| processed_block.number + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future::BoxFuture;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::rpc::RpcTransport;
+    use crate::TraceResponse;
+
+    // Answers eth_getBlockByNumber with the canonical hash of each block
+    #[derive(Debug)]
+    struct CanonicalChain(HashMap<u64, B256>);
+
+    impl RpcTransport for CanonicalChain {
+        fn send<'a>(
+            &'a self,
+            method: &'a str,
+            params: &'a Value,
+        ) -> BoxFuture<'a, anyhow::Result<String>> {
+            Box::pin(async move {
+                anyhow::ensure!(method == "eth_getBlockByNumber", "unexpected {}", method);
+                let block_num = params[0]
+                    .as_str()
+                    .unwrap_or_default()
+                    .trim_start_matches("0x");
+                let block_num = u64::from_str_radix(block_num, 16)?;
+                let result = self.0.get(&block_num).map(
+                    |hash| json!({ "hash": hash, "parentHash": B256::ZERO, "timestamp": "0x0" }),
+                );
+                Ok(json!({ "jsonrpc": "2.0", "id": 1, "result": result }).to_string())
+            })
+        }
+    }
+
+    #[derive(Default)]
+    struct RetractedBlocks(Vec<(u64, B256)>);
+
+    impl Sink for RetractedBlocks {
+        fn write_records(&mut self, _trace_responses: &[TraceResponse]) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        fn retract_block(
+            &mut self,
+            _chain_id: u64,
+            block_number: u64,
+            block_hash: B256,
+        ) -> anyhow::Result<()> {
+            self.0.push((block_number, block_hash));
+            Ok(())
+        }
+    }
+
+    fn client(canonical: &[(u64, B256)]) -> RpcClient {
+        RpcClient::new(Arc::new(CanonicalChain(
+            canonical.iter().copied().collect(),
+        )))
+    }
+
+    fn processed_block(number: u64, changes: Vec<DestroyedChange>) -> ProcessedBlock {
+        ProcessedBlock {
+            number,
+            hash: B256::with_last_byte(number as u8),
+            changes,
+        }
+    }
+
+    #[tokio::test]
+    async fn orphaned_blocks_are_retracted_newest_first_and_their_changes_undone() {
+        let destroyed_before = Address::repeat_byte(1);
+        let destroyed_orphaned = Address::repeat_byte(2);
+        let mut processed = VecDeque::from([
+            processed_block(10, vec![DestroyedChange::Destroyed(destroyed_before)]),
+            processed_block(11, vec![DestroyedChange::Destroyed(destroyed_orphaned)]),
+            processed_block(12, vec![DestroyedChange::Recreated(destroyed_before)]),
+        ]);
+        let mut destroyed = HashSet::from([destroyed_orphaned]);
+        let mut sink = RetractedBlocks::default();
+        let client = client(&[
+            (10, B256::with_last_byte(10)),
+            (11, B256::repeat_byte(0xaa)),
+            (12, B256::repeat_byte(0xbb)),
+        ]);
+
+        let next_block = rollback(&client, 1, &mut processed, &mut destroyed, &mut sink, None)
+            .await
+            .unwrap();
+
+        assert_eq!(next_block, 11);
+        assert_eq!(
+            sink.0,
+            vec![
+                (12, B256::with_last_byte(12)),
+                (11, B256::with_last_byte(11))
+            ]
+        );
+        assert_eq!(processed.len(), 1);
+        assert_eq!(destroyed, HashSet::from([destroyed_before]));
+    }
+
+    #[tokio::test]
+    async fn a_reorg_past_every_followed_block_retracts_nothing() {
+        let mut processed =
+            VecDeque::from([processed_block(10, vec![]), processed_block(11, vec![])]);
+        let mut destroyed = HashSet::new();
+        let mut sink = RetractedBlocks::default();
+        let client = client(&[(10, B256::repeat_byte(0xaa)), (11, B256::repeat_byte(0xbb))]);
+
+        let err = rollback(&client, 1, &mut processed, &mut destroyed, &mut sink, None)
+            .await
+            .unwrap_err();
+
+        assert!(err
+            .to_string()
+            .contains("reaches past the last 2 followed blocks"));
+        assert!(sink.0.is_empty());
+        assert_eq!(processed.len(), 2);
+    }
+
+    #[test]
+    fn undoing_a_block_that_destroyed_and_recreated_an_address_restores_it() {
+        let address = Address::repeat_byte(1);
+        let mut destroyed = HashSet::from([address]);
+        processed_block(
+            10,
+            vec![
+                DestroyedChange::Recreated(address),
+                DestroyedChange::Destroyed(address),
+            ],
+        )
+        .undo(&mut destroyed);
+        assert_eq!(destroyed, HashSet::from([address]));
+
+        let mut destroyed = HashSet::new();
+        processed_block(
+            10,
+            vec![
+                DestroyedChange::Destroyed(address),
+                DestroyedChange::Recreated(address),
+            ],
+        )
+        .undo(&mut destroyed);
+        assert!(destroyed.is_empty());
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::{BTreeSet, HashMap};
+
+use alloy_primitives::{hex, Address, Bytes, U256};
+use serde_json::json;
+
+use crate::creators::DELEGATION_PREFIX;
+use crate::progress::log_line;
+use crate::rpc::RpcClient;
+use crate::webhook::{Finding, FindingKind};
+use crate::TraceResponse;
+
+const JUMPI: u8 = 0x57;
+const PUSH1: u8 = 0x60;
+const PUSH32: u8 = 0x7f;
+// PUSH1 0x80 PUSH1 0x40 MSTORE, the free memory pointer setup solc starts runtime code with
+const FREE_MEMORY_POINTER: [u8; 5] = hex!("6080604052");
+// CALLVALUE DUP1 ISZERO, the check solc hoists in front of the dispatcher when nothing is payable
+const NONPAYABLE_CHECK: [u8; 3] = hex!("348015");
+// PUSH1 4 CALLDATASIZE LT, the dispatcher's jump for calldata without a selector
+const NO_SELECTOR: [u8; 4] = hex!("60043610");
+// Where that jump lands in contracts without receive or fallback: JUMPDEST, then a revert with
+// PUSH1 0 or PUSH0
+const EMPTY_FALLBACKS: [&[u8]; 3] = [&hex!("5b600080fd"), &hex!("5b5f80fd"), &hex!("5b5f5ffd")];
+
+// Whether the code can be sent ether by a plain call. Reads the dispatcher solc generates, code
+// it does not recognize (other compilers, hand-written code) is assumed to accept ether, so
+// findings are only raised for contracts that clearly reject it
+pub fn accepts_plain_ether(code: &[u8]) -> bool {
+    if code.is_empty() || code.starts_with(&DELEGATION_PREFIX) {
+        return true;
+    }
+    if code
+        .strip_prefix(&FREE_MEMORY_POINTER)
+        .is_some_and(|rest| rest.starts_with(&NONPAYABLE_CHECK))
+    {
+        return false;
+    }
+
+    let mut pc = 0;
+    while pc < code.len() {
+        let opcode = code[pc];
+        if code[pc..].starts_with(&NO_SELECTOR) {
+            return match fallback_target(code, pc + NO_SELECTOR.len()) {
+                Some(target) => !EMPTY_FALLBACKS
+                    .iter()
+                    .any(|empty_fallback| code[target..].starts_with(empty_fallback)),
+                None => true,
+            };
+        }
+        pc += 1 + push_size(opcode);
+    }
+    true
+}
+
+fn push_size(opcode: u8) -> usize {
+    match opcode {
+        PUSH1..=PUSH32 => (opcode - PUSH1) as usize + 1,
+        _ => 0,
+    }
+}
+
+// Jump target of the `PUSHn target JUMPI` at `pc`
+fn fallback_target(code: &[u8], pc: usize) -> Option<usize> {
+    let size = push_size(*code.get(pc)?);
+    if size == 0 || size > 4 || code.get(pc + 1 + size) != Some(&JUMPI) {
+        return None;
+    }
+    let target = code[pc + 1..pc + 1 + size]
+        .iter()
+        .fold(0, |target, byte| target << 8 | *byte as usize);
+    (target < code.len()).then_some(target)
+}
+
+// Selfdestructs that swept ether into a contract rejecting plain transfers, which can't refuse
+// ether sent this way. Contracts keeping their own balance in a variable are the classic victim
+// of such forced sends. The beneficiaries' code is read at the selfdestruct's block in batches.
+// Code that can't be read, e.g. from a node without historical state, raises no finding
+pub async fn findings<'a>(
+    client: &RpcClient,
+    self_destructed_trace_responses: impl Iterator<Item = &'a TraceResponse>,
+) -> Vec<Finding> {
+    let candidates = self_destructed_trace_responses
+        .filter(|trace_response| {
+            trace_response.revert_reason.is_none() && trace_response.value > U256::ZERO
+        })
+        .filter_map(|trace_response| {
+            trace_response
+                .beneficiary
+                .filter(|beneficiary| *beneficiary != trace_response.contract_address)
+                .map(|beneficiary| (trace_response, beneficiary))
+        })
+        .collect::<Vec<_>>();
+
+    let lookups = candidates
+        .iter()
+        .map(|(trace_response, beneficiary)| (*beneficiary, trace_response.block_number))
+        .collect::<BTreeSet<(Address, u64)>>();
+    if lookups.is_empty() {
+        return Vec::new();
+    }
+    let codes = match client
+        .request_batch::<Bytes>(
+            "eth_getCode",
+            lookups
+                .iter()
+                .map(|(beneficiary, block_number)| {
+                    json!([beneficiary, format!("0x{:x}", block_number)])
+                })
+                .collect(),
+        )
+        .await
+    {
+        Ok(codes) => codes,
+        Err(err) => {
+            log_line(format!(
+                "Looking up the code of {} beneficiaries failed, no forced ether is reported for them: {}",
+                lookups.len(),
+                err
+            ));
+            return Vec::new();
+        }
+    };
+    let mut rejecting = HashMap::new();
+    for (lookup, code) in lookups.into_iter().zip(codes) {
+        if let Ok(Some(code)) = code {
+            rejecting.insert(lookup, !accepts_plain_ether(&code));
+        }
+    }
+
+    candidates
+        .into_iter()
+        .filter(|(trace_response, beneficiary)| {
+            rejecting
+                .get(&(*beneficiary, trace_response.block_number))
+                .copied()
+                .unwrap_or_default()
+        })
+        .map(|(trace_response, beneficiary)| Finding {
+            kind: FindingKind::ForcedEther,
+            rule: None,
+            address: beneficiary,
+            block_number: Some(trace_response.block_number),
+            transaction_hash: trace_response.transaction_hash,
+        })
+        .collect()
+}
@@ -0,0 +1,149 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::daemon::{parse_interval, safe_head};
+use crate::distributed::chunk_cmd;
+use crate::manifest::Manifest;
+use crate::progress::log_line;
+use crate::rpc::{HttpTransport, RpcClient};
+use crate::Cmd;
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct ScanArgs {
+    /// Scan from genesis to the current safe head, chunk by chunk into
+    /// `<output-dir>/chunks/<start>-<end>`
+    #[arg(long, required = true)]
+    pub full: bool,
+    /// Blocks per chunk, chunks are aligned to multiples of it
+    #[arg(long, default_value_t = 100_000)]
+    pub chunk_size: u64,
+    /// Attempts per chunk before it is skipped and listed in full_scan.json
+    #[arg(long, default_value_t = 5)]
+    pub chunk_attempts: u32,
+    /// Time to wait before a failed chunk is attempted again, e.g. 30s, 5m
+    #[arg(long, default_value = "1m", value_parser = parse_interval)]
+    pub retry_delay: Duration,
+}
+
+// `full_scan.json` in the output directory, rewritten after every chunk
+#[derive(Debug, Default, serde::Serialize)]
+struct FullScanProgress {
+    safe_head: u64,
+    chunk_size: u64,
+    chunks: u64,
+    chunks_done: u64,
+    // Chunks that were written with failed blocks, rescanned by the next `scan --full`
+    incomplete_chunks: Vec<String>,
+    // Chunks that failed every attempt
+    failed_chunks: Vec<String>,
+}
+
+// Every chunk is a plain scan resuming from its own stage checkpoints. A chunk whose manifest
+// shows a complete scan is skipped, so an interrupted full scan is continued by starting it again.
+// The last chunk ends at the safe head and is replaced by a longer one on a later run
+pub async fn run(args: ScanArgs, cmd: Cmd) -> anyhow::Result<()> {
+    let client = RpcClient::new(Arc::new(HttpTransport::new(
+        cmd.rpc_url
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("Set RPC_URL or pass --rpc-url"))?,
+        &cmd.http,
+    )?));
+    let chunk_size = args.chunk_size.max(1);
+    let safe_head = safe_head(&client).await?;
+    let chunks_dir = cmd.output_dir.join("chunks");
+    std::fs::create_dir_all(&chunks_dir)?;
+
+    let chunks = (0..=safe_head / chunk_size)
+        .map(|chunk| {
+            let chunk_start = chunk * chunk_size;
+            (chunk_start, (chunk_start + chunk_size - 1).min(safe_head))
+        })
+        .collect::<Vec<_>>();
+    let mut progress = FullScanProgress {
+        safe_head,
+        chunk_size,
+        chunks: chunks.len() as u64,
+        ..Default::default()
+    };
+    log_line(format!(
+        "Scanning blocks 0-{} in {} chunks into {}",
+        safe_head,
+        chunks.len(),
+        chunks_dir.display()
+    ));
+
+    for (chunk_start, chunk_end) in chunks {
+        let chunk_name = format!("{}-{}", chunk_start, chunk_end);
+        remove_superseded_chunks(&chunks_dir, chunk_start, &chunk_name)?;
+
+        let mut chunk_cmd = chunk_cmd(&cmd, chunk_start, chunk_end, &chunks_dir);
+        chunk_cmd.resume = true;
+        let chunk_dir = chunk_cmd.output_dir.clone();
+        let mut scanned = false;
+        for attempt in 1..=args.chunk_attempts.max(1) {
+            match Box::pin(crate::amain(chunk_cmd.clone())).await {
+                Ok(()) => {
+                    scanned = true;
+                    break;
+                }
+                Err(err) => {
+                    log_line(format!(
+                        "Scanning blocks {} failed (attempt {} of {}): {}",
+                        chunk_name, attempt, args.chunk_attempts, err
+                    ));
+                    if attempt < args.chunk_attempts {
+                        tokio::time::sleep(args.retry_delay).await;
+                    }
+                }
+            }
+        }
+
+        if !scanned {
+            progress.failed_chunks.push(chunk_name);
+        } else if Manifest::load(&chunk_dir)?
+            .is_some_and(|manifest| manifest.completeness.failed_blocks > 0)
+        {
+            progress.incomplete_chunks.push(chunk_name);
+        }
+        progress.chunks_done += 1;
+        crate::stages::write_atomically(
+            &cmd.output_dir.join("full_scan.json"),
+            &serde_json::to_vec_pretty(&progress)?,
+        )?;
+    }
+
+    log_line(format!(
+        "Scanned {} chunks, {} incomplete and {} failed, see full_scan.json. `merge --chunks-dir {}` combines them",
+        progress.chunks_done,
+        progress.incomplete_chunks.len(),
+        progress.failed_chunks.len(),
+        chunks_dir.display()
+    ));
+    Ok(())
+}
+
+// The previous last chunk started at the same block but ended at an older safe head. Only
+// directories this tool wrote a manifest or stage checkpoints to are removed
+fn remove_superseded_chunks(
+    chunks_dir: &Path,
+    chunk_start: u64,
+    chunk_name: &str,
+) -> anyhow::Result<()> {
+    for entry in std::fs::read_dir(chunks_dir)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let superseded = name != chunk_name
+            && name.split_once('-').is_some_and(|(start, end)| {
+                start.parse::<u64>().ok() == Some(chunk_start) && end.parse::<u64>().is_ok()
+            })
+            && (path.join("manifest.json").is_file() || path.join("stages.json").is_file());
+        if superseded {
+            log_line(format!("Replacing chunk {} with {}", name, chunk_name));
+            std::fs::remove_dir_all(&path)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,192 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use tokio::sync::{mpsc, Mutex};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+
+use crate::compliance::SanctionsList;
+use crate::filters::TraceFilter;
+use crate::headers;
+use crate::l2::L2Adapter;
+use crate::labels::Labels;
+use crate::progress::log_line;
+use crate::receipts;
+use crate::rpc::RpcClient;
+use crate::selectors::SelectorDb;
+use crate::{annotate, trace_block, TraceResponse};
+
+pub mod proto {
+    tonic::include_proto!("blocktracer.v1");
+}
+
+use proto::tracer_server::{Tracer, TracerServer};
+use proto::{BlockRange, TraceRecord};
+
+// Records buffered per stream, a slow consumer pauses tracing once it is full
+const STREAM_BUFFER: usize = 64;
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct GrpcArgs {
+    /// Address the gRPC server listens on
+    #[arg(long, default_value = "127.0.0.1:50051")]
+    pub listen: SocketAddr,
+}
+
+pub struct GrpcContext {
+    pub client: RpcClient,
+    pub chain_id: u64,
+    pub labels: Labels,
+    pub sanctions_list: SanctionsList,
+    pub selector_db: SelectorDb,
+    pub adapter: Arc<dyn L2Adapter>,
+    pub filter: Arc<TraceFilter>,
+}
+
+struct TracerService {
+    client: RpcClient,
+    chain_id: u64,
+    labels: Arc<Labels>,
+    sanctions_list: Arc<SanctionsList>,
+    selector_db: Arc<Mutex<SelectorDb>>,
+    adapter: Arc<dyn L2Adapter>,
+    filter: Arc<TraceFilter>,
+}
+
+pub async fn serve(args: GrpcArgs, context: GrpcContext) -> anyhow::Result<()> {
+    let service = TracerService {
+        client: context.client,
+        chain_id: context.chain_id,
+        labels: Arc::new(context.labels),
+        sanctions_list: Arc::new(context.sanctions_list),
+        selector_db: Arc::new(Mutex::new(context.selector_db)),
+        adapter: context.adapter,
+        filter: context.filter,
+    };
+    log_line(format!("Serving gRPC on {}", args.listen));
+    tonic::transport::Server::builder()
+        .add_service(TracerServer::new(service))
+        .serve(args.listen)
+        .await?;
+    Ok(())
+}
+
+#[tonic::async_trait]
+impl Tracer for TracerService {
+    type StreamRecordsStream = ReceiverStream<Result<TraceRecord, Status>>;
+
+    async fn stream_records(
+        &self,
+        request: Request<BlockRange>,
+    ) -> Result<Response<Self::StreamRecordsStream>, Status> {
+        let BlockRange {
+            start_block,
+            end_block,
+        } = request.into_inner();
+        if start_block > end_block {
+            return Err(Status::invalid_argument(format!(
+                "start_block {} is after end_block {}",
+                start_block, end_block
+            )));
+        }
+
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+        let client = self.client.clone();
+        let labels = self.labels.clone();
+        let sanctions_list = self.sanctions_list.clone();
+        let selector_db = self.selector_db.clone();
+        let chain_id = self.chain_id;
+        let adapter = self.adapter.clone();
+        let filter = self.filter.clone();
+        tokio::spawn(async move {
+            for block_num in start_block..=end_block {
+                let mut trace_responses = match trace_block(
+                    client.clone(),
+                    block_num,
+                    chain_id,
+                    adapter.clone(),
+                    filter.clone(),
+                    false,
+                )
+                .await
+                {
+                    Ok(trace_responses) => trace_responses.unwrap_or_default(),
+                    Err(err) => {
+                        let _ = sender
+                            .send(Err(Status::unavailable(format!(
+                                "Tracing block {} failed: {}",
+                                block_num, err
+                            ))))
+                            .await;
+                        return;
+                    }
+                };
+                trace_responses.sort_by(|a, b| {
+                    (a.transaction_position, &a.trace_address)
+                        .cmp(&(b.transaction_position, &b.trace_address))
+                });
+                if !trace_responses.is_empty() {
+                    // Without it the records still go out, only without their timestamp
+                    let block_timestamp = headers::block_timestamp(&client, block_num)
+                        .await
+                        .ok()
+                        .flatten();
+                    for trace_response in trace_responses.iter_mut() {
+                        trace_response.block_timestamp = block_timestamp;
+                    }
+                    let _ = receipts::apply_rpc_receipts(&client, trace_responses.iter_mut()).await;
+                }
+
+                for mut trace_response in trace_responses {
+                    if let Some(selector) = trace_response.selector {
+                        trace_response.function = selector_db.lock().await.resolve(selector).await;
+                    }
+                    annotate(
+                        &mut trace_response,
+                        &labels,
+                        &sanctions_list,
+                        adapter.as_ref(),
+                    );
+                    // The consumer went away, stop tracing for it
+                    if sender.send(Ok(trace_record(trace_response))).await.is_err() {
+                        return;
+                    }
+                }
+                // Only the cache of later runs depends on it, the stream goes on
+                if let Err(err) = selector_db.lock().await.flush() {
+                    log_line(format!("Writing the selector cache failed: {:#}", err));
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+}
+
+fn trace_record(trace_response: TraceResponse) -> TraceRecord {
+    TraceRecord {
+        trace_type: trace_response.trace_type.to_string(),
+        contract_address: trace_response.contract_address.to_string(),
+        block_number: trace_response.block_number,
+        block_timestamp: trace_response.block_timestamp,
+        transaction_position: trace_response.transaction_position,
+        transaction_hash: trace_response.transaction_hash.map(|hash| hash.to_string()),
+        transaction_gas_used: trace_response.transaction_gas_used,
+        transaction_success: trace_response.transaction_success,
+        trace_address: trace_response
+            .trace_address
+            .iter()
+            .map(|&index| index as u64)
+            .collect(),
+        depth: trace_response.depth as u64,
+        function: trace_response.function,
+        code_hash: trace_response.code_hash.map(|hash| hash.to_string()),
+        deployer: trace_response.deployer.map(|address| address.to_string()),
+        beneficiary: trace_response
+            .beneficiary
+            .map(|address| address.to_string()),
+        contract_label: trace_response.contract_label.map(|label| label.label),
+        sanctioned: trace_response.sanctioned,
+        value: trace_response.value.to_string(),
+    }
+}
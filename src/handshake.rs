@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+
+use alloy_primitives::{B256, U64};
+use block_tracer::chain_db::ChainDb;
+use serde_json::json;
+
+use crate::follow::head_block;
+use crate::progress::log_line;
+use crate::rpc::RpcClient;
+
+#[derive(Debug, serde::Deserialize)]
+struct GenesisBlock {
+    hash: B256,
+}
+
+// Checks the node serves the expected chain with the API the chain is traced over enabled, returns
+// its genesis hash
+pub async fn handshake(
+    client: &RpcClient,
+    expected_chain_id: u64,
+    module: &str,
+) -> anyhow::Result<B256> {
+    let chain_id = client
+        .request::<U64>("eth_chainId", json!([]))
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Node did not answer eth_chainId"))?
+        .to::<u64>();
+    if chain_id != expected_chain_id {
+        anyhow::bail!(
+            "RPC_URL serves chain {} but chain {} is configured, pass --chain-id {} if that is intended",
+            chain_id,
+            expected_chain_id,
+            chain_id
+        );
+    }
+
+    let client_version = client
+        .request::<String>("web3_clientVersion", json!([]))
+        .await?
+        .unwrap_or_else(|| "unknown".to_string());
+    log_line(format!(
+        "Connected to {} on chain {}",
+        client_version, chain_id
+    ));
+
+    // rpc_modules is optional, nodes that don't implement it are given the benefit of the doubt
+    if let Some(modules) = client
+        .request::<HashMap<String, String>>("rpc_modules", json!([]))
+        .await
+        .ok()
+        .flatten()
+    {
+        if !modules.contains_key(module) {
+            anyhow::bail!(
+                "{} does not expose the {} API, enable it with --http.api {}",
+                client_version,
+                module,
+                module
+            );
+        }
+    }
+
+    genesis_hash(client).await
+}
+
+pub async fn genesis_hash(client: &RpcClient) -> anyhow::Result<B256> {
+    let genesis = client
+        .request::<GenesisBlock>("eth_getBlockByNumber", json!(["0x0", false]))
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Node did not return the genesis block"))?;
+    Ok(genesis.hash)
+}
+
+// Clamps the end of the range to the node's head, a range entirely past the head is refused since
+// it would only produce empty output after tracing nothing
+pub async fn validate_range(
+    client: &RpcClient,
+    start_block: u64,
+    end_block: u64,
+) -> anyhow::Result<u64> {
+    let head = head_block(client).await?;
+    if start_block > head {
+        anyhow::bail!(
+            "--start-block {} is past the node's head {}, the node may still be syncing",
+            start_block,
+            head
+        );
+    }
+    if end_block > head {
+        log_line(format!(
+            "--end-block {} is past the node's head {}, scanning up to {}",
+            end_block, head, head
+        ));
+        return Ok(head);
+    }
+    Ok(end_block)
+}
+
+pub fn verify_db_genesis(db: &dyn ChainDb, rpc_genesis_hash: B256) -> anyhow::Result<()> {
+    let db_genesis_hash = db
+        .canonical_hash(0)?
+        .ok_or_else(|| anyhow::anyhow!("DB_PATH has no genesis header"))?;
+    if db_genesis_hash != rpc_genesis_hash {
+        anyhow::bail!(
+            "DB_PATH genesis {} does not match RPC_URL genesis {}, they belong to different networks",
+            db_genesis_hash,
+            rpc_genesis_hash
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,112 @@
+use std::collections::{BTreeSet, HashMap};
+
+use alloy_primitives::{B256, U64};
+use block_tracer::chain_db::ChainDb;
+use serde_json::json;
+
+use crate::rpc::RpcClient;
+use crate::TraceResponse;
+
+#[derive(Debug, serde::Deserialize)]
+struct RpcHeader {
+    timestamp: U64,
+}
+
+// The node and the database disagree on which block is canonical at this height
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BlockHashMismatch {
+    pub block_number: u64,
+    pub rpc_hash: B256,
+    pub db_hash: B256,
+}
+
+// Hash and timestamp of the record's block, read from the local headers instead of RPC. Records
+// already carry the hash the node traced, a different local hash means the node and the database
+// are on different forks and is reported once per block
+pub fn apply_block_headers<'a>(
+    db: &dyn ChainDb,
+    trace_responses: impl Iterator<Item = &'a mut TraceResponse>,
+) -> anyhow::Result<Vec<BlockHashMismatch>> {
+    let mut headers: HashMap<u64, Option<(B256, u64)>> = HashMap::new();
+    let mut mismatches: Vec<BlockHashMismatch> = Vec::new();
+    for trace_response in trace_responses {
+        let header = match headers.get(&trace_response.block_number) {
+            Some(header) => *header,
+            None => {
+                let header = db
+                    .header(trace_response.block_number)?
+                    .map(|header| (header.hash, header.timestamp));
+                headers.insert(trace_response.block_number, header);
+                header
+            }
+        };
+        let Some((db_hash, block_timestamp)) = header else {
+            continue;
+        };
+
+        match trace_response.block_hash {
+            Some(rpc_hash) if rpc_hash != db_hash => {
+                if !mismatches
+                    .iter()
+                    .any(|mismatch| mismatch.block_number == trace_response.block_number)
+                {
+                    mismatches.push(BlockHashMismatch {
+                        block_number: trace_response.block_number,
+                        rpc_hash,
+                        db_hash,
+                    });
+                }
+            }
+            _ => {
+                trace_response.block_hash = Some(db_hash);
+                trace_response.block_timestamp = Some(block_timestamp);
+            }
+        }
+    }
+    Ok(mismatches)
+}
+
+// Unix timestamp of the block, `None` when the node does not know it
+pub async fn block_timestamp(client: &RpcClient, block_number: u64) -> anyhow::Result<Option<u64>> {
+    Ok(client
+        .request::<RpcHeader>(
+            "eth_getBlockByNumber",
+            json!([format!("0x{:x}", block_number), false]),
+        )
+        .await?
+        .map(|header| header.timestamp.to::<u64>()))
+}
+
+// Timestamps the local headers did not provide (no datadir, or blocks it does not have yet) are
+// read over RPC, one header per block
+pub async fn apply_rpc_timestamps<'a>(
+    client: &RpcClient,
+    trace_responses: impl Iterator<Item = &'a mut TraceResponse>,
+) -> anyhow::Result<()> {
+    let mut trace_responses = trace_responses
+        .filter(|trace_response| trace_response.block_timestamp.is_none())
+        .collect::<Vec<_>>();
+    let block_numbers = trace_responses
+        .iter()
+        .map(|trace_response| trace_response.block_number)
+        .collect::<BTreeSet<_>>();
+    let headers = client
+        .request_batch::<RpcHeader>(
+            "eth_getBlockByNumber",
+            block_numbers
+                .iter()
+                .map(|block_number| json!([format!("0x{:x}", block_number), false]))
+                .collect(),
+        )
+        .await?;
+    let mut timestamps = HashMap::new();
+    for (block_number, header) in block_numbers.iter().zip(headers) {
+        if let Some(header) = header? {
+            timestamps.insert(*block_number, header.timestamp.to::<u64>());
+        }
+    }
+    for trace_response in trace_responses.iter_mut() {
+        trace_response.block_timestamp = timestamps.get(&trace_response.block_number).copied();
+    }
+    Ok(())
+}
@@ -0,0 +1,322 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use alloy_primitives::{Address, TxHash};
+use serde_json::Value;
+
+use crate::results_db::ResultsDb;
+use crate::{TraceResponse, TraceType};
+
+// Trace records of earlier runs, looked up by contract address and by block. Multi-chain follow
+// output holds several chains, an address is only ever followed within its own chain
+#[derive(Debug, Default)]
+pub struct ResultIndex {
+    trace_responses: Vec<TraceResponse>,
+    by_address: HashMap<(u64, Address), Vec<usize>>,
+    by_block: BTreeMap<u64, Vec<usize>>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct Lifecycle<'a> {
+    pub chain_id: u64,
+    pub address: Address,
+    // Created again after it was destroyed
+    pub recreated: bool,
+    pub events: Vec<&'a TraceResponse>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Recreation {
+    pub chain_id: u64,
+    pub address: Address,
+    pub block_number: u64,
+    pub transaction_hash: Option<TxHash>,
+}
+
+// Line `follow` appends for a block orphaned by a reorg
+#[derive(Debug, serde::Deserialize)]
+struct Retraction {
+    chain_id: u64,
+    block_number: u64,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct IndexStats {
+    pub records: usize,
+    pub creates: usize,
+    pub selfdestructs: usize,
+    pub addresses: usize,
+    pub recreated_addresses: usize,
+    pub first_block: Option<u64>,
+    pub last_block: Option<u64>,
+}
+
+impl ResultIndex {
+    // Reads trace_records.json, newline delimited records such as follow output, or a
+    // --results-db directory. A retraction line drops the records read so far of its block
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        if path.is_dir() {
+            return Ok(Self::new(ResultsDb::open_read_only(path)?.records()?));
+        }
+        let contents = std::fs::read_to_string(path)?;
+        if contents.trim_start().starts_with('[') {
+            return Ok(Self::new(serde_json::from_str::<Vec<TraceResponse>>(
+                &contents,
+            )?));
+        }
+        let mut trace_responses = Vec::new();
+        for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+            let mut value = serde_json::from_str::<Value>(line)?;
+            match value.get_mut("retraction") {
+                Some(retraction) => {
+                    let retraction = serde_json::from_value::<Retraction>(retraction.take())?;
+                    trace_responses.retain(|trace_response: &TraceResponse| {
+                        (trace_response.chain_id, trace_response.block_number)
+                            != (retraction.chain_id, retraction.block_number)
+                    });
+                }
+                None => trace_responses.push(serde_json::from_value::<TraceResponse>(value)?),
+            }
+        }
+        Ok(Self::new(trace_responses))
+    }
+
+    pub fn new(mut trace_responses: Vec<TraceResponse>) -> Self {
+        trace_responses.sort_by(|a, b| {
+            (
+                a.chain_id,
+                a.block_number,
+                a.transaction_position,
+                &a.trace_address,
+            )
+                .cmp(&(
+                    b.chain_id,
+                    b.block_number,
+                    b.transaction_position,
+                    &b.trace_address,
+                ))
+        });
+
+        let mut index = Self::default();
+        for (position, trace_response) in trace_responses.iter().enumerate() {
+            index
+                .by_address
+                .entry((trace_response.chain_id, trace_response.contract_address))
+                .or_default()
+                .push(position);
+            index
+                .by_block
+                .entry(trace_response.block_number)
+                .or_default()
+                .push(position);
+        }
+        index.trace_responses = trace_responses;
+        index
+    }
+
+    // One lifecycle per chain the address has records on, by chain id
+    pub fn lifecycles(&self, address: Address) -> Vec<Lifecycle<'_>> {
+        let mut lifecycles = self
+            .by_address
+            .keys()
+            .filter(|(_, indexed_address)| *indexed_address == address)
+            .filter_map(|&(chain_id, address)| self.lifecycle(chain_id, address))
+            .collect::<Vec<_>>();
+        lifecycles.sort_by_key(|lifecycle| lifecycle.chain_id);
+        lifecycles
+    }
+
+    pub fn lifecycle(&self, chain_id: u64, address: Address) -> Option<Lifecycle<'_>> {
+        let events = self
+            .by_address
+            .get(&(chain_id, address))?
+            .iter()
+            .map(|&position| &self.trace_responses[position])
+            .collect::<Vec<_>>();
+        Some(Lifecycle {
+            chain_id,
+            address,
+            recreated: is_recreated(&events),
+            events,
+        })
+    }
+
+    pub fn block(&self, block_number: u64) -> Vec<&TraceResponse> {
+        self.by_block
+            .get(&block_number)
+            .map(|positions| {
+                positions
+                    .iter()
+                    .map(|&position| &self.trace_responses[position])
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    // Records of the blocks in the range, in chain order
+    pub fn range(&self, from_block: u64, to_block: u64) -> Vec<&TraceResponse> {
+        self.records(&self.range_positions(from_block, to_block))
+    }
+
+    // Positions of the records of the blocks in the range, for callers that outlive a borrow of
+    // the index and look the records up a few at a time
+    pub fn range_positions(&self, from_block: u64, to_block: u64) -> Vec<usize> {
+        self.by_block
+            .range(from_block..=to_block)
+            .flat_map(|(_, positions)| positions.iter().copied())
+            .collect()
+    }
+
+    pub fn records(&self, positions: &[usize]) -> Vec<&TraceResponse> {
+        positions
+            .iter()
+            .map(|&position| &self.trace_responses[position])
+            .collect()
+    }
+
+    // Creates in the range at addresses that were destroyed before
+    pub fn recreations(&self, from_block: u64, to_block: u64) -> Vec<Recreation> {
+        let mut recreations = Vec::new();
+        for positions in self.by_address.values() {
+            let mut destroyed = false;
+            for &position in positions {
+                let trace_response = &self.trace_responses[position];
+                if trace_response.revert_reason.is_some() {
+                    continue;
+                }
+                match trace_response.trace_type {
+                    TraceType::SelfDestruct => destroyed = true,
+                    TraceType::Create => {
+                        if destroyed
+                            && (from_block..=to_block).contains(&trace_response.block_number)
+                        {
+                            recreations.push(Recreation {
+                                chain_id: trace_response.chain_id,
+                                address: trace_response.contract_address,
+                                block_number: trace_response.block_number,
+                                transaction_hash: trace_response.transaction_hash,
+                            });
+                        }
+                        destroyed = false;
+                    }
+                    TraceType::Call | TraceType::Reward => {}
+                }
+            }
+        }
+        recreations.sort_by_key(|recreation| {
+            (
+                recreation.chain_id,
+                recreation.block_number,
+                recreation.address,
+            )
+        });
+        recreations
+    }
+
+    pub fn stats(&self) -> IndexStats {
+        let count = |trace_type: TraceType| {
+            self.trace_responses
+                .iter()
+                .filter(|trace_response| trace_response.trace_type == trace_type)
+                .count()
+        };
+        let recreated_addresses = self
+            .by_address
+            .keys()
+            .filter_map(|&(chain_id, address)| self.lifecycle(chain_id, address))
+            .filter(|lifecycle| lifecycle.recreated)
+            .count();
+        IndexStats {
+            records: self.trace_responses.len(),
+            creates: count(TraceType::Create),
+            selfdestructs: count(TraceType::SelfDestruct),
+            addresses: self.by_address.len(),
+            recreated_addresses,
+            first_block: self.by_block.keys().next().copied(),
+            last_block: self.by_block.keys().next_back().copied(),
+        }
+    }
+}
+
+// Events are in chain order, so a create following a selfdestruct is a recreation
+fn is_recreated(events: &[&TraceResponse]) -> bool {
+    events
+        .iter()
+        .filter(|event| event.revert_reason.is_none())
+        .skip_while(|event| event.trace_type != TraceType::SelfDestruct)
+        .any(|event| event.trace_type == TraceType::Create)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const ADDRESS: Address = Address::repeat_byte(0xaa);
+
+    fn record(chain_id: u64, trace_type: &str, block_number: u64) -> TraceResponse {
+        serde_json::from_value(json!({
+            "chain_id": chain_id,
+            "trace_type": trace_type,
+            "contract_address": ADDRESS,
+            "block_number": block_number,
+            "transaction_position": 0,
+            "blob_transaction": false,
+            "deposit_transaction": false,
+            "trace_address": [],
+            "depth": 0,
+            "sanctioned": false,
+            "value": "0x0",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn the_same_address_on_two_chains_is_no_recreation() {
+        // Destroyed on chain 1, created on chain 10 at a later block
+        let index = ResultIndex::new(vec![
+            record(1, "create", 100),
+            record(1, "selfdestruct", 200),
+            record(10, "create", 300),
+        ]);
+        assert!(index.recreations(0, u64::MAX).is_empty());
+
+        let lifecycles = index.lifecycles(ADDRESS);
+        assert_eq!(
+            lifecycles
+                .iter()
+                .map(|lifecycle| (
+                    lifecycle.chain_id,
+                    lifecycle.events.len(),
+                    lifecycle.recreated
+                ))
+                .collect::<Vec<_>>(),
+            vec![(1, 2, false), (10, 1, false)]
+        );
+        assert_eq!(index.stats().recreated_addresses, 0);
+    }
+
+    #[test]
+    fn recreations_are_found_per_chain_in_chain_order() {
+        let index = ResultIndex::new(vec![
+            record(10, "create", 30),
+            record(1, "create", 300),
+            record(10, "selfdestruct", 10),
+            record(1, "selfdestruct", 200),
+        ]);
+        assert_eq!(
+            index
+                .recreations(0, u64::MAX)
+                .iter()
+                .map(|recreation| (recreation.chain_id, recreation.block_number))
+                .collect::<Vec<_>>(),
+            vec![(1, 300), (10, 30)]
+        );
+        assert!(index
+            .recreations(0, 100)
+            .iter()
+            .all(|recreation| recreation.chain_id == 10));
+    }
+}
@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use alloy_primitives::{address, Address, TxHash, U160};
+use futures::future::BoxFuture;
+use serde_json::{json, Value};
+
+use crate::arbitrum::Arbitrum;
+use crate::op::OpStack;
+use crate::rpc::RpcClient;
+
+// OP-stack and Arbitrum both add this offset to a contract sending an L1 to L2 message, so the
+// contract can't pose as an L2 account at the same address
+const L1_TO_L2_ALIAS_OFFSET: Address = address!("1111000000000000000000000000000000001111");
+
+/// What differs between chains when tracing a block. A new rollup gets a module implementing this
+/// and a case in `adapter`, the scan, follow and gRPC pipelines only ever talk to the trait
+pub trait L2Adapter: Debug + Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// API module the startup checks require the node to expose
+    fn trace_module(&self) -> &'static str {
+        "trace"
+    }
+
+    /// Traces of the block in the format of Parity's `trace_block`, `None` when the node does not
+    /// know the block. `block_tag` is a hex block number or `pending`
+    fn trace_block<'a>(
+        &'a self,
+        client: &'a RpcClient,
+        block_tag: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<Option<Vec<Value>>>> {
+        Box::pin(async move {
+            client
+                .request::<Vec<Value>>("trace_block", json!([block_tag]))
+                .await
+        })
+    }
+
+    /// Transactions the chain inserts into the block itself (deposits, L1 to L2 messages) mapped to
+    /// their position. Their records are marked with `deposit_transaction`
+    fn system_transactions<'a>(
+        &'a self,
+        _client: &'a RpcClient,
+        _block_tag: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<HashMap<TxHash, u64>>> {
+        Box::pin(async { Ok(HashMap::new()) })
+    }
+
+    /// L1 address behind an address the chain aliased, `None` on chains without aliasing
+    fn unalias(&self, _address: Address) -> Option<Address> {
+        None
+    }
+
+    /// Receipt fields with fees on top of the L2 execution gas, e.g. the L1 data fee
+    fn fee_fields(&self) -> &'static [&'static str] {
+        &[]
+    }
+}
+
+/// Ethereum and every chain that traces like it
+#[derive(Debug)]
+pub struct Ethereum;
+
+impl L2Adapter for Ethereum {
+    fn name(&self) -> &'static str {
+        "ethereum"
+    }
+}
+
+pub fn adapter(op_stack: bool, arbitrum: bool) -> Arc<dyn L2Adapter> {
+    match (op_stack, arbitrum) {
+        (true, _) => Arc::new(OpStack),
+        (_, true) => Arc::new(Arbitrum),
+        _ => Arc::new(Ethereum),
+    }
+}
+
+pub fn undo_l1_to_l2_alias(address: Address) -> Address {
+    let offset = U160::from_be_bytes(L1_TO_L2_ALIAS_OFFSET.into_array());
+    let aliased = U160::from_be_bytes(address.into_array());
+    Address::from(aliased.wrapping_sub(offset).to_be_bytes::<20>())
+}
@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use alloy_primitives::Address;
+
+#[derive(Debug, Clone, serde::Deserialize)]
+struct LabelEntry {
+    address: Address,
+    label: String,
+    #[serde(default)]
+    category: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Label {
+    pub label: String,
+    pub category: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct Labels(HashMap<Address, Label>);
+
+impl Labels {
+    // Later files override labels of earlier ones
+    pub fn load(paths: &[PathBuf]) -> anyhow::Result<Self> {
+        let mut labels = HashMap::new();
+        for path in paths {
+            let entries = if path
+                .extension()
+                .is_some_and(|extension| extension == "json")
+            {
+                serde_json::from_slice::<Vec<LabelEntry>>(&std::fs::read(path)?)?
+            } else {
+                read_csv(path)?
+            };
+            for entry in entries {
+                labels.insert(
+                    entry.address,
+                    Label {
+                        label: entry.label,
+                        category: entry.category.filter(|category| !category.is_empty()),
+                    },
+                );
+            }
+        }
+        Ok(Self(labels))
+    }
+
+    pub fn get(&self, address: &Address) -> Option<&Label> {
+        self.0.get(address)
+    }
+}
+
+// `address,label,category` rows, the header row and the category column are optional
+fn read_csv(path: &Path) -> anyhow::Result<Vec<LabelEntry>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_path(path)?;
+
+    let mut entries = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        if record.get(0) == Some("address") {
+            continue;
+        }
+        entries.push(record.deserialize(None)?);
+    }
+    Ok(entries)
+}
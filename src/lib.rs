@@ -0,0 +1,26 @@
+//! Library side of block-tracer: opening a reth datadir and reading contract state from it,
+//! usable without the scanning CLI.
+//!
+//! Datadir access goes through [`chain_db::ChainDb`]. The backend for each supported reth
+//! release line lives behind its own feature, `reth-v1` (the default) for reth 1.x, and `erigon`
+//! reads the state of an Erigon node for verification.
+//!
+//! Blocks are fetched through [`rpc::RpcClient`] and traced by [`traces::trace_block`] with the
+//! [`l2::L2Adapter`] of the chain, the same path the scanning CLI takes.
+//! [`pipeline::Pipeline`] runs the fetch and verify steps of a scan on it with filters and
+//! enrichers registered by the embedding application.
+
+pub mod arbitrum;
+pub mod chain_db;
+#[cfg(feature = "erigon")]
+pub mod erigon;
+pub mod l2;
+pub mod op;
+pub mod pipeline;
+pub mod progress;
+#[cfg(feature = "reth-v1")]
+pub mod provider;
+pub mod rpc;
+mod rpc_auth;
+pub mod state;
+pub mod traces;
@@ -0,0 +1,214 @@
+use std::collections::{BTreeMap, HashMap};
+
+use alloy_primitives::{Address, TxHash};
+
+use crate::{TraceResponse, TraceType};
+
+// A create and the selfdestruct of its contract at most this many blocks apart form a cycle, and
+// cycles of one initiator at most this many blocks apart belong to the same loop
+const LOOP_WINDOW: u64 = 10;
+// Fewer cycles are taken for ordinary deploy-and-destroy use
+const MIN_CYCLES: usize = 5;
+
+// Repeated create and selfdestruct cycles of one initiator, typical of gas token minting and
+// freeing (GST2, CHI) or of gas griefing
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CreateDestroyLoop {
+    // Sender of the creates' transactions, or their deployer when the sender is unknown
+    pub initiator: Address,
+    pub first_block: u64,
+    pub last_block: u64,
+    pub cycles: usize,
+    pub contracts: usize,
+    // Set when all cycles happened in a single transaction
+    pub transaction_hash: Option<TxHash>,
+}
+
+struct Cycle {
+    contract: Address,
+    block_number: u64,
+    transaction_hash: Option<TxHash>,
+}
+
+// Reverted records never created or destroyed anything and are skipped
+pub fn create_destroy_loops<'a>(
+    trace_responses: impl Iterator<Item = &'a TraceResponse>,
+) -> Vec<CreateDestroyLoop> {
+    let mut trace_responses = trace_responses
+        .filter(|trace_response| trace_response.revert_reason.is_none())
+        .collect::<Vec<_>>();
+    trace_responses.sort_by(|a, b| {
+        (a.block_number, a.transaction_position, &a.trace_address).cmp(&(
+            b.block_number,
+            b.transaction_position,
+            &b.trace_address,
+        ))
+    });
+
+    let mut live: HashMap<Address, &TraceResponse> = HashMap::new();
+    let mut cycles: BTreeMap<Address, Vec<Cycle>> = BTreeMap::new();
+    for trace_response in trace_responses {
+        match trace_response.trace_type {
+            TraceType::Create => {
+                live.insert(trace_response.contract_address, trace_response);
+            }
+            TraceType::SelfDestruct => {
+                let Some(create) = live.remove(&trace_response.contract_address) else {
+                    continue;
+                };
+                let Some(initiator) = create.sender.or(create.deployer) else {
+                    continue;
+                };
+                if trace_response.block_number - create.block_number > LOOP_WINDOW {
+                    continue;
+                }
+                let same_transaction = create.transaction_hash == trace_response.transaction_hash;
+                cycles.entry(initiator).or_default().push(Cycle {
+                    contract: trace_response.contract_address,
+                    block_number: trace_response.block_number,
+                    transaction_hash: same_transaction
+                        .then_some(trace_response.transaction_hash)
+                        .flatten(),
+                });
+            }
+            TraceType::Call | TraceType::Reward => {}
+        }
+    }
+
+    let mut loops = Vec::new();
+    for (initiator, cycles) in cycles {
+        let mut start = 0;
+        for end in 1..=cycles.len() {
+            if end < cycles.len()
+                && cycles[end].block_number - cycles[end - 1].block_number <= LOOP_WINDOW
+            {
+                continue;
+            }
+            let window = &cycles[start..end];
+            start = end;
+            if window.len() < MIN_CYCLES {
+                continue;
+            }
+            let mut contracts = window
+                .iter()
+                .map(|cycle| cycle.contract)
+                .collect::<Vec<_>>();
+            contracts.sort();
+            contracts.dedup();
+            let transaction_hash = window[0].transaction_hash.filter(|transaction_hash| {
+                window
+                    .iter()
+                    .all(|cycle| cycle.transaction_hash == Some(*transaction_hash))
+            });
+            loops.push(CreateDestroyLoop {
+                initiator,
+                first_block: window[0].block_number,
+                last_block: window[window.len() - 1].block_number,
+                cycles: window.len(),
+                contracts: contracts.len(),
+                transaction_hash,
+            });
+        }
+    }
+    loops.sort_by_key(|create_destroy_loop| {
+        (
+            create_destroy_loop.first_block,
+            create_destroy_loop.initiator,
+        )
+    });
+    loops
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const INITIATOR: Address = Address::repeat_byte(0x11);
+
+    fn record(
+        trace_type: &str,
+        contract: u8,
+        block_number: u64,
+        transaction_position: u64,
+    ) -> TraceResponse {
+        serde_json::from_value(json!({
+            "chain_id": 1,
+            "trace_type": trace_type,
+            "contract_address": Address::repeat_byte(contract),
+            "block_number": block_number,
+            "transaction_position": transaction_position,
+            "transaction_hash": TxHash::with_last_byte(transaction_position as u8),
+            "sender": INITIATOR,
+            "blob_transaction": false,
+            "deposit_transaction": false,
+            "trace_address": [],
+            "depth": 0,
+            "sanctioned": false,
+            "value": "0x0",
+        }))
+        .unwrap()
+    }
+
+    // A create and selfdestruct of a fresh contract in the block, in their own transactions
+    fn cycles(blocks: &[u64]) -> Vec<TraceResponse> {
+        blocks
+            .iter()
+            .enumerate()
+            .flat_map(|(cycle, &block_number)| {
+                [
+                    record("create", cycle as u8, block_number, 0),
+                    record("selfdestruct", cycle as u8, block_number, 1),
+                ]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn repeated_cycles_of_one_initiator_are_a_loop() {
+        let records = cycles(&[100, 102, 104, 110, 120]);
+        let loops = create_destroy_loops(records.iter());
+        assert_eq!(loops.len(), 1);
+        assert_eq!(loops[0].initiator, INITIATOR);
+        assert_eq!((loops[0].first_block, loops[0].last_block), (100, 120));
+        assert_eq!((loops[0].cycles, loops[0].contracts), (5, 5));
+        assert_eq!(loops[0].transaction_hash, None);
+    }
+
+    #[test]
+    fn too_few_or_too_distant_cycles_are_no_loop() {
+        assert!(create_destroy_loops(cycles(&[100, 101, 102, 103]).iter()).is_empty());
+        // The gap after block 103 splits the cycles into two windows of four and one
+        assert!(create_destroy_loops(cycles(&[100, 101, 102, 103, 200]).iter()).is_empty());
+    }
+
+    #[test]
+    fn reverted_records_and_late_selfdestructs_make_no_cycle() {
+        let mut records = cycles(&[100, 101, 102, 103]);
+        let mut reverted = cycles(&[104]);
+        reverted[1].revert_reason = Some("Reverted".to_string());
+        records.extend(reverted);
+        assert!(create_destroy_loops(records.iter()).is_empty());
+
+        let mut records = cycles(&[100, 101, 102, 103]);
+        records.push(record("create", 0xaa, 104, 0));
+        records.push(record("selfdestruct", 0xaa, 104 + LOOP_WINDOW + 1, 1));
+        assert!(create_destroy_loops(records.iter()).is_empty());
+    }
+
+    #[test]
+    fn cycles_in_one_transaction_keep_its_hash() {
+        let records = (0..5)
+            .flat_map(|cycle| {
+                [
+                    record("create", cycle, 100, 3),
+                    record("selfdestruct", cycle, 100, 3),
+                ]
+            })
+            .collect::<Vec<_>>();
+        let loops = create_destroy_loops(records.iter());
+        assert_eq!(loops.len(), 1);
+        assert_eq!(loops[0].transaction_hash, Some(TxHash::with_last_byte(3)));
+    }
+}
@@ -1,36 +1,45 @@
 #![allow(dead_code)]
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::Display;
+use std::net::SocketAddr;
 use std::str::FromStr;
-use std::sync::Mutex;
-use std::{sync::Arc, time::Instant};
-use std::{env, path::Path};
+use std::{env, path::PathBuf};
+use std::{sync::Arc, time::Duration, time::Instant};
 
-use alloy_primitives::Address;
-use clap::Parser;
+use alloy_primitives::{keccak256, Address, Selector, TxHash, B256, U256};
+use block_tracer::chain_db::ChainDb;
+use block_tracer::l2::{self, L2Adapter};
+use block_tracer::progress::{self, log_line, progress};
+use block_tracer::rpc::{
+    self, HttpTransport, RecordingTransport, ReplayTransport, RpcClient, RpcTransport,
+    TracingTransport,
+};
+use block_tracer::traces;
+use clap::{Parser, Subcommand};
+use compliance::SanctionsList;
+use creators::CreatorKind;
+use filters::TraceFilter;
 use futures::future::join_all;
-use provider::get_reth_factory;
-use reqwest;
-use reth_db::tables;
+use labels::{Label, Labels};
+use plugins::Plugins;
+use pruning::Capability;
 use reth_rpc_types::trace::parity::*;
-use serde_json::json;
+use scripting::Hooks;
+use stages::StageId;
 
-use reth_db_api::{cursor::DbCursorRO, transaction::DbTx};
 use tokio::runtime::Builder;
-use tracing_subscriber::EnvFilter;
-
-use rayon::prelude::*;
-#[derive(Debug, serde::Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct RpcResponse {
-    result: Option<Vec<LocalizedTransactionTrace>>,
-    jsonrpc: String,
-    id: u32,
-}
+use tracing::Instrument;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(
+    Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
 enum TraceType {
     SelfDestruct,
     Create,
+    // Only extracted with --trace-types
+    Call,
+    Reward,
 }
 
 impl Display for TraceType {
@@ -38,6 +47,8 @@ impl Display for TraceType {
         match self {
             TraceType::SelfDestruct => write!(f, "selfdestruct"),
             TraceType::Create => write!(f, "create"),
+            TraceType::Call => write!(f, "call"),
+            TraceType::Reward => write!(f, "reward"),
         }
     }
 }
@@ -49,106 +60,459 @@ impl FromStr for TraceType {
         match s.to_lowercase().as_str() {
             "selfdestruct" => Ok(TraceType::SelfDestruct),
             "create" => Ok(TraceType::Create),
-            _ => panic!("Trace type invalid"),
+            "call" => Ok(TraceType::Call),
+            "reward" => Ok(TraceType::Reward),
+            other => Err(format!(
+                "{} is no trace type, expected create, selfdestruct, call or reward",
+                other
+            )),
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 struct TraceResponse {
+    chain_id: u64,
     trace_type: TraceType,
     contract_address: Address,
     block_number: u64,
+    block_hash: Option<B256>,
+    // Unix seconds
+    block_timestamp: Option<u64>,
     transaction_position: u64,
+    transaction_hash: Option<TxHash>,
+    // Account that signed the transaction
+    sender: Option<Address>,
+    // Gas used by the whole transaction and whether it succeeded, from its receipt
+    transaction_gas_used: Option<u64>,
+    transaction_success: Option<bool>,
+    blob_transaction: bool,
+    deposit_transaction: bool,
+    trace_address: Vec<usize>,
+    depth: usize,
+    // Error of the outermost failed frame above the trace, only kept with --include-reverted
+    revert_reason: Option<String>,
+    // Selector of the call frame that performed the create or selfdestruct
+    selector: Option<Selector>,
+    function: Option<String>,
+    contract_name: Option<String>,
+    verified: Option<bool>,
+    // Runtime code hash of a created contract
+    code_hash: Option<B256>,
+    source_url: Option<String>,
+    // Creator of a created contract
+    deployer: Option<Address>,
+    // Nonce the deployer EOA sent a top-level create with, its address is derived from the two
+    creator_nonce: Option<u64>,
+    // Whether the deployer was an EOA or a contract
+    creator_kind: Option<CreatorKind>,
+    deployer_ens: Option<String>,
+    // Recipient of the balance of a destructed contract
+    beneficiary: Option<Address>,
+    beneficiary_ens: Option<String>,
+    // Account that made the call, for call records
+    caller: Option<Address>,
+    contract_label: Option<Label>,
+    deployer_label: Option<Label>,
+    beneficiary_label: Option<Label>,
+    // Contract, deployer or beneficiary is on a loaded sanctions list
+    sanctioned: bool,
+    // Balance swept by a selfdestruct, or endowment of a create
+    value: U256,
+    value_usd: Option<f64>,
+    // Set by the classify hook of a --script
+    classification: Option<String>,
+    // Fields added by the on_record hook of a --script
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    script_fields: BTreeMap<String, serde_json::Value>,
 }
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
+#[command(subcommand_negates_reqs = true)]
 pub struct Cmd {
-    #[arg(short, long)]
-    pub start_block: u64,
-    #[arg(short, long)]
-    pub end_block: u64,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[arg(short, long, required_unless_present = "blocks_file")]
+    pub start_block: Option<u64>,
+    #[arg(short, long, required_unless_present = "blocks_file")]
+    pub end_block: Option<u64>,
+    /// Trace only the blocks listed in the file instead of a range, e.g. the failed_blocks.json
+    /// of an earlier run, a JSON array of block numbers or one block number per line
+    #[arg(long, conflicts_with_all = ["start_block", "end_block", "shard", "chains"])]
+    pub blocks_file: Option<PathBuf>,
+    /// Address to report per-block net ETH balance changes for (repeatable)
+    #[arg(long = "watch")]
+    pub watchlist: Vec<Address>,
+    /// Write per-block blob gas usage to blob_stats.json
+    #[arg(long)]
+    pub blob_stats: bool,
+    /// Write creations, selfdestructs, recreations and whether tracing failed for every scanned
+    /// block to block_counts.csv
+    #[arg(long)]
+    pub block_counts: bool,
+    /// Keep only creates and selfdestructs attributable to this deployer EOA or factory contract,
+    /// i.e. it sent the transaction or started a frame above the record (repeatable)
+    #[arg(long = "deployer")]
+    pub deployers: Vec<Address>,
+    /// Drop records involving an address listed in this file (one per line, `#` comments): as
+    /// contract, deployer, beneficiary or a factory above it (repeatable)
+    #[arg(long = "exclude-file")]
+    pub exclude_files: Vec<PathBuf>,
+    /// Action types records are extracted for: create, selfdestruct, call and reward. Call and
+    /// reward records are only written out, recreation checks and enrichment skip them
+    #[arg(long, value_delimiter = ',', default_value = "create,selfdestruct")]
+    pub trace_types: Vec<TraceType>,
+    /// Drop selfdestructs, and call records with --trace-types call, moving less than this, e.g.
+    /// 0.1eth, 20gwei or a plain number of wei. Creates are always kept
+    #[arg(long, value_parser = filters::parse_value)]
+    pub min_value: Option<U256>,
+    /// Drop records nested deeper than this in their transaction's call tree, 0 keeps only the
+    /// top-level frame, e.g. with --trace-types call
+    #[arg(long)]
+    pub max_depth: Option<usize>,
+    /// Target an OP-stack (Optimism, Base) node, where deposit transactions lead every block
+    #[arg(long)]
+    pub op_stack: bool,
+    /// Target an Arbitrum Nitro node, traced with `debug_traceBlockByNumber` since Nitro serves no
+    /// trace API
+    #[arg(long, conflicts_with = "op_stack")]
+    pub arbitrum: bool,
+    /// Keep creates and selfdestructs beneath failed frames, tagged with the revert reason
+    #[arg(long)]
+    pub include_reverted: bool,
+    /// Write the uncles included by every block in the range to uncles.json
+    #[arg(long)]
+    pub include_uncles: bool,
+    /// Database lookups after which the verification phase reopens its read transaction
+    #[arg(long, default_value_t = 10_000)]
+    pub tx_refresh_interval: usize,
+    /// Dump the current runtime code of every matched address to code/<codehash>.bin
+    #[arg(long)]
+    pub export_bytecode: bool,
+    /// Resolve unknown selectors via 4byte.directory, caching the results in this file
+    #[arg(long)]
+    pub selector_cache: Option<PathBuf>,
+    /// Tag created and recreated contracts with their Etherscan name and verification status, needs ETHERSCAN_API_KEY
+    #[arg(long)]
+    pub etherscan: bool,
+    /// Link created contracts to their Sourcify full or partial match by runtime code hash
+    #[arg(long)]
+    pub sourcify: bool,
+    /// Resolve ENS primary names of deployers and beneficiaries
+    #[arg(long)]
+    pub ens: bool,
+    /// CSV or JSON file of `address,label,category` entries attached to output records (repeatable)
+    #[arg(long = "labels")]
+    pub label_files: Vec<PathBuf>,
+    /// Sanctioned address list, e.g. an OFAC SDN export; matches are written to compliance_findings.json (repeatable)
+    #[arg(long = "sanctions-list")]
+    pub sanctions_lists: Vec<PathBuf>,
+    /// Value swept or endowed ETH in USD using the Chainlink ETH/USD feed at the record's block,
+    /// mainnet only
+    #[arg(long)]
+    pub usd: bool,
+    /// Flag selfdestructs sweeping ether into contracts that reject plain transfers, written to
+    /// forced_ether.json and sent to the webhooks. Reads every such beneficiary's code at its block
+    #[arg(long)]
+    pub forced_ether: bool,
+    /// Slack, Discord or generic HTTP webhook notified of recreations, watched selfdestructs, metamorphic redeploys and, with --forced-ether, forced ether sends (repeatable)
+    #[arg(long = "webhook")]
+    pub webhooks: Vec<String>,
+    /// JSON file of alert rules evaluated against every record, matches go to alerts.json and webhooks
+    #[arg(long)]
+    pub rules: Option<PathBuf>,
+    /// WASM module exporting `filter(record) -> decision` that drops, keeps or flags every
+    /// enriched record, needs the `wasm` feature (repeatable)
+    #[arg(long = "plugin")]
+    pub plugin_files: Vec<PathBuf>,
+    /// Rhai script with on_record, classify and on_block_done hooks run on every enriched
+    /// record, adding `script_fields` and a `classification`
+    #[arg(long)]
+    pub script: Option<PathBuf>,
+    /// Serve Prometheus metrics on this address, e.g. 127.0.0.1:9100
+    #[arg(long)]
+    pub metrics_addr: Option<SocketAddr>,
+    /// Export fetch, parse, verify and write spans to this OTLP/gRPC collector, e.g. http://localhost:4317
+    #[arg(long)]
+    pub otlp_endpoint: Option<String>,
+    /// Serve tokio-console runtime diagnostics, needs the `console` feature and `--cfg tokio_unstable`
+    #[arg(long)]
+    pub console: bool,
+    /// Save every raw RPC response into this directory
+    #[arg(long, conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+    /// Serve RPC responses from a directory written by --record instead of RPC_URL
+    #[arg(long)]
+    pub replay: Option<PathBuf>,
+    /// Print every JSON-RPC request and response with timing and size, credentials left out
+    #[arg(long)]
+    pub trace_rpc: bool,
+    /// Also write the request and response bodies seen by --trace-rpc into this directory
+    #[arg(long, requires = "trace_rpc")]
+    pub trace_rpc_dir: Option<PathBuf>,
+    /// Also write trace records as BigQuery newline delimited JSON with a schema file
+    #[arg(long)]
+    pub bigquery: bool,
+    /// Also commit trace records to this Delta Lake table, a local path or an s3:// URI,
+    /// partitioned by chain_id and block_date. Needs the `delta` feature
+    #[arg(long)]
+    pub delta_table: Option<String>,
+    /// Chain the RPC node and database are expected to serve
+    #[arg(long, default_value_t = 1)]
+    pub chain_id: u64,
+    /// Built-in profile setting the chain id, OP-stack handling, a public endpoint and follow's
+    /// poll interval: ethereum, polygon, bsc, gnosis, avalanche, base or arbitrum
+    #[arg(long, conflicts_with = "chain_id", value_parser = profiles::parse_profile)]
+    pub chain: Option<&'static profiles::ChainProfile>,
+    /// JSON-RPC endpoint of a node with the trace API
+    #[arg(long, env = "RPC_URL")]
+    pub rpc_url: Option<String>,
+    #[command(flatten)]
+    pub http: rpc::HttpArgs,
+    /// Seconds a block may take to trace before it is counted as failed
+    #[arg(long, default_value_t = 600)]
+    pub block_deadline: u64,
+    /// reth database directory, without it verification runs over RPC
+    #[arg(long, env = "DB_PATH")]
+    pub db_path: Option<PathBuf>,
+    /// reth static files directory
+    #[arg(long, env = "STATIC_FILES_PATH")]
+    pub static_files_path: Option<PathBuf>,
+    /// JSON file of `chain_id`, `rpc_url`, `db_path` and `static_files_path` entries scanned (or
+    /// followed) concurrently, each into `<output-dir>/<chain_id>`
+    #[arg(long)]
+    pub chains: Option<PathBuf>,
+    /// Also store trace records in this MDBX environment, shared across runs: records already
+    /// stored are counted as known, and creates at addresses destroyed in an earlier run are
+    /// reported as recreations
+    #[arg(long)]
+    pub results_db: Option<PathBuf>,
+    /// Verify recreations against the `chaindata` directory of an Erigon node instead of a reth
+    /// datadir, needs the `erigon` feature
+    #[arg(long, env = "ERIGON_CHAINDATA")]
+    pub erigon_chaindata: Option<PathBuf>,
+    /// Directory output files are written to, created when missing
+    #[arg(long, default_value = ".")]
+    pub output_dir: PathBuf,
+    /// Scan only every n-th chunk of the range starting at chunk i (`i/n`, zero based) into `<output-dir>/<start>-<end>`
+    #[arg(long)]
+    pub shard: Option<shard::Shard>,
+    /// Blocks per chunk when sharding
+    #[arg(long, default_value_t = 1000)]
+    pub shard_chunk_size: u64,
+    /// Show a live dashboard of throughput, endpoint health, findings and failed blocks, needs the `tui` feature
+    #[arg(long)]
+    pub tui: bool,
+    /// Resume every stage of an interrupted scan from the checkpoints in the output directory
+    #[arg(long)]
+    pub resume: bool,
+    /// Exit with an error and write no results when any block failed, instead of writing them
+    /// with a completeness percentage
+    #[arg(long)]
+    pub strict: bool,
+    /// Scan again even when the output directory holds a completed run with the same parameters
+    #[arg(long)]
+    pub force: bool,
+    /// Trace a random sample of the range and print the estimated tracing time, records, output
+    /// size and RPC calls of the full scan, without scanning
+    #[arg(long, conflicts_with = "shard")]
+    pub estimate: bool,
+    /// Blocks traced by --estimate
+    #[arg(long, default_value_t = 50)]
+    pub estimate_sample: usize,
+    /// Case of addresses in the output files
+    #[arg(long, value_enum, default_value_t = output::AddressFormat::Lowercase)]
+    pub address_format: output::AddressFormat,
+    /// Write addresses without the `0x` prefix
+    #[arg(long)]
+    pub strip_0x: bool,
+    /// File with a hex secp256k1 private key to sign manifest.json with, written to
+    /// manifest.sig.json
+    #[arg(long, env = "MANIFEST_SIGNING_KEY")]
+    pub manifest_signing_key: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Trace new blocks as they are confirmed and stream records until interrupted
+    Follow(follow::FollowArgs),
+    /// Look up single traces of a transaction with `trace_get` instead of tracing its block
+    TraceAt(trace_at::TraceAtArgs),
+    /// Simulate a call with `debug_traceCall` and report the contracts it would create or
+    /// selfdestruct
+    Simulate(simulate::SimulateArgs),
+    /// Simulate pending transactions and alert when one would selfdestruct a watched contract
+    Mempool(mempool::MempoolArgs),
+    /// Periodically scan from the last checkpoint to the safe head and append the records
+    Daemon(daemon::DaemonArgs),
+    /// Scan the whole chain from genesis to the safe head in checkpointed chunks
+    Scan(full_scan::ScanArgs),
+    /// Hand out chunks of the range to workers over HTTP, reassigning expired leases
+    Coordinator(distributed::CoordinatorArgs),
+    /// Scan chunks leased from a coordinator until none are left
+    Worker(distributed::WorkerArgs),
+    /// Combine the chunk directories written by shards or workers into one set of output files
+    Merge(shard::MergeArgs),
+    /// Reports over the output of a completed run, written to the output directory
+    Report(report::ReportArgs),
+    /// Compare the records of two runs and write those found in only one of them to diff.json
+    Diff(diff::DiffArgs),
+    /// Export the storage slots of one contract from the local database
+    Storage(storage::StorageArgs),
+    /// Reconstruct the storage of one contract as of a past block from the storage changesets
+    StateAt(storage::StateAtArgs),
+    /// Serve a REST API over trace records written by a scan or by follow
+    Serve(serve::ServeArgs),
+    /// Serve tracer_ JSON-RPC methods over trace records written by a scan or by follow
+    Rpc(rpc_server::RpcServerArgs),
+    /// Serve a gRPC StreamRecords API that traces requested ranges on demand
+    #[cfg(feature = "grpc")]
+    Grpc(grpc::GrpcArgs),
+    /// Check the node, its trace API, chain id, datadir and output directory and print a
+    /// pass/fail checklist, before committing to a long run
+    Doctor(doctor::DoctorArgs),
+    /// Print a shell completion script for bash, zsh, fish, elvish or powershell
+    Completions(cli_docs::CompletionsArgs),
+    /// Write man pages for block-tracer and its subcommands
+    Man(cli_docs::ManArgs),
 }
 
-mod provider;
+mod archive;
+#[cfg(any(feature = "flight", feature = "delta"))]
+mod arrow_records;
+mod balance;
+mod bigquery;
+mod blob;
+mod bytecode;
+mod chains;
+mod cli_docs;
+mod compliance;
+mod creators;
+mod daemon;
+mod delta;
+mod diff;
+mod distributed;
+mod doctor;
+mod ens;
+mod estimate;
+mod etherscan;
+mod failures;
+mod filters;
+#[cfg(feature = "flight")]
+mod flight;
+mod follow;
+mod forced_ether;
+mod full_scan;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod handshake;
+mod headers;
+mod index;
+mod labels;
+mod loops;
+mod manifest;
+mod mempool;
+mod merkle;
+mod nonces;
+mod output;
+mod plugins;
+mod pricing;
+mod profiles;
+mod prometheus;
+mod pruning;
+mod receipts;
+mod report;
+mod results_db;
+mod rpc_server;
+mod rules;
+mod scripting;
+mod selectors;
+mod serve;
+mod shard;
+mod simulate;
+mod sink;
+mod sourcify;
+mod stages;
+mod storage;
+mod telemetry;
+mod trace_at;
+mod transactions;
+#[cfg(feature = "tui")]
+mod tui;
+mod uncles;
+mod validation;
+mod webhook;
 
-async fn trace_block(block_num: u64) -> anyhow::Result<Option<Vec<TraceResponse>>> {
-    let client = reqwest::Client::new();
-    let reth_url = env::var("RPC_URL").unwrap();
+#[tracing::instrument(skip(client))]
+async fn trace_block(
+    client: RpcClient,
+    block_num: u64,
+    chain_id: u64,
+    adapter: Arc<dyn L2Adapter>,
+    filter: Arc<TraceFilter>,
+    include_reverted: bool,
+) -> anyhow::Result<Option<Vec<TraceResponse>>> {
     let block_num_hex = format!("0x{:x}", block_num);
-    let payload = json!({
-        "jsonrpc": "2.0",
-        "method": "trace_block",
-        "params": [block_num_hex],
-        "id": 1
-    });
-    // Used to remove traces of invalid transactions
-    let mut invalid_tx = Vec::new();
-    // println!("Block_number {}", block_num);
-    let result = client
-        .post(reth_url)
-        .json(&payload)
-        .send()
-        .await?
-        .json::<RpcResponse>()
-        .await?
-        .result;
-
-    let address_block_tuple = if let Some(localized_tx_traces) = result {
-        localized_tx_traces
-            .into_iter()
-            .map(|tx_trace| {
-                let trace = tx_trace.trace;
-                match (trace.action, trace.result, trace.error) {
-                    (_, _, Some(_)) => {
-                        invalid_tx.push(tx_trace.transaction_hash.unwrap());
-                        None
-                    }
+    trace_block_at(
+        client,
+        block_num_hex,
+        block_num,
+        chain_id,
+        adapter,
+        filter,
+        include_reverted,
+    )
+    .await
+}
 
-                    (
-                        Action::Selfdestruct(SelfdestructAction {
-                            address: destruced_contract,
-                            ..
-                        }),
-                        _,
-                        None,
-                    ) => {
-                        println!("Selfdestruct: {} ", destruced_contract);
-                        if invalid_tx.contains(&tx_trace.transaction_hash.unwrap()) {
-                            return None;
-                        }
-                        Some(TraceResponse {
-                            trace_type: TraceType::SelfDestruct,
-                            contract_address: destruced_contract,
-                            block_number: block_num,
-                            transaction_position: tx_trace.transaction_position.unwrap(),
-                        })
-                    }
-                    (
-                        Action::Create(CreateAction { .. }),
-                        Some(TraceOutput::Create(CreateOutput {
-                            address: created_contract,
-                            ..
-                        })),
-                        None,
-                    ) => {
-                        if invalid_tx.contains(&tx_trace.transaction_hash.unwrap()) {
-                            return None;
-                        }
-                        Some(TraceResponse {
-                            trace_type: TraceType::Create,
-                            contract_address: created_contract,
-                            block_number: block_num,
-                            transaction_position: tx_trace.transaction_position.unwrap(),
-                        })
-                    }
-                    _ => None,
-                }
-            })
-            .filter_map(|item| item)
-            .collect::<Vec<TraceResponse>>()
-    } else {
-        vec![]
-    };
+// `block_tag` is what `trace_block` is asked for, a block number or `pending`, while `block_num`
+// is the number the records get
+async fn trace_block_at(
+    client: RpcClient,
+    block_tag: String,
+    block_num: u64,
+    chain_id: u64,
+    adapter: Arc<dyn L2Adapter>,
+    filter: Arc<TraceFilter>,
+    include_reverted: bool,
+) -> anyhow::Result<Option<Vec<TraceResponse>>> {
+    let block_traces = traces::trace_block(
+        &client,
+        adapter.as_ref(),
+        &block_tag,
+        block_num,
+        // Only needed for --deployer and --exclude-file
+        filter.tracks_senders(),
+    )
+    .await?;
+    let address_block_tuple = block_traces
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|block_trace| filter.extracts(&block_trace.trace.action))
+        .filter(|block_trace| include_reverted || block_trace.revert_reason.is_none())
+        .filter_map(|block_trace| {
+            let senders = block_trace.senders;
+            trace_response(
+                TraceContext {
+                    chain_id,
+                    block_number: block_num,
+                    block_hash: block_trace.block_hash,
+                    transaction_position: block_trace.transaction_position,
+                    transaction_hash: block_trace.transaction_hash,
+                    deposit_transaction: block_trace.deposit_transaction,
+                    selector: block_trace.selector,
+                    revert_reason: block_trace.revert_reason,
+                },
+                block_trace.trace,
+            )
+            .filter(|trace_response| filter.keeps(trace_response, &senders))
+        })
+        .collect::<Vec<TraceResponse>>();
+
+    metrics::counter!("block_tracer_blocks_traced_total").increment(1);
+    for trace_response in &address_block_tuple {
+        metrics::counter!("block_tracer_records_total", "trace_type" => trace_response.trace_type.to_string())
+            .increment(1);
+    }
 
     if address_block_tuple.len() > 0 {
         Ok(Some(address_block_tuple))
@@ -157,58 +521,859 @@ async fn trace_block(block_num: u64) -> anyhow::Result<Option<Vec<TraceResponse>
     }
 }
 
+// Where a trace sits in the chain, and what its call tree says about it
+#[derive(Debug, Clone)]
+struct TraceContext {
+    chain_id: u64,
+    block_number: u64,
+    block_hash: Option<B256>,
+    transaction_position: u64,
+    transaction_hash: Option<TxHash>,
+    deposit_transaction: bool,
+    // Selector of the parent call frame
+    selector: Option<Selector>,
+    revert_reason: Option<String>,
+}
+
+impl TraceResponse {
+    // Record with only what the trace itself says, enrichment fills in the rest
+    fn new(
+        context: TraceContext,
+        trace_type: TraceType,
+        contract_address: Address,
+        trace_address: Vec<usize>,
+        value: U256,
+    ) -> Self {
+        TraceResponse {
+            chain_id: context.chain_id,
+            trace_type,
+            contract_address,
+            block_number: context.block_number,
+            block_hash: context.block_hash,
+            block_timestamp: None,
+            transaction_position: context.transaction_position,
+            transaction_hash: context.transaction_hash,
+            sender: None,
+            transaction_gas_used: None,
+            transaction_success: None,
+            blob_transaction: false,
+            deposit_transaction: context.deposit_transaction,
+            depth: trace_address.len(),
+            trace_address,
+            revert_reason: context.revert_reason,
+            selector: context.selector,
+            function: None,
+            contract_name: None,
+            verified: None,
+            code_hash: None,
+            source_url: None,
+            deployer: None,
+            creator_nonce: None,
+            creator_kind: None,
+            deployer_ens: None,
+            beneficiary: None,
+            beneficiary_ens: None,
+            caller: None,
+            contract_label: None,
+            deployer_label: None,
+            beneficiary_label: None,
+            sanctioned: false,
+            value,
+            value_usd: None,
+            classification: None,
+            script_fields: BTreeMap::new(),
+        }
+    }
+}
+
+// Record of a create, selfdestruct, call or reward trace, `None` for failed creates
+fn trace_response(context: TraceContext, trace: TransactionTrace) -> Option<TraceResponse> {
+    match (trace.action, trace.result, trace.error) {
+        // Block and uncle reward traces of pre-merge blocks have no transaction, they are placed
+        // after the block's last transaction
+        (Action::Reward(RewardAction { author, value, .. }), _, _) => Some(TraceResponse::new(
+            context,
+            TraceType::Reward,
+            author,
+            trace.trace_address,
+            value,
+        )),
+        (
+            Action::Selfdestruct(SelfdestructAction {
+                address: destruced_contract,
+                refund_address,
+                balance,
+            }),
+            _,
+            _,
+        ) => {
+            log_line(format!("Selfdestruct: {} ", destruced_contract));
+            Some(TraceResponse {
+                beneficiary: Some(refund_address),
+                ..TraceResponse::new(
+                    context,
+                    TraceType::SelfDestruct,
+                    destruced_contract,
+                    trace.trace_address,
+                    balance,
+                )
+            })
+        }
+        (
+            Action::Create(CreateAction { from, value, .. }),
+            Some(TraceOutput::Create(CreateOutput {
+                address: created_contract,
+                code,
+                ..
+            })),
+            _,
+        ) => Some(TraceResponse {
+            code_hash: Some(keccak256(&code)),
+            deployer: Some(from),
+            ..TraceResponse::new(
+                context,
+                TraceType::Create,
+                created_contract,
+                trace.trace_address,
+                value,
+            )
+        }),
+        (
+            Action::Call(CallAction {
+                from, to, value, ..
+            }),
+            _,
+            _,
+        ) => Some(TraceResponse {
+            caller: Some(from),
+            ..TraceResponse::new(context, TraceType::Call, to, trace.trace_address, value)
+        }),
+        _ => None,
+    }
+}
+
+// Enrichment that only needs local data, shared by batch scans and follow mode
+// An L1 contract messaging an L2 shows up there under its alias, which is looked up as the L1
+// address when the address itself is unknown
+fn annotate(
+    trace_response: &mut TraceResponse,
+    labels: &Labels,
+    sanctions_list: &SanctionsList,
+    adapter: &dyn L2Adapter,
+) {
+    let label = |address: &Address| {
+        labels.get(address).cloned().or_else(|| {
+            adapter
+                .unalias(*address)
+                .and_then(|l1_address| labels.get(&l1_address).cloned())
+        })
+    };
+    trace_response.contract_label = label(&trace_response.contract_address);
+    trace_response.deployer_label = trace_response.deployer.as_ref().and_then(label);
+    trace_response.beneficiary_label = trace_response.beneficiary.as_ref().and_then(label);
+    trace_response.sanctioned = [
+        Some(trace_response.contract_address),
+        trace_response.deployer,
+        trace_response.beneficiary,
+    ]
+    .iter()
+    .flatten()
+    .any(|address| {
+        sanctions_list.contains(address)
+            || adapter
+                .unalias(*address)
+                .is_some_and(|l1_address| sanctions_list.contains(&l1_address))
+    });
+}
+
 fn main() {
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
-        )
-        .init();
-    Builder::new_multi_thread()
+    // Loaded before parsing so .env can provide the RPC_URL, DB_PATH and STATIC_FILES_PATH options
+    dotenv::dotenv().ok();
+    let mut cmd = Cmd::parse();
+    profiles::apply(&mut cmd);
+    let runtime = Builder::new_multi_thread()
         .max_blocking_threads(num_cpus::get())
         .enable_all()
         .build()
-        .unwrap()
-        .block_on(amain())
         .unwrap();
+    let result = runtime.block_on(async {
+        telemetry::init(cmd.otlp_endpoint.as_deref(), cmd.console)?;
+        amain(cmd).await
+    });
+    telemetry::shutdown();
+    result.unwrap();
 }
 
-async fn amain() -> anyhow::Result<()> {
+// Client over the transport the global options ask for: fixtures of --replay, or the node at
+// RPC_URL, saved by --record and printed by --trace-rpc
+fn rpc_client(
+    rpc_url: Option<&str>,
+    http: &rpc::HttpArgs,
+    record: Option<PathBuf>,
+    replay: Option<PathBuf>,
+    trace_rpc: bool,
+    trace_rpc_dir: Option<PathBuf>,
+) -> anyhow::Result<RpcClient> {
+    let require_rpc_url = || {
+        rpc_url
+            .map(str::to_string)
+            .ok_or_else(|| anyhow::anyhow!("Set RPC_URL or pass --rpc-url"))
+    };
+    let mut transport: Arc<dyn RpcTransport> = match (record, replay) {
+        (_, Some(fixtures_dir)) => Arc::new(ReplayTransport::new(fixtures_dir)),
+        (Some(fixtures_dir), None) => Arc::new(RecordingTransport::new(
+            Arc::new(HttpTransport::new(require_rpc_url()?, http)?),
+            fixtures_dir,
+        )?),
+        (None, None) => Arc::new(HttpTransport::new(require_rpc_url()?, http)?),
+    };
+    if trace_rpc {
+        if let Some(rpc_url) = rpc_url {
+            log_line(format!(
+                "Tracing RPC traffic to {}",
+                rpc::redact_url(rpc_url)
+            ));
+        }
+        transport = Arc::new(TracingTransport::new(transport, trace_rpc_dir)?);
+    }
+    Ok(RpcClient::new(transport))
+}
+
+// The datadir backend is picked at build time, one feature per supported reth release line
+#[cfg(feature = "reth-v1")]
+fn open_chain_db(
+    db_path: &Option<PathBuf>,
+    static_files_path: &Option<PathBuf>,
+    chain_id: u64,
+    tx_refresh_interval: usize,
+) -> anyhow::Result<Box<dyn ChainDb>> {
+    let (Some(db_path), Some(static_files_path)) = (db_path, static_files_path) else {
+        anyhow::bail!("Set DB_PATH and STATIC_FILES_PATH to read the local datadir");
+    };
+    Ok(Box::new(
+        block_tracer::provider::RethDb::open(db_path, static_files_path, chain_id)?
+            .with_tx_refresh_interval(tx_refresh_interval),
+    ))
+}
+
+#[cfg(not(feature = "reth-v1"))]
+fn open_chain_db(
+    _db_path: &Option<PathBuf>,
+    _static_files_path: &Option<PathBuf>,
+    _chain_id: u64,
+    _tx_refresh_interval: usize,
+) -> anyhow::Result<Box<dyn ChainDb>> {
+    anyhow::bail!(
+        "Reading a local datadir requires a reth backend, build with `--features reth-v1`"
+    )
+}
+
+#[cfg(feature = "erigon")]
+fn open_erigon_db(chaindata_path: &std::path::Path) -> anyhow::Result<Box<dyn ChainDb>> {
+    Ok(Box::new(block_tracer::erigon::ErigonDb::open(
+        chaindata_path,
+    )?))
+}
+
+#[cfg(not(feature = "erigon"))]
+fn open_erigon_db(_chaindata_path: &std::path::Path) -> anyhow::Result<Box<dyn ChainDb>> {
+    anyhow::bail!("Reading an Erigon datadir requires building with `--features erigon`")
+}
+
+async fn amain(cmd: Cmd) -> anyhow::Result<()> {
     let start = Instant::now();
-    dotenv::dotenv().ok();
+    output::set_address_style(output::AddressStyle {
+        format: cmd.address_format,
+        strip_prefix: cmd.strip_0x,
+    });
+
+    // Workers, shards, the daemon, full-chain and multi-chain scans run a full scan with the
+    // global options for every chunk or chain, multi-chain follow a follower for every chain
+    let chunked = matches!(
+        cmd.command,
+        Some(Command::Worker(_)) | Some(Command::Daemon(_)) | Some(Command::Scan(_))
+    ) || (cmd.command.is_none() && (cmd.shard.is_some() || cmd.chains.is_some()))
+        || (matches!(cmd.command, Some(Command::Follow(_))) && cmd.chains.is_some());
+    if chunked {
+        if let Some(metrics_addr) = cmd.metrics_addr {
+            prometheus::install(metrics_addr)?;
+        }
+        return match (
+            cmd.command.clone(),
+            cmd.shard,
+            cmd.start_block,
+            cmd.end_block,
+        ) {
+            (Some(Command::Worker(args)), ..) => distributed::work(args, cmd).await,
+            (Some(Command::Daemon(args)), ..) => daemon::run(args, cmd).await,
+            (Some(Command::Scan(args)), ..) => full_scan::run(args, cmd).await,
+            (Some(Command::Follow(_)), ..) | (None, None, ..) => chains::run(cmd).await,
+            (None, Some(shard), Some(start_block), Some(end_block)) => {
+                shard::scan(cmd, shard, start_block, end_block).await
+            }
+            _ => unreachable!(),
+        };
+    }
 
     let Cmd {
+        command,
+        start_block,
+        end_block,
+        blocks_file,
+        watchlist,
+        blob_stats,
+        block_counts,
+        deployers,
+        exclude_files,
+        trace_types,
+        min_value,
+        max_depth,
+        op_stack,
+        arbitrum,
+        include_reverted,
+        include_uncles,
+        export_bytecode,
+        tx_refresh_interval,
+        selector_cache,
+        etherscan,
+        sourcify,
+        ens,
+        label_files,
+        sanctions_lists,
+        usd,
+        forced_ether,
+        webhooks,
+        rules,
+        plugin_files,
+        script,
+        metrics_addr,
+        otlp_endpoint: _,
+        console: _,
+        record,
+        replay,
+        trace_rpc,
+        trace_rpc_dir,
+        bigquery,
+        delta_table,
+        chain_id,
+        chain: _,
+        rpc_url,
+        http,
+        block_deadline,
+        db_path,
+        static_files_path,
+        erigon_chaindata,
+        chains: _,
+        results_db,
+        output_dir,
+        shard: _,
+        shard_chunk_size: _,
+        tui,
+        resume,
+        strict,
+        force,
+        estimate,
+        estimate_sample,
+        address_format,
+        strip_0x,
+        manifest_signing_key,
+    } = cmd;
+
+    if let Some(metrics_addr) = metrics_addr {
+        prometheus::install(metrics_addr)?;
+    }
+
+    if let (Some(start_block), Some(end_block)) = (start_block, end_block) {
+        if start_block > end_block {
+            anyhow::bail!(
+                "--start-block {} is after --end-block {}, the range is empty",
+                start_block,
+                end_block
+            );
+        }
+    }
+
+    // The only ETH/USD feed known is the mainnet one, other chains would get mainnet's answer or
+    // none at all
+    if usd && chain_id != pricing::ETH_USD_FEED_CHAIN_ID {
+        anyhow::bail!(
+            "--usd reads the mainnet Chainlink ETH/USD feed and is only supported on chain {}",
+            pricing::ETH_USD_FEED_CHAIN_ID
+        );
+    }
+
+    // A blocks file stands in for the range from its first to its last block
+    let blocks = blocks_file
+        .as_deref()
+        .map(failures::load_blocks_file)
+        .transpose()?;
+    let (start_block, end_block) = match &blocks {
+        Some(blocks) => (blocks.first().copied(), blocks.last().copied()),
+        None => (start_block, end_block),
+    };
+
+    // These only read existing results or the local database, no node is involved
+    let command = match command {
+        Some(Command::Completions(args)) => {
+            cli_docs::completions(args);
+            return Ok(());
+        }
+        Some(Command::Man(args)) => return cli_docs::man(args),
+        Some(Command::Serve(args)) => return serve::serve(args).await,
+        Some(Command::Rpc(args)) => return rpc_server::serve(args).await,
+        Some(Command::Merge(args)) => return shard::merge(args, &output_dir),
+        Some(Command::Report(args)) => return report::run(args, &output_dir),
+        Some(Command::Diff(args)) => return diff::run(args, &output_dir),
+        Some(Command::Storage(args)) => {
+            let db = open_chain_db(&db_path, &static_files_path, chain_id, tx_refresh_interval)?;
+            return storage::run(db.as_ref(), args, &output_dir);
+        }
+        Some(Command::StateAt(args)) => {
+            let db = open_chain_db(&db_path, &static_files_path, chain_id, tx_refresh_interval)?;
+            return storage::run_state_at(db.as_ref(), args, &output_dir);
+        }
+        Some(Command::Coordinator(args)) => {
+            let (Some(start_block), Some(end_block)) = (start_block, end_block) else {
+                anyhow::bail!("The coordinator needs --start-block and --end-block");
+            };
+            return distributed::coordinate(args, start_block, end_block, &output_dir).await;
+        }
+        Some(Command::Doctor(args)) => {
+            return doctor::run(
+                args,
+                doctor::DoctorContext {
+                    rpc_url,
+                    http,
+                    chain_id,
+                    adapter: l2::adapter(op_stack, arbitrum),
+                    db_path,
+                    static_files_path,
+                    erigon_chaindata,
+                    tx_refresh_interval,
+                    output_dir,
+                },
+            )
+            .await;
+        }
+        command => command,
+    };
+
+    let labels = Labels::load(&label_files)?;
+    let sanctions_list = SanctionsList::load(&sanctions_lists)?;
+    let rules_path = rules;
+    let rules = rules_path
+        .as_deref()
+        .map(rules::load)
+        .transpose()?
+        .unwrap_or_default();
+    let plugins = Arc::new(Plugins::load(&plugin_files)?);
+    let hooks = script
+        .as_deref()
+        .map(Hooks::load)
+        .transpose()?
+        .map(Arc::new);
+    let mut selector_db = selectors::SelectorDb::new(selector_cache.as_deref())?;
+    let watchlist = Arc::new(watchlist);
+    // Read up front, a bad key should not surface only after hours of scanning
+    let signing_key = manifest_signing_key
+        .as_deref()
+        .map(manifest::read_signing_key)
+        .transpose()?;
+    let etherscan_api_key = etherscan
+        .then(|| env::var("ETHERSCAN_API_KEY"))
+        .transpose()
+        .map_err(|err| anyhow::anyhow!("--etherscan needs ETHERSCAN_API_KEY: {}", err))?;
+    // Also up front, so a build without the feature or an unreachable bucket fails right away
+    let mut delta_sink = match &delta_table {
+        Some(delta_table) => Some(delta::DeltaSink::open(delta_table).await?),
+        None => None,
+    };
+
+    let client = rpc_client(
+        rpc_url.as_deref(),
+        &http,
+        record,
+        replay,
+        trace_rpc,
+        trace_rpc_dir,
+    )?;
+
+    let adapter = l2::adapter(op_stack, arbitrum);
+    let filter = Arc::new(TraceFilter {
+        trace_types: trace_types.iter().cloned().collect(),
+        deployers: deployers.iter().copied().collect(),
+        excluded: exclude_files
+            .iter()
+            .map(|exclude_file| filters::load_addresses(exclude_file))
+            .collect::<anyhow::Result<Vec<_>>>()?
+            .into_iter()
+            .flatten()
+            .collect(),
+        min_value: min_value.unwrap_or_default(),
+        max_depth,
+    });
+    // Pointing at the wrong network would otherwise produce silently wrong output
+    let rpc_genesis_hash = handshake::handshake(&client, chain_id, adapter.trace_module()).await?;
+
+    // Without a local datadir everything, verification included, goes through the (archive) node
+    let chain_db = match (&erigon_chaindata, &db_path, &static_files_path) {
+        (Some(erigon_chaindata), _, _) => {
+            let chain_db = open_erigon_db(erigon_chaindata)?;
+            handshake::verify_db_genesis(chain_db.as_ref(), rpc_genesis_hash)?;
+            Some(chain_db)
+        }
+        (None, Some(_), Some(_)) => {
+            let chain_db =
+                open_chain_db(&db_path, &static_files_path, chain_id, tx_refresh_interval)?;
+            handshake::verify_db_genesis(chain_db.as_ref(), rpc_genesis_hash)?;
+            Some(chain_db)
+        }
+        _ => {
+            log_line("DB_PATH or STATIC_FILES_PATH is not set, running in RPC-only mode");
+            None
+        }
+    };
+
+    match command {
+        Some(Command::Follow(args)) => {
+            std::fs::create_dir_all(&output_dir)?;
+            return follow::follow(
+                args,
+                follow::FollowContext {
+                    client,
+                    chain_id,
+                    labels,
+                    sanctions_list,
+                    rules,
+                    plugins,
+                    hooks,
+                    webhooks,
+                    watchlist,
+                    selector_db,
+                    adapter,
+                    filter,
+                    delta_sink,
+                    forced_ether,
+                    checkpoint: output_dir.join("follow_checkpoint.json"),
+                },
+            )
+            .await;
+        }
+        Some(Command::TraceAt(args)) => {
+            return trace_at::run(args, client, chain_id, &output_dir).await;
+        }
+        Some(Command::Simulate(args)) => {
+            return simulate::run(args, client, &output_dir).await;
+        }
+        Some(Command::Mempool(args)) => {
+            return mempool::monitor(args, client, &watchlist, &webhooks).await;
+        }
+        #[cfg(feature = "grpc")]
+        Some(Command::Grpc(args)) => {
+            return grpc::serve(
+                args,
+                grpc::GrpcContext {
+                    client,
+                    chain_id,
+                    labels,
+                    sanctions_list,
+                    selector_db,
+                    adapter,
+                    filter,
+                },
+            )
+            .await;
+        }
+        _ => {}
+    }
+    // Both are required by clap unless a subcommand or a blocks file is given
+    let (Some(start_block), Some(end_block)) = (start_block, end_block) else {
+        unreachable!()
+    };
+    let clamped_end_block = handshake::validate_range(&client, start_block, end_block).await?;
+    let blocks = match blocks {
+        Some(blocks) if clamped_end_block < end_block => anyhow::bail!(
+            "{} lists blocks up to {} but the node's head is {}",
+            blocks_file.unwrap_or_default().display(),
+            end_block,
+            clamped_end_block
+        ),
+        Some(blocks) => blocks,
+        None => (start_block..=clamped_end_block).collect(),
+    };
+    let end_block = clamped_end_block;
+    if estimate {
+        return estimate::run(
+            &blocks,
+            estimate_sample,
+            estimate::EstimateContext {
+                client,
+                chain_id,
+                adapter,
+                filter,
+                include_reverted,
+                block_deadline,
+            },
+        )
+        .await;
+    }
+    std::fs::create_dir_all(&output_dir)?;
+
+    // Multi-hour scans are not repeated by accident, an identical completed run is kept
+    let run_params = manifest::RunParams {
         start_block,
         end_block,
-    } = Cmd::parse();
+        blocks: blocks_file.is_some().then(|| blocks.clone()),
+        chain_id,
+        backend: match (&erigon_chaindata, &db_path) {
+            (Some(chaindata), _) => manifest::Backend::Erigon {
+                chaindata: chaindata.clone(),
+            },
+            (None, Some(db_path)) if chain_db.is_some() => manifest::Backend::Reth {
+                db_path: db_path.clone(),
+            },
+            _ => manifest::Backend::Rpc,
+        },
+        include_reverted,
+        op_stack,
+        arbitrum,
+        deployers: deployers.clone(),
+        exclude_files: exclude_files.clone(),
+        trace_types: trace_types.clone(),
+        min_value,
+        max_depth,
+        watchlist: watchlist.to_vec(),
+        blob_stats,
+        block_counts,
+        include_uncles,
+        export_bytecode,
+        etherscan,
+        sourcify,
+        ens,
+        usd,
+        forced_ether,
+        label_files: label_files.clone(),
+        sanctions_lists: sanctions_lists.clone(),
+        rules: rules_path.clone(),
+        plugin_files: plugin_files.clone(),
+        script: script.clone(),
+        bigquery,
+        delta_table: delta_table.clone(),
+        address_format,
+        strip_0x,
+        selector_cache: selector_cache.clone(),
+        manifest_signing_key: manifest_signing_key.clone(),
+        input_digests: manifest::input_digests(
+            exclude_files
+                .iter()
+                .chain(&label_files)
+                .chain(&sanctions_lists)
+                .chain(&rules_path)
+                .chain(&plugin_files)
+                .chain(&script),
+        )?,
+    };
+    if let Some(manifest) = manifest::Manifest::load(&output_dir)? {
+        if !force && manifest.is_complete_run_of(run_params.hash()?) {
+            log_line(format!(
+                "{} already holds a completed run with the same parameters, pass --force to scan again",
+                output_dir.display()
+            ));
+            return Ok(());
+        }
+    }
+    if chain_db.is_none() && (blob_stats || include_uncles || export_bytecode) {
+        anyhow::bail!("--blob-stats, --include-uncles and --export-bytecode read the local database and need DB_PATH and STATIC_FILES_PATH");
+    }
+    if erigon_chaindata.is_some() && (blob_stats || include_uncles || export_bytecode) {
+        anyhow::bail!("Only recreations are verified against an Erigon datadir, --blob-stats, --include-uncles and --export-bytecode need a reth one");
+    }
+
+    #[cfg(feature = "tui")]
+    let _dashboard = tui.then(tui::Dashboard::start).transpose()?;
+    #[cfg(not(feature = "tui"))]
+    if tui {
+        anyhow::bail!("--tui requires building with `--features tui`");
+    }
+
+    // A pruned node or datadir still gets scanned, but the output says what is missing
+    let mut degradations =
+        pruning::node_degradations(&client, start_block, end_block, chain_db.is_none()).await;
+    if let Some(chain_db) = &chain_db {
+        degradations.extend(pruning::db_degradations(chain_db.as_ref(), start_block)?);
+    }
+    for degradation in degradations.iter() {
+        log_line(format!("Degraded: {}", degradation.reason));
+    }
+    let historical_state = !pruning::is_degraded(&degradations, Capability::HistoricalState);
+
+    let mut stages = stages::Stages::open(&output_dir, start_block, end_block, resume)?;
+    if stages.is_done(StageId::Enrich) {
+        log_line(format!(
+            "Blocks {}-{} were already scanned into {}",
+            start_block,
+            end_block,
+            output_dir.display()
+        ));
+        return Ok(());
+    }
+
+    // Blocks up to the trace checkpoint are not traced again, their records are loaded
+    let trace_from = stages
+        .checkpoint(StageId::Trace)
+        .map_or(start_block, |traced_until| traced_until + 1);
+    let traced_trace_responses = stages
+        .load::<Vec<TraceResponse>>(StageId::Trace)?
+        .unwrap_or_default();
+    if trace_from <= end_block {
+        stages.reset_after(StageId::Trace)?;
+    }
+
+    let trace_blocks = blocks
+        .iter()
+        .copied()
+        .filter(|block| *block >= trace_from)
+        .collect::<Vec<_>>();
+    progress().start_blocks(trace_blocks.len() as u64);
 
     let mut reinitialized_contracts = Vec::new();
 
-    let handles: Vec<_> = (start_block..=end_block)
-        .into_iter()
-        .map(|block| tokio::spawn(async move { trace_block(block).await.unwrap() }))
+    let handles: Vec<_> = trace_blocks
+        .iter()
+        .copied()
+        .map(|block| {
+            let client = client.clone();
+            let adapter = adapter.clone();
+            let filter = filter.clone();
+            telemetry::spawn_named(&format!("trace_block {}", block), async move {
+                // A hung provider fails the block instead of stalling the scan
+                let traced = tokio::time::timeout(
+                    Duration::from_secs(block_deadline),
+                    trace_block(client, block, chain_id, adapter, filter, include_reverted),
+                )
+                .await
+                .unwrap_or_else(|elapsed| {
+                    Err(anyhow::Error::new(elapsed)
+                        .context(format!("exceeded the {}s block deadline", block_deadline)))
+                });
+                match traced {
+                    Ok(trace_responses) => {
+                        progress().block_done();
+                        Ok(trace_responses.unwrap_or_default())
+                    }
+                    Err(err) => {
+                        log_line(format!("Tracing block {} failed: {:#}", block, err));
+                        progress().block_failed(block);
+                        Err(failures::FailedBlock::new(block, &err))
+                    }
+                }
+            })
+        })
         .collect();
-    let results: Vec<std::result::Result<Option<Vec<TraceResponse>>, tokio::task::JoinError>> =
-        join_all(handles).await;
-    let combined_trace_responses = results
+    let results: Vec<
+        std::result::Result<
+            std::result::Result<Vec<TraceResponse>, failures::FailedBlock>,
+            tokio::task::JoinError,
+        >,
+    > = join_all(handles).await;
+    let mut failed_blocks = Vec::new();
+    let mut combined_trace_responses = Vec::new();
+    for (block, result) in trace_blocks.iter().copied().zip(results) {
+        match result {
+            Ok(Ok(trace_responses)) => combined_trace_responses.extend(trace_responses),
+            Ok(Err(failed_block)) => failed_blocks.push(failed_block),
+            Err(err) => failed_blocks.push(failures::FailedBlock::new(block, &err.into())),
+        }
+    }
+    let first_failed_block = failed_blocks
+        .first()
+        .map(|failed_block| failed_block.block_number);
+    // A follow-up run with `--blocks-file failed_blocks.json` traces exactly these again
+    if failed_blocks.is_empty() {
+        let _ = std::fs::remove_file(output_dir.join("failed_blocks.json"));
+    } else {
+        output::write_json(&output_dir, "failed_blocks.json", &failed_blocks)?;
+        log_line(format!(
+            "{} blocks could not be traced, see failed_blocks.json",
+            failed_blocks.len()
+        ));
+    }
+    // Pipelines must not pick up a dataset with holes as if it were complete
+    if strict && !failed_blocks.is_empty() {
+        anyhow::bail!(
+            "{} of {} blocks failed, no results are written in --strict mode",
+            failed_blocks.len(),
+            blocks.len()
+        );
+    }
+
+    // A trace is identified by its transaction and position in the call tree
+    combined_trace_responses.sort_by(|a, b| {
+        (a.block_number, a.transaction_position, &a.trace_address).cmp(&(
+            b.block_number,
+            b.transaction_position,
+            &b.trace_address,
+        ))
+    });
+    combined_trace_responses.dedup_by(|a, b| {
+        a.block_number == b.block_number
+            && a.transaction_position == b.transaction_position
+            && a.trace_address == b.trace_address
+            && a.trace_type == b.trace_type
+    });
+
+    for trace_response in combined_trace_responses.iter_mut() {
+        if let Some(selector) = trace_response.selector {
+            trace_response.function = selector_db.resolve(selector).await;
+        }
+    }
+    selector_db.flush()?;
+
+    // Blocks before the first failed one are complete and move the checkpoint
+    let combined_trace_responses = traced_trace_responses
         .into_iter()
-        .filter_map(|item| item.ok())
-        .flat_map(|item| item.unwrap_or_default())
-        .collect::<Vec<TraceResponse>>();
+        .chain(combined_trace_responses)
+        .collect::<Vec<_>>();
+    let traced_until = match first_failed_block {
+        Some(first_failed_block) => first_failed_block.checked_sub(1),
+        None => Some(end_block),
+    };
+    if let Some(traced_until) = traced_until {
+        if trace_from <= traced_until {
+            let complete_trace_responses = combined_trace_responses
+                .iter()
+                .filter(|trace_response| trace_response.block_number <= traced_until)
+                .collect::<Vec<_>>();
+            stages.save(StageId::Trace, traced_until, &complete_trace_responses)?;
+        }
+    }
 
-    let (self_destructed_trace_responses, created_trace_responses): (Vec<_>, Vec<_>) =
+    let (mut self_destructed_trace_responses, other_trace_responses): (Vec<_>, Vec<_>) =
         combined_trace_responses
             .into_iter()
             .partition(|trace_block_response| {
                 trace_block_response.trace_type == TraceType::SelfDestruct
             });
+    // Call and reward records (--trace-types) go to the output as they are
+    let (mut created_trace_responses, mut other_trace_responses): (Vec<_>, Vec<_>) =
+        other_trace_responses
+            .into_iter()
+            .partition(|trace_response| trace_response.trace_type == TraceType::Create);
 
     // Find reinitialized contracts in range [start_block_num, end_block_num], which is necessary if the plain state of contract is not available
-    for self_destructed_trace_response in &self_destructed_trace_responses {
+    // Reverted traces (--include-reverted) never changed the state, so they are no recreations
+    for self_destructed_trace_response in self_destructed_trace_responses
+        .iter()
+        .filter(|trace_response| trace_response.revert_reason.is_none())
+    {
         let sda = self_destructed_trace_response.contract_address; // self destructed address
         let sda_block_num = self_destructed_trace_response.block_number;
         let sda_transaction_position = self_destructed_trace_response.transaction_position;
 
-        for created_trace_response in &created_trace_responses {
+        for created_trace_response in created_trace_responses
+            .iter()
+            .filter(|trace_response| trace_response.revert_reason.is_none())
+        {
             let ca = created_trace_response.contract_address;
             let ca_block_num = created_trace_response.block_number;
             let ca_transaction_position = created_trace_response.transaction_position;
@@ -217,68 +1382,527 @@ async fn amain() -> anyhow::Result<()> {
                 && sda_transaction_position < ca_transaction_position
                 && sda == ca
             {
-                println!("Address {} has been recreated", sda);
+                log_line(format!("Address {} has been recreated", sda));
+                progress().finding(format!("{} recreated in block {}", sda, ca_block_num));
                 reinitialized_contracts.push(sda);
             } else if sda_block_num < ca_block_num && sda == ca {
-                println!("Address {} has been recreated", sda);
+                log_line(format!("Address {} has been recreated", sda));
+                progress().finding(format!("{} recreated in block {}", sda, ca_block_num));
                 reinitialized_contracts.push(sda);
             }
         }
     }
 
-    let db_files = env::var("DB_PATH").unwrap();
-    let static_files = env::var("STATIC_FILES_PATH").unwrap();
+    let mut balance_changes = Vec::new();
+    if !watchlist.is_empty() && historical_state {
+        let handles: Vec<_> = blocks
+            .iter()
+            .copied()
+            .map(|block| {
+                let client = client.clone();
+                let watchlist = watchlist.clone();
+                telemetry::spawn_named(&format!("balance_changes {}", block), async move {
+                    balance::balance_changes(client, block, watchlist).await
+                })
+            })
+            .collect();
+        // A block without its balance changes is as incomplete as one that was not traced
+        let mut balance_failures = Vec::new();
+        for (block, result) in blocks.iter().copied().zip(join_all(handles).await) {
+            match result
+                .map_err(anyhow::Error::from)
+                .and_then(|result| result)
+            {
+                Ok(block_balance_changes) => balance_changes.extend(block_balance_changes),
+                Err(err) => {
+                    log_line(format!(
+                        "Balance changes of block {} failed: {:#}",
+                        block, err
+                    ));
+                    balance_failures.push(failures::FailedBlock::new(block, &err));
+                }
+            }
+        }
+        if !balance_failures.is_empty() {
+            if strict {
+                anyhow::bail!(
+                    "Balance changes of {} blocks failed, no results are written in --strict mode",
+                    balance_failures.len()
+                );
+            }
+            for failed_block in balance_failures {
+                if !failed_blocks
+                    .iter()
+                    .any(|failed| failed.block_number == failed_block.block_number)
+                {
+                    failed_blocks.push(failed_block);
+                }
+            }
+            failed_blocks.sort_by_key(|failed_block| failed_block.block_number);
+            output::write_json(&output_dir, "failed_blocks.json", &failed_blocks)?;
+        }
+    }
+
+    if let Some(chain_db) = &chain_db {
+        let chain_db = chain_db.as_ref();
+        // Headers come from the local datadir, so enrichment needs no extra RPC calls
+        let mismatches = headers::apply_block_headers(
+            chain_db,
+            self_destructed_trace_responses
+                .iter_mut()
+                .chain(created_trace_responses.iter_mut())
+                .chain(other_trace_responses.iter_mut()),
+        )?;
+        for mismatch in mismatches.iter() {
+            log_line(format!(
+                "Block {} is {} on the node but {} in the database, they are on different forks",
+                mismatch.block_number, mismatch.rpc_hash, mismatch.db_hash
+            ));
+        }
+        if !mismatches.is_empty() {
+            output::write_json(&output_dir, "block_hash_mismatches.json", &mismatches)?;
+        }
 
-    let db_path = Path::new(&db_files);
-    let static_files_path = Path::new(&static_files);
-    let factory = get_reth_factory(db_path, static_files_path)?;
-    let provider = factory.provider()?;
+        transactions::resolve_transactions(
+            chain_db,
+            self_destructed_trace_responses
+                .iter_mut()
+                .chain(created_trace_responses.iter_mut()),
+        )?;
+        receipts::apply_local_receipts(
+            chain_db,
+            self_destructed_trace_responses
+                .iter_mut()
+                .chain(created_trace_responses.iter_mut())
+                .chain(other_trace_responses.iter_mut()),
+        )?;
 
-    let tx = Arc::new(provider.into_tx());
+        for trace_response in self_destructed_trace_responses
+            .iter_mut()
+            .chain(created_trace_responses.iter_mut())
+        {
+            if let Some(transaction_hash) = trace_response.transaction_hash {
+                trace_response.blob_transaction =
+                    blob::is_blob_transaction(chain_db, transaction_hash)?;
+            }
+        }
+
+        if blob_stats {
+            let blob_stats = blob::blob_stats(chain_db, start_block, end_block)?;
+            output::write_json(&output_dir, "blob_stats.json", &blob_stats)?;
+        }
+
+        if include_uncles {
+            let uncles = uncles::uncle_inclusions(chain_db, start_block, end_block)?;
+            output::write_json(&output_dir, "uncles.json", &uncles)?;
+        }
+
+        if !watchlist.is_empty() {
+            // Withdrawals credit accounts outside of transactions, so they never show up in stateDiff
+            balance::apply_withdrawals(
+                chain_db,
+                start_block,
+                end_block,
+                &watchlist,
+                &mut balance_changes,
+            )?;
+        }
+    }
+
+    creators::classify_creators(&client, created_trace_responses.iter_mut()).await;
+    // Without a datadir, or for transactions it does not have, nonces are read over RPC
+    nonces::apply_creator_nonces(&client, created_trace_responses.iter_mut()).await?;
+    for (address, sender, nonce) in nonces::derivation_mismatches(created_trace_responses.iter()) {
+        log_line(format!(
+            "Address {} is not the CREATE address of {} at nonce {}",
+            address, sender, nonce
+        ));
+    }
+
+    headers::apply_rpc_timestamps(
+        &client,
+        self_destructed_trace_responses
+            .iter_mut()
+            .chain(created_trace_responses.iter_mut())
+            .chain(other_trace_responses.iter_mut()),
+    )
+    .await?;
+    receipts::apply_rpc_receipts(
+        &client,
+        self_destructed_trace_responses
+            .iter_mut()
+            .chain(created_trace_responses.iter_mut())
+            .chain(other_trace_responses.iter_mut()),
+    )
+    .await?;
+
+    if !watchlist.is_empty() {
+        for balance_change in balance_changes.iter_mut() {
+            balance_change.label = labels.get(&balance_change.address).cloned();
+        }
+        output::write_json(&output_dir, "balance_changes.json", &balance_changes)?;
+    }
 
     let duration = start.elapsed();
-    println!(
+    log_line(format!(
         "Time elapsed in finding self destruct addresss is: {:?}",
         duration
-    );
+    ));
 
-    let recreated_contracts = Arc::new(Mutex::new(Vec::new()));
+    let verify_span = tracing::info_span!("verify");
+    let verified_contracts = stages.load::<Vec<Address>>(StageId::Verify)?;
+    let self_destructed_addresses = self_destructed_trace_responses
+        .iter()
+        .filter(|trace_response| trace_response.revert_reason.is_none())
+        .map(|trace_response| trace_response.contract_address)
+        .collect::<Vec<_>>();
+    let recreated_contracts = match (verified_contracts, &chain_db) {
+        (Some(verified_contracts), _) => verified_contracts,
+        (None, Some(chain_db)) => {
+            let _verify = verify_span.enter();
+            let recreated_contracts = chain_db.existing_accounts(&self_destructed_addresses)?;
+            for address in recreated_contracts.iter() {
+                log_line(format!("Address {} has been recreated", address));
+                progress().finding(format!("{} exists again after its selfdestruct", address));
+            }
+            recreated_contracts
+        }
+        (None, None) => {
+            if pruning::is_degraded(&degradations, Capability::RecreationVerification) {
+                Vec::new()
+            } else {
+                archive::recreated_at(&client, &self_destructed_addresses, end_block)
+                    .instrument(verify_span)
+                    .await?
+            }
+        }
+    };
+    // Verification covers the whole range, so it is only checkpointed once tracing is complete
+    if stages.is_done(StageId::Trace) && !stages.is_done(StageId::Verify) {
+        stages.save(StageId::Verify, end_block, &recreated_contracts)?;
+    }
+    reinitialized_contracts.extend(recreated_contracts);
 
-    self_destructed_trace_responses
-        .par_chunks(10)
-        .for_each(|chunk| {
-            let recreated_contracts_clone = recreated_contracts.clone();
-            for trace_block_response in chunk {
-                let sda = trace_block_response.contract_address;
-                let mut plain_account_cursor =
-                    tx.cursor_read::<tables::PlainAccountState>().unwrap();
+    // Opened after tracing, so a failed run leaves no partial results behind
+    let results_db = results_db
+        .as_deref()
+        .map(results_db::ResultsDb::open)
+        .transpose()?;
+    if let Some(results_db) = &results_db {
+        let recreated_across_runs =
+            results_db.recreated_across_runs(created_trace_responses.iter())?;
+        for address in recreated_across_runs.iter() {
+            log_line(format!(
+                "Address {} was destroyed in an earlier run and recreated",
+                address
+            ));
+            progress().finding(format!(
+                "{} recreated after an earlier run's selfdestruct",
+                address
+            ));
+        }
+        reinitialized_contracts.extend(recreated_across_runs);
+    }
 
-                let plain_account = plain_account_cursor.seek_exact(sda).unwrap();
+    reinitialized_contracts.sort();
+    reinitialized_contracts.dedup();
 
-                if plain_account.is_some() {
-                    println!("Address {} has been recreated", sda);
-                    recreated_contracts_clone.lock().unwrap().push(sda);
-                }
+    if export_bytecode {
+        let mut addresses = self_destructed_trace_responses
+            .iter()
+            .chain(created_trace_responses.iter())
+            .map(|trace_response| trace_response.contract_address)
+            .collect::<Vec<_>>();
+        addresses.sort();
+        addresses.dedup();
+        let Some(chain_db) = &chain_db else {
+            unreachable!("checked before scanning")
+        };
+        let exported =
+            bytecode::export_bytecodes(chain_db.as_ref(), &addresses, &output_dir.join("code"))?;
+        log_line(format!("Exported bytecode of {} addresses", exported.len()));
+        output::write_json(&output_dir, "bytecodes.json", &exported)?;
+    }
+
+    if let Some(api_key) = &etherscan_api_key {
+        let mut addresses = created_trace_responses
+            .iter()
+            .map(|trace_response| trace_response.contract_address)
+            .chain(reinitialized_contracts.iter().copied())
+            .collect::<Vec<_>>();
+        addresses.sort();
+        addresses.dedup();
+
+        let contract_infos = etherscan::contract_infos(api_key, &addresses).await?;
+        for trace_response in self_destructed_trace_responses
+            .iter_mut()
+            .chain(created_trace_responses.iter_mut())
+        {
+            if let Some(info) = contract_infos.get(&trace_response.contract_address) {
+                trace_response.contract_name = info.contract_name.clone();
+                trace_response.verified = Some(info.verified);
             }
-        });
-
-    reinitialized_contracts.extend(
-        Arc::try_unwrap(recreated_contracts)
-            .unwrap()
-            .into_inner()
-            .unwrap()
-            .into_iter(),
+        }
+    }
+
+    if sourcify {
+        let contracts = created_trace_responses
+            .iter()
+            .filter_map(|trace_response| {
+                trace_response
+                    .code_hash
+                    .map(|code_hash| (trace_response.contract_address, code_hash))
+            })
+            .collect::<Vec<_>>();
+        let source_urls = sourcify::source_urls(chain_id, &contracts).await?;
+        for trace_response in created_trace_responses.iter_mut() {
+            trace_response.source_url = trace_response
+                .code_hash
+                .and_then(|code_hash| source_urls.get(&code_hash).cloned());
+        }
+    }
+
+    if ens {
+        let addresses = self_destructed_trace_responses
+            .iter()
+            .chain(created_trace_responses.iter())
+            .flat_map(|trace_response| [trace_response.deployer, trace_response.beneficiary])
+            .flatten()
+            .collect::<Vec<_>>();
+        let names = ens::reverse_names(&client, &addresses).await;
+        for trace_response in self_destructed_trace_responses
+            .iter_mut()
+            .chain(created_trace_responses.iter_mut())
+        {
+            trace_response.deployer_ens = trace_response
+                .deployer
+                .and_then(|deployer| names.get(&deployer).cloned());
+            trace_response.beneficiary_ens = trace_response
+                .beneficiary
+                .and_then(|beneficiary| names.get(&beneficiary).cloned());
+        }
+    }
+
+    for trace_response in self_destructed_trace_responses
+        .iter_mut()
+        .chain(created_trace_responses.iter_mut())
+        .chain(other_trace_responses.iter_mut())
+    {
+        annotate(trace_response, &labels, &sanctions_list, adapter.as_ref());
+    }
+
+    if !sanctions_lists.is_empty() {
+        let compliance_findings = compliance::findings(
+            &sanctions_list,
+            self_destructed_trace_responses
+                .iter()
+                .chain(created_trace_responses.iter()),
+        );
+        log_line(format!("{} compliance findings", compliance_findings.len()));
+        output::write_json(
+            &output_dir,
+            "compliance_findings.json",
+            &compliance_findings,
+        )?;
+    }
+
+    if usd && historical_state {
+        let mut eth_usd_prices: HashMap<u64, Option<f64>> = HashMap::new();
+        for trace_response in self_destructed_trace_responses
+            .iter_mut()
+            .chain(created_trace_responses.iter_mut())
+        {
+            if trace_response.value.is_zero() {
+                continue;
+            }
+            let eth_usd_price = match eth_usd_prices.get(&trace_response.block_number) {
+                Some(eth_usd_price) => *eth_usd_price,
+                None => {
+                    let eth_usd_price =
+                        pricing::eth_usd_price(&client, trace_response.block_number).await?;
+                    eth_usd_prices.insert(trace_response.block_number, eth_usd_price);
+                    eth_usd_price
+                }
+            };
+            trace_response.value_usd = eth_usd_price
+                .map(|eth_usd_price| pricing::wei_to_usd(trace_response.value, eth_usd_price))
+                .transpose()?;
+        }
+    }
+
+    if let Some(hooks) = &hooks {
+        hooks.apply(
+            self_destructed_trace_responses
+                .iter_mut()
+                .chain(created_trace_responses.iter_mut())
+                .chain(other_trace_responses.iter_mut()),
+        )?;
+    }
+
+    // Plugins see the records fully enriched, recreations were found among all of them already
+    let mut plugin_findings = Vec::new();
+    if !plugins.is_empty() {
+        for trace_responses in [
+            &mut self_destructed_trace_responses,
+            &mut created_trace_responses,
+            &mut other_trace_responses,
+        ] {
+            plugin_findings.extend(plugins.apply(trace_responses)?);
+        }
+        log_line(format!(
+            "{} records flagged by plugins",
+            plugin_findings.len()
+        ));
+        output::write_json(&output_dir, "plugin_findings.json", &plugin_findings)?;
+    }
+
+    let mut forced_ether_findings = Vec::new();
+    if forced_ether {
+        forced_ether_findings =
+            forced_ether::findings(&client, self_destructed_trace_responses.iter()).await;
+        for finding in forced_ether_findings.iter() {
+            log_line(format!(
+                "Selfdestruct forced ether into {}",
+                finding.address
+            ));
+            progress().finding(format!("{} was forced ether", finding.address));
+        }
+        output::write_json(&output_dir, "forced_ether.json", &forced_ether_findings)?;
+    }
+
+    let create_destroy_loops = loops::create_destroy_loops(
+        self_destructed_trace_responses
+            .iter()
+            .chain(created_trace_responses.iter()),
     );
+    for create_destroy_loop in create_destroy_loops.iter() {
+        log_line(format!(
+            "{} ran {} create and selfdestruct cycles in blocks {}-{}",
+            create_destroy_loop.initiator,
+            create_destroy_loop.cycles,
+            create_destroy_loop.first_block,
+            create_destroy_loop.last_block
+        ));
+    }
+    output::write_json(
+        &output_dir,
+        "create_destroy_loops.json",
+        &create_destroy_loops,
+    )?;
 
-    reinitialized_contracts.sort();
-    reinitialized_contracts.dedup();
+    if !webhooks.is_empty() || !rules.is_empty() {
+        let mut findings = webhook::findings(
+            &reinitialized_contracts,
+            &created_trace_responses,
+            &self_destructed_trace_responses,
+            &watchlist,
+        );
+        findings.extend(forced_ether_findings);
+        findings.extend(plugin_findings);
+
+        if !rules.is_empty() {
+            let alerts = rules::evaluate(
+                &rules,
+                self_destructed_trace_responses
+                    .iter()
+                    .chain(created_trace_responses.iter()),
+            );
+            log_line(format!("{} alerts raised", alerts.len()));
+            output::write_json(&output_dir, "alerts.json", &alerts)?;
+            findings.extend(alerts);
+        }
+
+        webhook::notify(&webhooks, &findings).await;
+    }
+
+    let trace_records = self_destructed_trace_responses
+        .iter()
+        .chain(created_trace_responses.iter())
+        .chain(other_trace_responses.iter())
+        .collect::<Vec<_>>();
+    output::write_json(&output_dir, "trace_records.json", &trace_records)?;
+    if block_counts {
+        let block_counts =
+            report::block_counts(&blocks, trace_records.iter().copied(), &failed_blocks);
+        output::write_csv(&output_dir, "block_counts.csv", &block_counts)?;
+    }
+    if bigquery {
+        bigquery::write_trace_records(&output_dir, &trace_records)?;
+    }
+    if let (Some(delta_sink), Some(delta_table)) = (&mut delta_sink, &delta_table) {
+        delta_sink.append(&trace_records).await?;
+        log_line(format!(
+            "Committed {} records to the Delta table {}",
+            trace_records.len(),
+            delta_table
+        ));
+    }
+    if let Some(results_db) = &results_db {
+        let stats = results_db.insert(trace_records.iter().copied())?;
+        log_line(format!(
+            "Stored {} new records in the results database, {} were already known",
+            stats.new_records, stats.known_records
+        ));
+    }
+
+    output::write_json(
+        &output_dir,
+        "reinitialized_contracts.json",
+        &reinitialized_contracts,
+    )?;
+
+    // Which client variant served the traces shows in what it left out
+    let absent_fields = progress().absent_fields();
+    if !absent_fields.is_empty() {
+        output::write_json(&output_dir, "absent_fields.json", &absent_fields)?;
+    }
+
+    // Error rates by provider, to compare providers over the same range
+    let providers = progress().providers();
+    if !providers.is_empty() {
+        output::write_json(&output_dir, "rpc_stats.json", &providers)?;
+        for (provider, stats) in &providers {
+            log_line(format!(
+                "{}: {} requests, {:.2}% failed ({} timeouts, {} 4xx, {} 5xx, {} undecodable), {} retries",
+                provider,
+                stats.requests,
+                stats.failures() as f64 * 100.0 / stats.requests.max(1) as f64,
+                stats.timeouts,
+                stats.client_errors,
+                stats.server_errors,
+                stats.deserialization_errors,
+                stats.retries
+            ));
+        }
+    }
+
+    let completeness = failures::Completeness::new(blocks.len() as u64, failed_blocks.len() as u64);
+    output::write_json(&output_dir, "completeness.json", &completeness)?;
+    if completeness.failed_blocks > 0 {
+        log_line(format!(
+            "INCOMPLETE: {:.2}% of the blocks were traced, see failed_blocks.json",
+            completeness.percent
+        ));
+    }
 
-    let reinitialized_contracts_json = serde_json::to_string(&reinitialized_contracts)?;
-    let reinitialized_contracts_file = Path::new("reinitialized_contracts.json");
-    std::fs::write(reinitialized_contracts_file, reinitialized_contracts_json)?;
+    if !degradations.is_empty() {
+        output::write_json(&output_dir, "degradations.json", &degradations)?;
+        log_line(format!(
+            "PARTIAL ANALYSIS: {} capabilities were degraded, see degradations.json",
+            degradations.len()
+        ));
+    }
+
+    if stages.is_done(StageId::Verify) {
+        stages.save(StageId::Enrich, end_block, &trace_records.len())?;
+    }
+    let records_root = merkle::records_root(trace_records.iter().copied());
+    log_line(format!("Merkle root of the records: {}", records_root));
+    manifest::Manifest::new(run_params, completeness, records_root, &output_dir)?
+        .write(&output_dir, signing_key.as_ref())?;
 
     let duration = start.elapsed();
-    println!("Time elapsed in total is: {:?}", duration);
+    log_line(format!("Time elapsed in total is: {:?}", duration));
 
     Ok(())
 }
@@ -0,0 +1,289 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use alloy_primitives::{eip191_hash_message, hex, keccak256, Address, Bytes, B256, U256};
+use k256::ecdsa::SigningKey;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::failures::Completeness;
+use crate::output::AddressFormat;
+use crate::TraceType;
+
+// Everything that changes what a range scan writes. Two runs with the same parameters produce
+// the same output, so the hash of these identifies a run
+#[derive(Debug, Clone, Serialize, serde::Deserialize)]
+pub struct RunParams {
+    pub start_block: u64,
+    pub end_block: u64,
+    // Only set for `--blocks-file` runs, which trace a subset of the range
+    pub blocks: Option<Vec<u64>>,
+    pub chain_id: u64,
+    pub backend: Backend,
+    pub include_reverted: bool,
+    pub op_stack: bool,
+    #[serde(default)]
+    pub arbitrum: bool,
+    #[serde(default)]
+    pub deployers: Vec<Address>,
+    #[serde(default)]
+    pub exclude_files: Vec<PathBuf>,
+    #[serde(default = "default_trace_types")]
+    pub trace_types: Vec<TraceType>,
+    #[serde(default)]
+    pub min_value: Option<U256>,
+    #[serde(default)]
+    pub max_depth: Option<usize>,
+    pub watchlist: Vec<Address>,
+    pub blob_stats: bool,
+    #[serde(default)]
+    pub block_counts: bool,
+    pub include_uncles: bool,
+    pub export_bytecode: bool,
+    pub etherscan: bool,
+    pub sourcify: bool,
+    pub ens: bool,
+    pub usd: bool,
+    #[serde(default)]
+    pub forced_ether: bool,
+    pub label_files: Vec<PathBuf>,
+    pub sanctions_lists: Vec<PathBuf>,
+    pub rules: Option<PathBuf>,
+    #[serde(default)]
+    pub plugin_files: Vec<PathBuf>,
+    #[serde(default)]
+    pub script: Option<PathBuf>,
+    pub bigquery: bool,
+    #[serde(default)]
+    pub delta_table: Option<String>,
+    #[serde(default)]
+    pub address_format: AddressFormat,
+    #[serde(default)]
+    pub strip_0x: bool,
+    #[serde(default)]
+    pub selector_cache: Option<PathBuf>,
+    #[serde(default)]
+    pub manifest_signing_key: Option<PathBuf>,
+    // SHA-256 of the contents of the exclude, label, sanctions, rules, plugin and script files by
+    // their path, an edited file changes the output while its path stays the same
+    #[serde(default)]
+    pub input_digests: BTreeMap<PathBuf, B256>,
+}
+
+// Manifests written before --trace-types only extracted these
+fn default_trace_types() -> Vec<TraceType> {
+    vec![TraceType::Create, TraceType::SelfDestruct]
+}
+
+#[derive(Debug, Clone, Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Backend {
+    Rpc,
+    Reth { db_path: PathBuf },
+    Erigon { chaindata: PathBuf },
+}
+
+impl RunParams {
+    pub fn hash(&self) -> anyhow::Result<B256> {
+        Ok(keccak256(serde_json::to_vec(self)?))
+    }
+}
+
+// `manifest.json`, written last by a range scan that got to the end
+#[derive(Debug, Clone, Serialize, serde::Deserialize)]
+pub struct Manifest {
+    pub params_hash: B256,
+    pub params: RunParams,
+    pub completeness: Completeness,
+    pub finished_at: u64,
+    // Merkle root over the records, equal for two runs that found the same creates and
+    // selfdestructs whatever their enrichment options
+    #[serde(default)]
+    pub records_root: B256,
+    // SHA-256 of every output file, by its path relative to the output directory
+    #[serde(default)]
+    pub artifacts: BTreeMap<String, B256>,
+}
+
+// `manifest.sig.json`, an EIP-191 signature over the SHA-256 of the exact bytes of
+// `manifest.json`. The manifest pins every other file through its digests
+#[derive(Debug, Clone, Serialize, serde::Deserialize)]
+pub struct ManifestSignature {
+    pub manifest_sha256: B256,
+    pub signer: Address,
+    pub signature: Bytes,
+}
+
+impl Manifest {
+    // Digests the files this process wrote into the output directory
+    pub fn new(
+        params: RunParams,
+        completeness: Completeness,
+        records_root: B256,
+        output_dir: &Path,
+    ) -> anyhow::Result<Self> {
+        let mut artifacts = BTreeMap::new();
+        for path in crate::output::written_files(output_dir) {
+            let relative_path = path
+                .strip_prefix(output_dir)?
+                .to_string_lossy()
+                .into_owned();
+            artifacts.insert(relative_path, sha256(&std::fs::read(&path)?));
+        }
+        Ok(Self {
+            params_hash: params.hash()?,
+            params,
+            completeness,
+            finished_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            records_root,
+            artifacts,
+        })
+    }
+
+    pub fn load(output_dir: &Path) -> anyhow::Result<Option<Self>> {
+        let path = output_dir.join("manifest.json");
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_slice(&std::fs::read(path)?)?))
+    }
+
+    // A stale signature of an earlier manifest is removed, it would not verify anyway
+    pub fn write(&self, output_dir: &Path, signing_key: Option<&SigningKey>) -> anyhow::Result<()> {
+        let manifest = serde_json::to_vec_pretty(self)?;
+        crate::stages::write_atomically(&output_dir.join("manifest.json"), &manifest)?;
+
+        let signature_path = output_dir.join("manifest.sig.json");
+        match signing_key {
+            Some(signing_key) => crate::stages::write_atomically(
+                &signature_path,
+                &serde_json::to_vec_pretty(&sign(signing_key, sha256(&manifest))?)?,
+            ),
+            None => {
+                let _ = std::fs::remove_file(signature_path);
+                Ok(())
+            }
+        }
+    }
+
+    // A run with holes is never taken as done, rerunning it is how the holes get patched
+    pub fn is_complete_run_of(&self, params_hash: B256) -> bool {
+        self.params_hash == params_hash && self.completeness.failed_blocks == 0
+    }
+}
+
+// Digests of the input files a run reads, see `RunParams::input_digests`
+pub fn input_digests<'a>(
+    paths: impl IntoIterator<Item = &'a PathBuf>,
+) -> anyhow::Result<BTreeMap<PathBuf, B256>> {
+    paths
+        .into_iter()
+        .map(|path| {
+            let contents = std::fs::read(path)
+                .map_err(|err| anyhow::anyhow!("Reading {} failed: {}", path.display(), err))?;
+            Ok((path.clone(), sha256(&contents)))
+        })
+        .collect()
+}
+
+fn sha256(contents: &[u8]) -> B256 {
+    B256::from_slice(&Sha256::digest(contents))
+}
+
+fn sign(signing_key: &SigningKey, manifest_sha256: B256) -> anyhow::Result<ManifestSignature> {
+    let (signature, recovery_id) =
+        signing_key.sign_prehash_recoverable(eip191_hash_message(manifest_sha256).as_slice())?;
+    let mut signature = signature.to_bytes().to_vec();
+    signature.push(27 + recovery_id.to_byte());
+    let public_key = signing_key.verifying_key().to_encoded_point(false);
+    Ok(ManifestSignature {
+        manifest_sha256,
+        signer: Address::from_raw_public_key(&public_key.as_bytes()[1..]),
+        signature: signature.into(),
+    })
+}
+
+// A hex encoded secp256k1 private key, as exported by Ethereum wallets
+pub fn read_signing_key(path: &Path) -> anyhow::Result<SigningKey> {
+    let key = hex::decode(std::fs::read_to_string(path)?.trim())?;
+    SigningKey::from_slice(&key)
+        .map_err(|_| anyhow::anyhow!("{} holds no valid secp256k1 private key", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn run_params(input_digests: BTreeMap<PathBuf, B256>) -> RunParams {
+        let mut run_params = serde_json::from_value::<RunParams>(json!({
+            "start_block": 100,
+            "end_block": 200,
+            "chain_id": 1,
+            "backend": "rpc",
+            "include_reverted": false,
+            "op_stack": false,
+            "watchlist": [],
+            "blob_stats": false,
+            "include_uncles": false,
+            "export_bytecode": false,
+            "etherscan": false,
+            "sourcify": false,
+            "ens": false,
+            "usd": false,
+            "label_files": [],
+            "sanctions_lists": [],
+            "rules": "rules.toml",
+            "bigquery": false,
+        }))
+        .unwrap();
+        run_params.input_digests = input_digests;
+        run_params
+    }
+
+    #[test]
+    fn editing_an_input_file_changes_the_params_hash() {
+        let path =
+            std::env::temp_dir().join(format!("block-tracer-manifest-{}.toml", std::process::id()));
+        std::fs::write(&path, "[[rule]]\nmin_value = 1\n").unwrap();
+        let before = run_params(input_digests([&path]).unwrap());
+        std::fs::write(&path, "[[rule]]\nmin_value = 2\n").unwrap();
+        let after = run_params(input_digests([&path]).unwrap());
+        std::fs::remove_file(&path).unwrap();
+
+        assert_ne!(before.hash().unwrap(), after.hash().unwrap());
+        assert_eq!(
+            before.hash().unwrap(),
+            run_params(before.input_digests.clone()).hash().unwrap()
+        );
+    }
+
+    #[test]
+    fn a_run_with_failed_blocks_is_not_complete() {
+        let params = run_params(BTreeMap::new());
+        let params_hash = params.hash().unwrap();
+        let manifest = |failed_blocks| Manifest {
+            params_hash,
+            params: params.clone(),
+            completeness: Completeness::new(101, failed_blocks),
+            finished_at: 0,
+            records_root: B256::ZERO,
+            artifacts: BTreeMap::new(),
+        };
+
+        assert!(manifest(0).is_complete_run_of(params_hash));
+        assert!(!manifest(1).is_complete_run_of(params_hash));
+        assert!(!manifest(0).is_complete_run_of(B256::ZERO));
+    }
+
+    #[test]
+    fn unreadable_input_files_are_reported() {
+        let path = PathBuf::from("/nonexistent/block-tracer/rules.toml");
+        let err = input_digests([&path]).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("/nonexistent/block-tracer/rules.toml"));
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use alloy_primitives::{Address, Bytes, TxHash, U256, U64};
+use serde_json::json;
+
+use crate::progress::log_line;
+use crate::rpc::RpcClient;
+use crate::webhook::{self, Finding, FindingKind};
+
+const MAX_SEEN_TRANSACTIONS: usize = 100_000;
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct MempoolArgs {
+    /// Seconds between polls of the pending transaction filter
+    #[arg(long, default_value_t = 1)]
+    pub poll_interval: u64,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct PendingTransaction {
+    hash: TxHash,
+    from: Address,
+    to: Option<Address>,
+    input: Bytes,
+    value: U256,
+    gas: U64,
+}
+
+// Frame of the geth callTracer. For SELFDESTRUCT frames `from` is the destructed contract and
+// `to` the beneficiary, for CREATE and CREATE2 frames `to` is the created contract
+#[derive(Debug, serde::Deserialize)]
+pub struct CallFrame {
+    #[serde(rename = "type")]
+    pub frame_type: String,
+    pub from: Address,
+    #[serde(default)]
+    pub to: Option<Address>,
+    #[serde(default)]
+    pub value: Option<U256>,
+    // Set on frames that failed, their subcalls are rolled back with them
+    #[serde(default)]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub calls: Vec<CallFrame>,
+}
+
+impl CallFrame {
+    // Selfdestructs that take effect, a failed frame rolls back the ones below it
+    pub fn self_destructed(&self, addresses: &mut Vec<Address>) {
+        if self.error.is_some() {
+            return;
+        }
+        if self.frame_type == "SELFDESTRUCT" {
+            addresses.push(self.from);
+        }
+        for call in self.calls.iter() {
+            call.self_destructed(addresses);
+        }
+    }
+}
+
+// Simulates every new pending transaction against the latest state and alerts before a watched
+// contract is destroyed, runs until interrupted
+pub async fn monitor(
+    args: MempoolArgs,
+    client: RpcClient,
+    watchlist: &[Address],
+    webhooks: &[String],
+) -> anyhow::Result<()> {
+    if watchlist.is_empty() {
+        anyhow::bail!("Mempool monitoring needs at least one --watch address");
+    }
+
+    let filter_id = client
+        .request::<String>("eth_newPendingTransactionFilter", json!([]))
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Node does not support pending transaction filters"))?;
+    log_line(format!(
+        "Watching the mempool for selfdestructs of {} addresses",
+        watchlist.len()
+    ));
+
+    // Transactions can be announced again after a reorg or replacement
+    let mut seen: HashSet<TxHash> = HashSet::new();
+    let mut interval = tokio::time::interval(Duration::from_secs(args.poll_interval));
+    loop {
+        interval.tick().await;
+        let tx_hashes = client
+            .request::<Vec<TxHash>>("eth_getFilterChanges", json!([filter_id]))
+            .await?
+            .unwrap_or_default();
+
+        // Forgetting old hashes at worst simulates a long pending transaction twice
+        if seen.len() > MAX_SEEN_TRANSACTIONS {
+            seen.clear();
+        }
+        for tx_hash in tx_hashes {
+            if !seen.insert(tx_hash) {
+                continue;
+            }
+            let destructed = match simulate(&client, tx_hash).await {
+                Ok(destructed) => destructed,
+                // Pending transactions get mined or dropped while they are looked at
+                Err(err) => {
+                    tracing::debug!("Simulating {} failed: {}", tx_hash, err);
+                    continue;
+                }
+            };
+
+            let findings = destructed
+                .into_iter()
+                .filter(|address| watchlist.contains(address))
+                .map(|address| Finding {
+                    kind: FindingKind::PendingSelfdestruct,
+                    rule: None,
+                    address,
+                    block_number: None,
+                    transaction_hash: Some(tx_hash),
+                })
+                .collect::<Vec<_>>();
+            for finding in findings.iter() {
+                log_line(format!(
+                    "Pending transaction {} would selfdestruct {}",
+                    tx_hash, finding.address
+                ));
+            }
+            if !findings.is_empty() {
+                webhook::notify(webhooks, &findings).await;
+            }
+        }
+    }
+}
+
+// Addresses the transaction would selfdestruct if it was included on top of the latest block
+async fn simulate(client: &RpcClient, tx_hash: TxHash) -> anyhow::Result<Vec<Address>> {
+    let Some(transaction) = client
+        .request::<PendingTransaction>("eth_getTransactionByHash", json!([tx_hash]))
+        .await?
+    else {
+        return Ok(Vec::new());
+    };
+    // Plain transfers and deployments can't destroy an existing contract
+    if transaction.to.is_none() || transaction.input.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let call = json!({
+        "from": transaction.from,
+        "to": transaction.to,
+        "data": transaction.input,
+        "value": transaction.value,
+        "gas": transaction.gas,
+    });
+    let Some(frame) = client
+        .request::<CallFrame>(
+            "debug_traceCall",
+            json!([call, "latest", { "tracer": "callTracer" }]),
+        )
+        .await?
+    else {
+        return Ok(Vec::new());
+    };
+
+    let mut destructed = Vec::new();
+    frame.self_destructed(&mut destructed);
+    Ok(destructed)
+}
@@ -0,0 +1,146 @@
+use alloy_primitives::{keccak256, B256};
+
+use crate::{TraceResponse, TraceType};
+
+// Domain separation of leaves and inner nodes, so a leaf can never pass for a node
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+// Only what the chain itself determines goes into a leaf. Enrichment (labels, ENS names, USD
+// values, ...) depends on options and external services, two parties comparing findings must
+// not differ on it
+fn leaf(trace_record: &TraceResponse) -> B256 {
+    let mut encoded = vec![LEAF_PREFIX];
+    encoded.extend_from_slice(&trace_record.chain_id.to_be_bytes());
+    encoded.extend_from_slice(&trace_record.block_number.to_be_bytes());
+    encoded.extend_from_slice(&trace_record.transaction_position.to_be_bytes());
+    encoded.extend_from_slice(&(trace_record.trace_address.len() as u32).to_be_bytes());
+    for position in trace_record.trace_address.iter() {
+        encoded.extend_from_slice(&(*position as u32).to_be_bytes());
+    }
+    encoded.push(match trace_record.trace_type {
+        TraceType::Create => 0,
+        TraceType::SelfDestruct => 1,
+        TraceType::Call => 2,
+        TraceType::Reward => 3,
+    });
+    encoded.extend_from_slice(trace_record.contract_address.as_slice());
+    encoded.extend_from_slice(&trace_record.value.to_be_bytes::<32>());
+    keccak256(encoded)
+}
+
+// Root over the records that changed the state, in chain order. An odd node at the end of a
+// level is carried up unchanged, and no records at all give the zero hash
+pub fn records_root<'a>(trace_records: impl Iterator<Item = &'a TraceResponse>) -> B256 {
+    let mut trace_records = trace_records
+        .filter(|trace_record| trace_record.revert_reason.is_none())
+        .collect::<Vec<_>>();
+    trace_records.sort_by(|a, b| {
+        (
+            a.block_number,
+            a.transaction_position,
+            &a.trace_address,
+            &a.trace_type,
+        )
+            .cmp(&(
+                b.block_number,
+                b.transaction_position,
+                &b.trace_address,
+                &b.trace_type,
+            ))
+    });
+
+    let mut level = trace_records.into_iter().map(leaf).collect::<Vec<_>>();
+    if level.is_empty() {
+        return B256::ZERO;
+    }
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => {
+                    let mut encoded = vec![NODE_PREFIX];
+                    encoded.extend_from_slice(left.as_slice());
+                    encoded.extend_from_slice(right.as_slice());
+                    keccak256(encoded)
+                }
+                [single] => *single,
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+    level[0]
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::Address;
+    use serde_json::json;
+
+    use super::*;
+
+    fn record(trace_type: &str, block_number: u64, trace_address: &[usize]) -> TraceResponse {
+        serde_json::from_value(json!({
+            "chain_id": 1,
+            "trace_type": trace_type,
+            "contract_address": Address::repeat_byte(block_number as u8),
+            "block_number": block_number,
+            "transaction_position": 0,
+            "blob_transaction": false,
+            "deposit_transaction": false,
+            "trace_address": trace_address,
+            "depth": trace_address.len(),
+            "sanctioned": false,
+            "value": "0x0",
+        }))
+        .unwrap()
+    }
+
+    fn node(left: B256, right: B256) -> B256 {
+        keccak256([&[NODE_PREFIX][..], left.as_slice(), right.as_slice()].concat())
+    }
+
+    #[test]
+    fn no_records_give_the_zero_hash() {
+        assert_eq!(records_root(std::iter::empty()), B256::ZERO);
+    }
+
+    #[test]
+    fn an_odd_node_is_carried_up_unchanged() {
+        let records = [
+            record("create", 1, &[]),
+            record("selfdestruct", 2, &[]),
+            record("create", 3, &[0]),
+        ];
+        let leaves = records.iter().map(leaf).collect::<Vec<_>>();
+        assert_eq!(records_root(records[..1].iter()), leaves[0]);
+        assert_eq!(
+            records_root(records.iter()),
+            node(node(leaves[0], leaves[1]), leaves[2])
+        );
+    }
+
+    #[test]
+    fn the_root_ignores_record_order_enrichment_and_reverted_records() {
+        let records = vec![
+            record("create", 1, &[]),
+            record("selfdestruct", 2, &[]),
+            record("create", 2, &[0, 1]),
+        ];
+        let root = records_root(records.iter());
+
+        let mut reordered = records.clone();
+        reordered.reverse();
+        assert_eq!(records_root(reordered.iter()), root);
+
+        let mut enriched = records.clone();
+        enriched[0].contract_name = Some("Token".to_string());
+        enriched[1].value_usd = Some(1.5);
+        assert_eq!(records_root(enriched.iter()), root);
+
+        let mut reverted = record("create", 3, &[0]);
+        reverted.revert_reason = Some("Reverted".to_string());
+        assert_eq!(records_root(records.iter().chain([&reverted])), root);
+        assert_ne!(records_root(records[..2].iter()), root);
+    }
+}
@@ -0,0 +1,72 @@
+use alloy_primitives::{Address, U64};
+use serde_json::json;
+
+use crate::rpc::RpcClient;
+use crate::{TraceResponse, TraceType};
+
+#[derive(Debug, serde::Deserialize)]
+struct RpcTransaction {
+    from: Address,
+    nonce: U64,
+}
+
+// A create at the top of a transaction is made by the EOA that sent it, with the transaction's
+// nonce. Deposit transactions carry no nonce of their sender and are left out
+pub fn is_eoa_creation(trace_response: &TraceResponse) -> bool {
+    trace_response.trace_type == TraceType::Create
+        && trace_response.trace_address.is_empty()
+        && !trace_response.deposit_transaction
+}
+
+// Fills in `creator_nonce` over RPC for the EOA creations the local database did not resolve
+pub async fn apply_creator_nonces<'a>(
+    client: &RpcClient,
+    trace_responses: impl Iterator<Item = &'a mut TraceResponse>,
+) -> anyhow::Result<()> {
+    let mut unresolved = trace_responses
+        .filter(|trace_response| {
+            is_eoa_creation(trace_response) && trace_response.creator_nonce.is_none()
+        })
+        .filter_map(|trace_response| {
+            trace_response
+                .transaction_hash
+                .map(|transaction_hash| (trace_response, transaction_hash))
+        })
+        .collect::<Vec<_>>();
+
+    let transactions = client
+        .request_batch::<RpcTransaction>(
+            "eth_getTransactionByHash",
+            unresolved
+                .iter()
+                .map(|(_, transaction_hash)| json!([transaction_hash]))
+                .collect(),
+        )
+        .await?;
+    for ((trace_response, _), transaction) in unresolved.iter_mut().zip(transactions) {
+        if let Some(transaction) = transaction? {
+            trace_response.sender.get_or_insert(transaction.from);
+            trace_response.creator_nonce = Some(transaction.nonce.to::<u64>());
+        }
+    }
+    Ok(())
+}
+
+// EOA creations whose address is not the CREATE address of their sender and nonce, which points
+// at a wrongly resolved transaction or a chain deriving addresses differently. Returns the
+// contract, sender and nonce of each
+pub fn derivation_mismatches<'a>(
+    trace_responses: impl Iterator<Item = &'a TraceResponse>,
+) -> Vec<(Address, Address, u64)> {
+    trace_responses
+        .filter_map(|trace_response| {
+            let sender = trace_response.sender?;
+            let nonce = trace_response.creator_nonce?;
+            (sender.create(nonce) != trace_response.contract_address).then_some((
+                trace_response.contract_address,
+                sender,
+                nonce,
+            ))
+        })
+        .collect()
+}
@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+
+use alloy_primitives::{Address, TxHash, U64};
+use futures::future::BoxFuture;
+use serde_json::json;
+
+use crate::l2::{undo_l1_to_l2_alias, L2Adapter};
+use crate::rpc::RpcClient;
+
+// EIP-2718 type of OP-stack deposit transactions
+const DEPOSIT_TX_TYPE: u64 = 0x7e;
+
+// Only the fields shared by every transaction type are read, deposits carry no gas price or signature
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BlockTransaction {
+    hash: TxHash,
+    transaction_index: U64,
+    #[serde(rename = "type")]
+    tx_type: U64,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Block {
+    transactions: Vec<BlockTransaction>,
+}
+
+/// Deposit transactions of the block mapped to their position, deposits always come first
+pub async fn deposit_transactions(
+    client: &RpcClient,
+    block_tag: &str,
+) -> anyhow::Result<HashMap<TxHash, u64>> {
+    let block = client
+        .request::<Block>("eth_getBlockByNumber", json!([block_tag, true]))
+        .await?;
+
+    Ok(block
+        .map(|block| block.transactions)
+        .unwrap_or_default()
+        .into_iter()
+        .take_while(|transaction| transaction.tx_type.to::<u64>() == DEPOSIT_TX_TYPE)
+        .map(|transaction| (transaction.hash, transaction.transaction_index.to::<u64>()))
+        .collect())
+}
+
+/// Optimism, Base and the other OP-stack chains, traced like Ethereum apart from their deposits
+#[derive(Debug)]
+pub struct OpStack;
+
+impl L2Adapter for OpStack {
+    fn name(&self) -> &'static str {
+        "op-stack"
+    }
+
+    fn system_transactions<'a>(
+        &'a self,
+        client: &'a RpcClient,
+        block_tag: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<HashMap<TxHash, u64>>> {
+        Box::pin(deposit_transactions(client, block_tag))
+    }
+
+    fn unalias(&self, address: Address) -> Option<Address> {
+        Some(undo_l1_to_l2_alias(address))
+    }
+
+    fn fee_fields(&self) -> &'static [&'static str] {
+        &["l1Fee", "l1GasUsed", "l1GasPrice", "l1BlobBaseFee"]
+    }
+}
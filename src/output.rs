@@ -0,0 +1,159 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
+use alloy_primitives::Address;
+use serde::Serialize;
+use serde_json::Value;
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum, Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum AddressFormat {
+    #[default]
+    Lowercase,
+    // EIP-55 mixed case
+    Checksum,
+}
+
+// How addresses are written to every output file, set once at startup
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AddressStyle {
+    pub format: AddressFormat,
+    pub strip_prefix: bool,
+}
+
+static ADDRESS_STYLE: OnceLock<AddressStyle> = OnceLock::new();
+
+// Every file written by this process, digested into the manifest of the run that wrote it
+static WRITTEN_FILES: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+pub fn record_written(path: PathBuf) {
+    WRITTEN_FILES.lock().unwrap().insert(path);
+}
+
+// Files written into the directory (or below it) that still exist
+pub fn written_files(dir: &Path) -> Vec<PathBuf> {
+    WRITTEN_FILES
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|path| path.starts_with(dir) && path.exists())
+        .cloned()
+        .collect()
+}
+
+// Chunked modes run the scan several times in one process, the first call wins
+pub fn set_address_style(style: AddressStyle) {
+    let _ = ADDRESS_STYLE.set(style);
+}
+
+fn address_style() -> AddressStyle {
+    ADDRESS_STYLE.get().copied().unwrap_or_default()
+}
+
+// Addresses serialize as lowercase `0x` hex, any other style rewrites every 20 byte hex string
+// of the serialized value, object keys included
+pub fn to_output_value<T: Serialize + ?Sized>(value: &T) -> anyhow::Result<Value> {
+    let mut value = serde_json::to_value(value)?;
+    let style = address_style();
+    if style.format != AddressFormat::Lowercase || style.strip_prefix {
+        restyle_addresses(&mut value, style);
+    }
+    Ok(value)
+}
+
+fn restyle_addresses(value: &mut Value, style: AddressStyle) {
+    match value {
+        Value::String(string) => {
+            if let Some(restyled) = restyle_address(string, style) {
+                *string = restyled;
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                restyle_addresses(value, style);
+            }
+        }
+        Value::Object(map) => {
+            *map = std::mem::take(map)
+                .into_iter()
+                .map(|(key, mut value)| {
+                    restyle_addresses(&mut value, style);
+                    (restyle_address(&key, style).unwrap_or(key), value)
+                })
+                .collect();
+        }
+        _ => {}
+    }
+}
+
+fn restyle_address(string: &str, style: AddressStyle) -> Option<String> {
+    if string.len() != 42 || !string.starts_with("0x") {
+        return None;
+    }
+    let address = Address::from_str(string).ok()?;
+    let formatted = match style.format {
+        AddressFormat::Lowercase => string.to_ascii_lowercase(),
+        AddressFormat::Checksum => address.to_checksum(None),
+    };
+    Some(match style.strip_prefix {
+        true => formatted.trim_start_matches("0x").to_string(),
+        false => formatted,
+    })
+}
+
+#[tracing::instrument(name = "write", skip(value))]
+pub fn write_json<T: Serialize + ?Sized>(
+    output_dir: &Path,
+    file_name: &str,
+    value: &T,
+) -> anyhow::Result<()> {
+    let started = Instant::now();
+    let json = serde_json::to_string(&to_output_value(value)?)?;
+    std::fs::write(output_dir.join(file_name), json)?;
+    record_written(output_dir.join(file_name));
+    metrics::histogram!("block_tracer_output_write_duration_seconds", "file" => file_name.to_string())
+        .record(started.elapsed().as_secs_f64());
+    Ok(())
+}
+
+#[tracing::instrument(name = "write", skip(values))]
+pub fn write_ndjson<T: Serialize>(
+    output_dir: &Path,
+    file_name: &str,
+    values: &[T],
+) -> anyhow::Result<()> {
+    let started = Instant::now();
+    let mut ndjson = String::new();
+    for value in values {
+        ndjson.push_str(&serde_json::to_string(&to_output_value(value)?)?);
+        ndjson.push('\n');
+    }
+    std::fs::write(output_dir.join(file_name), ndjson)?;
+    record_written(output_dir.join(file_name));
+    metrics::histogram!("block_tracer_output_write_duration_seconds", "file" => file_name.to_string())
+        .record(started.elapsed().as_secs_f64());
+    Ok(())
+}
+
+#[tracing::instrument(name = "write", skip(rows))]
+pub fn write_csv<T: Serialize>(
+    output_dir: &Path,
+    file_name: &str,
+    rows: &[T],
+) -> anyhow::Result<()> {
+    let started = Instant::now();
+    let mut writer = csv::Writer::from_path(output_dir.join(file_name))?;
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    writer.flush()?;
+    record_written(output_dir.join(file_name));
+    metrics::histogram!("block_tracer_output_write_duration_seconds", "file" => file_name.to_string())
+        .record(started.elapsed().as_secs_f64());
+    Ok(())
+}
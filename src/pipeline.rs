@@ -0,0 +1,213 @@
+//! Fetch, filter, enrich and verify steps of a scan, for applications that embed block-tracer
+//! and add their own logic in between.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use alloy_primitives::{Address, TxHash, U256};
+use reth_rpc_types::trace::parity::{
+    Action, CreateAction, CreateOutput, SelfdestructAction, TraceOutput,
+};
+
+use crate::chain_db::ChainDb;
+use crate::l2::{Ethereum, L2Adapter};
+use crate::rpc::RpcClient;
+use crate::traces::{self, BlockTrace};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordKind {
+    Create,
+    Selfdestruct,
+}
+
+/// A create or selfdestruct that took effect, reverted frames never produce records.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Record {
+    pub kind: RecordKind,
+    /// Created or destroyed contract
+    pub contract_address: Address,
+    pub block_number: u64,
+    pub transaction_position: u64,
+    pub transaction_hash: Option<TxHash>,
+    pub trace_address: Vec<usize>,
+    /// Creator of a created contract
+    pub deployer: Option<Address>,
+    /// Recipient of the balance of a destroyed contract
+    pub beneficiary: Option<Address>,
+    /// Balance swept by a selfdestruct, or endowment of a create
+    pub value: U256,
+    /// Whatever enrichers add
+    pub fields: BTreeMap<String, serde_json::Value>,
+}
+
+/// Decides which records the pipeline keeps. Closures taking a `&Record` are filters too.
+pub trait RecordFilter: Send + Sync {
+    fn keep(&self, record: &Record) -> bool;
+}
+
+impl<F> RecordFilter for F
+where
+    F: Fn(&Record) -> bool + Send + Sync,
+{
+    fn keep(&self, record: &Record) -> bool {
+        self(record)
+    }
+}
+
+/// Adds to the records the filters kept. Closures taking a `&mut Record` are enrichers too.
+pub trait Enricher: Send + Sync {
+    fn enrich(&self, record: &mut Record) -> anyhow::Result<()>;
+}
+
+impl<F> Enricher for F
+where
+    F: Fn(&mut Record) -> anyhow::Result<()> + Send + Sync,
+{
+    fn enrich(&self, record: &mut Record) -> anyhow::Result<()> {
+        self(record)
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PipelineOutput {
+    /// Kept and enriched records in chain order
+    pub records: Vec<Record>,
+    /// Destroyed contracts that exist again in the latest state, only checked with a datadir
+    pub recreated: Vec<Address>,
+}
+
+/// Traces a block range the way a scan does and runs the records through the registered filters
+/// and enrichers, in the order they were added, then verifies recreations against the datadir if
+/// one is set.
+pub struct Pipeline {
+    client: RpcClient,
+    adapter: Arc<dyn L2Adapter>,
+    db: Option<Box<dyn ChainDb>>,
+    filters: Vec<Box<dyn RecordFilter>>,
+    enrichers: Vec<Box<dyn Enricher>>,
+}
+
+impl Pipeline {
+    /// Traces through the client with the [`Ethereum`] adapter.
+    pub fn new(client: RpcClient) -> Self {
+        Self {
+            client,
+            adapter: Arc::new(Ethereum),
+            db: None,
+            filters: Vec::new(),
+            enrichers: Vec::new(),
+        }
+    }
+
+    /// Adapter of the chain the blocks are traced on, e.g. from [`crate::l2::adapter`].
+    pub fn with_adapter(mut self, adapter: Arc<dyn L2Adapter>) -> Self {
+        self.adapter = adapter;
+        self
+    }
+
+    /// Datadir recreations are verified against.
+    pub fn with_db(mut self, db: impl ChainDb + 'static) -> Self {
+        self.db = Some(Box::new(db));
+        self
+    }
+
+    /// A record is kept only when every filter keeps it.
+    pub fn with_filter(mut self, filter: impl RecordFilter + 'static) -> Self {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    pub fn with_enricher(mut self, enricher: impl Enricher + 'static) -> Self {
+        self.enrichers.push(Box::new(enricher));
+        self
+    }
+
+    /// Both ends are inclusive. Blocks the node does not know are skipped.
+    pub async fn run(&self, start_block: u64, end_block: u64) -> anyhow::Result<PipelineOutput> {
+        let mut records = Vec::new();
+        for block_number in start_block..=end_block {
+            let Some(block_traces) = traces::trace_block(
+                &self.client,
+                self.adapter.as_ref(),
+                &format!("0x{:x}", block_number),
+                block_number,
+                false,
+            )
+            .await?
+            else {
+                continue;
+            };
+            for mut record in block_traces
+                .into_iter()
+                .filter_map(|block_trace| record(block_number, block_trace))
+            {
+                if !self.filters.iter().all(|filter| filter.keep(&record)) {
+                    continue;
+                }
+                for enricher in self.enrichers.iter() {
+                    enricher.enrich(&mut record)?;
+                }
+                records.push(record);
+            }
+        }
+
+        let recreated = match &self.db {
+            Some(db) => {
+                let mut destroyed = records
+                    .iter()
+                    .filter(|record| record.kind == RecordKind::Selfdestruct)
+                    .map(|record| record.contract_address)
+                    .collect::<Vec<_>>();
+                destroyed.sort();
+                destroyed.dedup();
+                db.existing_accounts(&destroyed)?
+            }
+            None => Vec::new(),
+        };
+        Ok(PipelineOutput { records, recreated })
+    }
+}
+
+/// Record of a create or selfdestruct that took effect, `None` for other traces, failed creates
+/// and traces rolled back by a failed frame above them.
+fn record(block_number: u64, block_trace: BlockTrace) -> Option<Record> {
+    if block_trace.revert_reason.is_some() {
+        return None;
+    }
+    let trace = block_trace.trace;
+    let (kind, contract_address, deployer, beneficiary, value) = match (trace.action, trace.result)
+    {
+        (
+            Action::Create(CreateAction { from, value, .. }),
+            Some(TraceOutput::Create(CreateOutput { address, .. })),
+        ) => (RecordKind::Create, address, Some(from), None, value),
+        (
+            Action::Selfdestruct(SelfdestructAction {
+                address,
+                refund_address,
+                balance,
+            }),
+            _,
+        ) => (
+            RecordKind::Selfdestruct,
+            address,
+            None,
+            Some(refund_address),
+            balance,
+        ),
+        _ => return None,
+    };
+    Some(Record {
+        kind,
+        contract_address,
+        block_number,
+        transaction_position: block_trace.transaction_position,
+        transaction_hash: block_trace.transaction_hash,
+        trace_address: trace.trace_address,
+        deployer,
+        beneficiary,
+        value,
+        fields: BTreeMap::new(),
+    })
+}
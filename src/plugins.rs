@@ -0,0 +1,205 @@
+use std::path::{Path, PathBuf};
+
+use crate::webhook::{Finding, FindingKind};
+use crate::TraceResponse;
+
+// What a plugin's `filter` returns for a record
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Drop,
+    Keep,
+    // Keep the record and raise a `plugin` finding for it
+    Flag,
+}
+
+impl Decision {
+    fn from_code(code: i32) -> anyhow::Result<Self> {
+        match code {
+            0 => Ok(Decision::Drop),
+            1 => Ok(Decision::Keep),
+            2 => Ok(Decision::Flag),
+            _ => anyhow::bail!("Plugin returned unknown decision {}", code),
+        }
+    }
+}
+
+// User supplied WASM filters, run in the order given. The first plugin dropping a record drops
+// it, later plugins never see it
+#[derive(Default)]
+pub struct Plugins {
+    #[cfg(feature = "wasm")]
+    plugins: Vec<wasm::WasmPlugin>,
+}
+
+impl Plugins {
+    pub fn load(paths: &[PathBuf]) -> anyhow::Result<Self> {
+        #[cfg(feature = "wasm")]
+        return Ok(Plugins {
+            plugins: paths
+                .iter()
+                .map(|path| wasm::WasmPlugin::load(path))
+                .collect::<anyhow::Result<_>>()?,
+        });
+        #[cfg(not(feature = "wasm"))]
+        {
+            if !paths.is_empty() {
+                anyhow::bail!("--plugin requires building with `--features wasm`");
+            }
+            Ok(Plugins::default())
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        #[cfg(feature = "wasm")]
+        return self.plugins.is_empty();
+        #[cfg(not(feature = "wasm"))]
+        true
+    }
+
+    // Drops the records a plugin rejects and returns the findings for the flagged ones
+    pub fn apply(&self, trace_responses: &mut Vec<TraceResponse>) -> anyhow::Result<Vec<Finding>> {
+        let mut findings = Vec::new();
+        if self.is_empty() {
+            return Ok(findings);
+        }
+        let mut kept = Vec::with_capacity(trace_responses.len());
+        'records: for trace_response in trace_responses.drain(..) {
+            let record = serde_json::to_vec(&trace_response)?;
+            for (name, decision) in self.decisions(&record) {
+                match decision? {
+                    Decision::Drop => continue 'records,
+                    Decision::Keep => {}
+                    Decision::Flag => findings.push(Finding {
+                        kind: FindingKind::Plugin,
+                        rule: Some(name.to_string()),
+                        address: trace_response.contract_address,
+                        block_number: Some(trace_response.block_number),
+                        transaction_hash: trace_response.transaction_hash,
+                    }),
+                }
+            }
+            kept.push(trace_response);
+        }
+        *trace_responses = kept;
+        Ok(findings)
+    }
+
+    // Lazily, so plugins after a dropping one are not called
+    fn decisions<'a>(
+        &'a self,
+        record: &'a [u8],
+    ) -> impl Iterator<Item = (&'a str, anyhow::Result<Decision>)> + 'a {
+        #[cfg(feature = "wasm")]
+        return self
+            .plugins
+            .iter()
+            .map(move |plugin| (plugin.name.as_str(), plugin.filter(record)));
+        #[cfg(not(feature = "wasm"))]
+        {
+            let _ = record;
+            std::iter::empty()
+        }
+    }
+}
+
+// The file stem names the plugin in findings
+fn plugin_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+#[cfg(feature = "wasm")]
+mod wasm {
+    use std::path::Path;
+    use std::sync::Mutex;
+
+    use wasmtime::{Config, Engine, Instance, Memory, Module, Store, TypedFunc};
+
+    use super::Decision;
+
+    // Fuel a plugin gets per record, about one unit per WASM instruction. A plugin that loops
+    // forever fails the record instead of hanging the scan
+    const FUEL_PER_RECORD: u64 = 100_000_000;
+
+    // A module without imports exporting `memory`, `alloc(len) -> ptr` and
+    // `filter(ptr, len) -> decision`. Every record is passed as JSON, written to a buffer the
+    // module allocates for it and frees in its optional `dealloc(ptr, len)` export
+    pub struct WasmPlugin {
+        pub name: String,
+        module: Module,
+        instance: Mutex<PluginInstance>,
+    }
+
+    struct PluginInstance {
+        store: Store<()>,
+        memory: Memory,
+        alloc: TypedFunc<i32, i32>,
+        dealloc: Option<TypedFunc<(i32, i32), ()>>,
+        filter: TypedFunc<(i32, i32), i32>,
+    }
+
+    impl PluginInstance {
+        fn new(module: &Module) -> anyhow::Result<Self> {
+            let mut store = Store::new(module.engine(), ());
+            // The start function runs on fuel as well
+            store.set_fuel(FUEL_PER_RECORD)?;
+            let instance = Instance::new(&mut store, module, &[])?;
+            let memory = instance
+                .get_memory(&mut store, "memory")
+                .ok_or_else(|| anyhow::anyhow!("the module exports no memory"))?;
+            let alloc = instance.get_typed_func::<i32, i32>(&mut store, "alloc")?;
+            let dealloc = instance
+                .get_typed_func::<(i32, i32), ()>(&mut store, "dealloc")
+                .ok();
+            let filter = instance.get_typed_func::<(i32, i32), i32>(&mut store, "filter")?;
+            Ok(PluginInstance {
+                store,
+                memory,
+                alloc,
+                dealloc,
+                filter,
+            })
+        }
+
+        fn filter(&mut self, record: &[u8]) -> anyhow::Result<Decision> {
+            self.store.set_fuel(FUEL_PER_RECORD)?;
+            let len = i32::try_from(record.len())?;
+            let ptr = self.alloc.call(&mut self.store, len)?;
+            self.memory.write(&mut self.store, ptr as usize, record)?;
+            let code = self.filter.call(&mut self.store, (ptr, len))?;
+            if let Some(dealloc) = &self.dealloc {
+                dealloc.call(&mut self.store, (ptr, len))?;
+            }
+            Decision::from_code(code)
+        }
+    }
+
+    impl WasmPlugin {
+        pub fn load(path: &Path) -> anyhow::Result<Self> {
+            let engine = Engine::new(Config::new().consume_fuel(true))?;
+            let module = Module::from_file(&engine, path)?;
+            let instance = PluginInstance::new(&module)
+                .map_err(|err| anyhow::anyhow!("{}: {}", path.display(), err))?;
+            Ok(WasmPlugin {
+                name: super::plugin_name(path),
+                module,
+                instance: Mutex::new(instance),
+            })
+        }
+
+        pub fn filter(&self, record: &[u8]) -> anyhow::Result<Decision> {
+            let mut instance = self
+                .instance
+                .lock()
+                .map_err(|_| anyhow::anyhow!("Plugin {} panicked", self.name))?;
+            let decision = instance.filter(record);
+            // Without `dealloc` the buffers are never freed, and a trap can leave the module's
+            // state half updated, so the next record gets a fresh instance
+            if decision.is_err() || instance.dealloc.is_none() {
+                *instance = PluginInstance::new(&self.module)?;
+            }
+            decision.map_err(|err| anyhow::anyhow!("Plugin {} failed: {}", self.name, err))
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use alloy_primitives::{
+    address,
+    utils::{format_ether, format_units},
+    Address, Bytes, U256,
+};
+use serde_json::json;
+
+use crate::rpc::RpcClient;
+
+// Chainlink ETH/USD price feed proxy on mainnet, deployed at block 10606501
+const ETH_USD_FEED: Address = address!("5f4eC3Df9cbd43714FE2740f5E3616155c5b8419");
+pub const ETH_USD_FEED_CHAIN_ID: u64 = 1;
+const ETH_USD_FEED_DECIMALS: u8 = 8;
+// latestRoundData()
+const LATEST_ROUND_DATA_SELECTOR: [u8; 4] = [0xfe, 0xaf, 0x96, 0x8c];
+
+// ETH/USD answer of the feed as of the given block, None before the feed existed
+pub async fn eth_usd_price(client: &RpcClient, block_number: u64) -> anyhow::Result<Option<f64>> {
+    let block_num_hex = format!("0x{:x}", block_number);
+    let output = client
+        .request::<Bytes>(
+            "eth_call",
+            json!([
+                { "to": ETH_USD_FEED, "data": Bytes::from(LATEST_ROUND_DATA_SELECTOR) },
+                block_num_hex
+            ]),
+        )
+        .await?
+        .unwrap_or_default();
+
+    // (roundId, answer, startedAt, updatedAt, answeredInRound)
+    let Some(answer) = output.get(32..64) else {
+        return Ok(None);
+    };
+    let answer = U256::from_be_slice(answer);
+    Ok(Some(
+        format_units(answer, ETH_USD_FEED_DECIMALS)?.parse::<f64>()?,
+    ))
+}
+
+pub fn wei_to_usd(value: U256, eth_usd_price: f64) -> anyhow::Result<f64> {
+    Ok(format_ether(value).parse::<f64>()? * eth_usd_price)
+}
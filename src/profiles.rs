@@ -0,0 +1,150 @@
+use crate::follow::FollowArgs;
+use crate::progress::log_line;
+use crate::{Cmd, Command};
+
+// How the chain's usual clients trace blocks. block-tracer needs `trace_block`, which
+// geth-derived clients only serve when run on an Erigon or reth codebase
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFlavor {
+    Parity,
+    Geth,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChainProfile {
+    pub name: &'static str,
+    pub chain_id: u64,
+    pub trace_flavor: TraceFlavor,
+    // Rounded up to whole seconds, used as follow's poll interval
+    pub block_time: u64,
+    pub op_stack: bool,
+    pub arbitrum: bool,
+    // Rarely expose the trace API, they are a fallback to get the handshake going
+    pub public_rpc_urls: &'static [&'static str],
+}
+
+pub static PROFILES: &[ChainProfile] = &[
+    ChainProfile {
+        name: "ethereum",
+        chain_id: 1,
+        trace_flavor: TraceFlavor::Parity,
+        block_time: 12,
+        op_stack: false,
+        arbitrum: false,
+        public_rpc_urls: &["https://ethereum-rpc.publicnode.com"],
+    },
+    ChainProfile {
+        name: "polygon",
+        chain_id: 137,
+        trace_flavor: TraceFlavor::Geth,
+        block_time: 2,
+        op_stack: false,
+        arbitrum: false,
+        public_rpc_urls: &[
+            "https://polygon-rpc.com",
+            "https://polygon-bor-rpc.publicnode.com",
+        ],
+    },
+    ChainProfile {
+        name: "bsc",
+        chain_id: 56,
+        trace_flavor: TraceFlavor::Geth,
+        block_time: 1,
+        op_stack: false,
+        arbitrum: false,
+        public_rpc_urls: &[
+            "https://bsc-dataseed.bnbchain.org",
+            "https://bsc-rpc.publicnode.com",
+        ],
+    },
+    ChainProfile {
+        name: "gnosis",
+        chain_id: 100,
+        trace_flavor: TraceFlavor::Parity,
+        block_time: 5,
+        op_stack: false,
+        arbitrum: false,
+        public_rpc_urls: &[
+            "https://rpc.gnosischain.com",
+            "https://gnosis-rpc.publicnode.com",
+        ],
+    },
+    ChainProfile {
+        name: "avalanche",
+        chain_id: 43114,
+        trace_flavor: TraceFlavor::Geth,
+        block_time: 2,
+        op_stack: false,
+        arbitrum: false,
+        public_rpc_urls: &["https://api.avax.network/ext/bc/C/rpc"],
+    },
+    ChainProfile {
+        name: "base",
+        chain_id: 8453,
+        trace_flavor: TraceFlavor::Parity,
+        block_time: 2,
+        op_stack: true,
+        arbitrum: false,
+        public_rpc_urls: &[
+            "https://mainnet.base.org",
+            "https://base-rpc.publicnode.com",
+        ],
+    },
+    ChainProfile {
+        name: "arbitrum",
+        chain_id: 42161,
+        trace_flavor: TraceFlavor::Geth,
+        block_time: 1,
+        op_stack: false,
+        arbitrum: true,
+        public_rpc_urls: &["https://arb1.arbitrum.io/rpc"],
+    },
+];
+
+pub fn parse_profile(name: &str) -> Result<&'static ChainProfile, String> {
+    let name = name.to_lowercase();
+    let name = match name.as_str() {
+        "mainnet" => "ethereum",
+        "bnb" | "binance" => "bsc",
+        "avax" | "avalanche-c" => "avalanche",
+        "arbitrum-one" => "arbitrum",
+        name => name,
+    };
+    PROFILES
+        .iter()
+        .find(|profile| profile.name == name)
+        .ok_or_else(|| {
+            let names = PROFILES
+                .iter()
+                .map(|profile| profile.name)
+                .collect::<Vec<_>>();
+            format!("unknown chain, known chains are {}", names.join(", "))
+        })
+}
+
+// Fills in what the options left open, an explicit `--rpc-url` or `--poll-interval` always wins
+pub fn apply(cmd: &mut Cmd) {
+    let Some(profile) = cmd.chain else {
+        return;
+    };
+    cmd.chain_id = profile.chain_id;
+    cmd.op_stack |= profile.op_stack;
+    cmd.arbitrum |= profile.arbitrum;
+    if cmd.rpc_url.is_none() {
+        cmd.rpc_url = profile.public_rpc_urls.first().map(|url| url.to_string());
+        log_line(format!(
+            "RPC_URL is not set, using the public {} endpoint, which may not expose the trace API",
+            profile.name
+        ));
+    }
+    if let Some(Command::Follow(FollowArgs { poll_interval, .. })) = &mut cmd.command {
+        poll_interval.get_or_insert(profile.block_time);
+    }
+    // Nitro is traced over the debug API by the Arbitrum adapter
+    if profile.trace_flavor == TraceFlavor::Geth && !profile.arbitrum {
+        log_line(format!(
+            "Most {} clients only serve debug_ tracing, block-tracer needs a node with the trace API (e.g. an Erigon or reth based client)",
+            profile.name
+        ));
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Recent findings kept for display, older ones are in the output files
+const RECENT_FINDINGS: usize = 50;
+// Output lines kept for the dashboard log pane while it covers the terminal
+const RECENT_LOG_LINES: usize = 200;
+
+#[derive(Debug, Clone, Default)]
+pub struct EndpointStats {
+    pub requests: u64,
+    pub errors: u64,
+    /// Responses with HTTP 429, retried after the provider's Retry-After
+    pub rate_limited: u64,
+    pub total_latency: Duration,
+}
+
+/// Outcomes of the requests sent to one RPC provider, to compare providers by their error rates
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct ProviderStats {
+    pub requests: u64,
+    pub timeouts: u64,
+    /// HTTP 4xx responses other than 429
+    pub client_errors: u64,
+    pub server_errors: u64,
+    /// Bodies that were not a JSON-RPC response of the expected shape
+    pub deserialization_errors: u64,
+    /// Requests sent again after a 429
+    pub retries: u64,
+}
+
+impl ProviderStats {
+    pub fn failures(&self) -> u64 {
+        self.timeouts + self.client_errors + self.server_errors + self.deserialization_errors
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProviderEvent {
+    Request,
+    Timeout,
+    ClientError,
+    ServerError,
+    Deserialization,
+    Retry,
+}
+
+impl ProviderEvent {
+    fn as_str(self) -> &'static str {
+        match self {
+            ProviderEvent::Request => "request",
+            ProviderEvent::Timeout => "timeout",
+            ProviderEvent::ClientError => "4xx",
+            ProviderEvent::ServerError => "5xx",
+            ProviderEvent::Deserialization => "deserialization",
+            ProviderEvent::Retry => "retry",
+        }
+    }
+}
+
+/// Live state of the running scan, read by the dashboard
+#[derive(Debug)]
+pub struct Progress {
+    blocks_total: AtomicU64,
+    blocks_done: AtomicU64,
+    endpoints: Mutex<BTreeMap<String, EndpointStats>>,
+    // By provider URL reduced to scheme and host
+    providers: Mutex<BTreeMap<String, ProviderStats>>,
+    findings: Mutex<VecDeque<String>>,
+    failed_blocks: Mutex<Vec<u64>>,
+    // Response fields that were missing or malformed, by `method.field`
+    absent_fields: Mutex<BTreeMap<String, u64>>,
+    // Set while the dashboard is drawn, output then goes to `log` as well
+    dashboard: AtomicBool,
+    // Set when stdout is the terminal the dashboard covers, output then goes to `log` only
+    stdout_covered: AtomicBool,
+    log: Mutex<VecDeque<String>>,
+}
+
+static PROGRESS: Progress = Progress {
+    blocks_total: AtomicU64::new(0),
+    blocks_done: AtomicU64::new(0),
+    endpoints: Mutex::new(BTreeMap::new()),
+    providers: Mutex::new(BTreeMap::new()),
+    findings: Mutex::new(VecDeque::new()),
+    failed_blocks: Mutex::new(Vec::new()),
+    absent_fields: Mutex::new(BTreeMap::new()),
+    dashboard: AtomicBool::new(false),
+    stdout_covered: AtomicBool::new(false),
+    log: Mutex::new(VecDeque::new()),
+};
+
+pub fn progress() -> &'static Progress {
+    &PROGRESS
+}
+
+/// Prints a line of user-facing output. While the dashboard is drawn it goes to its log pane
+/// instead, and no longer to stdout once that is the terminal in raw mode it would garble
+pub fn log_line(output: impl std::fmt::Display) {
+    let output = output.to_string();
+    if !progress().log(&output) {
+        println!("{}", output);
+    }
+}
+
+impl Progress {
+    pub fn start_blocks(&self, blocks: u64) {
+        self.blocks_total.fetch_add(blocks, Ordering::Relaxed);
+    }
+
+    pub fn block_done(&self) {
+        self.blocks_done.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn block_failed(&self, block_num: u64) {
+        self.block_done();
+        self.failed_blocks.lock().unwrap().push(block_num);
+    }
+
+    pub fn request(&self, endpoint: &str, latency: Duration, failed: bool) {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        let stats = endpoints.entry(endpoint.to_string()).or_default();
+        stats.requests += 1;
+        stats.errors += failed as u64;
+        stats.total_latency += latency;
+    }
+
+    pub fn rate_limited(&self, endpoint: &str) {
+        metrics::counter!("block_tracer_rpc_rate_limited_total", "method" => endpoint.to_string())
+            .increment(1);
+        let mut endpoints = self.endpoints.lock().unwrap();
+        endpoints
+            .entry(endpoint.to_string())
+            .or_default()
+            .rate_limited += 1;
+    }
+
+    pub fn provider_event(&self, provider: &str, event: ProviderEvent) {
+        if event == ProviderEvent::Request {
+            metrics::counter!("block_tracer_rpc_provider_requests_total", "provider" => provider.to_string())
+                .increment(1);
+        } else {
+            metrics::counter!(
+                "block_tracer_rpc_provider_errors_total",
+                "provider" => provider.to_string(),
+                "kind" => event.as_str()
+            )
+            .increment(1);
+        }
+        let mut providers = self.providers.lock().unwrap();
+        let stats = providers.entry(provider.to_string()).or_default();
+        match event {
+            ProviderEvent::Request => stats.requests += 1,
+            ProviderEvent::Timeout => stats.timeouts += 1,
+            ProviderEvent::ClientError => stats.client_errors += 1,
+            ProviderEvent::ServerError => stats.server_errors += 1,
+            ProviderEvent::Deserialization => stats.deserialization_errors += 1,
+            ProviderEvent::Retry => stats.retries += 1,
+        }
+    }
+
+    pub fn absent_field(&self, method: &str, field: &str) {
+        let field = format!("{}.{}", method, field);
+        metrics::counter!("block_tracer_rpc_absent_fields_total", "field" => field.clone())
+            .increment(1);
+        *self.absent_fields.lock().unwrap().entry(field).or_default() += 1;
+    }
+
+    pub fn finding(&self, finding: String) {
+        let mut findings = self.findings.lock().unwrap();
+        if findings.len() == RECENT_FINDINGS {
+            findings.pop_front();
+        }
+        findings.push_back(finding);
+    }
+
+    pub fn set_dashboard(&self, active: bool, stdout_covered: bool) {
+        self.stdout_covered
+            .store(active && stdout_covered, Ordering::Relaxed);
+        self.dashboard.store(active, Ordering::Relaxed);
+    }
+
+    /// Keeps the output for the dashboard, false when it still has to be printed to stdout, see
+    /// `log_line`
+    pub fn log(&self, output: &str) -> bool {
+        if !self.dashboard.load(Ordering::Relaxed) {
+            return false;
+        }
+        let mut log = self.log.lock().unwrap();
+        for line in output.lines() {
+            if log.len() == RECENT_LOG_LINES {
+                log.pop_front();
+            }
+            log.push_back(line.to_string());
+        }
+        self.stdout_covered.load(Ordering::Relaxed)
+    }
+
+    pub fn blocks(&self) -> (u64, u64) {
+        (
+            self.blocks_done.load(Ordering::Relaxed),
+            self.blocks_total.load(Ordering::Relaxed),
+        )
+    }
+
+    pub fn endpoints(&self) -> BTreeMap<String, EndpointStats> {
+        self.endpoints.lock().unwrap().clone()
+    }
+
+    pub fn providers(&self) -> BTreeMap<String, ProviderStats> {
+        self.providers.lock().unwrap().clone()
+    }
+
+    pub fn findings(&self) -> Vec<String> {
+        self.findings.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn log_lines(&self) -> Vec<String> {
+        self.log.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn failed_blocks(&self) -> Vec<u64> {
+        self.failed_blocks.lock().unwrap().clone()
+    }
+
+    pub fn absent_fields(&self) -> BTreeMap<String, u64> {
+        self.absent_fields.lock().unwrap().clone()
+    }
+}
@@ -0,0 +1,22 @@
+use std::net::SocketAddr;
+
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder};
+
+use crate::progress::log_line;
+
+const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+
+// Serves `/metrics` on the given address for as long as the process runs
+pub fn install(listen_addr: SocketAddr) -> anyhow::Result<()> {
+    PrometheusBuilder::new()
+        .with_http_listener(listen_addr)
+        .set_buckets_for_metric(
+            Matcher::Suffix("duration_seconds".to_string()),
+            LATENCY_BUCKETS,
+        )?
+        .install()?;
+    log_line(format!("Serving metrics on http://{}/metrics", listen_addr));
+    Ok(())
+}
@@ -1,22 +1,406 @@
+use std::collections::BTreeSet;
+use std::env;
 use std::path::Path;
+use std::sync::Arc;
 
-use reth_chainspec::ChainSpecBuilder;
-use reth_db::{mdbx::DatabaseArguments, open_db_read_only, DatabaseEnv};
-use reth_provider::{providers::StaticFileProvider, ProviderFactory};
+use alloy_primitives::{Address, Bytes, TxHash, B256};
+use rayon::prelude::*;
+use reth_chainspec::{ChainSpec, HOLESKY, MAINNET, SEPOLIA};
+use reth_db::{mdbx::DatabaseArguments, open_db_read_only, tables, DatabaseEnv};
+use reth_db_api::{
+    cursor::{DbCursorRO, DbDupCursorRO},
+    models::{storage_sharded_key::StorageShardedKey, BlockNumberAddress},
+    transaction::DbTx,
+};
+use reth_primitives::TransactionSigned;
+use reth_provider::{
+    providers::StaticFileProvider, BlockReader, HeaderProvider, ProviderFactory, ReceiptProvider,
+    TransactionsProvider,
+};
+use reth_prune_types::PruneSegment;
 
+use crate::chain_db::{
+    BlockHeader, ChainDb, HistorySegment, LocalReceipt, LocalTransaction, Ommer, StorageChange,
+    StorageSlot, Withdrawal,
+};
+
+/// Default number of lookups after which `existing_accounts` reopens its read transaction.
+pub const DEFAULT_TX_REFRESH_INTERVAL: usize = 10_000;
+
+/// Chain spec of a datadir's chain. Only the chains reth ships a spec for can be opened, the
+/// tables of other chains (L2s in particular) are not read with a wrong one.
+pub fn chain_spec(chain_id: u64) -> anyhow::Result<Arc<ChainSpec>> {
+    match chain_id {
+        1 => Ok(MAINNET.clone()),
+        11155111 => Ok(SEPOLIA.clone()),
+        17000 => Ok(HOLESKY.clone()),
+        _ => anyhow::bail!(
+            "Reading the datadir of chain {} is not supported, only mainnet, Sepolia and Holesky datadirs are. Leave DB_PATH and STATIC_FILES_PATH unset to verify over RPC",
+            chain_id
+        ),
+    }
+}
+
+/// Opens the reth database and static files read only, with the chain spec of `chain_id`.
 pub fn get_reth_factory(
     db_path: &Path,
     static_files_path: &Path,
+    chain_id: u64,
 ) -> anyhow::Result<ProviderFactory<DatabaseEnv>> {
-    let db =
-        open_db_read_only(db_path, DatabaseArguments::default()).expect("Could not open database");
+    let spec = chain_spec(chain_id)?;
+    let db = open_db_read_only(db_path, DatabaseArguments::default()).map_err(|err| {
+        anyhow::anyhow!(
+            "Could not open the database at {}: {}",
+            db_path.display(),
+            err
+        )
+    })?;
+    let static_file_provider = StaticFileProvider::read_only(static_files_path).map_err(|err| {
+        anyhow::anyhow!(
+            "Could not open the static files at {}: {}",
+            static_files_path.display(),
+            err
+        )
+    })?;
+
+    Ok(ProviderFactory::new(db, spec, static_file_provider))
+}
+
+/// Opens the datadir given by the `DB_PATH` and `STATIC_FILES_PATH` environment variables.
+pub fn get_reth_factory_from_env(chain_id: u64) -> anyhow::Result<ProviderFactory<DatabaseEnv>> {
+    let db_path = env::var("DB_PATH")?;
+    let static_files_path = env::var("STATIC_FILES_PATH")?;
+    get_reth_factory(Path::new(&db_path), Path::new(&static_files_path), chain_id)
+}
+
+/// [`ChainDb`] over a reth 1.x datadir. Every call opens its own short read transaction, except
+/// `existing_accounts` which reuses one for `tx_refresh_interval` lookups.
+pub struct RethDb {
+    factory: ProviderFactory<DatabaseEnv>,
+    tx_refresh_interval: usize,
+}
+
+impl RethDb {
+    pub fn open(db_path: &Path, static_files_path: &Path, chain_id: u64) -> anyhow::Result<Self> {
+        Ok(Self {
+            factory: get_reth_factory(db_path, static_files_path, chain_id)?,
+            tx_refresh_interval: DEFAULT_TX_REFRESH_INTERVAL,
+        })
+    }
+
+    pub fn from_env(chain_id: u64) -> anyhow::Result<Self> {
+        Ok(Self {
+            factory: get_reth_factory_from_env(chain_id)?,
+            tx_refresh_interval: DEFAULT_TX_REFRESH_INTERVAL,
+        })
+    }
+
+    /// A read transaction pins the pages it has seen, so against a live datadir it is reopened
+    /// every `tx_refresh_interval` lookups to let the node reuse them.
+    pub fn with_tx_refresh_interval(mut self, tx_refresh_interval: usize) -> Self {
+        self.tx_refresh_interval = tx_refresh_interval.max(1);
+        self
+    }
+
+    pub fn factory(&self) -> &ProviderFactory<DatabaseEnv> {
+        &self.factory
+    }
+}
+
+fn local_transaction(
+    transaction: &TransactionSigned,
+    tx_number: u64,
+    block_number: u64,
+    index: u64,
+) -> LocalTransaction {
+    LocalTransaction {
+        hash: transaction.hash(),
+        tx_number,
+        block_number,
+        index,
+        tx_type: transaction.tx_type() as u8,
+        blob_count: transaction
+            .blob_versioned_hashes()
+            .map(|hashes| hashes.len())
+            .unwrap_or_default(),
+        nonce: transaction.nonce(),
+    }
+}
+
+impl ChainDb for RethDb {
+    fn canonical_hash(&self, block_number: u64) -> anyhow::Result<Option<B256>> {
+        Ok(self
+            .factory
+            .provider()?
+            .tx_ref()
+            .get::<tables::CanonicalHeaders>(block_number)?)
+    }
+
+    // Headers come from the static files
+    fn header(&self, block_number: u64) -> anyhow::Result<Option<BlockHeader>> {
+        Ok(self
+            .factory
+            .static_file_provider()
+            .sealed_header(block_number)?
+            .map(|header| BlockHeader {
+                hash: header.hash(),
+                timestamp: header.header().timestamp,
+                blob_gas_used: header.header().blob_gas_used,
+                excess_blob_gas: header.header().excess_blob_gas,
+            }))
+    }
+
+    fn transaction_by_hash(&self, hash: TxHash) -> anyhow::Result<Option<LocalTransaction>> {
+        let provider = self.factory.provider()?;
+        let Some(tx_number) = provider.transaction_id(hash)? else {
+            return Ok(None);
+        };
+        Ok(provider
+            .transaction_by_hash_with_meta(hash)?
+            .map(|(transaction, meta)| {
+                local_transaction(&transaction, tx_number, meta.block_number, meta.index)
+            }))
+    }
+
+    fn transaction_at(
+        &self,
+        block_number: u64,
+        index: u64,
+    ) -> anyhow::Result<Option<LocalTransaction>> {
+        let provider = self.factory.provider()?;
+        let Some(body_indices) = provider.block_body_indices(block_number)? else {
+            return Ok(None);
+        };
+        if index >= body_indices.tx_count {
+            return Ok(None);
+        }
+        let tx_number = body_indices.first_tx_num + index;
+        Ok(provider
+            .transaction_by_id(tx_number)?
+            .map(|transaction| local_transaction(&transaction, tx_number, block_number, index)))
+    }
+
+    fn block_transactions(&self, block_number: u64) -> anyhow::Result<Vec<LocalTransaction>> {
+        let provider = self.factory.provider()?;
+        let Some(body_indices) = provider.block_body_indices(block_number)? else {
+            return Ok(Vec::new());
+        };
+        Ok(provider
+            .transactions_by_block(block_number.into())?
+            .unwrap_or_default()
+            .iter()
+            .enumerate()
+            .map(|(index, transaction)| {
+                local_transaction(
+                    transaction,
+                    body_indices.first_tx_num + index as u64,
+                    block_number,
+                    index as u64,
+                )
+            })
+            .collect())
+    }
+
+    // Stored senders first, the rest is recovered from signatures in parallel
+    fn senders(&self, tx_numbers: &[u64]) -> anyhow::Result<Vec<Option<Address>>> {
+        let provider = self.factory.provider()?;
+        let mut senders = Vec::with_capacity(tx_numbers.len());
+        let mut unrecovered = Vec::new();
+        for (position, tx_number) in tx_numbers.iter().enumerate() {
+            let sender = provider.transaction_sender(*tx_number)?;
+            if sender.is_none() {
+                if let Some(transaction) = provider.transaction_by_id_no_hash(*tx_number)? {
+                    unrecovered.push((position, transaction));
+                }
+            }
+            senders.push(sender);
+        }
+
+        let recovered = unrecovered
+            .par_iter()
+            .map(|(position, transaction)| (*position, transaction.recover_signer()))
+            .collect::<Vec<_>>();
+        for (position, sender) in recovered {
+            senders[position] = sender;
+        }
+        Ok(senders)
+    }
+
+    // Receipts store the block's cumulative gas, a transaction's own gas is the difference to
+    // the one before it
+    fn receipts(&self, block_number: u64) -> anyhow::Result<Vec<LocalReceipt>> {
+        let receipts = self
+            .factory
+            .provider()?
+            .receipts_by_block(block_number.into())?
+            .unwrap_or_default();
+        let mut previous_gas_used = 0;
+        Ok(receipts
+            .into_iter()
+            .enumerate()
+            .map(|(index, receipt)| {
+                let gas_used = receipt.cumulative_gas_used - previous_gas_used;
+                previous_gas_used = receipt.cumulative_gas_used;
+                LocalReceipt {
+                    index: index as u64,
+                    success: receipt.success,
+                    gas_used,
+                }
+            })
+            .collect())
+    }
+
+    fn ommers(&self, start_block: u64, end_block: u64) -> anyhow::Result<Vec<Ommer>> {
+        let provider = self.factory.provider()?;
+        let mut ommers_cursor = provider.tx_ref().cursor_read::<tables::BlockOmmers>()?;
+        let mut ommers = Vec::new();
+        for entry in ommers_cursor.walk_range(start_block..=end_block)? {
+            let (block_number, block_ommers) = entry?;
+            for ommer in block_ommers.ommers {
+                ommers.push(Ommer {
+                    block_number,
+                    number: ommer.number,
+                    hash: ommer.hash_slow(),
+                    beneficiary: ommer.beneficiary,
+                });
+            }
+        }
+        Ok(ommers)
+    }
+
+    fn withdrawals(&self, start_block: u64, end_block: u64) -> anyhow::Result<Vec<Withdrawal>> {
+        let provider = self.factory.provider()?;
+        let mut withdrawals_cursor = provider
+            .tx_ref()
+            .cursor_read::<tables::BlockWithdrawals>()?;
+        let mut withdrawals = Vec::new();
+        for entry in withdrawals_cursor.walk_range(start_block..=end_block)? {
+            let (block_number, block_withdrawals) = entry?;
+            for withdrawal in block_withdrawals.withdrawals.iter() {
+                withdrawals.push(Withdrawal {
+                    block_number,
+                    address: withdrawal.address,
+                    amount_wei: withdrawal.amount_wei(),
+                });
+            }
+        }
+        Ok(withdrawals)
+    }
+
+    fn existing_accounts(&self, addresses: &[Address]) -> anyhow::Result<Vec<Address>> {
+        let mut existing = Vec::new();
+        for batch in addresses.chunks(self.tx_refresh_interval) {
+            let tx = self.factory.provider()?.into_tx();
+            let batch_existing = batch
+                .par_chunks(10)
+                .map(|chunk| -> anyhow::Result<Vec<Address>> {
+                    let mut plain_account_cursor = tx.cursor_read::<tables::PlainAccountState>()?;
+                    let mut chunk_existing = Vec::new();
+                    for address in chunk {
+                        if plain_account_cursor.seek_exact(*address)?.is_some() {
+                            chunk_existing.push(*address);
+                        }
+                    }
+                    Ok(chunk_existing)
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            existing.extend(batch_existing.into_iter().flatten());
+        }
+        Ok(existing)
+    }
+
+    fn bytecode(&self, address: Address) -> anyhow::Result<Option<(B256, Bytes)>> {
+        let provider = self.factory.provider()?;
+        let tx = provider.tx_ref();
+        let Some(account) = tx.get::<tables::PlainAccountState>(address)? else {
+            return Ok(None);
+        };
+        let Some(code_hash) = account.bytecode_hash else {
+            return Ok(None);
+        };
+        Ok(tx
+            .get::<tables::Bytecodes>(code_hash)?
+            .map(|bytecode| (code_hash, bytecode.original_bytes())))
+    }
+
+    fn pruned_segments(&self) -> anyhow::Result<Vec<(HistorySegment, u64)>> {
+        let provider = self.factory.provider()?;
+        let mut cursor = provider
+            .tx_ref()
+            .cursor_read::<tables::PruneCheckpoints>()?;
+        let mut segments = Vec::new();
+        for entry in cursor.walk(None)? {
+            let (segment, checkpoint) = entry?;
+            let Some(pruned_until) = checkpoint.block_number else {
+                continue;
+            };
+            let segment = match segment {
+                PruneSegment::SenderRecovery => HistorySegment::Senders,
+                PruneSegment::TransactionLookup => HistorySegment::TransactionLookup,
+                PruneSegment::StorageHistory => HistorySegment::StorageHistory,
+                PruneSegment::AccountHistory => HistorySegment::AccountHistory,
+                // Receipts are only read for gas used and success, a pruned block has none and
+                // its records go without them. Logs are never read
+                _ => continue,
+            };
+            segments.push((segment, pruned_until));
+        }
+        Ok(segments)
+    }
+
+    fn plain_storage(&self, address: Address) -> anyhow::Result<Vec<StorageSlot>> {
+        let provider = self.factory.provider()?;
+        let mut cursor = provider
+            .tx_ref()
+            .cursor_dup_read::<tables::PlainStorageState>()?;
+        let mut slots = Vec::new();
+        for entry in cursor.walk_dup(Some(address), None)? {
+            let (_, storage_entry) = entry?;
+            slots.push(StorageSlot {
+                slot: storage_entry.key,
+                value: storage_entry.value,
+            });
+        }
+        Ok(slots)
+    }
 
-    let spec = ChainSpecBuilder::mainnet().build();
-    let factory: ProviderFactory<reth_db::DatabaseEnv> = ProviderFactory::new(
-        db,
-        spec.into(),
-        StaticFileProvider::read_only(static_files_path).unwrap(),
-    );
+    // The history index lists the blocks that changed each slot of the contract, so only the
+    // changesets of those blocks are read instead of every changeset from `from_block` on
+    fn storage_changes(
+        &self,
+        address: Address,
+        from_block: u64,
+    ) -> anyhow::Result<Vec<StorageChange>> {
+        let provider = self.factory.provider()?;
+        let tx = provider.tx_ref();
+        let mut history_cursor = tx.cursor_read::<tables::StoragesHistory>()?;
+        let mut blocks = BTreeSet::new();
+        for entry in history_cursor.walk(Some(StorageShardedKey::new(address, B256::ZERO, 0)))? {
+            let (key, block_numbers) = entry?;
+            if key.address != address {
+                break;
+            }
+            blocks.extend(
+                block_numbers
+                    .iter()
+                    .filter(|block_number| *block_number >= from_block),
+            );
+        }
 
-    Ok(factory)
+        let mut changeset_cursor = tx.cursor_dup_read::<tables::StorageChangeSets>()?;
+        let mut changes = Vec::new();
+        for block_number in blocks {
+            for entry in changeset_cursor
+                .walk_dup(Some(BlockNumberAddress((block_number, address))), None)?
+            {
+                let (_, storage_entry) = entry?;
+                changes.push(StorageChange {
+                    block_number,
+                    slot: storage_entry.key,
+                    previous_value: storage_entry.value,
+                });
+            }
+        }
+        Ok(changes)
+    }
 }
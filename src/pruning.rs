@@ -0,0 +1,90 @@
+use alloy_primitives::{Address, U256};
+use block_tracer::chain_db::{ChainDb, HistorySegment};
+use serde_json::json;
+
+use crate::rpc::RpcClient;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    // Transaction senders from the local database
+    Senders,
+    // Transaction hash and index lookups in the local database
+    TransactionLookup,
+    // Storage changesets, used by storage history
+    StorageHistory,
+    AccountHistory,
+    // Node state at historical blocks, used by balance changes and USD values
+    HistoricalState,
+    // Node state at the end of the range, used by RPC-only verification
+    RecreationVerification,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Degradation {
+    pub capability: Capability,
+    pub reason: String,
+}
+
+pub fn is_degraded(degradations: &[Degradation], capability: Capability) -> bool {
+    degradations
+        .iter()
+        .any(|degradation| degradation.capability == capability)
+}
+
+// Segments the local node pruned up to a block inside the range
+pub fn db_degradations(db: &dyn ChainDb, start_block: u64) -> anyhow::Result<Vec<Degradation>> {
+    Ok(db
+        .pruned_segments()?
+        .into_iter()
+        .filter(|(_, pruned_until)| *pruned_until >= start_block)
+        .map(|(segment, pruned_until)| Degradation {
+            capability: match segment {
+                HistorySegment::Senders => Capability::Senders,
+                HistorySegment::TransactionLookup => Capability::TransactionLookup,
+                HistorySegment::StorageHistory => Capability::StorageHistory,
+                HistorySegment::AccountHistory => Capability::AccountHistory,
+            },
+            reason: format!(
+                "{:?} is pruned up to block {} in the local database",
+                segment, pruned_until
+            ),
+        })
+        .collect())
+}
+
+// Probes the node for state at the start and end of the range, a full node only keeps recent state
+pub async fn node_degradations(
+    client: &RpcClient,
+    start_block: u64,
+    end_block: u64,
+    rpc_only: bool,
+) -> Vec<Degradation> {
+    let mut degradations = Vec::new();
+    if !has_state_at(client, start_block).await {
+        degradations.push(Degradation {
+            capability: Capability::HistoricalState,
+            reason: format!("The node has no state at block {}", start_block),
+        });
+    }
+    if rpc_only && !has_state_at(client, end_block).await {
+        degradations.push(Degradation {
+            capability: Capability::RecreationVerification,
+            reason: format!(
+                "The node has no state at block {}, only recreations inside the range are found",
+                end_block
+            ),
+        });
+    }
+    degradations
+}
+
+async fn has_state_at(client: &RpcClient, block_num: u64) -> bool {
+    let block_num_hex = format!("0x{:x}", block_num);
+    matches!(
+        client
+            .request::<U256>("eth_getBalance", json!([Address::ZERO, block_num_hex]))
+            .await,
+        Ok(Some(_))
+    )
+}
@@ -0,0 +1,93 @@
+use std::collections::{BTreeSet, HashMap};
+
+use alloy_primitives::{TxHash, U64};
+use block_tracer::chain_db::ChainDb;
+use serde_json::json;
+
+use crate::rpc::RpcClient;
+use crate::TraceResponse;
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RpcReceipt {
+    transaction_hash: TxHash,
+    transaction_index: U64,
+    gas_used: U64,
+    // Receipts before Byzantium carry a state root instead
+    #[serde(default)]
+    status: Option<U64>,
+}
+
+// Gas used and status of the record's transaction from the local receipts, matched by the
+// record's position in the block. Records without a transaction (rewards) match none
+pub fn apply_local_receipts<'a>(
+    db: &dyn ChainDb,
+    trace_responses: impl Iterator<Item = &'a mut TraceResponse>,
+) -> anyhow::Result<()> {
+    let mut receipts = HashMap::new();
+    for trace_response in trace_responses {
+        if !receipts.contains_key(&trace_response.block_number) {
+            receipts.insert(
+                trace_response.block_number,
+                db.receipts(trace_response.block_number)?,
+            );
+        }
+        let Some(receipt) = receipts[&trace_response.block_number]
+            .iter()
+            .find(|receipt| receipt.index == trace_response.transaction_position)
+        else {
+            continue;
+        };
+        trace_response.transaction_gas_used = Some(receipt.gas_used);
+        trace_response.transaction_success = Some(receipt.success);
+    }
+    Ok(())
+}
+
+// Receipts the local datadir did not provide (no datadir, pruned receipts) are read with one
+// `eth_getBlockReceipts` per block, which also fills in missing transaction hashes
+pub async fn apply_rpc_receipts<'a>(
+    client: &RpcClient,
+    trace_responses: impl Iterator<Item = &'a mut TraceResponse>,
+) -> anyhow::Result<()> {
+    let mut trace_responses = trace_responses
+        .filter(|trace_response| trace_response.transaction_gas_used.is_none())
+        .collect::<Vec<_>>();
+    let block_numbers = trace_responses
+        .iter()
+        .map(|trace_response| trace_response.block_number)
+        .collect::<BTreeSet<_>>();
+    let block_receipts = client
+        .request_batch::<Vec<RpcReceipt>>(
+            "eth_getBlockReceipts",
+            block_numbers
+                .iter()
+                .map(|block_number| json!([format!("0x{:x}", block_number)]))
+                .collect(),
+        )
+        .await?;
+    let mut receipts = HashMap::new();
+    for (block_number, block_receipts) in block_numbers.iter().zip(block_receipts) {
+        for receipt in block_receipts?.unwrap_or_default() {
+            receipts.insert(
+                (*block_number, receipt.transaction_index.to::<u64>()),
+                receipt,
+            );
+        }
+    }
+
+    for trace_response in trace_responses.iter_mut() {
+        let Some(receipt) = receipts.get(&(
+            trace_response.block_number,
+            trace_response.transaction_position,
+        )) else {
+            continue;
+        };
+        trace_response
+            .transaction_hash
+            .get_or_insert(receipt.transaction_hash);
+        trace_response.transaction_gas_used = Some(receipt.gas_used.to::<u64>());
+        trace_response.transaction_success = receipt.status.map(|status| status == U64::from(1));
+    }
+    Ok(())
+}
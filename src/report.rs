@@ -0,0 +1,706 @@
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use alloy_primitives::Address;
+
+use crate::creators::CreatorKind;
+use crate::failures::FailedBlock;
+use crate::index::ResultIndex;
+use crate::labels::Label;
+use crate::manifest::Manifest;
+use crate::output;
+use crate::progress::log_line;
+use crate::shard::read_json;
+use crate::validation;
+use crate::{TraceResponse, TraceType};
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct ReportArgs {
+    #[command(subcommand)]
+    pub report: Report,
+}
+
+#[derive(clap::Subcommand, Debug, Clone)]
+pub enum Report {
+    /// Totals, unique addresses, recreations and findings per block of a completed run
+    Summary(RunArgs),
+    /// Creates, selfdestructs and recreations per calendar day or week, as CSV
+    Timeseries(TimeseriesArgs),
+    /// Selfdestructs and recreations per bucket of blocks across the scanned range, as CSV
+    Histogram(HistogramArgs),
+    /// Precision and recall of a run against a reference dataset of creates and selfdestructs
+    Validate(ValidateArgs),
+    /// Deployers ranked by creations, destroyed contracts and recreations
+    Deployers(DeployersArgs),
+    /// Addresses ranked by how often they were destroyed and recreated
+    Metamorphic(MetamorphicArgs),
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct RunArgs {
+    /// Output directory of the run to report on
+    pub run_dir: PathBuf,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct TimeseriesArgs {
+    #[command(flatten)]
+    pub run: RunArgs,
+    #[arg(long, value_enum, default_value_t = Period::Day)]
+    pub period: Period,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct HistogramArgs {
+    #[command(flatten)]
+    pub run: RunArgs,
+    /// Blocks per bucket
+    #[arg(long, default_value_t = 1000)]
+    pub bucket_size: u64,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct ValidateArgs {
+    #[command(flatten)]
+    pub run: RunArgs,
+    /// CSV (or, with the `parquet` feature, Parquet) file with `block_number`, `address` and
+    /// `trace_type` columns
+    #[arg(long)]
+    pub reference: PathBuf,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct DeployersArgs {
+    #[command(flatten)]
+    pub run: RunArgs,
+    /// Number of deployers in the leaderboard
+    #[arg(long, default_value_t = 20)]
+    pub top: usize,
+    #[arg(long, value_enum, default_value_t = RankBy::Creations)]
+    pub rank_by: RankBy,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct MetamorphicArgs {
+    #[command(flatten)]
+    pub run: RunArgs,
+    /// Number of addresses in the report
+    #[arg(long, default_value_t = 20)]
+    pub top: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum RankBy {
+    Creations,
+    Destroyed,
+    Recreations,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Period {
+    Day,
+    // ISO weeks, starting on Monday
+    Week,
+}
+
+// Buckets printed by `report histogram`, the CSV has all of them
+const BUSIEST_BUCKETS: usize = 10;
+
+pub fn run(args: ReportArgs, output_dir: &Path) -> anyhow::Result<()> {
+    std::fs::create_dir_all(output_dir)?;
+    match args.report {
+        Report::Summary(args) => {
+            let summary = summary(&RunOutput::load(&args.run_dir)?);
+            log_line(serde_json::to_string_pretty(&summary)?);
+            output::write_json(output_dir, "report_summary.json", &summary)
+        }
+        Report::Timeseries(args) => {
+            let rows = timeseries(&RunOutput::load(&args.run.run_dir)?, args.period);
+            let file_name = match args.period {
+                Period::Day => "timeseries_daily.csv",
+                Period::Week => "timeseries_weekly.csv",
+            };
+            log_line(format!("{} periods written to {}", rows.len(), file_name));
+            output::write_csv(output_dir, file_name, &rows)
+        }
+        Report::Histogram(args) => {
+            let buckets = histogram(&RunOutput::load(&args.run.run_dir)?, args.bucket_size)?;
+            let mut busiest = buckets
+                .iter()
+                .filter(|bucket| bucket.selfdestructs > 0)
+                .collect::<Vec<_>>();
+            busiest.sort_by_key(|bucket| std::cmp::Reverse(bucket.selfdestructs));
+            for bucket in busiest.into_iter().take(BUSIEST_BUCKETS) {
+                log_line(format!(
+                    "Blocks {}-{}: {} selfdestructs, {} recreations",
+                    bucket.start_block, bucket.end_block, bucket.selfdestructs, bucket.recreations
+                ));
+            }
+            output::write_csv(output_dir, "histogram.csv", &buckets)
+        }
+        Report::Validate(args) => {
+            let validation = validation::validate(
+                &RunOutput::load(&args.run.run_dir)?,
+                validation::load_reference(&args.reference)?,
+            );
+            log_line(format!(
+                "{} records matched, precision {:.4}, recall {:.4}",
+                validation.matched, validation.precision, validation.recall
+            ));
+            output::write_json(output_dir, "validation.json", &validation)
+        }
+        Report::Deployers(args) => {
+            let leaderboard =
+                deployer_leaderboard(&RunOutput::load(&args.run.run_dir)?, args.rank_by, args.top);
+            for (rank, deployer) in leaderboard.iter().enumerate() {
+                log_line(format!(
+                    "{:>3}. {} {} creations, {} destroyed, {} recreations",
+                    rank + 1,
+                    deployer.deployer,
+                    deployer.creations,
+                    deployer.destroyed,
+                    deployer.recreations
+                ));
+            }
+            output::write_json(output_dir, "deployer_leaderboard.json", &leaderboard)
+        }
+        Report::Metamorphic(args) => {
+            let addresses = most_recreated(&RunOutput::load(&args.run.run_dir)?, args.top);
+            for (rank, address) in addresses.iter().enumerate() {
+                log_line(format!(
+                    "{:>3}. {} {} cycles, blocks {}-{}",
+                    rank + 1,
+                    address.address,
+                    address.cycles,
+                    address.first_recreated_block,
+                    address.last_recreated_block
+                ));
+            }
+            output::write_json(output_dir, "most_recreated.json", &addresses)
+        }
+    }
+}
+
+// What a scan wrote to its output directory
+pub struct RunOutput {
+    pub trace_records: Vec<TraceResponse>,
+    pub reinitialized_contracts: Vec<Address>,
+    // Blocks that were scanned, failed ones included
+    pub blocks: BlockSet,
+    pub failed_blocks: Vec<FailedBlock>,
+}
+
+#[derive(Debug, Clone)]
+pub enum BlockSet {
+    Range(u64, u64),
+    Listed(Vec<u64>),
+}
+
+impl BlockSet {
+    pub fn count(&self) -> u64 {
+        match self {
+            BlockSet::Range(start_block, end_block) => (end_block + 1).saturating_sub(*start_block),
+            BlockSet::Listed(blocks) => blocks.len() as u64,
+        }
+    }
+
+    pub fn contains(&self, block_number: u64) -> bool {
+        match self {
+            BlockSet::Range(start_block, end_block) => {
+                (*start_block..=*end_block).contains(&block_number)
+            }
+            BlockSet::Listed(blocks) => blocks.binary_search(&block_number).is_ok(),
+        }
+    }
+
+    pub fn bounds(&self) -> Option<(u64, u64)> {
+        match self {
+            BlockSet::Range(start_block, end_block) => Some((*start_block, *end_block)),
+            BlockSet::Listed(blocks) => Some((*blocks.first()?, *blocks.last()?)),
+        }
+    }
+}
+
+impl RunOutput {
+    // The scanned blocks come from the manifest, a run without one (e.g. merged chunks) is taken
+    // to cover its first to its last record
+    pub fn load(run_dir: &Path) -> anyhow::Result<Self> {
+        let trace_records = read_json::<Vec<TraceResponse>>(&run_dir.join("trace_records.json"))?;
+        let reinitialized_contracts = match run_dir.join("reinitialized_contracts.json") {
+            path if path.exists() => read_json(&path)?,
+            _ => Vec::new(),
+        };
+        let failed_blocks = match run_dir.join("failed_blocks.json") {
+            path if path.exists() => read_json(&path)?,
+            _ => Vec::new(),
+        };
+        let blocks = match Manifest::load(run_dir)? {
+            Some(manifest) => match manifest.params.blocks {
+                Some(blocks) => BlockSet::Listed(blocks),
+                None => BlockSet::Range(manifest.params.start_block, manifest.params.end_block),
+            },
+            None => BlockSet::Range(
+                trace_records
+                    .iter()
+                    .map(|trace_record| trace_record.block_number)
+                    .min()
+                    .unwrap_or_default(),
+                trace_records
+                    .iter()
+                    .map(|trace_record| trace_record.block_number)
+                    .max()
+                    .unwrap_or_default(),
+            ),
+        };
+        Ok(Self {
+            trace_records,
+            reinitialized_contracts,
+            blocks,
+            failed_blocks,
+        })
+    }
+
+    // Creates and selfdestructs of the run in chain order, reverted ones (--include-reverted) and
+    // call and reward records (--trace-types) left out
+    pub fn effective_records(&self) -> impl Iterator<Item = &TraceResponse> {
+        self.trace_records.iter().filter(|trace_record| {
+            trace_record.revert_reason.is_none()
+                && matches!(
+                    trace_record.trace_type,
+                    TraceType::Create | TraceType::SelfDestruct
+                )
+        })
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Summary {
+    pub first_block: Option<u64>,
+    pub last_block: Option<u64>,
+    pub blocks: u64,
+    pub failed_blocks: u64,
+    pub blocks_with_findings: u64,
+    pub empty_blocks: u64,
+    pub records: usize,
+    pub creates: usize,
+    // Creates split by their deployer, runs from before the classification count in neither
+    pub eoa_creates: usize,
+    pub contract_creates: usize,
+    pub selfdestructs: usize,
+    pub reverted: usize,
+    pub unique_contracts: usize,
+    pub unique_deployers: usize,
+    pub unique_beneficiaries: usize,
+    // Creates at an address destroyed earlier in the run
+    pub recreations: usize,
+    pub reinitialized_contracts: usize,
+    pub findings_per_block: Percentiles,
+}
+
+// Nearest-rank percentiles of the number of records per scanned block, empty blocks included
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Percentiles {
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub max: u64,
+}
+
+pub fn summary(run: &RunOutput) -> Summary {
+    let mut per_block: BTreeMap<u64, u64> = BTreeMap::new();
+    let (mut creates, mut selfdestructs) = (0, 0);
+    let (mut eoa_creates, mut contract_creates) = (0, 0);
+    let mut contracts = HashSet::new();
+    let mut deployers = HashSet::new();
+    let mut beneficiaries = HashSet::new();
+    for trace_record in run.effective_records() {
+        *per_block.entry(trace_record.block_number).or_default() += 1;
+        match trace_record.trace_type {
+            TraceType::Create => creates += 1,
+            TraceType::SelfDestruct => selfdestructs += 1,
+            TraceType::Call | TraceType::Reward => {}
+        }
+        match trace_record.creator_kind {
+            Some(CreatorKind::Eoa) => eoa_creates += 1,
+            Some(CreatorKind::Contract) => contract_creates += 1,
+            None => {}
+        }
+        contracts.insert(trace_record.contract_address);
+        deployers.extend(trace_record.deployer);
+        beneficiaries.extend(trace_record.beneficiary);
+    }
+
+    let failed_blocks = run.failed_blocks.len() as u64;
+    let traced_blocks = run.blocks.count().saturating_sub(failed_blocks);
+    let blocks_with_findings = per_block.len() as u64;
+    let empty_blocks = traced_blocks.saturating_sub(blocks_with_findings);
+    let mut counts = per_block.values().copied().collect::<Vec<_>>();
+    counts.sort_unstable();
+    let bounds = run.blocks.bounds();
+
+    Summary {
+        first_block: bounds.map(|(first_block, _)| first_block),
+        last_block: bounds.map(|(_, last_block)| last_block),
+        blocks: run.blocks.count(),
+        failed_blocks,
+        blocks_with_findings,
+        empty_blocks,
+        records: creates + selfdestructs,
+        creates,
+        eoa_creates,
+        contract_creates,
+        selfdestructs,
+        reverted: run
+            .trace_records
+            .iter()
+            .filter(|trace_record| {
+                trace_record.revert_reason.is_some()
+                    && matches!(
+                        trace_record.trace_type,
+                        TraceType::Create | TraceType::SelfDestruct
+                    )
+            })
+            .count(),
+        unique_contracts: contracts.len(),
+        unique_deployers: deployers.len(),
+        unique_beneficiaries: beneficiaries.len(),
+        recreations: ResultIndex::new(run.trace_records.clone())
+            .recreations(0, u64::MAX)
+            .len(),
+        reinitialized_contracts: run.reinitialized_contracts.len(),
+        findings_per_block: Percentiles {
+            p50: percentile(&counts, empty_blocks, 0.5),
+            p90: percentile(&counts, empty_blocks, 0.9),
+            p99: percentile(&counts, empty_blocks, 0.99),
+            max: counts.last().copied().unwrap_or_default(),
+        },
+    }
+}
+
+// `counts` are the sorted counts of the non-empty blocks, the empty ones rank below all of them
+fn percentile(counts: &[u64], empty_blocks: u64, percentile: f64) -> u64 {
+    let total = empty_blocks + counts.len() as u64;
+    if total == 0 {
+        return 0;
+    }
+    let rank = ((percentile * total as f64).ceil() as u64).max(1) - 1;
+    match rank.checked_sub(empty_blocks) {
+        Some(rank) => counts[rank as usize],
+        None => 0,
+    }
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct PeriodCounts {
+    // First day of the period, `YYYY-MM-DD` in UTC
+    pub period_start: String,
+    pub creates: u64,
+    pub selfdestructs: u64,
+    pub recreations: u64,
+}
+
+// Periods without activity are left out. Records without a block timestamp (RPC-only runs)
+// cannot be placed and are skipped with a warning
+pub fn timeseries(run: &RunOutput, period: Period) -> Vec<PeriodCounts> {
+    let mut timestamps = BTreeMap::new();
+    let mut untimed = 0;
+    let mut periods: BTreeMap<i64, PeriodCounts> = BTreeMap::new();
+    for trace_record in run.effective_records() {
+        let Some(block_timestamp) = trace_record.block_timestamp else {
+            untimed += 1;
+            continue;
+        };
+        timestamps.insert(trace_record.block_number, block_timestamp);
+        let counts = period_counts(&mut periods, block_timestamp, period);
+        match trace_record.trace_type {
+            TraceType::Create => counts.creates += 1,
+            TraceType::SelfDestruct => counts.selfdestructs += 1,
+            TraceType::Call | TraceType::Reward => {}
+        }
+    }
+    // A recreation is a create, so its block always has a timestamp when the create had one
+    for recreation in ResultIndex::new(run.trace_records.clone()).recreations(0, u64::MAX) {
+        if let Some(block_timestamp) = timestamps.get(&recreation.block_number) {
+            period_counts(&mut periods, *block_timestamp, period).recreations += 1;
+        }
+    }
+    if untimed > 0 {
+        log_line(format!(
+            "{} records have no block timestamp and are left out of the time series",
+            untimed
+        ));
+    }
+    periods.into_values().collect()
+}
+
+fn period_counts(
+    periods: &mut BTreeMap<i64, PeriodCounts>,
+    block_timestamp: u64,
+    period: Period,
+) -> &mut PeriodCounts {
+    let day = (block_timestamp / 86_400) as i64;
+    let first_day = match period {
+        Period::Day => day,
+        // 1970-01-01 was a Thursday
+        Period::Week => day - (day + 3).rem_euclid(7),
+    };
+    periods.entry(first_day).or_insert_with(|| PeriodCounts {
+        period_start: format_date(first_day),
+        ..Default::default()
+    })
+}
+
+// Days since the Unix epoch to a proleptic Gregorian date, Howard Hinnant's `civil_from_days`
+pub fn format_date(days: i64) -> String {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Bucket {
+    pub start_block: u64,
+    pub end_block: u64,
+    pub selfdestructs: u64,
+    pub recreations: u64,
+}
+
+// Buckets are aligned to multiples of the bucket size and cover the scanned range, empty ones
+// included, so bursts stand out against the quiet stretches around them
+pub fn histogram(run: &RunOutput, bucket_size: u64) -> anyhow::Result<Vec<Bucket>> {
+    if bucket_size == 0 {
+        anyhow::bail!("--bucket-size must be at least 1");
+    }
+    let Some((first_block, last_block)) = run.blocks.bounds() else {
+        return Ok(Vec::new());
+    };
+    let first_bucket = first_block / bucket_size;
+    let mut buckets = (first_bucket..=last_block / bucket_size)
+        .map(|bucket| Bucket {
+            start_block: (bucket * bucket_size).max(first_block),
+            end_block: (bucket * bucket_size + bucket_size - 1).min(last_block),
+            selfdestructs: 0,
+            recreations: 0,
+        })
+        .collect::<Vec<_>>();
+    let bucket_index = |block_number: u64| {
+        (block_number / bucket_size)
+            .checked_sub(first_bucket)
+            .map(|bucket| bucket as usize)
+    };
+
+    for trace_record in run
+        .effective_records()
+        .filter(|trace_record| trace_record.trace_type == TraceType::SelfDestruct)
+    {
+        if let Some(bucket) =
+            bucket_index(trace_record.block_number).and_then(|bucket| buckets.get_mut(bucket))
+        {
+            bucket.selfdestructs += 1;
+        }
+    }
+    for recreation in ResultIndex::new(run.trace_records.clone()).recreations(0, u64::MAX) {
+        if let Some(bucket) =
+            bucket_index(recreation.block_number).and_then(|bucket| buckets.get_mut(bucket))
+        {
+            bucket.recreations += 1;
+        }
+    }
+    Ok(buckets)
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct BlockCounts {
+    pub block: u64,
+    pub creations: u64,
+    pub selfdestructs: u64,
+    pub recreations: u64,
+    pub failed: bool,
+}
+
+// One row per scanned block, empty and failed ones included, so gaps in coverage show
+pub fn block_counts<'a>(
+    blocks: &[u64],
+    trace_records: impl Iterator<Item = &'a TraceResponse>,
+    failed_blocks: &[FailedBlock],
+) -> Vec<BlockCounts> {
+    let mut rows = blocks
+        .iter()
+        .map(|&block| {
+            (
+                block,
+                BlockCounts {
+                    block,
+                    ..Default::default()
+                },
+            )
+        })
+        .collect::<BTreeMap<_, _>>();
+    let trace_records = trace_records
+        .filter(|trace_record| trace_record.revert_reason.is_none())
+        .cloned()
+        .collect::<Vec<_>>();
+    for trace_record in trace_records.iter() {
+        if let Some(row) = rows.get_mut(&trace_record.block_number) {
+            match trace_record.trace_type {
+                TraceType::Create => row.creations += 1,
+                TraceType::SelfDestruct => row.selfdestructs += 1,
+                TraceType::Call | TraceType::Reward => {}
+            }
+        }
+    }
+    for recreation in ResultIndex::new(trace_records).recreations(0, u64::MAX) {
+        if let Some(row) = rows.get_mut(&recreation.block_number) {
+            row.recreations += 1;
+        }
+    }
+    for failed_block in failed_blocks {
+        if let Some(row) = rows.get_mut(&failed_block.block_number) {
+            row.failed = true;
+        }
+    }
+    rows.into_values().collect()
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DeployerStats {
+    // EOA or factory contract that performed the creates
+    pub deployer: Address,
+    pub kind: Option<CreatorKind>,
+    pub label: Option<Label>,
+    pub creations: u64,
+    // Contracts it created that were selfdestructed later in the run
+    pub destroyed: u64,
+    // Creates at addresses that were destroyed earlier in the run
+    pub recreations: u64,
+}
+
+// Walks every address's records in chain order, crediting each create and what later happens
+// to the contract to the deployer of that create. Ties are broken by the other counts
+pub fn deployer_leaderboard(run: &RunOutput, rank_by: RankBy, top: usize) -> Vec<DeployerStats> {
+    let mut by_address: BTreeMap<Address, Vec<&TraceResponse>> = BTreeMap::new();
+    for trace_record in run.effective_records() {
+        by_address
+            .entry(trace_record.contract_address)
+            .or_default()
+            .push(trace_record);
+    }
+
+    let mut deployers: BTreeMap<Address, DeployerStats> = BTreeMap::new();
+    for events in by_address.values() {
+        let mut destroyed = false;
+        let mut current_deployer = None;
+        for event in events {
+            match event.trace_type {
+                TraceType::Create => {
+                    current_deployer = event.deployer;
+                    if let Some(deployer) = event.deployer {
+                        let stats = deployers.entry(deployer).or_insert_with(|| DeployerStats {
+                            deployer,
+                            kind: event.creator_kind,
+                            label: event.deployer_label.clone(),
+                            creations: 0,
+                            destroyed: 0,
+                            recreations: 0,
+                        });
+                        stats.creations += 1;
+                        stats.recreations += destroyed as u64;
+                    }
+                    destroyed = false;
+                }
+                TraceType::SelfDestruct => {
+                    if let Some(stats) = current_deployer
+                        .take()
+                        .and_then(|deployer| deployers.get_mut(&deployer))
+                    {
+                        stats.destroyed += 1;
+                    }
+                    destroyed = true;
+                }
+                TraceType::Call | TraceType::Reward => {}
+            }
+        }
+    }
+
+    let mut leaderboard = deployers.into_values().collect::<Vec<_>>();
+    leaderboard.sort_by_key(|stats| {
+        std::cmp::Reverse(match rank_by {
+            RankBy::Creations => (stats.creations, stats.destroyed, stats.recreations),
+            RankBy::Destroyed => (stats.destroyed, stats.recreations, stats.creations),
+            RankBy::Recreations => (stats.recreations, stats.destroyed, stats.creations),
+        })
+    });
+    leaderboard.truncate(top);
+    leaderboard
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RecreatedAddress {
+    pub address: Address,
+    pub label: Option<Label>,
+    // Selfdestructs followed by a create at the same address
+    pub cycles: u64,
+    pub first_recreated_block: u64,
+    pub last_recreated_block: u64,
+    // Distinct code hashes deployed at the address, more than one is a metamorphic contract
+    pub code_hashes: usize,
+}
+
+// Most cycles first, so the addresses that deserve manual review come first
+pub fn most_recreated(run: &RunOutput, top: usize) -> Vec<RecreatedAddress> {
+    let mut addresses: BTreeMap<Address, RecreatedAddress> = BTreeMap::new();
+    for recreation in ResultIndex::new(run.trace_records.clone()).recreations(0, u64::MAX) {
+        let address = addresses
+            .entry(recreation.address)
+            .or_insert_with(|| RecreatedAddress {
+                address: recreation.address,
+                label: None,
+                cycles: 0,
+                first_recreated_block: recreation.block_number,
+                last_recreated_block: recreation.block_number,
+                code_hashes: 0,
+            });
+        address.cycles += 1;
+        address.first_recreated_block = address.first_recreated_block.min(recreation.block_number);
+        address.last_recreated_block = address.last_recreated_block.max(recreation.block_number);
+    }
+
+    let mut code_hashes: BTreeMap<Address, HashSet<_>> = BTreeMap::new();
+    for trace_record in run.effective_records() {
+        let Some(address) = addresses.get_mut(&trace_record.contract_address) else {
+            continue;
+        };
+        address.label = address
+            .label
+            .take()
+            .or_else(|| trace_record.contract_label.clone());
+        if let Some(code_hash) = trace_record.code_hash {
+            code_hashes
+                .entry(trace_record.contract_address)
+                .or_default()
+                .insert(code_hash);
+        }
+    }
+    for (address, code_hashes) in code_hashes {
+        if let Some(address) = addresses.get_mut(&address) {
+            address.code_hashes = code_hashes.len();
+        }
+    }
+
+    let mut addresses = addresses.into_values().collect::<Vec<_>>();
+    addresses.sort_by_key(|address| std::cmp::Reverse((address.cycles, address.code_hashes)));
+    addresses.truncate(top);
+    addresses
+}
@@ -0,0 +1,160 @@
+use std::path::Path;
+
+use alloy_primitives::{keccak256, Address};
+use reth_libmdbx::{DatabaseFlags, Environment, EnvironmentFlags, Geometry, Mode, WriteFlags};
+
+use crate::{TraceResponse, TraceType};
+
+// Record JSON keyed by `block number ++ transaction position ++ record hash`, so a cursor walks
+// records in chain order and a record found again by a later run lands on the same key
+const RECORDS: &str = "Records";
+// Record keys of every address, dup sorted
+const ADDRESS_RECORDS: &str = "AddressRecords";
+
+const RECORD_KEY_LEN: usize = 24;
+
+// block-tracer's own MDBX environment, separate from the node's datadir, that accumulates trace
+// records over runs
+#[derive(Debug)]
+pub struct ResultsDb {
+    env: Environment,
+}
+
+#[derive(Debug, Default)]
+pub struct InsertStats {
+    pub new_records: usize,
+    pub known_records: usize,
+}
+
+impl ResultsDb {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(path)?;
+        let env = Environment::builder()
+            .set_max_dbs(2)
+            .set_geometry(Geometry {
+                size: Some(0..(1 << 40)),
+                growth_step: Some(1 << 28),
+                ..Default::default()
+            })
+            .open(path)?;
+
+        let txn = env.begin_rw_txn()?;
+        txn.create_db(Some(RECORDS), DatabaseFlags::empty())?;
+        txn.create_db(Some(ADDRESS_RECORDS), DatabaseFlags::DUP_SORT)?;
+        txn.commit()?;
+        Ok(Self { env })
+    }
+
+    // Serving only reads, so the environment is not locked for writers
+    pub fn open_read_only(path: &Path) -> anyhow::Result<Self> {
+        let env = Environment::builder()
+            .set_max_dbs(2)
+            .set_flags(EnvironmentFlags {
+                mode: Mode::ReadOnly,
+                ..Default::default()
+            })
+            .open(path)?;
+        Ok(Self { env })
+    }
+
+    // Stores the records not seen by an earlier run, in one write transaction
+    pub fn insert<'a>(
+        &self,
+        trace_responses: impl Iterator<Item = &'a TraceResponse>,
+    ) -> anyhow::Result<InsertStats> {
+        let txn = self.env.begin_rw_txn()?;
+        let records = txn.open_db(Some(RECORDS))?;
+        let address_records = txn.open_db(Some(ADDRESS_RECORDS))?;
+
+        let mut stats = InsertStats::default();
+        for trace_response in trace_responses {
+            let key = record_key(trace_response);
+            if txn.get::<()>(records.dbi(), &key)?.is_some() {
+                stats.known_records += 1;
+                continue;
+            }
+            txn.put(
+                records.dbi(),
+                key,
+                serde_json::to_vec(trace_response)?,
+                WriteFlags::empty(),
+            )?;
+            txn.put(
+                address_records.dbi(),
+                trace_response.contract_address,
+                key,
+                WriteFlags::empty(),
+            )?;
+            stats.new_records += 1;
+        }
+        txn.commit()?;
+        Ok(stats)
+    }
+
+    // Every stored record, in chain order
+    pub fn records(&self) -> anyhow::Result<Vec<TraceResponse>> {
+        let txn = self.env.begin_ro_txn()?;
+        let records = txn.open_db(Some(RECORDS))?;
+        let mut cursor = txn.cursor(&records)?;
+        let mut trace_responses = Vec::new();
+        for entry in cursor.iter_start::<(), Vec<u8>>() {
+            let (_, value) = entry?;
+            trace_responses.push(serde_json::from_slice(&value)?);
+        }
+        Ok(trace_responses)
+    }
+
+    // Records of the address from every run, in chain order
+    pub fn address_records(&self, address: Address) -> anyhow::Result<Vec<TraceResponse>> {
+        let txn = self.env.begin_ro_txn()?;
+        let records = txn.open_db(Some(RECORDS))?;
+        let address_records = txn.open_db(Some(ADDRESS_RECORDS))?;
+        let mut cursor = txn.cursor(&address_records)?;
+        let mut trace_responses = Vec::new();
+        for entry in cursor.iter_dup_of::<(), Vec<u8>>(address.as_slice()) {
+            let (_, key) = entry?;
+            if let Some(value) = txn.get::<Vec<u8>>(records.dbi(), &key)? {
+                trace_responses.push(serde_json::from_slice(&value)?);
+            }
+        }
+        Ok(trace_responses)
+    }
+
+    // Addresses created in this run that an earlier run saw destroyed before the create
+    pub fn recreated_across_runs<'a>(
+        &self,
+        created_trace_responses: impl Iterator<Item = &'a TraceResponse>,
+    ) -> anyhow::Result<Vec<Address>> {
+        let mut recreated = Vec::new();
+        for created in created_trace_responses {
+            if created.revert_reason.is_some() {
+                continue;
+            }
+            let destroyed_before =
+                self.address_records(created.contract_address)?
+                    .iter()
+                    .any(|stored| {
+                        stored.trace_type == TraceType::SelfDestruct
+                            && stored.revert_reason.is_none()
+                            && (stored.block_number, stored.transaction_position)
+                                < (created.block_number, created.transaction_position)
+                    });
+            if destroyed_before {
+                recreated.push(created.contract_address);
+            }
+        }
+        Ok(recreated)
+    }
+}
+
+fn record_key(trace_response: &TraceResponse) -> [u8; RECORD_KEY_LEN] {
+    let identity = format!(
+        "{}:{}:{:?}",
+        trace_response.trace_type, trace_response.contract_address, trace_response.trace_address
+    );
+    let mut key = [0u8; RECORD_KEY_LEN];
+    key[..8].copy_from_slice(&trace_response.block_number.to_be_bytes());
+    key[8..16].copy_from_slice(&trace_response.transaction_position.to_be_bytes());
+    key[16..].copy_from_slice(&keccak256(identity)[..8]);
+    key
+}
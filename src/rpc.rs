@@ -0,0 +1,1165 @@
+use std::fmt::{Debug, Display};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use alloy_primitives::{hex, keccak256};
+use futures::future::{join_all, BoxFuture};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+
+use crate::progress::{log_line, progress, ProviderEvent};
+use crate::rpc_auth::{self, RpcAuth};
+
+// `result` and `error` are both optional, nodes differ in which of them they leave out
+#[derive(Debug, serde::Deserialize)]
+struct RpcResponse<T> {
+    result: Option<T>,
+    error: Option<JsonRpcError>,
+}
+
+/// Error object of a JSON-RPC response, returned as the error of `RpcClient::request` so callers
+/// can downcast it and tell an unsupported method from a failed call
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct JsonRpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(default)]
+    pub data: Option<Value>,
+}
+
+impl JsonRpcError {
+    pub fn is_method_not_found(&self) -> bool {
+        self.code == -32601
+    }
+
+    pub fn is_invalid_params(&self) -> bool {
+        self.code == -32602
+    }
+}
+
+impl Display for JsonRpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "JSON-RPC error {}: {}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for JsonRpcError {}
+
+/// Returned once a method is still rate limited after all retries
+#[derive(Debug, Clone)]
+pub struct RateLimited {
+    pub method: String,
+}
+
+impl Display for RateLimited {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Still rate limited on {} after {} retries",
+            self.method, MAX_RATE_LIMIT_RETRIES
+        )
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+/// Sends one JSON-RPC request and returns the raw response body, which keeps parsing in
+/// `RpcClient` so canned bodies (malformed or partial ones included) exercise the same code
+pub trait RpcTransport: Debug + Send + Sync {
+    fn send<'a>(
+        &'a self,
+        method: &'a str,
+        params: &'a Value,
+    ) -> BoxFuture<'a, anyhow::Result<String>>;
+
+    /// Provider the requests go to, scheme and host only, `None` when no node is involved
+    fn provider(&self) -> Option<String> {
+        None
+    }
+
+    /// Whether `send_batch` is supported, calls are otherwise sent one by one
+    fn batches(&self) -> bool {
+        false
+    }
+
+    /// Sends one call of the method per params as JSON-RPC batches and returns the response body
+    /// of each call, or the error of a call that could not be sent, in the order of the params
+    fn send_batch<'a>(
+        &'a self,
+        method: &'a str,
+        _params: &'a [Value],
+    ) -> BoxFuture<'a, anyhow::Result<Vec<anyhow::Result<String>>>> {
+        Box::pin(async move { anyhow::bail!("Batching {} is not supported", method) })
+    }
+}
+
+// Used when a 429 response has no usable Retry-After header
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(1);
+// Retry-After values beyond this are capped, a provider asking for hours is treated as down
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(300);
+const MAX_RATE_LIMIT_RETRIES: usize = 8;
+// Batch responses are kept under these, a larger or slower one halves the batch size and one
+// under a quarter of both doubles it
+const TARGET_BATCH_BYTES: usize = 2 * 1024 * 1024;
+const TARGET_BATCH_LATENCY: Duration = Duration::from_secs(2);
+const INITIAL_BATCH_SIZE: usize = 10;
+const DEFAULT_CONNECT_TIMEOUT: u64 = 10;
+const DEFAULT_REQUEST_TIMEOUT: u64 = 120;
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct HttpArgs {
+    /// Seconds to wait for a connection to the node
+    #[arg(long, default_value_t = DEFAULT_CONNECT_TIMEOUT)]
+    pub connect_timeout: u64,
+    /// Seconds to wait for a whole JSON-RPC response, trace calls of busy blocks take a while
+    #[arg(long, default_value_t = DEFAULT_REQUEST_TIMEOUT)]
+    pub request_timeout: u64,
+    /// Proxy every request to the node goes through, http://, https://, socks5:// or socks5h://.
+    /// HTTP_PROXY and HTTPS_PROXY are honored without it
+    #[arg(long, env = "ALL_PROXY")]
+    pub proxy: Option<String>,
+    /// Header sent with every request to the node, `Name: value`, repeatable
+    #[arg(long = "rpc-header", value_parser = rpc_auth::parse_header)]
+    pub rpc_headers: Vec<(String, String)>,
+    /// Bearer token sent with every request to the node
+    #[arg(long, env = "RPC_BEARER_TOKEN", hide_env_values = true, conflicts_with_all = ["rpc_basic_auth", "rpc_jwt_secret"])]
+    pub rpc_bearer_token: Option<String>,
+    /// Basic auth credentials for the node, `user:password`
+    #[arg(
+        long,
+        env = "RPC_BASIC_AUTH",
+        hide_env_values = true,
+        conflicts_with = "rpc_jwt_secret"
+    )]
+    pub rpc_basic_auth: Option<String>,
+    /// File with a hex encoded 32 byte secret, as for an Engine API endpoint: every request
+    /// carries a freshly signed HS256 token
+    #[arg(long, env = "RPC_JWT_SECRET")]
+    pub rpc_jwt_secret: Option<PathBuf>,
+    /// PEM client certificate for gateways that require mutual TLS
+    #[arg(long, env = "RPC_CLIENT_CERT", requires = "rpc_client_key")]
+    pub rpc_client_cert: Option<PathBuf>,
+    /// PEM PKCS#8 private key of the client certificate
+    #[arg(long, env = "RPC_CLIENT_KEY", requires = "rpc_client_cert")]
+    pub rpc_client_key: Option<PathBuf>,
+    /// PEM CA certificate the node's certificate is checked against, on top of the system roots
+    #[arg(long, env = "RPC_CA_CERT")]
+    pub rpc_ca_cert: Option<PathBuf>,
+    /// Send per-block and per-transaction lookups as JSON-RPC batches of up to this many calls,
+    /// resized to the observed response sizes and latencies
+    #[arg(long, value_parser = clap::value_parser!(usize).range(1..))]
+    pub rpc_batch_size: Option<usize>,
+}
+
+// What the flags default to, for library users building a transport without the CLI
+impl Default for HttpArgs {
+    fn default() -> Self {
+        Self {
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            proxy: None,
+            rpc_headers: Vec::new(),
+            rpc_bearer_token: None,
+            rpc_basic_auth: None,
+            rpc_jwt_secret: None,
+            rpc_client_cert: None,
+            rpc_client_key: None,
+            rpc_ca_cert: None,
+            rpc_batch_size: None,
+        }
+    }
+}
+
+// Calls per batch, shared by every batch sent through a transport. Shrinks for blocks heavy with
+// transactions before their responses hit the provider's payload limits, and grows back over
+// light ones
+#[derive(Debug)]
+struct BatchSizer {
+    max_size: usize,
+    size: AtomicUsize,
+}
+
+impl BatchSizer {
+    fn new(max_size: usize) -> Self {
+        Self {
+            max_size,
+            size: AtomicUsize::new(INITIAL_BATCH_SIZE.min(max_size)),
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.size.load(Ordering::Relaxed)
+    }
+
+    fn set(&self, size: usize) {
+        let size = size.clamp(1, self.max_size);
+        self.size.store(size, Ordering::Relaxed);
+        metrics::gauge!("block_tracer_rpc_batch_size").set(size as f64);
+    }
+
+    fn observe(&self, calls: usize, bytes: usize, latency: Duration) {
+        if bytes > TARGET_BATCH_BYTES || latency > TARGET_BATCH_LATENCY {
+            self.set(calls / 2);
+        } else if calls >= self.size()
+            && bytes < TARGET_BATCH_BYTES / 4
+            && latency < TARGET_BATCH_LATENCY / 4
+        {
+            self.set(calls * 2);
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpTransport {
+    client: reqwest::Client,
+    url: String,
+    // `url` redacted, the key of its provider stats
+    provider: String,
+    auth: RpcAuth,
+    // Set by a 429 response, every request through this transport waits for it so the whole
+    // fetch pool backs off together instead of each task hammering the provider on its own
+    paused_until: Arc<Mutex<Option<tokio::time::Instant>>>,
+    // Set with --rpc-batch-size
+    batch_sizer: Option<Arc<BatchSizer>>,
+}
+
+impl HttpTransport {
+    pub fn new(url: String, args: &HttpArgs) -> anyhow::Result<Self> {
+        let mut builder = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(args.connect_timeout))
+            .timeout(Duration::from_secs(args.request_timeout))
+            .default_headers(rpc_auth::header_map(&args.rpc_headers)?);
+        if let Some(proxy) = &args.proxy {
+            builder =
+                builder.proxy(reqwest::Proxy::all(proxy)?.no_proxy(reqwest::NoProxy::from_env()));
+        }
+        if let (Some(cert_path), Some(key_path)) = (&args.rpc_client_cert, &args.rpc_client_key) {
+            builder = builder.identity(reqwest::Identity::from_pkcs8_pem(
+                &std::fs::read(cert_path)?,
+                &std::fs::read(key_path)?,
+            )?);
+        }
+        if let Some(ca_path) = &args.rpc_ca_cert {
+            builder = builder
+                .add_root_certificate(reqwest::Certificate::from_pem(&std::fs::read(ca_path)?)?);
+        }
+        let client = builder.build()?;
+        Ok(Self {
+            client,
+            provider: redact_url(&url),
+            url,
+            auth: RpcAuth::from_args(args)?,
+            paused_until: Arc::new(Mutex::new(None)),
+            batch_sizer: args
+                .rpc_batch_size
+                .map(|max_size| Arc::new(BatchSizer::new(max_size))),
+        })
+    }
+
+    fn record(&self, event: ProviderEvent) {
+        progress().provider_event(&self.provider, event);
+    }
+
+    fn record_error(&self, err: &reqwest::Error) {
+        if err.is_timeout() {
+            self.record(ProviderEvent::Timeout);
+        }
+    }
+
+    async fn wait_for_pause(&self) {
+        let paused_until = *self.paused_until.lock().unwrap();
+        if let Some(paused_until) = paused_until {
+            tokio::time::sleep_until(paused_until).await;
+        }
+    }
+
+    // Concurrent 429s extend the pause, they never shorten it
+    fn pause(&self, retry_after: Duration) {
+        let resume_at = tokio::time::Instant::now() + retry_after;
+        let mut paused_until = self.paused_until.lock().unwrap();
+        if paused_until.map_or(true, |paused_until| paused_until < resume_at) {
+            *paused_until = Some(resume_at);
+        }
+    }
+}
+
+// Only the delay-seconds form is understood, an HTTP date falls back to the default
+fn retry_after(response: &reqwest::Response) -> Duration {
+    response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .map_or(DEFAULT_RETRY_AFTER, Duration::from_secs)
+        .min(MAX_RETRY_AFTER)
+}
+
+impl HttpTransport {
+    // Posts the payload, retrying while rate limited, and returns the status and body of the
+    // response. The body is returned for any other status, nodes put JSON-RPC errors in 4xx and
+    // 5xx bodies
+    async fn post(
+        &self,
+        method: &str,
+        payload: &Value,
+    ) -> anyhow::Result<(reqwest::StatusCode, String)> {
+        self.record(ProviderEvent::Request);
+        for attempt in 0..MAX_RATE_LIMIT_RETRIES {
+            if attempt > 0 {
+                self.record(ProviderEvent::Retry);
+            }
+            self.wait_for_pause().await;
+            let request = self.auth.apply(self.client.post(&self.url).json(payload))?;
+            let response = request
+                .send()
+                .await
+                .inspect_err(|err| self.record_error(err))?;
+            let status = response.status();
+            if status != reqwest::StatusCode::TOO_MANY_REQUESTS {
+                if status.is_client_error() {
+                    self.record(ProviderEvent::ClientError);
+                } else if status.is_server_error() {
+                    self.record(ProviderEvent::ServerError);
+                }
+                let body = response
+                    .text()
+                    .await
+                    .inspect_err(|err| self.record_error(err))?;
+                return Ok((status, body));
+            }
+            let retry_after = retry_after(&response);
+            log_line(format!(
+                "Rate limited on {}, pausing requests for {:?}",
+                method, retry_after
+            ));
+            progress().rate_limited(method);
+            self.pause(retry_after);
+        }
+        Err(RateLimited {
+            method: method.to_string(),
+        }
+        .into())
+    }
+
+    // One batch, answered with an array of responses in any order. A provider refusing the
+    // batch as a whole answers with an error status or a single error object instead
+    async fn post_batch(&self, method: &str, params: &[Value]) -> anyhow::Result<Vec<String>> {
+        let payload = params
+            .iter()
+            .enumerate()
+            .map(|(id, params)| {
+                json!({
+                    "jsonrpc": "2.0",
+                    "method": method,
+                    "params": params,
+                    "id": id
+                })
+            })
+            .collect::<Value>();
+        let (status, body) = self.post(method, &payload).await?;
+        if !status.is_success() {
+            anyhow::bail!(
+                "batch of {} {} calls failed with HTTP {}",
+                params.len(),
+                method,
+                status
+            );
+        }
+        let mut responses = serde_json::from_str::<Vec<Value>>(&body).map_err(|err| {
+            anyhow::anyhow!(
+                "batch of {} {} calls was not answered with an array: {}",
+                params.len(),
+                method,
+                err
+            )
+        })?;
+        if responses.len() != params.len() {
+            anyhow::bail!(
+                "batch of {} {} calls was answered with {} responses",
+                params.len(),
+                method,
+                responses.len()
+            );
+        }
+        responses.sort_by_key(|response| response.get("id").and_then(Value::as_u64));
+        Ok(responses
+            .iter()
+            .map(|response| response.to_string())
+            .collect())
+    }
+}
+
+impl RpcTransport for HttpTransport {
+    fn send<'a>(
+        &'a self,
+        method: &'a str,
+        params: &'a Value,
+    ) -> BoxFuture<'a, anyhow::Result<String>> {
+        Box::pin(async move {
+            let payload = json!({
+                "jsonrpc": "2.0",
+                "method": method,
+                "params": params,
+                "id": 1
+            });
+            let (_, body) = self.post(method, &payload).await?;
+            Ok(body)
+        })
+    }
+
+    fn provider(&self) -> Option<String> {
+        Some(self.provider.clone())
+    }
+
+    fn batches(&self) -> bool {
+        self.batch_sizer.is_some()
+    }
+
+    // Sends the calls in batches of the current size. A failed batch is halved and sent again,
+    // down to a single call, whose error is then that call's alone
+    fn send_batch<'a>(
+        &'a self,
+        method: &'a str,
+        params: &'a [Value],
+    ) -> BoxFuture<'a, anyhow::Result<Vec<anyhow::Result<String>>>> {
+        Box::pin(async move {
+            let Some(batch_sizer) = &self.batch_sizer else {
+                anyhow::bail!("Batching {} is not enabled", method);
+            };
+            let mut bodies = Vec::with_capacity(params.len());
+            let mut size = batch_sizer.size();
+            while bodies.len() < params.len() {
+                let chunk = &params[bodies.len()..params.len().min(bodies.len() + size)];
+                let started = Instant::now();
+                match self.post_batch(method, chunk).await {
+                    Ok(chunk_bodies) => {
+                        let bytes = chunk_bodies.iter().map(String::len).sum();
+                        batch_sizer.observe(chunk.len(), bytes, started.elapsed());
+                        bodies.extend(chunk_bodies.into_iter().map(Ok));
+                        size = batch_sizer.size();
+                    }
+                    // The pause is shared, carrying on would only hit the limit again
+                    Err(err) if err.is::<RateLimited>() => return Err(err),
+                    Err(err) if chunk.len() > 1 => {
+                        size = chunk.len() / 2;
+                        log_line(format!("{:#}, retrying in batches of {}", err, size));
+                        batch_sizer.set(size);
+                    }
+                    Err(err) => {
+                        bodies.push(Err(err));
+                        size = batch_sizer.size();
+                    }
+                }
+            }
+            Ok(bodies)
+        })
+    }
+}
+
+/// Saves every response body of the inner transport into the directory
+#[derive(Debug)]
+pub struct RecordingTransport {
+    inner: Arc<dyn RpcTransport>,
+    fixtures_dir: PathBuf,
+}
+
+impl RecordingTransport {
+    pub fn new(inner: Arc<dyn RpcTransport>, fixtures_dir: PathBuf) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(&fixtures_dir)?;
+        Ok(Self {
+            inner,
+            fixtures_dir,
+        })
+    }
+}
+
+impl RpcTransport for RecordingTransport {
+    fn send<'a>(
+        &'a self,
+        method: &'a str,
+        params: &'a Value,
+    ) -> BoxFuture<'a, anyhow::Result<String>> {
+        Box::pin(async move {
+            let body = self.inner.send(method, params).await?;
+            std::fs::write(self.fixtures_dir.join(fixture_name(method, params)), &body)?;
+            Ok(body)
+        })
+    }
+
+    fn provider(&self) -> Option<String> {
+        self.inner.provider()
+    }
+
+    fn batches(&self) -> bool {
+        self.inner.batches()
+    }
+
+    // Every call of the batch is saved on its own, so a replay without batching finds it
+    fn send_batch<'a>(
+        &'a self,
+        method: &'a str,
+        params: &'a [Value],
+    ) -> BoxFuture<'a, anyhow::Result<Vec<anyhow::Result<String>>>> {
+        Box::pin(async move {
+            let bodies = self.inner.send_batch(method, params).await?;
+            for (params, body) in params.iter().zip(&bodies) {
+                if let Ok(body) = body {
+                    std::fs::write(self.fixtures_dir.join(fixture_name(method, params)), body)?;
+                }
+            }
+            Ok(bodies)
+        })
+    }
+}
+
+/// Prints every request and response of the inner transport with timing and size, and with a
+/// dump directory also writes both bodies to `<seq>-<method>.request.json`/`.response.json`.
+/// Credentials live in headers and the URL, which are never part of what is logged
+#[derive(Debug)]
+pub struct TracingTransport {
+    inner: Arc<dyn RpcTransport>,
+    dump_dir: Option<PathBuf>,
+    sequence: AtomicU64,
+}
+
+impl TracingTransport {
+    pub fn new(inner: Arc<dyn RpcTransport>, dump_dir: Option<PathBuf>) -> anyhow::Result<Self> {
+        if let Some(dump_dir) = &dump_dir {
+            std::fs::create_dir_all(dump_dir)?;
+        }
+        Ok(Self {
+            inner,
+            dump_dir,
+            sequence: AtomicU64::new(0),
+        })
+    }
+}
+
+impl RpcTransport for TracingTransport {
+    fn send<'a>(
+        &'a self,
+        method: &'a str,
+        params: &'a Value,
+    ) -> BoxFuture<'a, anyhow::Result<String>> {
+        Box::pin(async move {
+            let sequence = self.sequence.fetch_add(1, Ordering::Relaxed);
+            let request = params.to_string();
+            log_line(format!(
+                "rpc -> #{} {} {} ({} bytes)",
+                sequence,
+                method,
+                truncate(&request, 200),
+                request.len()
+            ));
+            if let Some(dump_dir) = &self.dump_dir {
+                std::fs::write(
+                    dump_dir.join(format!("{:06}-{}.request.json", sequence, method)),
+                    &request,
+                )?;
+            }
+
+            let started = Instant::now();
+            let response = self.inner.send(method, params).await;
+            match &response {
+                Ok(body) => {
+                    log_line(format!(
+                        "rpc <- #{} {} {} ms, {} bytes",
+                        sequence,
+                        method,
+                        started.elapsed().as_millis(),
+                        body.len()
+                    ));
+                    if let Some(dump_dir) = &self.dump_dir {
+                        std::fs::write(
+                            dump_dir.join(format!("{:06}-{}.response.json", sequence, method)),
+                            body,
+                        )?;
+                    }
+                }
+                Err(err) => log_line(format!(
+                    "rpc <- #{} {} {} ms, failed: {}",
+                    sequence,
+                    method,
+                    started.elapsed().as_millis(),
+                    err
+                )),
+            }
+            response
+        })
+    }
+
+    fn provider(&self) -> Option<String> {
+        self.inner.provider()
+    }
+
+    fn batches(&self) -> bool {
+        self.inner.batches()
+    }
+
+    // Logged as one request and response, each call of the batch takes a sequence number of
+    // its own for the dump files
+    fn send_batch<'a>(
+        &'a self,
+        method: &'a str,
+        params: &'a [Value],
+    ) -> BoxFuture<'a, anyhow::Result<Vec<anyhow::Result<String>>>> {
+        Box::pin(async move {
+            let first_sequence = self
+                .sequence
+                .fetch_add(params.len() as u64, Ordering::Relaxed);
+            let request_bytes = params
+                .iter()
+                .map(|params| params.to_string().len())
+                .sum::<usize>();
+            log_line(format!(
+                "rpc -> #{}..#{} {} batch of {} calls ({} bytes)",
+                first_sequence,
+                first_sequence + params.len() as u64 - 1,
+                method,
+                params.len(),
+                request_bytes
+            ));
+            if let Some(dump_dir) = &self.dump_dir {
+                for (sequence, params) in (first_sequence..).zip(params) {
+                    std::fs::write(
+                        dump_dir.join(format!("{:06}-{}.request.json", sequence, method)),
+                        params.to_string(),
+                    )?;
+                }
+            }
+
+            let started = Instant::now();
+            let response = self.inner.send_batch(method, params).await;
+            match &response {
+                Ok(bodies) => {
+                    log_line(format!(
+                        "rpc <- #{}..#{} {} {} ms, {} bytes, {} failed",
+                        first_sequence,
+                        first_sequence + params.len() as u64 - 1,
+                        method,
+                        started.elapsed().as_millis(),
+                        bodies.iter().flatten().map(String::len).sum::<usize>(),
+                        bodies.iter().filter(|body| body.is_err()).count()
+                    ));
+                    if let Some(dump_dir) = &self.dump_dir {
+                        for (sequence, body) in (first_sequence..).zip(bodies) {
+                            if let Ok(body) = body {
+                                std::fs::write(
+                                    dump_dir
+                                        .join(format!("{:06}-{}.response.json", sequence, method)),
+                                    body,
+                                )?;
+                            }
+                        }
+                    }
+                }
+                Err(err) => log_line(format!(
+                    "rpc <- #{}..#{} {} {} ms, failed: {}",
+                    first_sequence,
+                    first_sequence + params.len() as u64 - 1,
+                    method,
+                    started.elapsed().as_millis(),
+                    err
+                )),
+            }
+            response
+        })
+    }
+}
+
+fn truncate(s: &str, max_len: usize) -> String {
+    match s.char_indices().nth(max_len) {
+        Some((end, _)) => format!("{}...", &s[..end]),
+        None => s.to_string(),
+    }
+}
+
+/// Scheme and host only, providers put API keys in the userinfo, the path or the query
+pub fn redact_url(url: &str) -> String {
+    match reqwest::Url::parse(url) {
+        Ok(url) => format!(
+            "{}://{}{}",
+            url.scheme(),
+            url.host_str().unwrap_or_default(),
+            url.port()
+                .map(|port| format!(":{}", port))
+                .unwrap_or_default()
+        ),
+        Err(_) => "<unparseable url>".to_string(),
+    }
+}
+
+/// Serves response bodies written by `RecordingTransport`, no node needed
+#[derive(Debug)]
+pub struct ReplayTransport {
+    fixtures_dir: PathBuf,
+}
+
+impl ReplayTransport {
+    pub fn new(fixtures_dir: PathBuf) -> Self {
+        Self { fixtures_dir }
+    }
+}
+
+impl RpcTransport for ReplayTransport {
+    fn send<'a>(
+        &'a self,
+        method: &'a str,
+        params: &'a Value,
+    ) -> BoxFuture<'a, anyhow::Result<String>> {
+        Box::pin(async move {
+            let fixture_name = fixture_name(method, params);
+            std::fs::read_to_string(self.fixtures_dir.join(&fixture_name)).map_err(|err| {
+                anyhow::anyhow!("No recorded response for {}: {}", fixture_name, err)
+            })
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RpcClient {
+    transport: Arc<dyn RpcTransport>,
+}
+
+impl RpcClient {
+    pub fn new(transport: Arc<dyn RpcTransport>) -> Self {
+        Self { transport }
+    }
+
+    #[tracing::instrument(name = "fetch", skip(self, params))]
+    pub async fn request<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: Value,
+    ) -> anyhow::Result<Option<T>> {
+        let started = Instant::now();
+        let result = match self.transport.send(method, &params).await {
+            Ok(body) => parse_response::<T>(&body),
+            Err(err) => Err(err),
+        };
+        self.record(method, started.elapsed(), &result);
+        result
+    }
+
+    /// One call of the method per params, results in the order of the params. Sent as batches
+    /// with --rpc-batch-size, concurrently one by one otherwise, e.g. when recording or replaying
+    #[tracing::instrument(name = "fetch", skip(self, params))]
+    pub async fn request_batch<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: Vec<Value>,
+    ) -> anyhow::Result<Vec<anyhow::Result<Option<T>>>> {
+        if !self.transport.batches() || params.len() < 2 {
+            return Ok(join_all(
+                params
+                    .into_iter()
+                    .map(|params| self.request::<T>(method, params)),
+            )
+            .await);
+        }
+        let started = Instant::now();
+        let bodies = self.transport.send_batch(method, &params).await?;
+        // Calls of a batch share its latency
+        let latency = started.elapsed();
+        Ok(bodies
+            .into_iter()
+            .map(|body| {
+                let result = body.and_then(|body| parse_response::<T>(&body));
+                self.record(method, latency, &result);
+                result
+            })
+            .collect())
+    }
+
+    fn record<T>(&self, method: &str, latency: Duration, result: &anyhow::Result<T>) {
+        if let (Err(err), Some(provider)) = (result, self.transport.provider()) {
+            if err.is::<serde_json::Error>() {
+                progress().provider_event(&provider, ProviderEvent::Deserialization);
+            }
+        }
+        progress().request(method, latency, result.is_err());
+        if result.is_ok() {
+            metrics::histogram!("block_tracer_rpc_request_duration_seconds", "method" => method.to_string())
+                .record(latency.as_secs_f64());
+        }
+    }
+}
+
+// An error object wins over a result, some nodes send `"result": null` alongside it
+fn parse_response<T: DeserializeOwned>(body: &str) -> anyhow::Result<Option<T>> {
+    let response = serde_json::from_str::<RpcResponse<T>>(body)?;
+    match response.error {
+        Some(error) => Err(error.into()),
+        None => Ok(response.result),
+    }
+}
+
+// Requests are identified by method and params, so a replay must issue the same calls
+fn fixture_name(method: &str, params: &Value) -> String {
+    let params_hash = keccak256(params.to_string());
+    format!("{}-{}.json", method, hex::encode(&params_hash[..8]))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    // Canned response bodies by method and params, answered after a delay so concurrent calls
+    // can finish out of order
+    #[derive(Debug, Default)]
+    struct MockTransport {
+        responses: HashMap<(String, String), (Duration, String)>,
+        batches: bool,
+        sent: Mutex<Vec<String>>,
+    }
+
+    impl MockTransport {
+        fn respond(mut self, method: &str, params: Value, delay_ms: u64, body: String) -> Self {
+            self.responses.insert(
+                (method.to_string(), params.to_string()),
+                (Duration::from_millis(delay_ms), body),
+            );
+            self
+        }
+
+        fn sent(&self) -> Vec<String> {
+            self.sent.lock().unwrap().clone()
+        }
+    }
+
+    impl RpcTransport for MockTransport {
+        fn send<'a>(
+            &'a self,
+            method: &'a str,
+            params: &'a Value,
+        ) -> BoxFuture<'a, anyhow::Result<String>> {
+            Box::pin(async move {
+                self.sent
+                    .lock()
+                    .unwrap()
+                    .push(format!("{} {}", method, params));
+                let Some((delay, body)) = self
+                    .responses
+                    .get(&(method.to_string(), params.to_string()))
+                else {
+                    anyhow::bail!("no response for {} {}", method, params);
+                };
+                tokio::time::sleep(*delay).await;
+                Ok(body.clone())
+            })
+        }
+
+        fn batches(&self) -> bool {
+            self.batches
+        }
+
+        fn send_batch<'a>(
+            &'a self,
+            method: &'a str,
+            params: &'a [Value],
+        ) -> BoxFuture<'a, anyhow::Result<Vec<anyhow::Result<String>>>> {
+            Box::pin(async move {
+                self.sent
+                    .lock()
+                    .unwrap()
+                    .push(format!("{} batch of {}", method, params.len()));
+                Ok(params
+                    .iter()
+                    .map(|params| {
+                        self.responses
+                            .get(&(method.to_string(), params.to_string()))
+                            .map(|(_, body)| body.clone())
+                            .ok_or_else(|| anyhow::anyhow!("no response for {}", params))
+                    })
+                    .collect())
+            })
+        }
+    }
+
+    fn result_body(result: Value) -> String {
+        json!({ "jsonrpc": "2.0", "id": 1, "result": result }).to_string()
+    }
+
+    fn http_args(rpc_batch_size: Option<usize>) -> HttpArgs {
+        HttpArgs {
+            connect_timeout: 5,
+            request_timeout: 5,
+            rpc_batch_size,
+            ..HttpArgs::default()
+        }
+    }
+
+    // Answers one request per connection with the next of the canned status, extra headers and
+    // body, and collects the request bodies
+    async fn serve(
+        responses: Vec<(u16, Vec<(&'static str, String)>, String)>,
+    ) -> (String, Arc<Mutex<Vec<Value>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = requests.clone();
+        tokio::spawn(async move {
+            for (status, headers, body) in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0u8; 4096];
+                let header_end = loop {
+                    let read = stream.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+                    if let Some(position) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                        break position + 4;
+                    }
+                };
+                let head = String::from_utf8_lossy(&request[..header_end]).to_lowercase();
+                let content_length = head
+                    .lines()
+                    .find_map(|line| line.strip_prefix("content-length:"))
+                    .and_then(|length| length.trim().parse::<usize>().ok())
+                    .unwrap_or_default();
+                while request.len() < header_end + content_length {
+                    let read = stream.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+                }
+                received
+                    .lock()
+                    .unwrap()
+                    .push(serde_json::from_slice(&request[header_end..]).unwrap());
+
+                let mut response = format!(
+                    "HTTP/1.1 {} Canned\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+                    status,
+                    body.len()
+                );
+                for (name, value) in headers {
+                    response.push_str(&format!("{}: {}\r\n", name, value));
+                }
+                response.push_str("\r\n");
+                response.push_str(&body);
+                stream.write_all(response.as_bytes()).await.unwrap();
+                stream.shutdown().await.unwrap();
+            }
+        });
+        (url, requests)
+    }
+
+    #[test]
+    fn parse_response_returns_the_result() {
+        let result = parse_response::<u64>(r#"{"jsonrpc":"2.0","id":1,"result":7}"#).unwrap();
+        assert_eq!(result, Some(7));
+    }
+
+    #[test]
+    fn parse_response_returns_the_error_object() {
+        let err = parse_response::<u64>(
+            r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32601,"message":"the method does not exist"}}"#,
+        )
+        .unwrap_err();
+        let err = err.downcast_ref::<JsonRpcError>().unwrap();
+        assert!(err.is_method_not_found());
+        assert_eq!(err.message, "the method does not exist");
+    }
+
+    #[test]
+    fn parse_response_prefers_the_error_over_a_null_result() {
+        let err = parse_response::<u64>(
+            r#"{"jsonrpc":"2.0","id":1,"result":null,"error":{"code":-32602,"message":"invalid params"}}"#,
+        )
+        .unwrap_err();
+        assert!(err
+            .downcast_ref::<JsonRpcError>()
+            .unwrap()
+            .is_invalid_params());
+    }
+
+    #[test]
+    fn parse_response_without_result_or_error_is_none() {
+        assert_eq!(
+            parse_response::<u64>(r#"{"jsonrpc":"2.0","id":1}"#).unwrap(),
+            None
+        );
+        assert_eq!(
+            parse_response::<u64>(r#"{"jsonrpc":"2.0","id":1,"result":null}"#).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn parse_response_rejects_malformed_bodies() {
+        let err = parse_response::<u64>("<html>Bad Gateway</html>").unwrap_err();
+        assert!(err.is::<serde_json::Error>());
+        let err = parse_response::<u64>(r#"{"jsonrpc":"2.0","id":1,"result":"0x1"}"#).unwrap_err();
+        assert!(err.is::<serde_json::Error>());
+    }
+
+    #[tokio::test]
+    async fn request_batch_keeps_the_order_of_the_params() {
+        // The first call is answered last
+        let transport = MockTransport::default()
+            .respond("eth_getCode", json!([1]), 30, result_body(json!("0x01")))
+            .respond("eth_getCode", json!([2]), 15, result_body(json!("0x02")))
+            .respond("eth_getCode", json!([3]), 0, result_body(json!("0x03")));
+        let client = RpcClient::new(Arc::new(transport));
+
+        let results = client
+            .request_batch::<String>("eth_getCode", vec![json!([1]), json!([2]), json!([3])])
+            .await
+            .unwrap();
+
+        let results = results
+            .into_iter()
+            .map(|result| result.unwrap().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(results, ["0x01", "0x02", "0x03"]);
+    }
+
+    #[tokio::test]
+    async fn request_batch_fails_calls_on_their_own() {
+        let transport = Arc::new(MockTransport {
+            batches: true,
+            ..MockTransport::default()
+                .respond("eth_getCode", json!([1]), 0, result_body(json!("0x01")))
+                .respond(
+                    "eth_getCode",
+                    json!([3]),
+                    0,
+                    json!({ "jsonrpc": "2.0", "id": 1, "error": { "code": -32000, "message": "header not found" } })
+                        .to_string(),
+                )
+        });
+        let client = RpcClient::new(transport.clone());
+
+        let results = client
+            .request_batch::<String>("eth_getCode", vec![json!([1]), json!([2]), json!([3])])
+            .await
+            .unwrap();
+
+        assert_eq!(transport.sent(), ["eth_getCode batch of 3"]);
+        assert_eq!(results[0].as_ref().unwrap().as_deref(), Some("0x01"));
+        assert!(results[1].is_err());
+        assert_eq!(
+            results[2]
+                .as_ref()
+                .unwrap_err()
+                .downcast_ref::<JsonRpcError>()
+                .unwrap()
+                .code,
+            -32000
+        );
+    }
+
+    #[tokio::test]
+    async fn http_batch_responses_are_matched_by_id() {
+        let (url, requests) = serve(vec![(
+            200,
+            Vec::new(),
+            json!([
+                { "jsonrpc": "2.0", "id": 2, "result": "0x03" },
+                { "jsonrpc": "2.0", "id": 0, "result": "0x01" },
+                { "jsonrpc": "2.0", "id": 1, "result": "0x02" }
+            ])
+            .to_string(),
+        )])
+        .await;
+        let client = RpcClient::new(Arc::new(
+            HttpTransport::new(url, &http_args(Some(10))).unwrap(),
+        ));
+
+        let results = client
+            .request_batch::<String>("eth_getCode", vec![json!([1]), json!([2]), json!([3])])
+            .await
+            .unwrap();
+
+        let results = results
+            .into_iter()
+            .map(|result| result.unwrap().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(results, ["0x01", "0x02", "0x03"]);
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].as_array().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn http_batch_is_split_down_to_the_failing_call() {
+        let refused = (500, Vec::new(), "overloaded".to_string());
+        let answered = |id: u64, result: &str| {
+            (
+                200,
+                Vec::new(),
+                json!([{ "jsonrpc": "2.0", "id": id, "result": result }]).to_string(),
+            )
+        };
+        // The batch of two is refused, then its first call, while the second goes through
+        let (url, _) = serve(vec![refused.clone(), refused, answered(0, "0x02")]).await;
+        let client = RpcClient::new(Arc::new(
+            HttpTransport::new(url, &http_args(Some(10))).unwrap(),
+        ));
+
+        let results = client
+            .request_batch::<String>("eth_getCode", vec![json!([1]), json!([2])])
+            .await
+            .unwrap();
+
+        assert!(results[0].is_err());
+        assert_eq!(results[1].as_ref().unwrap().as_deref(), Some("0x02"));
+    }
+
+    #[tokio::test]
+    async fn http_retries_after_a_rate_limit() {
+        let (url, requests) = serve(vec![
+            (429, vec![("Retry-After", "1".to_string())], String::new()),
+            (200, Vec::new(), result_body(json!("0x1"))),
+        ])
+        .await;
+        let client = RpcClient::new(Arc::new(HttpTransport::new(url, &http_args(None)).unwrap()));
+
+        let started = Instant::now();
+        let result = client
+            .request::<String>("eth_blockNumber", json!([]))
+            .await
+            .unwrap();
+
+        assert_eq!(result.as_deref(), Some("0x1"));
+        assert!(started.elapsed() >= Duration::from_secs(1));
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn http_gives_up_when_still_rate_limited() {
+        let rate_limited = (429, vec![("Retry-After", "0".to_string())], String::new());
+        let (url, requests) = serve(vec![rate_limited; MAX_RATE_LIMIT_RETRIES]).await;
+        let client = RpcClient::new(Arc::new(HttpTransport::new(url, &http_args(None)).unwrap()));
+
+        let err = client
+            .request::<String>("eth_blockNumber", json!([]))
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            err.downcast_ref::<RateLimited>().unwrap().method,
+            "eth_blockNumber"
+        );
+        assert_eq!(requests.lock().unwrap().len(), MAX_RATE_LIMIT_RETRIES);
+    }
+
+    #[tokio::test]
+    async fn concurrent_rate_limits_only_extend_the_pause() {
+        let transport =
+            HttpTransport::new("http://127.0.0.1:1".to_string(), &http_args(None)).unwrap();
+
+        transport.pause(Duration::from_secs(60));
+        let paused_until = transport.paused_until.lock().unwrap().unwrap();
+        transport.pause(Duration::from_secs(1));
+
+        assert_eq!(*transport.paused_until.lock().unwrap(), Some(paused_until));
+        transport.pause(Duration::from_secs(120));
+        assert!(transport.paused_until.lock().unwrap().unwrap() > paused_until);
+    }
+}
@@ -0,0 +1,121 @@
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use alloy_primitives::hex;
+use jsonwebtoken::{EncodingKey, Header};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde_json::json;
+
+use crate::rpc::HttpArgs;
+
+/// Credentials added to every request to the node
+#[derive(Clone)]
+pub enum RpcAuth {
+    None,
+    Bearer(String),
+    Basic {
+        username: String,
+        password: Option<String>,
+    },
+    /// Engine API style: a fresh HS256 token per request, nodes reject tokens older than a minute
+    Jwt(EncodingKey),
+}
+
+// Secrets never end up in logs
+impl std::fmt::Debug for RpcAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RpcAuth::None => write!(f, "None"),
+            RpcAuth::Bearer(_) => write!(f, "Bearer(<redacted>)"),
+            RpcAuth::Basic { username, .. } => write!(f, "Basic({}:<redacted>)", username),
+            RpcAuth::Jwt(_) => write!(f, "Jwt(<redacted>)"),
+        }
+    }
+}
+
+impl RpcAuth {
+    pub fn from_args(args: &HttpArgs) -> anyhow::Result<Self> {
+        if let Some(token) = &args.rpc_bearer_token {
+            return Ok(RpcAuth::Bearer(token.clone()));
+        }
+        if let Some(credentials) = &args.rpc_basic_auth {
+            let (username, password) = match credentials.split_once(':') {
+                Some((username, password)) => (username, Some(password.to_string())),
+                None => (credentials.as_str(), None),
+            };
+            return Ok(RpcAuth::Basic {
+                username: username.to_string(),
+                password,
+            });
+        }
+        if let Some(secret_path) = &args.rpc_jwt_secret {
+            return Ok(RpcAuth::Jwt(EncodingKey::from_secret(&read_jwt_secret(
+                secret_path,
+            )?)));
+        }
+        Ok(RpcAuth::None)
+    }
+
+    pub fn apply(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> anyhow::Result<reqwest::RequestBuilder> {
+        Ok(match self {
+            RpcAuth::None => request,
+            RpcAuth::Bearer(token) => request.bearer_auth(token),
+            RpcAuth::Basic { username, password } => {
+                request.basic_auth(username, password.as_ref())
+            }
+            RpcAuth::Jwt(key) => {
+                let issued_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+                let token =
+                    jsonwebtoken::encode(&Header::default(), &json!({ "iat": issued_at }), key)?;
+                request.bearer_auth(token)
+            }
+        })
+    }
+}
+
+// The same file format as reth's and geth's `--authrpc.jwtsecret`: 32 hex encoded bytes
+fn read_jwt_secret(path: &Path) -> anyhow::Result<Vec<u8>> {
+    let secret = hex::decode(std::fs::read_to_string(path)?.trim())?;
+    if secret.len() != 32 {
+        anyhow::bail!(
+            "{} holds a {} byte JWT secret, expected 32",
+            path.display(),
+            secret.len()
+        );
+    }
+    Ok(secret)
+}
+
+/// `Name: value`, as given to --rpc-header
+pub fn parse_header(s: &str) -> Result<(String, String), String> {
+    let (name, value) = s
+        .split_once(':')
+        .ok_or_else(|| format!("Header {} is not in the form `Name: value`", s))?;
+    Ok((name.trim().to_string(), value.trim().to_string()))
+}
+
+/// Values of authorization-like headers are marked sensitive, so reqwest keeps them out of its
+/// debug output
+pub fn header_map(headers: &[(String, String)]) -> anyhow::Result<HeaderMap> {
+    let mut header_map = HeaderMap::new();
+    for (name, value) in headers {
+        let name = HeaderName::from_bytes(name.as_bytes())?;
+        let mut value = HeaderValue::from_str(value)?;
+        if is_secret_header(name.as_str()) {
+            value.set_sensitive(true);
+        }
+        header_map.insert(name, value);
+    }
+    Ok(header_map)
+}
+
+pub fn is_secret_header(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    name.contains("token")
+        || name.contains("key")
+        || name.contains("secret")
+        || name.contains("auth")
+}
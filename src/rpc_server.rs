@@ -0,0 +1,54 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use alloy_primitives::{Address, U64};
+use jsonrpsee::server::{RpcModule, Server};
+use jsonrpsee::types::ErrorObjectOwned;
+
+use crate::index::ResultIndex;
+use crate::progress::log_line;
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct RpcServerArgs {
+    /// Trace records to serve: trace_records.json, newline delimited records or a --results-db directory
+    #[arg(long)]
+    pub db: PathBuf,
+    /// Address the JSON-RPC endpoint listens on
+    #[arg(long, default_value = "127.0.0.1:8546")]
+    pub listen: SocketAddr,
+}
+
+// Serves the `tracer_` namespace over HTTP and WebSocket until interrupted
+pub async fn serve(args: RpcServerArgs) -> anyhow::Result<()> {
+    let index = ResultIndex::load(&args.db)?;
+    let mut module = RpcModule::new(index);
+
+    module.register_method("tracer_getRecreatedContracts", |params, index, _| {
+        let (from_block, to_block) = params.parse::<(U64, U64)>()?;
+        Ok::<_, ErrorObjectOwned>(index.recreations(from_block.to(), to_block.to()))
+    })?;
+    module.register_method("tracer_getLifecycle", |params, index, _| {
+        let address = params.one::<Address>()?;
+        // Borrowed records are serialized here since responses have to own their data
+        Ok::<_, ErrorObjectOwned>(
+            serde_json::to_value(index.lifecycles(address)).unwrap_or_default(),
+        )
+    })?;
+    module.register_method("tracer_getBlockTraces", |params, index, _| {
+        let block_number = params.one::<U64>()?;
+        Ok::<_, ErrorObjectOwned>(
+            serde_json::to_value(index.block(block_number.to())).unwrap_or_default(),
+        )
+    })?;
+    module.register_method("tracer_stats", |_, index, _| {
+        Ok::<_, ErrorObjectOwned>(index.stats())
+    })?;
+
+    let server = Server::builder().build(args.listen).await?;
+    log_line(format!(
+        "Serving tracer_ JSON-RPC on {}",
+        server.local_addr()?
+    ));
+    server.start(module).stopped().await;
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use std::path::Path;
+
+use alloy_primitives::Address;
+
+use crate::webhook::{Finding, FindingKind};
+use crate::{TraceResponse, TraceType};
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+    // The contract at the address selfdestructs
+    Selfdestruct(Address),
+    // The deployer creates any contract
+    DeployerCreates(Address),
+    // Any contract is created at the address. Traces don't tell CREATE and CREATE2 apart, but
+    // only CREATE2 can target an address that was already used
+    CreateAt(Address),
+}
+
+// e.g. `{"name": "vault destroyed", "selfdestruct": "0x..."}`
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Rule {
+    pub name: String,
+    #[serde(flatten)]
+    pub condition: Condition,
+}
+
+impl Rule {
+    fn matches(&self, trace_response: &TraceResponse) -> bool {
+        match (&self.condition, &trace_response.trace_type) {
+            (Condition::Selfdestruct(address), TraceType::SelfDestruct) => {
+                trace_response.contract_address == *address
+            }
+            (Condition::DeployerCreates(deployer), TraceType::Create) => {
+                trace_response.deployer == Some(*deployer)
+            }
+            (Condition::CreateAt(address), TraceType::Create) => {
+                trace_response.contract_address == *address
+            }
+            _ => false,
+        }
+    }
+}
+
+pub fn load(path: &Path) -> anyhow::Result<Vec<Rule>> {
+    Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+}
+
+// Records are evaluated block by block, in the order they were traced
+pub fn evaluate<'a>(
+    rules: &[Rule],
+    trace_responses: impl Iterator<Item = &'a TraceResponse>,
+) -> Vec<Finding> {
+    let mut trace_responses = trace_responses.collect::<Vec<_>>();
+    trace_responses.sort_by_key(|trace_response| {
+        (
+            trace_response.block_number,
+            trace_response.transaction_position,
+        )
+    });
+
+    let mut alerts = Vec::new();
+    for trace_response in trace_responses {
+        for rule in rules.iter().filter(|rule| rule.matches(trace_response)) {
+            alerts.push(Finding {
+                kind: FindingKind::Alert,
+                rule: Some(rule.name.clone()),
+                address: trace_response.contract_address,
+                block_number: Some(trace_response.block_number),
+                transaction_hash: trace_response.transaction_hash,
+            });
+        }
+    }
+    alerts
+}
@@ -0,0 +1,111 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use rhai::{Dynamic, Engine, Scope, AST};
+
+use crate::TraceResponse;
+
+// Callbacks of a `--script`, each optional:
+//   on_record(record)               map of fields to add to the record's `script_fields`
+//   classify(record)                string stored as the record's `classification`
+//   on_block_done(block, records)   called once per block with its records, e.g. to print
+// Records are passed as maps of their JSON fields, returning `()` adds nothing
+pub struct Hooks {
+    engine: Engine,
+    ast: AST,
+    on_record: bool,
+    classify: bool,
+    on_block_done: bool,
+}
+
+impl Hooks {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let engine = Engine::new();
+        let ast = engine
+            .compile_file(path.into())
+            .map_err(|err| anyhow::anyhow!("Could not compile {}: {}", path.display(), err))?;
+        let defines = |name: &str, params: usize| {
+            ast.iter_functions()
+                .any(|function| function.name == name && function.params.len() == params)
+        };
+        let (on_record, classify, on_block_done) = (
+            defines("on_record", 1),
+            defines("classify", 1),
+            defines("on_block_done", 2),
+        );
+        if !(on_record || classify || on_block_done) {
+            anyhow::bail!(
+                "{} defines none of on_record(record), classify(record) and on_block_done(block, records)",
+                path.display()
+            );
+        }
+        Ok(Hooks {
+            engine,
+            ast,
+            on_record,
+            classify,
+            on_block_done,
+        })
+    }
+
+    // Runs `on_record` and `classify` on every record and `on_block_done` after the last record
+    // of each block. Records are visited in chain order
+    pub fn apply<'a>(
+        &self,
+        trace_responses: impl Iterator<Item = &'a mut TraceResponse>,
+    ) -> anyhow::Result<()> {
+        let mut blocks: BTreeMap<u64, Vec<&mut TraceResponse>> = BTreeMap::new();
+        for trace_response in trace_responses {
+            blocks
+                .entry(trace_response.block_number)
+                .or_default()
+                .push(trace_response);
+        }
+
+        for (block_number, mut trace_responses) in blocks {
+            trace_responses.sort_by(|a, b| {
+                (a.transaction_position, &a.trace_address)
+                    .cmp(&(b.transaction_position, &b.trace_address))
+            });
+            let mut records = Vec::with_capacity(trace_responses.len());
+            for trace_response in trace_responses {
+                self.apply_record(trace_response)?;
+                records.push(rhai::serde::to_dynamic(&*trace_response)?);
+            }
+            if self.on_block_done {
+                self.call("on_block_done", (block_number as i64, records))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_record(&self, trace_response: &mut TraceResponse) -> anyhow::Result<()> {
+        if self.on_record {
+            let fields = self.call("on_record", (rhai::serde::to_dynamic(&*trace_response)?,))?;
+            if !fields.is_unit() {
+                let fields: BTreeMap<String, serde_json::Value> =
+                    rhai::serde::from_dynamic(&fields)
+                        .map_err(|err| anyhow::anyhow!("on_record returned no map: {}", err))?;
+                trace_response.script_fields.extend(fields);
+            }
+        }
+        if self.classify {
+            let classification =
+                self.call("classify", (rhai::serde::to_dynamic(&*trace_response)?,))?;
+            if !classification.is_unit() {
+                trace_response.classification = Some(
+                    classification
+                        .into_string()
+                        .map_err(|ty| anyhow::anyhow!("classify returned {}, not a string", ty))?,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn call(&self, name: &str, args: impl rhai::FuncArgs) -> anyhow::Result<Dynamic> {
+        self.engine
+            .call_fn::<Dynamic>(&mut Scope::new(), &self.ast, name, args)
+            .map_err(|err| anyhow::anyhow!("{} failed: {}", name, err))
+    }
+}
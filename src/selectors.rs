@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use alloy_primitives::{keccak256, Selector};
+
+use crate::progress::log_line;
+
+const FOUR_BYTE_URL: &str = "https://www.4byte.directory/api/v1/signatures/";
+
+// Functions commonly found around contract creation and destruction
+const BUILTIN_SIGNATURES: &[&str] = &[
+    "kill()",
+    "destroy()",
+    "destruct()",
+    "selfDestruct()",
+    "close()",
+    "deploy(bytes,uint256)",
+    "deploy(bytes32,bytes)",
+    "deploy(uint256,bytes32,bytes)",
+    "create(bytes)",
+    "create2(uint256,bytes)",
+    "createPair(address,address)",
+    "createPool(address,address,uint24)",
+    "clone(address)",
+    "cloneDeterministic(address,bytes32)",
+    "mint(uint256)",
+    "free(uint256)",
+    "freeUpTo(uint256)",
+    "freeFrom(address,uint256)",
+    "freeFromUpTo(address,uint256)",
+    "transfer(address,uint256)",
+    "transferFrom(address,address,uint256)",
+    "approve(address,uint256)",
+    "withdraw(uint256)",
+    "withdraw()",
+    "execute(address,uint256,bytes)",
+    "multicall(bytes[])",
+    "aggregate((address,bytes)[])",
+    "upgradeTo(address)",
+    "upgradeToAndCall(address,bytes)",
+    "initialize()",
+];
+
+#[derive(Debug, serde::Deserialize)]
+struct FourByteResponse {
+    results: Vec<FourByteSignature>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct FourByteSignature {
+    id: u64,
+    text_signature: String,
+}
+
+pub struct SelectorDb {
+    // `None` for selectors 4byte.directory does not know, so they are not looked up again
+    signatures: HashMap<Selector, Option<String>>,
+    cache_path: Option<PathBuf>,
+    // Lookups since the cache file was last written
+    dirty: bool,
+    client: reqwest::Client,
+}
+
+impl SelectorDb {
+    // Built-in signatures, extended by the cache of earlier 4byte.directory lookups when given
+    pub fn new(cache_path: Option<&Path>) -> anyhow::Result<Self> {
+        let mut signatures = BUILTIN_SIGNATURES
+            .iter()
+            .map(|signature| (selector_of(signature), Some(signature.to_string())))
+            .collect::<HashMap<_, _>>();
+
+        if let Some(cache_path) = cache_path {
+            if cache_path.exists() {
+                let cached: HashMap<Selector, Option<String>> =
+                    serde_json::from_slice(&std::fs::read(cache_path)?)?;
+                signatures.extend(cached);
+            }
+        }
+
+        Ok(Self {
+            signatures,
+            cache_path: cache_path.map(Path::to_path_buf),
+            dirty: false,
+            client: reqwest::Client::new(),
+        })
+    }
+
+    // Online lookups are only done when a cache file is configured. A failed lookup only costs
+    // the function name, it is printed and tried again for the next record
+    pub async fn resolve(&mut self, selector: Selector) -> Option<String> {
+        if let Some(signature) = self.signatures.get(&selector) {
+            return signature.clone();
+        }
+        self.cache_path.as_ref()?;
+
+        match self.lookup(selector).await {
+            Ok(signature) => {
+                self.signatures.insert(selector, signature.clone());
+                self.dirty = true;
+                signature
+            }
+            Err(err) => {
+                log_line(format!(
+                    "Looking up selector {} failed: {:#}",
+                    selector, err
+                ));
+                None
+            }
+        }
+    }
+
+    async fn lookup(&self, selector: Selector) -> anyhow::Result<Option<String>> {
+        let response = self
+            .client
+            .get(FOUR_BYTE_URL)
+            .query(&[("hex_signature", selector.to_string())])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<FourByteResponse>()
+            .await?;
+
+        // The oldest submission is the least likely to be a spoofed collision
+        Ok(response
+            .results
+            .into_iter()
+            .min_by_key(|signature| signature.id)
+            .map(|signature| signature.text_signature))
+    }
+
+    // Writes the lookups made since the last flush to the cache file
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        let Some(cache_path) = &self.cache_path else {
+            return Ok(());
+        };
+        if self.dirty {
+            std::fs::write(cache_path, serde_json::to_string(&self.signatures)?)?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+}
+
+fn selector_of(signature: &str) -> Selector {
+    Selector::from_slice(&keccak256(signature.as_bytes())[..4])
+}
@@ -0,0 +1,76 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use alloy_primitives::Address;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+
+use crate::index::ResultIndex;
+use crate::progress::log_line;
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct ServeArgs {
+    /// Trace records to serve: trace_records.json, newline delimited records or a --results-db directory
+    #[arg(long)]
+    pub db: PathBuf,
+    /// Address the API listens on
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    pub listen: SocketAddr,
+    /// Also serve the records as Arrow record batches over Arrow Flight on this address, needs
+    /// the `flight` feature
+    #[arg(long)]
+    pub flight_listen: Option<SocketAddr>,
+}
+
+pub async fn serve(args: ServeArgs) -> anyhow::Result<()> {
+    #[cfg(not(feature = "flight"))]
+    if args.flight_listen.is_some() {
+        anyhow::bail!("--flight-listen requires building with `--features flight`");
+    }
+    let index = Arc::new(ResultIndex::load(&args.db)?);
+    let stats = index.stats();
+    log_line(format!(
+        "Serving {} records of {} addresses on {}",
+        stats.records, stats.addresses, args.listen
+    ));
+
+    let app = Router::new()
+        .route("/address/:address/lifecycle", get(lifecycle))
+        .route("/block/:block_number/traces", get(block_traces))
+        .route("/stats", get(index_stats))
+        .with_state(index.clone());
+    let listener = tokio::net::TcpListener::bind(args.listen).await?;
+    let api = async { Ok::<_, anyhow::Error>(axum::serve(listener, app).await?) };
+    #[cfg(feature = "flight")]
+    if let Some(flight_listen) = args.flight_listen {
+        tokio::try_join!(api, crate::flight::serve(flight_listen, index))?;
+        return Ok(());
+    }
+    api.await
+}
+
+async fn lifecycle(
+    State(index): State<Arc<ResultIndex>>,
+    Path(address): Path<Address>,
+) -> Response {
+    let lifecycles = index.lifecycles(address);
+    if lifecycles.is_empty() {
+        return (StatusCode::NOT_FOUND, format!("No records for {}", address)).into_response();
+    }
+    Json(lifecycles).into_response()
+}
+
+async fn block_traces(
+    State(index): State<Arc<ResultIndex>>,
+    Path(block_number): Path<u64>,
+) -> Response {
+    Json(index.block(block_number)).into_response()
+}
+
+async fn index_stats(State(index): State<Arc<ResultIndex>>) -> Response {
+    Json(index.stats()).into_response()
+}
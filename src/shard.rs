@@ -0,0 +1,332 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use alloy_primitives::Address;
+use serde_json::Value;
+
+use crate::distributed::chunk_cmd;
+use crate::failures;
+use crate::index::ResultIndex;
+use crate::progress::log_line;
+use crate::{output, Cmd, TraceResponse};
+
+// Files of every chunk directory that hold a JSON array and are concatenated by `merge`
+const MERGED_FILES: &[&str] = &[
+    "balance_changes.json",
+    "blob_stats.json",
+    "uncles.json",
+    "compliance_findings.json",
+    "alerts.json",
+];
+
+// Zero based shard `index` out of `count`, written as `index/count`
+#[derive(Debug, Clone, Copy)]
+pub struct Shard {
+    pub index: u64,
+    pub count: u64,
+}
+
+impl FromStr for Shard {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (index, count) = s
+            .split_once('/')
+            .ok_or_else(|| format!("Shard {} is not of the form i/n", s))?;
+        let index = index.parse::<u64>().map_err(|err| err.to_string())?;
+        let count = count.parse::<u64>().map_err(|err| err.to_string())?;
+        if index >= count {
+            return Err(format!(
+                "Shard index {} has to be below the count {}",
+                index, count
+            ));
+        }
+        Ok(Self { index, count })
+    }
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct MergeArgs {
+    /// Directory holding the `<start>-<end>` chunk directories of the shards or workers
+    #[arg(long, default_value = "chunks")]
+    pub chunks_dir: PathBuf,
+    /// Merge even when chunks have failed blocks, are unfinished or leave blocks uncovered. Their
+    /// failed blocks are still written to failed_blocks.json
+    #[arg(long)]
+    pub allow_incomplete: bool,
+}
+
+// Scans every `count`-th chunk of the range starting at chunk `index`, so shards never overlap
+pub async fn scan(cmd: Cmd, shard: Shard, start_block: u64, end_block: u64) -> anyhow::Result<()> {
+    let chunk_size = cmd.shard_chunk_size.max(1);
+    let chunks = (start_block..=end_block)
+        .step_by(chunk_size as usize)
+        .enumerate()
+        .filter(|(position, _)| *position as u64 % shard.count == shard.index)
+        .map(|(_, chunk_start)| {
+            (
+                chunk_start,
+                chunk_start.saturating_add(chunk_size - 1).min(end_block),
+            )
+        })
+        .collect::<Vec<_>>();
+    log_line(format!(
+        "Shard {}/{} scans {} chunks into {}",
+        shard.index,
+        shard.count,
+        chunks.len(),
+        cmd.output_dir.display()
+    ));
+
+    // A chunk whose manifest shows a complete run of it is skipped by the scan itself, chunks
+    // that were interrupted or finished with failed blocks resume from their stage checkpoints
+    for (chunk_start, chunk_end) in chunks {
+        let mut chunk_cmd = chunk_cmd(&cmd, chunk_start, chunk_end, &cmd.output_dir);
+        chunk_cmd.resume = true;
+        Box::pin(crate::amain(chunk_cmd)).await?;
+    }
+    Ok(())
+}
+
+// Combines the chunk directories into one set of output files in `output_dir`. Failed blocks of
+// the chunks are combined too, and without --allow-incomplete nothing is merged while there are
+// any, or unfinished chunks, or blocks no chunk covers
+pub fn merge(args: MergeArgs, output_dir: &Path) -> anyhow::Result<()> {
+    let mut chunk_dirs = BTreeMap::new();
+    for entry in std::fs::read_dir(&args.chunks_dir)? {
+        let path = entry?.path();
+        let Some((chunk_start, chunk_end)) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.split_once('-'))
+        else {
+            continue;
+        };
+        if let (Ok(chunk_start), Ok(chunk_end)) =
+            (chunk_start.parse::<u64>(), chunk_end.parse::<u64>())
+        {
+            chunk_dirs.insert((chunk_start, chunk_end), path);
+        }
+    }
+
+    let mut failed_blocks = Vec::new();
+    let mut problems = Vec::new();
+    let mut next_block = None;
+    for ((chunk_start, chunk_end), chunk_dir) in chunk_dirs.iter() {
+        if let Some(next_block) = next_block.filter(|next_block| next_block < chunk_start) {
+            problems.push(format!(
+                "blocks {}-{} are in no chunk",
+                next_block,
+                chunk_start - 1
+            ));
+        }
+        next_block = Some(
+            next_block
+                .unwrap_or_default()
+                .max(chunk_end.saturating_add(1)),
+        );
+        if !chunk_dir.join("manifest.json").exists() {
+            problems.push(format!(
+                "chunk {}-{} did not finish",
+                chunk_start, chunk_end
+            ));
+        }
+        failed_blocks.extend(failures::read_failed_blocks(chunk_dir)?);
+    }
+    failed_blocks.sort_by_key(|failed_block| failed_block.block_number);
+    if !failed_blocks.is_empty() {
+        problems.push(format!("{} blocks failed", failed_blocks.len()));
+    }
+    if !problems.is_empty() && !args.allow_incomplete {
+        anyhow::bail!(
+            "The chunks are incomplete: {}. Scan them again or pass --allow-incomplete",
+            problems.join(", ")
+        );
+    }
+    std::fs::create_dir_all(output_dir)?;
+
+    let mut trace_records = Vec::new();
+    let mut reinitialized_contracts = Vec::new();
+    for chunk_dir in chunk_dirs.values() {
+        // Only an unfinished chunk has none, it is already reported
+        if !chunk_dir.join("trace_records.json").exists() {
+            continue;
+        }
+        trace_records.extend(read_json::<Vec<TraceResponse>>(
+            &chunk_dir.join("trace_records.json"),
+        )?);
+        reinitialized_contracts.extend(read_json::<Vec<Address>>(
+            &chunk_dir.join("reinitialized_contracts.json"),
+        )?);
+    }
+
+    // A contract destroyed in one chunk and created again in a later one is only visible here
+    let index = ResultIndex::new(trace_records.clone());
+    reinitialized_contracts.extend(
+        index
+            .recreations(0, u64::MAX)
+            .into_iter()
+            .map(|recreation| recreation.address),
+    );
+    reinitialized_contracts.sort();
+    reinitialized_contracts.dedup();
+
+    trace_records.sort_by(|a, b| {
+        (a.block_number, a.transaction_position, &a.trace_address).cmp(&(
+            b.block_number,
+            b.transaction_position,
+            &b.trace_address,
+        ))
+    });
+    output::write_json(output_dir, "trace_records.json", &trace_records)?;
+    output::write_json(
+        output_dir,
+        "reinitialized_contracts.json",
+        &reinitialized_contracts,
+    )?;
+
+    for file_name in MERGED_FILES {
+        let mut merged = Vec::new();
+        let mut found = false;
+        for chunk_dir in chunk_dirs.values() {
+            let path = chunk_dir.join(file_name);
+            if path.exists() {
+                found = true;
+                merged.extend(read_json::<Vec<Value>>(&path)?);
+            }
+        }
+        if found {
+            output::write_json(output_dir, file_name, &merged)?;
+        }
+    }
+
+    // A follow-up run with `--blocks-file failed_blocks.json` traces exactly these again
+    if failed_blocks.is_empty() {
+        let _ = std::fs::remove_file(output_dir.join("failed_blocks.json"));
+    } else {
+        output::write_json(output_dir, "failed_blocks.json", &failed_blocks)?;
+    }
+
+    log_line(format!(
+        "Merged {} chunks with {} records and {} reinitialized contracts",
+        chunk_dirs.len(),
+        trace_records.len(),
+        reinitialized_contracts.len()
+    ));
+    if !problems.is_empty() {
+        log_line(format!("INCOMPLETE: {}", problems.join(", ")));
+    }
+    Ok(())
+}
+
+pub fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> anyhow::Result<T> {
+    let contents = std::fs::read(path)
+        .map_err(|err| anyhow::anyhow!("Reading {} failed: {}", path.display(), err))?;
+    Ok(serde_json::from_slice(&contents)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    // A finished chunk without records, with the given failed blocks
+    fn write_chunk(chunks_dir: &Path, name: &str, failed_blocks: &[u64]) {
+        let chunk_dir = chunks_dir.join(name);
+        std::fs::create_dir_all(&chunk_dir).unwrap();
+        for file_name in [
+            "trace_records.json",
+            "reinitialized_contracts.json",
+            "manifest.json",
+        ] {
+            std::fs::write(chunk_dir.join(file_name), "[]").unwrap();
+        }
+        if !failed_blocks.is_empty() {
+            let failed_blocks = failed_blocks
+                .iter()
+                .map(|block_number| {
+                    json!({"block_number": block_number, "category": "timeout", "error": "timed out"})
+                })
+                .collect::<Vec<_>>();
+            std::fs::write(
+                chunk_dir.join("failed_blocks.json"),
+                serde_json::to_vec(&failed_blocks).unwrap(),
+            )
+            .unwrap();
+        }
+    }
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "block-tracer-merge-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn merge_args(chunks_dir: &Path, allow_incomplete: bool) -> MergeArgs {
+        MergeArgs {
+            chunks_dir: chunks_dir.to_path_buf(),
+            allow_incomplete,
+        }
+    }
+
+    #[test]
+    fn merge_refuses_chunks_with_failed_blocks() {
+        let dir = test_dir("failed");
+        let chunks_dir = dir.join("chunks");
+        write_chunk(&chunks_dir, "0-9", &[]);
+        write_chunk(&chunks_dir, "10-19", &[12, 15]);
+
+        let err = merge(merge_args(&chunks_dir, false), &dir.join("merged")).unwrap_err();
+        assert!(err.to_string().contains("2 blocks failed"), "{}", err);
+        assert!(!dir.join("merged/trace_records.json").exists());
+
+        merge(merge_args(&chunks_dir, true), &dir.join("merged")).unwrap();
+        assert_eq!(
+            failures::load_blocks_file(&dir.join("merged/failed_blocks.json")).unwrap(),
+            vec![12, 15]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn merge_refuses_uncovered_and_unfinished_chunks() {
+        let dir = test_dir("gaps");
+        let chunks_dir = dir.join("chunks");
+        write_chunk(&chunks_dir, "0-9", &[]);
+        write_chunk(&chunks_dir, "20-29", &[]);
+        write_chunk(&chunks_dir, "30-39", &[]);
+        std::fs::remove_file(chunks_dir.join("30-39/manifest.json")).unwrap();
+
+        let err = merge(merge_args(&chunks_dir, false), &dir.join("merged")).unwrap_err();
+        assert!(
+            err.to_string().contains("blocks 10-19 are in no chunk"),
+            "{}",
+            err
+        );
+        assert!(
+            err.to_string().contains("chunk 30-39 did not finish"),
+            "{}",
+            err
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn merge_of_complete_chunks_writes_no_failed_blocks() {
+        let dir = test_dir("complete");
+        let chunks_dir = dir.join("chunks");
+        write_chunk(&chunks_dir, "0-9", &[]);
+        write_chunk(&chunks_dir, "10-19", &[]);
+
+        merge(merge_args(&chunks_dir, false), &dir.join("merged")).unwrap();
+        assert!(dir.join("merged/trace_records.json").exists());
+        assert!(!dir.join("merged/failed_blocks.json").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
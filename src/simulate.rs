@@ -0,0 +1,133 @@
+use std::path::Path;
+
+use alloy_primitives::{Address, Bytes, U256};
+use serde_json::json;
+
+use crate::mempool::CallFrame;
+use crate::output;
+use crate::progress::log_line;
+use crate::rpc::RpcClient;
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct SimulateArgs {
+    /// Contract to call
+    #[arg(long)]
+    pub to: Address,
+    /// Calldata of the call
+    #[arg(long, default_value = "0x")]
+    pub data: Bytes,
+    /// Sender of the call, e.g. the admin account that would send the transaction
+    #[arg(long)]
+    pub from: Option<Address>,
+    /// Wei sent with the call
+    #[arg(long, default_value_t = U256::ZERO)]
+    pub value: U256,
+    /// Block whose state the call runs on, defaults to the latest block
+    #[arg(long)]
+    pub block: Option<u64>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SimulatedEffect {
+    pub kind: SimulatedEffectKind,
+    pub address: Address,
+    // Beneficiary of a selfdestruct, deployer of a create
+    pub counterparty: Address,
+    pub value: U256,
+    // Set when the frame or one of its callers fails, the effect would be rolled back
+    pub reverted: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SimulatedEffectKind {
+    Create,
+    Selfdestruct,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Simulation {
+    pub block: String,
+    // Error of the top-level call, the whole call would revert
+    pub error: Option<String>,
+    pub effects: Vec<SimulatedEffect>,
+}
+
+// Pre-flight check of an admin transaction: runs the call with `debug_traceCall` and reports
+// every contract it would create or selfdestruct, nothing is sent
+pub async fn run(args: SimulateArgs, client: RpcClient, output_dir: &Path) -> anyhow::Result<()> {
+    let block = args
+        .block
+        .map_or_else(|| "latest".to_string(), |block| format!("0x{:x}", block));
+    let call = json!({
+        "from": args.from,
+        "to": args.to,
+        "data": args.data,
+        "value": args.value,
+    });
+    let frame = client
+        .request::<CallFrame>(
+            "debug_traceCall",
+            json!([call, block, { "tracer": "callTracer" }]),
+        )
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("The node returned no trace for the call"))?;
+
+    let mut effects = Vec::new();
+    collect_effects(&frame, false, &mut effects);
+    let simulation = Simulation {
+        block,
+        error: frame.error.clone(),
+        effects,
+    };
+
+    if let Some(error) = &simulation.error {
+        log_line(format!("The call would revert: {}", error));
+    }
+    for effect in simulation.effects.iter() {
+        let kind = match effect.kind {
+            SimulatedEffectKind::Create => "create",
+            SimulatedEffectKind::Selfdestruct => "selfdestruct",
+        };
+        log_line(format!(
+            "The call would {} {}{}",
+            kind,
+            effect.address,
+            if effect.reverted {
+                " (rolled back)"
+            } else {
+                ""
+            }
+        ));
+    }
+    if simulation.effects.is_empty() {
+        log_line("The call creates and selfdestructs no contracts");
+    }
+    std::fs::create_dir_all(output_dir)?;
+    output::write_json(output_dir, "simulation.json", &simulation)
+}
+
+fn collect_effects(frame: &CallFrame, reverted: bool, effects: &mut Vec<SimulatedEffect>) {
+    let reverted = reverted || frame.error.is_some();
+    let value = frame.value.unwrap_or_default();
+    match (frame.frame_type.as_str(), frame.to) {
+        ("SELFDESTRUCT", Some(beneficiary)) => effects.push(SimulatedEffect {
+            kind: SimulatedEffectKind::Selfdestruct,
+            address: frame.from,
+            counterparty: beneficiary,
+            value,
+            reverted,
+        }),
+        ("CREATE" | "CREATE2", Some(created)) => effects.push(SimulatedEffect {
+            kind: SimulatedEffectKind::Create,
+            address: created,
+            counterparty: frame.from,
+            value,
+            reverted,
+        }),
+        _ => {}
+    }
+    for call in frame.calls.iter() {
+        collect_effects(call, reverted, effects);
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use alloy_primitives::B256;
+use serde_json::{json, Value};
+
+use crate::output;
+use crate::TraceResponse;
+
+// Destination of records produced block by block by the long running modes
+pub trait Sink: Send {
+    fn write_records(&mut self, trace_responses: &[TraceResponse]) -> anyhow::Result<()>;
+    // Records of the block were written before it was orphaned by a reorg and have to be dropped
+    fn retract_block(
+        &mut self,
+        chain_id: u64,
+        block_number: u64,
+        block_hash: B256,
+    ) -> anyhow::Result<()>;
+}
+
+// Appends one JSON record per line, flushed after every block so readers never see partial blocks
+pub struct NdjsonSink {
+    writer: BufWriter<File>,
+}
+
+impl NdjsonSink {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+        })
+    }
+
+    // Size of the file with everything written so far
+    pub fn written_bytes(&mut self) -> anyhow::Result<u64> {
+        self.writer.flush()?;
+        Ok(self.writer.get_ref().metadata()?.len())
+    }
+
+    // Drops the lines appended after the file had `len` bytes, the next ones follow them
+    pub fn truncate(&mut self, len: u64) -> anyhow::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_ref().set_len(len)?;
+        Ok(())
+    }
+}
+
+impl Sink for NdjsonSink {
+    fn write_records(&mut self, trace_responses: &[TraceResponse]) -> anyhow::Result<()> {
+        let started = Instant::now();
+        // Written at once, so a crash leaves a block's lines whole or not at all
+        let mut lines = Vec::new();
+        for trace_response in trace_responses {
+            serde_json::to_writer(&mut lines, &output::to_output_value(trace_response)?)?;
+            lines.push(b'\n');
+        }
+        self.writer.write_all(&lines)?;
+        self.writer.flush()?;
+        metrics::histogram!("block_tracer_output_write_duration_seconds", "file" => "sink")
+            .record(started.elapsed().as_secs_f64());
+        Ok(())
+    }
+
+    fn retract_block(
+        &mut self,
+        chain_id: u64,
+        block_number: u64,
+        block_hash: B256,
+    ) -> anyhow::Result<()> {
+        let retraction = json!({
+            "retraction": {
+                "chain_id": chain_id,
+                "block_number": block_number,
+                "block_hash": block_hash,
+            }
+        });
+        serde_json::to_writer(&mut self.writer, &retraction)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+// NDJSON sinks by path, so followers of several chains in one process append to the same file
+// without interleaving their lines
+static SHARED_SINKS: Mutex<BTreeMap<PathBuf, Arc<Mutex<NdjsonSink>>>> = Mutex::new(BTreeMap::new());
+
+#[derive(Clone)]
+pub struct SharedSink(Arc<Mutex<NdjsonSink>>);
+
+impl SharedSink {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let mut shared_sinks = SHARED_SINKS.lock().unwrap();
+        if let Some(sink) = shared_sinks.get(path) {
+            return Ok(Self(sink.clone()));
+        }
+        let sink = Arc::new(Mutex::new(NdjsonSink::open(path)?));
+        shared_sinks.insert(path.to_path_buf(), sink.clone());
+        Ok(Self(sink))
+    }
+
+    pub fn written_bytes(&self) -> anyhow::Result<u64> {
+        self.0.lock().unwrap().written_bytes()
+    }
+}
+
+// Highest block of the chain with records after `offset` in the file that were not retracted
+// again, so a restarted follower does not write them twice. Lines a crash cut off are ignored
+pub fn last_written_block(path: &Path, chain_id: u64, offset: u64) -> anyhow::Result<Option<u64>> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    file.seek(SeekFrom::Start(offset))?;
+    let mut last_written = None;
+    for line in BufReader::new(file).lines() {
+        let Ok(line) = serde_json::from_str::<Value>(&line?) else {
+            continue;
+        };
+        let (retracted, line) = match line.get("retraction") {
+            Some(retraction) => (true, retraction),
+            None => (false, &line),
+        };
+        if line["chain_id"].as_u64() != Some(chain_id) {
+            continue;
+        }
+        let Some(block_number) = line["block_number"].as_u64() else {
+            continue;
+        };
+        last_written = if retracted {
+            // Blocks from the retracted one on are gone
+            last_written
+                .filter(|_| block_number > 0)
+                .map(|last_written: u64| last_written.min(block_number - 1))
+        } else {
+            last_written.max(Some(block_number))
+        };
+    }
+    Ok(last_written)
+}
+
+impl Sink for SharedSink {
+    fn write_records(&mut self, trace_responses: &[TraceResponse]) -> anyhow::Result<()> {
+        self.0.lock().unwrap().write_records(trace_responses)
+    }
+
+    fn retract_block(
+        &mut self,
+        chain_id: u64,
+        block_number: u64,
+        block_hash: B256,
+    ) -> anyhow::Result<()> {
+        self.0
+            .lock()
+            .unwrap()
+            .retract_block(chain_id, block_number, block_hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_last_written_block_follows_records_and_retractions_of_the_chain() {
+        let path =
+            std::env::temp_dir().join(format!("block-tracer-sink-{}.ndjson", std::process::id()));
+        let lines = [
+            json!({"chain_id": 1, "block_number": 10}),
+            json!({"chain_id": 1, "block_number": 11}),
+            json!({"chain_id": 10, "block_number": 500}),
+            json!({"retraction": {"chain_id": 1, "block_number": 11, "block_hash": B256::ZERO}}),
+        ];
+        let mut contents = lines
+            .iter()
+            .map(|line| format!("{}\n", line))
+            .collect::<String>();
+        // Past the records of chain 1, only the retraction follows
+        let offset = lines[..2]
+            .iter()
+            .map(|line| line.to_string().len() as u64 + 1)
+            .sum::<u64>();
+        contents.push_str("{\"chain_id\": 1, \"block_num");
+        std::fs::write(&path, &contents).unwrap();
+
+        assert_eq!(last_written_block(&path, 1, 0).unwrap(), Some(10));
+        assert_eq!(last_written_block(&path, 1, offset).unwrap(), None);
+        assert_eq!(last_written_block(&path, 10, 0).unwrap(), Some(500));
+        assert_eq!(last_written_block(&path, 137, 0).unwrap(), None);
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(last_written_block(&path, 1, 0).unwrap(), None);
+    }
+}
@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+
+use alloy_primitives::{Address, B256};
+
+const SOURCIFY_API_URL: &str = "https://sourcify.dev/server/check-by-addresses";
+const SOURCIFY_REPO_URL: &str = "https://repo.sourcify.dev/contracts";
+const ADDRESSES_PER_REQUEST: usize = 50;
+
+#[derive(Debug, serde::Deserialize)]
+struct CheckResult {
+    address: Address,
+    status: String,
+}
+
+// Contracts sharing runtime code share sources, so only one address per code hash is looked up
+pub async fn source_urls(
+    chain_id: u64,
+    contracts: &[(Address, B256)],
+) -> anyhow::Result<HashMap<B256, String>> {
+    let client = reqwest::Client::new();
+    let mut by_code_hash: HashMap<B256, Address> = HashMap::new();
+    for (address, code_hash) in contracts {
+        by_code_hash.entry(*code_hash).or_insert(*address);
+    }
+    let representatives: HashMap<Address, B256> = by_code_hash
+        .into_iter()
+        .map(|(code_hash, address)| (address, code_hash))
+        .collect();
+
+    let addresses = representatives.keys().copied().collect::<Vec<_>>();
+    let mut urls = HashMap::new();
+    for chunk in addresses.chunks(ADDRESSES_PER_REQUEST) {
+        let addresses_param = chunk
+            .iter()
+            .map(|address| address.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        let chain_ids_param = chain_id.to_string();
+        let results = client
+            .get(SOURCIFY_API_URL)
+            .query(&[
+                ("addresses", addresses_param.as_str()),
+                ("chainIds", chain_ids_param.as_str()),
+            ])
+            .send()
+            .await?
+            .json::<Vec<CheckResult>>()
+            .await?;
+
+        for result in results {
+            let match_type = match result.status.as_str() {
+                "perfect" => "full_match",
+                "partial" => "partial_match",
+                _ => continue,
+            };
+            if let Some(code_hash) = representatives.get(&result.address) {
+                urls.insert(
+                    *code_hash,
+                    format!(
+                        "{}/{}/{}/{}/",
+                        SOURCIFY_REPO_URL,
+                        match_type,
+                        chain_id,
+                        result.address.to_checksum(None)
+                    ),
+                );
+            }
+        }
+    }
+
+    Ok(urls)
+}
@@ -0,0 +1,168 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::progress::log_line;
+
+// Phases of a range scan, in the order they run. Like reth's stages each keeps its own
+// checkpoint, the last block up to which its output is complete, so a rerun resumes every phase
+// where it stopped instead of starting over
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StageId {
+    // trace_block over the range, function names included
+    Trace,
+    // Recreation detection, in range and against the state
+    Verify,
+    // Everything layered on top of the records, up to the final output files
+    Enrich,
+}
+
+impl StageId {
+    pub const ALL: [StageId; 3] = [StageId::Trace, StageId::Verify, StageId::Enrich];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StageId::Trace => "trace",
+            StageId::Verify => "verify",
+            StageId::Enrich => "enrich",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, serde::Deserialize)]
+pub struct StageCheckpoint {
+    pub block_number: u64,
+}
+
+#[derive(Debug, Serialize, serde::Deserialize)]
+struct StagesFile {
+    start_block: u64,
+    end_block: u64,
+    checkpoints: BTreeMap<StageId, StageCheckpoint>,
+}
+
+// `stages.json` in the output directory, plus what each stage produced up to its checkpoint in
+// `stages/<stage>.json`. Orchestrators can poll `stages.json` for progress
+#[derive(Debug)]
+pub struct Stages {
+    path: PathBuf,
+    state_dir: PathBuf,
+    file: StagesFile,
+}
+
+impl Stages {
+    // Without `resume` earlier checkpoints are discarded. Resuming a different range is refused,
+    // checkpoints only mean something for the range they were written for
+    pub fn open(
+        output_dir: &Path,
+        start_block: u64,
+        end_block: u64,
+        resume: bool,
+    ) -> anyhow::Result<Self> {
+        let path = output_dir.join("stages.json");
+        let state_dir = output_dir.join("stages");
+        std::fs::create_dir_all(&state_dir)?;
+
+        let file = if resume && path.exists() {
+            let file = serde_json::from_slice::<StagesFile>(&std::fs::read(&path)?)?;
+            if (file.start_block, file.end_block) != (start_block, end_block) {
+                anyhow::bail!(
+                    "{} has checkpoints for blocks {}-{}, not {}-{}",
+                    path.display(),
+                    file.start_block,
+                    file.end_block,
+                    start_block,
+                    end_block
+                );
+            }
+            file
+        } else {
+            StagesFile {
+                start_block,
+                end_block,
+                checkpoints: BTreeMap::new(),
+            }
+        };
+
+        let stages = Self {
+            path,
+            state_dir,
+            file,
+        };
+        for stage in StageId::ALL {
+            if let Some(checkpoint) = stages.checkpoint(stage) {
+                log_line(format!(
+                    "Resuming stage {} after block {}",
+                    stage.as_str(),
+                    checkpoint
+                ));
+                stages.record_metric(stage, checkpoint);
+            }
+        }
+        Ok(stages)
+    }
+
+    pub fn checkpoint(&self, stage: StageId) -> Option<u64> {
+        self.file
+            .checkpoints
+            .get(&stage)
+            .map(|checkpoint| checkpoint.block_number)
+    }
+
+    // The stage already covers the whole range
+    pub fn is_done(&self, stage: StageId) -> bool {
+        self.checkpoint(stage) == Some(self.file.end_block)
+    }
+
+    // Output of the stage up to its checkpoint, `None` when it has none
+    pub fn load<T: DeserializeOwned>(&self, stage: StageId) -> anyhow::Result<Option<T>> {
+        if self.checkpoint(stage).is_none() {
+            return Ok(None);
+        }
+        let path = self.state_path(stage);
+        Ok(Some(serde_json::from_slice(&std::fs::read(path)?)?))
+    }
+
+    // Stores the stage output before moving the checkpoint, so a checkpoint never points past
+    // output that was not written
+    pub fn save<T: Serialize + ?Sized>(
+        &mut self,
+        stage: StageId,
+        block_number: u64,
+        output: &T,
+    ) -> anyhow::Result<()> {
+        write_atomically(&self.state_path(stage), &serde_json::to_vec(output)?)?;
+        self.file
+            .checkpoints
+            .insert(stage, StageCheckpoint { block_number });
+        write_atomically(&self.path, &serde_json::to_vec_pretty(&self.file)?)?;
+        self.record_metric(stage, block_number);
+        Ok(())
+    }
+
+    // Later stages built on output that is about to change
+    pub fn reset_after(&mut self, stage: StageId) -> anyhow::Result<()> {
+        self.file.checkpoints.retain(|&other, _| other <= stage);
+        write_atomically(&self.path, &serde_json::to_vec_pretty(&self.file)?)
+    }
+
+    fn state_path(&self, stage: StageId) -> PathBuf {
+        self.state_dir.join(format!("{}.json", stage.as_str()))
+    }
+
+    fn record_metric(&self, stage: StageId, block_number: u64) {
+        metrics::gauge!("block_tracer_stage_checkpoint", "stage" => stage.as_str())
+            .set(block_number as f64);
+    }
+}
+
+// Written next to the target and renamed over it, so an interrupted write never loses it
+pub fn write_atomically(path: &Path, contents: &[u8]) -> anyhow::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    std::fs::write(&tmp_path, contents)?;
+    std::fs::rename(tmp_path, path)?;
+    Ok(())
+}
@@ -0,0 +1,48 @@
+use std::collections::BTreeMap;
+
+use alloy_primitives::Address;
+
+pub use crate::chain_db::{StorageChange, StorageSlot};
+
+use crate::chain_db::{ChainDb, HistorySegment};
+
+/// Non-zero slots of the contract as of the end of `block_number`. Starts from the latest state
+/// and reverts every later change: the first change of a slot after the block recorded the value
+/// the slot had at the block. Needs unpruned storage changesets from `block_number + 1` on, and
+/// fails when the node pruned them.
+pub fn storage_at(
+    db: &dyn ChainDb,
+    address: Address,
+    block_number: u64,
+) -> anyhow::Result<Vec<StorageSlot>> {
+    let pruned_until = db
+        .pruned_segments()?
+        .into_iter()
+        .find(|(segment, _)| *segment == HistorySegment::StorageHistory)
+        .map(|(_, pruned_until)| pruned_until);
+    if let Some(pruned_until) = pruned_until.filter(|pruned_until| *pruned_until > block_number) {
+        anyhow::bail!(
+            "Storage changesets are pruned up to block {}, the storage at block {} cannot be reconstructed",
+            pruned_until,
+            block_number
+        );
+    }
+
+    let mut storage = db
+        .plain_storage(address)?
+        .into_iter()
+        .map(|slot| (slot.slot, slot.value))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut reverted = BTreeMap::new();
+    for change in db.storage_changes(address, block_number + 1)? {
+        reverted.entry(change.slot).or_insert(change.previous_value);
+    }
+    storage.extend(reverted);
+
+    Ok(storage
+        .into_iter()
+        .filter(|(_, value)| !value.is_zero())
+        .map(|(slot, value)| StorageSlot { slot, value })
+        .collect())
+}
@@ -0,0 +1,87 @@
+use std::path::Path;
+
+use alloy_primitives::Address;
+use block_tracer::chain_db::ChainDb;
+use block_tracer::state::storage_at;
+
+use crate::output;
+use crate::progress::log_line;
+use crate::pruning::{self, Capability};
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct StorageArgs {
+    /// Contract whose storage is exported
+    #[arg(long)]
+    pub address: Address,
+    /// Also export every slot change since this block from the storage changesets
+    #[arg(long)]
+    pub changes_since: Option<u64>,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct StateAtArgs {
+    /// Contract whose storage is reconstructed
+    #[arg(long)]
+    pub address: Address,
+    /// Block at the end of which the storage is reconstructed
+    #[arg(long)]
+    pub block: u64,
+}
+
+pub fn run(db: &dyn ChainDb, args: StorageArgs, output_dir: &Path) -> anyhow::Result<()> {
+    std::fs::create_dir_all(output_dir)?;
+
+    let slots = db.plain_storage(args.address)?;
+    log_line(format!(
+        "{} has {} non-zero storage slots",
+        args.address,
+        slots.len()
+    ));
+    output::write_json(
+        output_dir,
+        &format!("storage_{}.json", args.address),
+        &slots,
+    )?;
+
+    if let Some(changes_since) = args.changes_since {
+        if let Some(degradation) = pruning::db_degradations(db, changes_since)?
+            .into_iter()
+            .find(|degradation| degradation.capability == Capability::StorageHistory)
+        {
+            anyhow::bail!(
+                "{}, the changes since block {} are incomplete",
+                degradation.reason,
+                changes_since
+            );
+        }
+        let changes = db.storage_changes(args.address, changes_since)?;
+        log_line(format!(
+            "{} storage changes since block {}",
+            changes.len(),
+            changes_since
+        ));
+        output::write_json(
+            output_dir,
+            &format!("storage_changes_{}.json", args.address),
+            &changes,
+        )?;
+    }
+    Ok(())
+}
+
+pub fn run_state_at(db: &dyn ChainDb, args: StateAtArgs, output_dir: &Path) -> anyhow::Result<()> {
+    std::fs::create_dir_all(output_dir)?;
+
+    let slots = storage_at(db, args.address, args.block)?;
+    log_line(format!(
+        "{} had {} non-zero storage slots at block {}",
+        args.address,
+        slots.len(),
+        args.block
+    ));
+    output::write_json(
+        output_dir,
+        &format!("storage_{}_at_{}.json", args.address, args.block),
+        &slots,
+    )
+}
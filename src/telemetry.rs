@@ -0,0 +1,73 @@
+use std::future::Future;
+
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{runtime, trace, Resource};
+use tokio::task::JoinHandle;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+
+// Spans are exported over OTLP/gRPC only when an endpoint is given, log output is unchanged
+pub fn init(otlp_endpoint: Option<&str>, console: bool) -> anyhow::Result<()> {
+    let otel_layer = match otlp_endpoint {
+        Some(otlp_endpoint) => {
+            let tracer = opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_exporter(
+                    opentelemetry_otlp::new_exporter()
+                        .tonic()
+                        .with_endpoint(otlp_endpoint),
+                )
+                .with_trace_config(trace::config().with_resource(Resource::new(vec![
+                    KeyValue::new("service.name", "block-tracer"),
+                ])))
+                .install_batch(runtime::Tokio)?;
+            Some(tracing_opentelemetry::layer().with_tracer(tracer))
+        }
+        None => None,
+    };
+
+    // The console layer needs the runtime's own trace events, so RUST_LOG only filters the other layers
+    #[cfg(feature = "console")]
+    let console_layer = console.then(console_subscriber::spawn);
+    #[cfg(not(feature = "console"))]
+    let console_layer: Option<tracing_subscriber::layer::Identity> = if console {
+        anyhow::bail!("--console requires building with `--features console` and RUSTFLAGS=\"--cfg tokio_unstable\"");
+    } else {
+        None
+    };
+
+    tracing_subscriber::registry()
+        .with(console_layer)
+        .with(tracing_subscriber::fmt::layer().with_filter(env_filter()))
+        .with(otel_layer.with_filter(env_filter()))
+        .try_init()?;
+    Ok(())
+}
+
+fn env_filter() -> EnvFilter {
+    EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"))
+}
+
+// Flushes spans still buffered in the batch exporter
+pub fn shutdown() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+// Named tasks show up by name in tokio-console, which is only possible with tokio_unstable
+pub fn spawn_named<F>(name: &str, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    #[cfg(tokio_unstable)]
+    return tokio::task::Builder::new()
+        .name(name)
+        .spawn(future)
+        .expect("failed to spawn task");
+
+    #[cfg(not(tokio_unstable))]
+    {
+        let _ = name;
+        tokio::spawn(future)
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+
+use alloy_primitives::{Selector, TxHash};
+use block_tracer::traces::missing_positions;
+use reth_rpc_types::trace::parity::{Action, CallAction, LocalizedTransactionTrace};
+use serde_json::json;
+
+use crate::progress::log_line;
+use crate::rpc::RpcClient;
+use crate::{output, trace_response, TraceContext};
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct TraceAtArgs {
+    /// Hash of the transaction
+    #[arg(long)]
+    pub tx: TxHash,
+    /// Position of the trace in the transaction's call tree, e.g. `0,2,1`, empty for the top-level
+    /// call (repeatable)
+    #[arg(long = "path", required = true)]
+    pub paths: Vec<TracePath>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TracePath(pub Vec<usize>);
+
+impl FromStr for TracePath {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(str::trim)
+            .filter(|index| !index.is_empty())
+            .map(|index| {
+                index
+                    .parse::<usize>()
+                    .map_err(|_| format!("{} is not a trace index", index))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(TracePath)
+    }
+}
+
+// Re-checks single traces of a known transaction without tracing its block. The record comes
+// out as in a scan, with the selector of the parent frame and the revert reason of its ancestors
+pub async fn run(
+    args: TraceAtArgs,
+    client: RpcClient,
+    chain_id: u64,
+    output_dir: &Path,
+) -> anyhow::Result<()> {
+    let mut trace_responses = Vec::new();
+    for TracePath(path) in args.paths.iter() {
+        let frames = frames(&client, args.tx, path).await?;
+        let Some(target) = frames.last() else {
+            unreachable!("a path has at least the top-level frame")
+        };
+        let Some(block_number) = target.block_number else {
+            anyhow::bail!("The trace of {} has no block number", args.tx);
+        };
+        let transaction_position = match target.transaction_position {
+            Some(transaction_position) => transaction_position,
+            None => missing_positions(&client, std::slice::from_ref(target), &HashMap::new())
+                .await?
+                .get(&args.tx)
+                .copied()
+                .ok_or_else(|| anyhow::anyhow!("Transaction {} is not mined", args.tx))?,
+        };
+        let selector = frames
+            .iter()
+            .rev()
+            .nth(1)
+            .and_then(|parent| match &parent.trace.action {
+                Action::Call(CallAction { input, .. }) => input.get(..4).map(Selector::from_slice),
+                _ => None,
+            });
+        let revert_reason = frames.iter().find_map(|frame| frame.trace.error.clone());
+
+        let context = TraceContext {
+            chain_id,
+            block_number,
+            block_hash: target.block_hash,
+            transaction_position,
+            transaction_hash: Some(args.tx),
+            deposit_transaction: false,
+            selector,
+            revert_reason,
+        };
+        match trace_response(context, target.trace.clone()) {
+            Some(trace_response) => {
+                log_line(format!(
+                    "{}",
+                    serde_json::to_string_pretty(&trace_response)?
+                ));
+                trace_responses.push(trace_response);
+            }
+            None => log_line(format!(
+                "The trace of {} at [{}] is no create or selfdestruct",
+                args.tx,
+                format_path(path)
+            )),
+        }
+    }
+
+    std::fs::create_dir_all(output_dir)?;
+    output::write_json(output_dir, "trace_at.json", &trace_responses)
+}
+
+// The frame at every prefix of the path, top-level call first. `trace_get` takes the position
+// in the call tree on Parity-style nodes, while reth and Erigon take the index into the flat trace
+// list. A node answering with another frame than asked for gets one `trace_transaction` instead
+async fn frames(
+    client: &RpcClient,
+    tx: TxHash,
+    path: &[usize],
+) -> anyhow::Result<Vec<LocalizedTransactionTrace>> {
+    let mut frames = Vec::new();
+    for depth in 0..=path.len() {
+        let prefix = &path[..depth];
+        let indices = prefix
+            .iter()
+            .map(|index| format!("0x{:x}", index))
+            .collect::<Vec<_>>();
+        match client
+            .request::<LocalizedTransactionTrace>("trace_get", json!([tx, indices]))
+            .await
+        {
+            Ok(Some(frame)) if frame.trace.trace_address == prefix => frames.push(frame),
+            _ => return frames_from_transaction(client, tx, path).await,
+        }
+    }
+    Ok(frames)
+}
+
+async fn frames_from_transaction(
+    client: &RpcClient,
+    tx: TxHash,
+    path: &[usize],
+) -> anyhow::Result<Vec<LocalizedTransactionTrace>> {
+    let traces = client
+        .request::<Vec<LocalizedTransactionTrace>>("trace_transaction", json!([tx]))
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Transaction {} is unknown to the node", tx))?;
+    (0..=path.len())
+        .map(|depth| {
+            traces
+                .iter()
+                .find(|trace| trace.trace.trace_address == path[..depth])
+                .cloned()
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Transaction {} has no trace at [{}]",
+                        tx,
+                        format_path(&path[..depth])
+                    )
+                })
+        })
+        .collect()
+}
+
+fn format_path(path: &[usize]) -> String {
+    path.iter()
+        .map(|index| index.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
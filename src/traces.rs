@@ -0,0 +1,459 @@
+//! Tracing a block the way every scan does: through the chain's [`L2Adapter`], with each trace
+//! placed in its transaction's call tree.
+
+use std::collections::HashMap;
+
+use alloy_primitives::{Address, Selector, TxHash, B256};
+use reth_rpc_types::trace::parity::{
+    Action, CallAction, CreateAction, LocalizedTransactionTrace, TransactionTrace,
+};
+use serde_json::json;
+
+use crate::l2::L2Adapter;
+use crate::progress::{log_line, progress};
+use crate::rpc::RpcClient;
+
+/// A trace of a block, with its transaction resolved and what the frames above it say about it.
+#[derive(Debug, Clone)]
+pub struct BlockTrace {
+    pub block_hash: Option<B256>,
+    /// Position of the transaction in the block. Reward traces have no transaction, they follow
+    /// the block's last one
+    pub transaction_position: u64,
+    pub transaction_hash: Option<TxHash>,
+    /// The chain inserted the transaction itself, see [`L2Adapter::system_transactions`]
+    pub deposit_transaction: bool,
+    /// Selector of the parent call frame
+    pub selector: Option<Selector>,
+    /// Error of the outermost failed frame above the trace, whose rollback undid the trace
+    pub revert_reason: Option<String>,
+    /// Accounts that started the frame of the trace and every frame above it, outermost first.
+    /// Only collected when asked for
+    pub senders: Vec<Address>,
+    pub trace: TransactionTrace,
+}
+
+/// Traces of the block in chain order, `None` when the node does not know the block.
+/// `block_tag` is a hex block number or `pending`, `block_number` names the block in messages.
+/// Traces whose transaction can't be placed are skipped.
+pub async fn trace_block(
+    client: &RpcClient,
+    adapter: &dyn L2Adapter,
+    block_tag: &str,
+    block_number: u64,
+    track_senders: bool,
+) -> anyhow::Result<Option<Vec<BlockTrace>>> {
+    let deposits = adapter.system_transactions(client, block_tag).await?;
+    let Some(localized_tx_traces) = adapter
+        .trace_block(client, block_tag)
+        .await?
+        .map(parse_traces)
+    else {
+        return Ok(None);
+    };
+    let looked_up_positions = missing_positions(client, &localized_tx_traces, &deposits).await?;
+
+    let parse_span = tracing::info_span!("parse", block = block_number);
+    Ok(Some(parse_span.in_scope(|| {
+        place_traces(
+            localized_tx_traces,
+            &deposits,
+            &looked_up_positions,
+            block_number,
+            track_senders,
+        )
+    })))
+}
+
+fn place_traces(
+    localized_tx_traces: Vec<LocalizedTransactionTrace>,
+    deposits: &HashMap<TxHash, u64>,
+    looked_up_positions: &HashMap<TxHash, u64>,
+    block_number: u64,
+    track_senders: bool,
+) -> Vec<BlockTrace> {
+    let reward_position = localized_tx_traces
+        .iter()
+        .filter_map(|tx_trace| tx_trace.transaction_position)
+        .max()
+        .map_or(0, |last_position| last_position + 1);
+    // Frames are keyed by position rather than hash, traces of system transactions and rewards
+    // may carry no hash and would otherwise share one key
+    let positions = localized_tx_traces
+        .iter()
+        .map(|tx_trace| {
+            let reward = matches!(tx_trace.trace.action, Action::Reward(_));
+            tx_trace
+                .transaction_position
+                .or_else(|| {
+                    tx_trace.transaction_hash.and_then(|hash| {
+                        deposits
+                            .get(&hash)
+                            .or_else(|| looked_up_positions.get(&hash))
+                            .copied()
+                    })
+                })
+                // Reward traces have no transaction, they follow the last one
+                .or(reward.then_some(reward_position))
+        })
+        .collect::<Vec<_>>();
+    // A failed frame can precede or follow the traces it rolls back, so all of them are
+    // collected before any trace is placed
+    let reverted_frames = reverted_frames(&localized_tx_traces, &positions);
+    // Selectors of call frames, parents always precede their subtraces
+    let mut call_selectors: HashMap<(u64, Vec<usize>), Selector> = HashMap::new();
+    // Accounts that started each frame, only kept when senders are tracked
+    let mut frame_senders: HashMap<(u64, Vec<usize>), Address> = HashMap::new();
+
+    let mut block_traces = Vec::with_capacity(localized_tx_traces.len());
+    for (tx_trace, transaction_position) in localized_tx_traces.into_iter().zip(positions) {
+        let Some(transaction_position) = transaction_position else {
+            log_line(format!(
+                "Skipping a trace of block {} without transaction position or hash",
+                block_number
+            ));
+            continue;
+        };
+        let trace = tx_trace.trace;
+        let trace_address = &trace.trace_address;
+        if let Action::Call(CallAction { input, .. }) = &trace.action {
+            if let Some(selector) = input.get(..4) {
+                call_selectors.insert(
+                    (transaction_position, trace_address.clone()),
+                    Selector::from_slice(selector),
+                );
+            }
+        }
+        let mut senders = Vec::new();
+        if track_senders {
+            if let Action::Call(CallAction { from, .. })
+            | Action::Create(CreateAction { from, .. }) = &trace.action
+            {
+                frame_senders.insert((transaction_position, trace_address.clone()), *from);
+            }
+            senders.extend((0..=trace_address.len()).filter_map(|depth| {
+                frame_senders
+                    .get(&(transaction_position, trace_address[..depth].to_vec()))
+                    .copied()
+            }));
+        }
+        let selector = trace_address.split_last().and_then(|(_, parent)| {
+            call_selectors
+                .get(&(transaction_position, parent.to_vec()))
+                .copied()
+        });
+        let revert_reason = revert_reason(&reverted_frames, transaction_position, trace_address);
+        block_traces.push(BlockTrace {
+            block_hash: tx_trace.block_hash,
+            transaction_position,
+            transaction_hash: tx_trace.transaction_hash,
+            deposit_transaction: tx_trace
+                .transaction_hash
+                .is_some_and(|hash| deposits.contains_key(&hash)),
+            selector,
+            revert_reason,
+            senders,
+            trace,
+        });
+    }
+    block_traces
+}
+
+// Entries that do not deserialize are skipped and counted instead of failing the whole block.
+// Optional fields a node left out are counted too, some clients omit them on system traces
+fn parse_traces(entries: Vec<serde_json::Value>) -> Vec<LocalizedTransactionTrace> {
+    entries
+        .into_iter()
+        .filter_map(|entry| {
+            // Reward traces never belong to a transaction
+            let reward = entry.get("type").and_then(|value| value.as_str()) == Some("reward");
+            for field in ["transactionHash", "transactionPosition", "blockHash"] {
+                if !(reward && field != "blockHash")
+                    && entry.get(field).map_or(true, |value| value.is_null())
+                {
+                    progress().absent_field("trace_block", field);
+                }
+            }
+            match serde_json::from_value::<LocalizedTransactionTrace>(entry) {
+                Ok(tx_trace) => Some(tx_trace),
+                Err(err) => {
+                    log_line(format!("Skipping a malformed trace: {}", err));
+                    progress().absent_field("trace_block", "trace");
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TransactionIndex {
+    transaction_index: Option<alloy_primitives::U64>,
+}
+
+/// Positions of transactions whose traces carry a hash but no position, looked up one by one.
+/// Transactions in `deposits` are already placed.
+pub async fn missing_positions(
+    client: &RpcClient,
+    localized_tx_traces: &[LocalizedTransactionTrace],
+    deposits: &HashMap<TxHash, u64>,
+) -> anyhow::Result<HashMap<TxHash, u64>> {
+    let mut hashes = localized_tx_traces
+        .iter()
+        .filter(|tx_trace| tx_trace.transaction_position.is_none())
+        .filter_map(|tx_trace| tx_trace.transaction_hash)
+        .filter(|hash| !deposits.contains_key(hash))
+        .collect::<Vec<_>>();
+    hashes.sort();
+    hashes.dedup();
+
+    let mut positions = HashMap::new();
+    for hash in hashes {
+        let transaction = client
+            .request::<TransactionIndex>("eth_getTransactionByHash", json!([hash]))
+            .await?;
+        if let Some(transaction_index) = transaction.and_then(|tx| tx.transaction_index) {
+            positions.insert(hash, transaction_index.to::<u64>());
+        }
+    }
+    Ok(positions)
+}
+
+// Trace addresses and errors of the frames that failed, per transaction. A failed frame rolls back
+// its whole subtree, including subtraces that report no error of their own, while its siblings
+// and parent may still succeed
+fn reverted_frames(
+    localized_tx_traces: &[LocalizedTransactionTrace],
+    positions: &[Option<u64>],
+) -> HashMap<u64, Vec<(Vec<usize>, String)>> {
+    let mut reverted_frames: HashMap<u64, Vec<(Vec<usize>, String)>> = HashMap::new();
+    for (tx_trace, position) in localized_tx_traces.iter().zip(positions) {
+        if let (Some(error), Some(position)) = (&tx_trace.trace.error, position) {
+            reverted_frames
+                .entry(*position)
+                .or_default()
+                .push((tx_trace.trace.trace_address.clone(), error.clone()));
+        }
+    }
+    reverted_frames
+}
+
+// The outermost failed frame is reported, it is the one that decided the rollback
+fn revert_reason(
+    reverted_frames: &HashMap<u64, Vec<(Vec<usize>, String)>>,
+    transaction_position: u64,
+    trace_address: &[usize],
+) -> Option<String> {
+    reverted_frames
+        .get(&transaction_position)?
+        .iter()
+        .filter(|(frame, _)| trace_address.starts_with(frame))
+        .min_by_key(|(frame, _)| frame.len())
+        .map(|(_, error)| error.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    const BLOCK: u64 = 100;
+
+    fn address(byte: u8) -> Address {
+        Address::repeat_byte(byte)
+    }
+
+    fn transaction_hash(position: u64) -> TxHash {
+        TxHash::with_last_byte(position as u8 + 1)
+    }
+
+    // A trace of the transaction at `position` in the shape `trace_block` answers with. Failed
+    // frames carry their error and no result
+    fn localized(
+        position: Option<u64>,
+        trace_address: &[usize],
+        kind: &str,
+        error: Option<&str>,
+    ) -> LocalizedTransactionTrace {
+        let (action, result) = match kind {
+            "call" => (
+                json!({
+                    "callType": "call",
+                    "from": address(0x11),
+                    "to": address(0x22),
+                    "gas": "0x0",
+                    "input": "0x12345678",
+                    "value": "0x0",
+                }),
+                json!({"gasUsed": "0x0", "output": "0x"}),
+            ),
+            "create" => (
+                json!({"from": address(0x22), "gas": "0x0", "init": "0x", "value": "0x0"}),
+                json!({"address": address(0x33), "code": "0x", "gasUsed": "0x0"}),
+            ),
+            "reward" => (
+                json!({"author": address(0x44), "rewardType": "block", "value": "0x0"}),
+                Value::Null,
+            ),
+            _ => unreachable!(),
+        };
+        let mut trace = json!({
+            "type": kind,
+            "action": action,
+            "result": if error.is_some() { Value::Null } else { result },
+            "subtraces": 0,
+            "traceAddress": trace_address,
+            "blockHash": B256::repeat_byte(0xbb),
+            "blockNumber": BLOCK,
+            "transactionHash": position.map(transaction_hash),
+            "transactionPosition": position,
+        });
+        if let Some(error) = error {
+            trace["error"] = json!(error);
+        }
+        serde_json::from_value(trace).unwrap()
+    }
+
+    fn place(localized_tx_traces: Vec<LocalizedTransactionTrace>) -> Vec<BlockTrace> {
+        place_traces(
+            localized_tx_traces,
+            &HashMap::new(),
+            &HashMap::new(),
+            BLOCK,
+            false,
+        )
+    }
+
+    fn revert_reasons(block_traces: &[BlockTrace]) -> Vec<(u64, Vec<usize>, Option<&str>)> {
+        block_traces
+            .iter()
+            .map(|block_trace| {
+                (
+                    block_trace.transaction_position,
+                    block_trace.trace.trace_address.clone(),
+                    block_trace.revert_reason.as_deref(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_failed_frame_rolls_back_its_subtree_before_or_after_it() {
+        let block_traces = place(vec![
+            localized(Some(0), &[], "call", None),
+            // Reported before the frame that failed
+            localized(Some(0), &[0, 0], "create", None),
+            localized(Some(0), &[0], "call", Some("Reverted")),
+            // Reported after the frame that failed
+            localized(Some(0), &[1], "call", Some("Out of gas")),
+            localized(Some(0), &[1, 0], "create", None),
+        ]);
+        assert_eq!(
+            revert_reasons(&block_traces),
+            vec![
+                (0, vec![], None),
+                (0, vec![0, 0], Some("Reverted")),
+                (0, vec![0], Some("Reverted")),
+                (0, vec![1], Some("Out of gas")),
+                (0, vec![1, 0], Some("Out of gas")),
+            ]
+        );
+    }
+
+    #[test]
+    fn the_outermost_failed_frame_is_the_revert_reason() {
+        let block_traces = place(vec![
+            localized(Some(0), &[], "call", None),
+            localized(Some(0), &[0, 0], "call", Some("Reverted")),
+            localized(Some(0), &[0, 0, 0], "create", None),
+            localized(Some(0), &[0], "call", Some("Out of gas")),
+        ]);
+        assert_eq!(
+            revert_reasons(&block_traces),
+            vec![
+                (0, vec![], None),
+                (0, vec![0, 0], Some("Out of gas")),
+                (0, vec![0, 0, 0], Some("Out of gas")),
+                (0, vec![0], Some("Out of gas")),
+            ]
+        );
+    }
+
+    #[test]
+    fn siblings_and_other_transactions_of_a_failed_frame_stay_live() {
+        let block_traces = place(vec![
+            localized(Some(0), &[], "call", None),
+            localized(Some(0), &[0], "call", Some("Reverted")),
+            localized(Some(0), &[1], "create", None),
+            localized(Some(0), &[10], "create", None),
+            // Same trace address as the failed frame, in another transaction
+            localized(Some(1), &[0], "create", None),
+        ]);
+        assert_eq!(
+            revert_reasons(&block_traces),
+            vec![
+                (0, vec![], None),
+                (0, vec![0], Some("Reverted")),
+                (0, vec![1], None),
+                (0, vec![10], None),
+                (1, vec![0], None),
+            ]
+        );
+    }
+
+    #[test]
+    fn traces_without_position_are_placed_by_hash_or_skipped() {
+        let mut deposit = localized(None, &[], "create", None);
+        deposit.transaction_hash = Some(transaction_hash(0));
+        let mut looked_up = localized(None, &[], "create", None);
+        looked_up.transaction_hash = Some(transaction_hash(2));
+        let block_traces = place_traces(
+            vec![
+                deposit,
+                localized(Some(1), &[], "create", None),
+                looked_up,
+                // Neither position nor hash
+                localized(None, &[], "create", None),
+                localized(None, &[], "reward", None),
+            ],
+            &HashMap::from([(transaction_hash(0), 0)]),
+            &HashMap::from([(transaction_hash(2), 2)]),
+            BLOCK,
+            false,
+        );
+        assert_eq!(
+            block_traces
+                .iter()
+                .map(|block_trace| (
+                    block_trace.transaction_position,
+                    block_trace.deposit_transaction
+                ))
+                .collect::<Vec<_>>(),
+            // The reward follows the last transaction that carried a position
+            vec![(0, true), (1, false), (2, false), (2, false)]
+        );
+        assert!(matches!(block_traces[3].trace.action, Action::Reward(_)));
+    }
+
+    #[test]
+    fn senders_and_selectors_come_from_the_frames_above() {
+        let block_traces = place_traces(
+            vec![
+                localized(Some(0), &[], "call", None),
+                localized(Some(0), &[0], "create", None),
+            ],
+            &HashMap::new(),
+            &HashMap::new(),
+            BLOCK,
+            true,
+        );
+        assert_eq!(block_traces[1].senders, vec![address(0x11), address(0x22)]);
+        assert_eq!(
+            block_traces[1].selector,
+            Some(Selector::from([0x12, 0x34, 0x56, 0x78]))
+        );
+        assert_eq!(block_traces[0].selector, None);
+    }
+}
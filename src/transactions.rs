@@ -0,0 +1,44 @@
+use block_tracer::chain_db::ChainDb;
+
+use crate::nonces;
+use crate::TraceResponse;
+
+// Fills in transaction hash, index and sender of every record from the local database: records
+// with a hash are looked up by hash, records without one through their position in the block.
+// Senders missing from the database (pruned, or not yet written by the node) are recovered from
+// the transaction signatures by the backend. Creates at the top of a transaction get the nonce
+// it was sent with
+pub fn resolve_transactions<'a>(
+    db: &dyn ChainDb,
+    trace_responses: impl Iterator<Item = &'a mut TraceResponse>,
+) -> anyhow::Result<()> {
+    let mut resolved = Vec::new();
+    for trace_response in trace_responses {
+        let transaction = match trace_response.transaction_hash {
+            Some(transaction_hash) => db.transaction_by_hash(transaction_hash)?,
+            None => db.transaction_at(
+                trace_response.block_number,
+                trace_response.transaction_position,
+            )?,
+        };
+        let Some(transaction) = transaction else {
+            continue;
+        };
+        trace_response.transaction_hash = Some(transaction.hash);
+        trace_response.transaction_position = transaction.index;
+        if nonces::is_eoa_creation(trace_response) {
+            trace_response.creator_nonce = Some(transaction.nonce);
+        }
+        resolved.push((trace_response, transaction.tx_number));
+    }
+
+    let tx_numbers = resolved
+        .iter()
+        .map(|(_, tx_number)| *tx_number)
+        .collect::<Vec<_>>();
+    let senders = db.senders(&tx_numbers)?;
+    for ((trace_response, _), sender) in resolved.into_iter().zip(senders) {
+        trace_response.sender = sender;
+    }
+    Ok(())
+}
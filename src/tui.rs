@@ -0,0 +1,205 @@
+use std::io::{stderr, stdout, IsTerminal, Stderr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use ratatui::backend::CrosstermBackend;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyModifiers};
+use ratatui::crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use ratatui::crossterm::ExecutableCommand;
+use ratatui::layout::{Constraint, Layout};
+use ratatui::widgets::{Block, Borders, Gauge, List, ListItem, Paragraph, Row, Table};
+use ratatui::{Frame, Terminal};
+
+use crate::progress::progress;
+
+const REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+// Exit status of a process stopped by SIGINT
+const INTERRUPTED: i32 = 130;
+
+// Draws on stderr so stdout can still be redirected to a log file, restored when dropped. Output
+// of the scan is shown in the log pane meanwhile
+pub struct Dashboard {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Dashboard {
+    pub fn start() -> anyhow::Result<Self> {
+        enable_raw_mode()?;
+        stderr().execute(EnterAlternateScreen)?;
+        let mut terminal = Terminal::new(CrosstermBackend::new(stderr()))?;
+        terminal.clear()?;
+        progress().set_dashboard(true, stdout().is_terminal());
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let handle = std::thread::spawn(move || {
+            let started = Instant::now();
+            while !thread_stop.load(Ordering::Relaxed) {
+                if terminal.draw(|frame| draw(frame, started)).is_err() {
+                    break;
+                }
+                // Closing the dashboard leaves the scan running
+                if event::poll(REFRESH_INTERVAL).unwrap_or(false) {
+                    if let Ok(Event::Key(key)) = event::read() {
+                        if key.code == KeyCode::Char('q') {
+                            break;
+                        }
+                        // Raw mode delivers Ctrl-C as a key instead of SIGINT
+                        if key.code == KeyCode::Char('c')
+                            && key.modifiers.contains(KeyModifiers::CONTROL)
+                        {
+                            restore(&mut terminal);
+                            std::process::exit(INTERRUPTED);
+                        }
+                    }
+                }
+            }
+            restore(&mut terminal);
+        });
+
+        Ok(Self {
+            stop,
+            handle: Some(handle),
+        })
+    }
+}
+
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn restore(terminal: &mut Terminal<CrosstermBackend<Stderr>>) {
+    progress().set_dashboard(false, false);
+    let _ = disable_raw_mode();
+    let _ = terminal.backend_mut().execute(LeaveAlternateScreen);
+    let _ = terminal.show_cursor();
+}
+
+fn draw(frame: &mut Frame, started: Instant) {
+    let progress = progress();
+    let [throughput_area, body_area, failed_area, log_area] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Min(5),
+        Constraint::Length(3),
+        Constraint::Percentage(30),
+    ])
+    .areas(frame.size());
+    let [endpoints_area, findings_area] =
+        Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .areas(body_area);
+
+    let (blocks_done, blocks_total) = progress.blocks();
+    let blocks_per_second = blocks_done as f64 / started.elapsed().as_secs_f64().max(1.0);
+    frame.render_widget(
+        Gauge::default()
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Throughput (q closes, Ctrl-C stops the scan)"),
+            )
+            .ratio(if blocks_total == 0 {
+                0.0
+            } else {
+                (blocks_done as f64 / blocks_total as f64).min(1.0)
+            })
+            .label(format!(
+                "{}/{} blocks, {:.1} blocks/s",
+                blocks_done, blocks_total, blocks_per_second
+            )),
+        throughput_area,
+    );
+
+    let rows = progress
+        .endpoints()
+        .into_iter()
+        .map(|(endpoint, stats)| {
+            let average_latency =
+                stats.total_latency.as_secs_f64() * 1000.0 / stats.requests.max(1) as f64;
+            Row::new(vec![
+                endpoint,
+                stats.requests.to_string(),
+                stats.errors.to_string(),
+                stats.rate_limited.to_string(),
+                format!("{:.0} ms", average_latency),
+            ])
+        })
+        .collect::<Vec<_>>();
+    frame.render_widget(
+        Table::new(
+            rows,
+            [
+                Constraint::Percentage(40),
+                Constraint::Percentage(15),
+                Constraint::Percentage(15),
+                Constraint::Percentage(15),
+                Constraint::Percentage(15),
+            ],
+        )
+        .header(Row::new(vec![
+            "Endpoint",
+            "Requests",
+            "Errors",
+            "Rate limited",
+            "Avg latency",
+        ]))
+        .block(Block::default().borders(Borders::ALL).title("Endpoints")),
+        endpoints_area,
+    );
+
+    let findings = progress
+        .findings()
+        .into_iter()
+        .rev()
+        .map(ListItem::new)
+        .collect::<Vec<_>>();
+    frame.render_widget(
+        List::new(findings).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Recent findings"),
+        ),
+        findings_area,
+    );
+
+    let failed_blocks = progress.failed_blocks();
+    let failed = if failed_blocks.is_empty() {
+        "none".to_string()
+    } else {
+        failed_blocks
+            .iter()
+            .map(|block_num| block_num.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    frame.render_widget(
+        Paragraph::new(failed).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!("Failed blocks ({})", failed_blocks.len())),
+        ),
+        failed_area,
+    );
+
+    // The most recent lines that fit, oldest at the top like a terminal
+    let log_lines = progress.log_lines();
+    let visible = log_area.height.saturating_sub(2) as usize;
+    let log = log_lines[log_lines.len().saturating_sub(visible)..]
+        .iter()
+        .cloned()
+        .map(ListItem::new)
+        .collect::<Vec<_>>();
+    frame.render_widget(
+        List::new(log).block(Block::default().borders(Borders::ALL).title("Log")),
+        log_area,
+    );
+}
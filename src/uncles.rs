@@ -0,0 +1,27 @@
+use alloy_primitives::{Address, B256};
+use block_tracer::chain_db::ChainDb;
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct UncleInclusion {
+    pub block_number: u64,
+    pub uncle_number: u64,
+    pub uncle_hash: B256,
+    pub miner: Address,
+}
+
+pub fn uncle_inclusions(
+    db: &dyn ChainDb,
+    start_block: u64,
+    end_block: u64,
+) -> anyhow::Result<Vec<UncleInclusion>> {
+    Ok(db
+        .ommers(start_block, end_block)?
+        .into_iter()
+        .map(|ommer| UncleInclusion {
+            block_number: ommer.block_number,
+            uncle_number: ommer.number,
+            uncle_hash: ommer.hash,
+            miner: ommer.beneficiary,
+        })
+        .collect())
+}
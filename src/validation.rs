@@ -0,0 +1,179 @@
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+use alloy_primitives::Address;
+
+use crate::report::RunOutput;
+use crate::TraceType;
+
+// A create or selfdestruct known from an external dataset, e.g. a data warehouse export
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize)]
+pub struct ReferenceRecord {
+    pub block_number: u64,
+    pub address: Address,
+    pub trace_type: TraceType,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct ReferenceRow {
+    block_number: u64,
+    #[serde(alias = "contract_address")]
+    address: Address,
+    trace_type: String,
+}
+
+// Warehouses name selfdestructs after the pre-EIP-6 opcode
+fn parse_trace_type(trace_type: &str) -> anyhow::Result<TraceType> {
+    match trace_type.to_lowercase().as_str() {
+        "create" | "create2" => Ok(TraceType::Create),
+        "selfdestruct" | "suicide" => Ok(TraceType::SelfDestruct),
+        other => anyhow::bail!("Unknown trace type {} in the reference dataset", other),
+    }
+}
+
+// `.parquet` files need the `parquet` feature, anything else is read as CSV with a header row
+pub fn load_reference(path: &Path) -> anyhow::Result<Vec<ReferenceRecord>> {
+    if path
+        .extension()
+        .is_some_and(|extension| extension == "parquet")
+    {
+        return read_parquet(path);
+    }
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_path(path)?;
+    let mut records = Vec::new();
+    for row in reader.deserialize::<ReferenceRow>() {
+        let row = row?;
+        records.push(ReferenceRecord {
+            block_number: row.block_number,
+            address: row.address,
+            trace_type: parse_trace_type(&row.trace_type)?,
+        });
+    }
+    Ok(records)
+}
+
+#[cfg(feature = "parquet")]
+fn read_parquet(path: &Path) -> anyhow::Result<Vec<ReferenceRecord>> {
+    use std::str::FromStr;
+
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::Field;
+
+    let reader = SerializedFileReader::new(std::fs::File::open(path)?)?;
+    let mut records = Vec::new();
+    for row in reader.get_row_iter(None)? {
+        let row = row?;
+        let (mut block_number, mut address, mut trace_type) = (None, None, None);
+        for (name, field) in row.get_column_iter() {
+            match (name.as_str(), field) {
+                ("block_number", Field::Long(value)) => block_number = Some(*value as u64),
+                ("block_number", Field::ULong(value)) => block_number = Some(*value),
+                ("block_number", Field::Int(value)) => block_number = Some(*value as u64),
+                ("address" | "contract_address", Field::Str(value)) => {
+                    address = Some(Address::from_str(value)?)
+                }
+                ("address" | "contract_address", Field::Bytes(value)) => {
+                    address = Some(Address::try_from(value.data())?)
+                }
+                ("trace_type", Field::Str(value)) => trace_type = Some(parse_trace_type(value)?),
+                _ => {}
+            }
+        }
+        let (Some(block_number), Some(address), Some(trace_type)) =
+            (block_number, address, trace_type)
+        else {
+            anyhow::bail!(
+                "{} needs block_number, address and trace_type columns",
+                path.display()
+            );
+        };
+        records.push(ReferenceRecord {
+            block_number,
+            address,
+            trace_type,
+        });
+    }
+    Ok(records)
+}
+
+#[cfg(not(feature = "parquet"))]
+fn read_parquet(_path: &Path) -> anyhow::Result<Vec<ReferenceRecord>> {
+    anyhow::bail!("Reading a Parquet reference dataset requires building with `--features parquet`")
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Validation {
+    pub first_block: Option<u64>,
+    pub last_block: Option<u64>,
+    pub matched: usize,
+    // Share of block-tracer's records that are in the reference
+    pub precision: f64,
+    // Share of the reference's records that block-tracer found
+    pub recall: f64,
+    pub only_in_run: Vec<ReferenceRecord>,
+    pub only_in_reference: Vec<ReferenceRecord>,
+}
+
+// Records are matched by block, address and type, since warehouses rarely carry the position in
+// the call tree. The reference is cut to the run's scanned range, and its rows in blocks the run
+// failed to trace are not held against it
+pub fn validate(run: &RunOutput, reference: Vec<ReferenceRecord>) -> Validation {
+    let bounds = run.blocks.bounds();
+    let failed_blocks = run
+        .failed_blocks
+        .iter()
+        .map(|failed_block| failed_block.block_number)
+        .collect::<HashSet<_>>();
+    let scanned = |block_number: u64| {
+        run.blocks.contains(block_number) && !failed_blocks.contains(&block_number)
+    };
+
+    let mut counts: BTreeMap<ReferenceRecord, (usize, usize)> = BTreeMap::new();
+    for trace_record in run.effective_records() {
+        let record = ReferenceRecord {
+            block_number: trace_record.block_number,
+            address: trace_record.contract_address,
+            trace_type: trace_record.trace_type.clone(),
+        };
+        counts.entry(record).or_default().0 += 1;
+    }
+    for record in reference
+        .into_iter()
+        .filter(|record| scanned(record.block_number))
+    {
+        counts.entry(record).or_default().1 += 1;
+    }
+
+    let (mut matched, mut run_total, mut reference_total) = (0, 0, 0);
+    let mut only_in_run = Vec::new();
+    let mut only_in_reference = Vec::new();
+    for (record, (in_run, in_reference)) in counts {
+        matched += in_run.min(in_reference);
+        run_total += in_run;
+        reference_total += in_reference;
+        only_in_run
+            .extend(std::iter::repeat(record.clone()).take(in_run.saturating_sub(in_reference)));
+        only_in_reference
+            .extend(std::iter::repeat(record).take(in_reference.saturating_sub(in_run)));
+    }
+    Validation {
+        first_block: bounds.map(|(first_block, _)| first_block),
+        last_block: bounds.map(|(_, last_block)| last_block),
+        matched,
+        precision: ratio(matched, run_total),
+        recall: ratio(matched, reference_total),
+        only_in_run,
+        only_in_reference,
+    }
+}
+
+// Nothing to find and nothing found is a perfect score
+fn ratio(matched: usize, total: usize) -> f64 {
+    if total == 0 {
+        1.0
+    } else {
+        matched as f64 / total as f64
+    }
+}
@@ -0,0 +1,140 @@
+use alloy_primitives::{Address, TxHash};
+use serde_json::json;
+
+use crate::progress::log_line;
+use crate::TraceResponse;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FindingKind {
+    Recreation,
+    WatchedSelfdestruct,
+    // Recreation that deployed different runtime code than before
+    MetamorphicRedeploy,
+    // A user defined rule matched
+    Alert,
+    // A pending transaction would selfdestruct a watched contract
+    PendingSelfdestruct,
+    // A transaction in the pending block would recreate a contract destroyed while following
+    PendingRecreation,
+    // A selfdestruct swept ether into a contract without receive or payable fallback
+    ForcedEther,
+    // A --plugin flagged the record
+    Plugin,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Finding {
+    pub kind: FindingKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rule: Option<String>,
+    pub address: Address,
+    pub block_number: Option<u64>,
+    pub transaction_hash: Option<TxHash>,
+}
+
+impl Finding {
+    fn summary(&self) -> String {
+        let kind = match (&self.kind, &self.rule) {
+            (FindingKind::Recreation, _) => "Recreated contract".to_string(),
+            (FindingKind::WatchedSelfdestruct, _) => "Watched contract selfdestructed".to_string(),
+            (FindingKind::MetamorphicRedeploy, _) => "Metamorphic redeploy".to_string(),
+            (FindingKind::Alert, Some(rule)) => format!("Alert \"{}\":", rule),
+            (FindingKind::Alert, None) => "Alert:".to_string(),
+            (FindingKind::Plugin, Some(plugin)) => format!("Plugin \"{}\" flagged", plugin),
+            (FindingKind::Plugin, None) => "Plugin flagged".to_string(),
+            (FindingKind::PendingSelfdestruct, _) => {
+                "Pending transaction would selfdestruct watched contract".to_string()
+            }
+            (FindingKind::PendingRecreation, _) => {
+                "Pending transaction would recreate contract".to_string()
+            }
+            (FindingKind::ForcedEther, _) => {
+                "Selfdestruct forced ether into non-payable contract".to_string()
+            }
+        };
+        match self.block_number {
+            Some(block_number) => format!("{} {} in block {}", kind, self.address, block_number),
+            None => format!("{} {}", kind, self.address),
+        }
+    }
+}
+
+// Recreations (metamorphic ones flagged separately) and selfdestructs of watched addresses
+pub fn findings(
+    reinitialized_contracts: &[Address],
+    created_trace_responses: &[TraceResponse],
+    self_destructed_trace_responses: &[TraceResponse],
+    watchlist: &[Address],
+) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for address in reinitialized_contracts {
+        let recreation = created_trace_responses
+            .iter()
+            .filter(|trace_response| trace_response.contract_address == *address)
+            .last();
+        findings.push(Finding {
+            kind: FindingKind::Recreation,
+            rule: None,
+            address: *address,
+            block_number: recreation.map(|trace_response| trace_response.block_number),
+            transaction_hash: recreation.and_then(|trace_response| trace_response.transaction_hash),
+        });
+
+        let mut code_hashes = created_trace_responses
+            .iter()
+            .filter(|trace_response| trace_response.contract_address == *address)
+            .filter_map(|trace_response| trace_response.code_hash)
+            .collect::<Vec<_>>();
+        code_hashes.dedup();
+        if code_hashes.len() > 1 {
+            findings.push(Finding {
+                kind: FindingKind::MetamorphicRedeploy,
+                rule: None,
+                address: *address,
+                block_number: recreation.map(|trace_response| trace_response.block_number),
+                transaction_hash: recreation
+                    .and_then(|trace_response| trace_response.transaction_hash),
+            });
+        }
+    }
+    for trace_response in self_destructed_trace_responses {
+        if watchlist.contains(&trace_response.contract_address) {
+            findings.push(Finding {
+                kind: FindingKind::WatchedSelfdestruct,
+                rule: None,
+                address: trace_response.contract_address,
+                block_number: Some(trace_response.block_number),
+                transaction_hash: trace_response.transaction_hash,
+            });
+        }
+    }
+    findings
+}
+
+// Slack and Discord get a chat message, any other URL the raw finding
+fn payload(url: &str, finding: &Finding) -> serde_json::Value {
+    if url.starts_with("https://hooks.slack.com/") {
+        json!({ "text": finding.summary() })
+    } else if url.starts_with("https://discord.com/api/webhooks/")
+        || url.starts_with("https://discordapp.com/api/webhooks/")
+    {
+        json!({ "content": finding.summary() })
+    } else {
+        json!(finding)
+    }
+}
+
+// Delivery failures are reported but never abort the scan
+pub async fn notify(webhooks: &[String], findings: &[Finding]) {
+    let client = reqwest::Client::new();
+    for finding in findings {
+        for url in webhooks {
+            let response = client.post(url).json(&payload(url, finding)).send().await;
+            match response.and_then(|response| response.error_for_status()) {
+                Ok(_) => {}
+                Err(err) => log_line(format!("Webhook {} failed: {}", url, err)),
+            }
+        }
+    }
+}
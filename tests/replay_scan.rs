@@ -0,0 +1,97 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde_json::Value;
+
+// Responses recorded with `--record` for a scan of block 100 holding a single contract creation
+// by an EOA, see tests/fixtures/replay_scan
+fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/replay_scan")
+}
+
+fn scan(output_dir: &Path) {
+    let _ = std::fs::remove_dir_all(output_dir);
+    std::fs::create_dir_all(output_dir).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_block-tracer"))
+        .arg("--replay")
+        .arg(fixtures_dir())
+        .args(["--start-block", "100", "--end-block", "100"])
+        .arg("--output-dir")
+        .arg(output_dir)
+        // A .env or a datadir of the environment must not leak into the run
+        .current_dir(output_dir)
+        .env_remove("RPC_URL")
+        .env_remove("DB_PATH")
+        .env_remove("STATIC_FILES_PATH")
+        .env_remove("ERIGON_CHAINDATA")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "scan failed\nstdout:\n{}\nstderr:\n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+fn read_json(path: PathBuf) -> Value {
+    serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap()
+}
+
+#[test]
+fn replayed_scan_writes_the_recorded_records() {
+    let output_dir =
+        std::env::temp_dir().join(format!("block-tracer-replay-{}", std::process::id()));
+    scan(&output_dir);
+
+    let records = read_json(output_dir.join("trace_records.json"));
+    let records = records.as_array().unwrap();
+    assert_eq!(records.len(), 1);
+    let record = &records[0];
+    assert_eq!(record["trace_type"], "create");
+    assert_eq!(
+        record["contract_address"],
+        "0xcd234a471b72ba2f1ccf0a70fcaba648a5eecd8d"
+    );
+    assert_eq!(record["chain_id"], 1);
+    assert_eq!(record["block_number"], 100);
+    assert_eq!(
+        record["block_hash"],
+        "0x8e38b4dbf6b11fcc3b9dee84fb7986e29ca0a02cecd8977c161ff7333329681e"
+    );
+    assert_eq!(record["block_timestamp"], 1438270017);
+    assert_eq!(record["transaction_position"], 0);
+    assert_eq!(
+        record["transaction_hash"],
+        "0x5f1e1b0a7f6c2d4b3a9e8d7c6b5a49382716f5e4d3c2b1a0f9e8d7c6b5a49382"
+    );
+    assert_eq!(record["trace_address"], serde_json::json!([]));
+    assert_eq!(record["depth"], 0);
+    assert_eq!(
+        record["deployer"],
+        "0x6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0"
+    );
+    assert_eq!(
+        record["sender"],
+        "0x6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0"
+    );
+    // The address is the CREATE address of the sender at this nonce
+    assert_eq!(record["creator_nonce"], 0);
+    assert_eq!(record["creator_kind"], "eoa");
+    assert_eq!(record["transaction_gas_used"], 53000);
+    assert_eq!(record["transaction_success"], true);
+    assert_eq!(
+        record["code_hash"],
+        "0xbc36789e7a1e281436464229828f817d6612f7b477d66591ff96a9e064bcc98a"
+    );
+    assert_eq!(record["revert_reason"], Value::Null);
+
+    assert_eq!(
+        read_json(output_dir.join("reinitialized_contracts.json")),
+        serde_json::json!([])
+    );
+    let completeness = read_json(output_dir.join("completeness.json"));
+    assert_eq!(completeness["failed_blocks"], 0);
+
+    std::fs::remove_dir_all(&output_dir).unwrap();
+}